@@ -0,0 +1,337 @@
+use anyhow::{Context, Result};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::metrics;
+
+/// CIDR allow and deny lists; deny wins, and a non-empty allow list admits only its ranges
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<IpNet>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<IpNet>,
+}
+
+impl AccessListConfig {
+    /// Whether `ip` passes this list
+    fn permits(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Access lists for the query port and all listeners, re-read from the config file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListsConfig {
+    /// Checked on every listener
+    #[serde(default)]
+    pub global: AccessListConfig,
+    /// Checked on the query port (plaintext and TLS)
+    #[serde(default)]
+    pub query: AccessListConfig,
+    /// How often the lists are re-read from the config file (default: 30)
+    #[serde(default = "default_reload_seconds")]
+    pub reload_seconds: u64,
+}
+
+fn default_reload_seconds() -> u64 {
+    30
+}
+
+/// Listener a connection or packet arrived on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Listener {
+    Query,
+    Data(u16),
+}
+
+impl Listener {
+    fn label(&self) -> String {
+        match self {
+            Listener::Query => "query".to_string(),
+            Listener::Data(port) => port.to_string(),
+        }
+    }
+}
+
+/// The lists in effect
+#[derive(Debug, Default, PartialEq)]
+struct AccessLists {
+    global: AccessListConfig,
+    query: AccessListConfig,
+    /// Data port -> its `dataPorts[].access` list
+    data_ports: HashMap<u16, AccessListConfig>,
+}
+
+impl AccessLists {
+    fn from_config(config: &Config) -> Self {
+        let (global, query) = config
+            .access_lists
+            .as_ref()
+            .map(|lists| (lists.global.clone(), lists.query.clone()))
+            .unwrap_or_default();
+        let data_ports = config
+            .get_data_ports()
+            .into_iter()
+            .filter_map(|port_config| Some((port_config.port, port_config.access?)))
+            .collect();
+        Self {
+            global,
+            query,
+            data_ports,
+        }
+    }
+
+    /// The list that rejects `ip` on `listener`, if any
+    fn rejecting_list(&self, listener: Listener, ip: IpAddr) -> Option<&'static str> {
+        if !self.global.permits(ip) {
+            return Some("global");
+        }
+        let permitted = match listener {
+            Listener::Query => self.query.permits(ip),
+            Listener::Data(port) => self
+                .data_ports
+                .get(&port)
+                .is_none_or(|list| list.permits(ip)),
+        };
+        (!permitted).then_some("listener")
+    }
+}
+
+/// Checks client addresses against the configured CIDR lists
+#[derive(Clone)]
+pub struct AccessControl {
+    reload_seconds: u64,
+    lists: Arc<RwLock<AccessLists>>,
+}
+
+impl AccessControl {
+    pub fn new(config: &Config) -> Self {
+        let lists = AccessLists::from_config(config);
+        info!(
+            "Access lists loaded ({} global, {} query and {} data port rule(s))",
+            lists.global.allow.len() + lists.global.deny.len(),
+            lists.query.allow.len() + lists.query.deny.len(),
+            lists
+                .data_ports
+                .values()
+                .map(|list| list.allow.len() + list.deny.len())
+                .sum::<usize>()
+        );
+        Self {
+            reload_seconds: config
+                .access_lists
+                .as_ref()
+                .map_or_else(default_reload_seconds, |lists| lists.reload_seconds),
+            lists: Arc::new(RwLock::new(lists)),
+        }
+    }
+
+    /// Re-read the lists from the config file periodically so ConfigMap edits need no restart
+    pub async fn run(self) -> Result<()> {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.reload_seconds.max(1)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.reload().await {
+                warn!("Failed to reload access lists: {:#}", e);
+            }
+        }
+    }
+
+    /// Replace the lists, keeping the old ones if the config cannot be loaded
+    async fn reload(&self) -> Result<()> {
+        let config = Config::load()
+            .await
+            .context("Keeping the current access lists")?;
+        let lists = AccessLists::from_config(&config);
+        let mut current = self.lists.write().unwrap_or_else(|e| e.into_inner());
+        if *current != lists {
+            info!("Reloaded access lists");
+            *current = lists;
+        }
+        Ok(())
+    }
+
+    /// Whether `ip` may use `listener`; rejections are counted
+    pub fn allows(&self, listener: Listener, ip: IpAddr) -> bool {
+        let Some(list) = self
+            .lists
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .rejecting_list(listener, ip)
+        else {
+            return true;
+        };
+        debug!(
+            "Rejected {} on listener {} ({} access list)",
+            ip,
+            listener.label(),
+            list
+        );
+        metrics::record_access_denied(&listener.label(), list);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn list(allow: &[&str], deny: &[&str]) -> AccessListConfig {
+        AccessListConfig {
+            allow: allow.iter().map(|net| net.parse().unwrap()).collect(),
+            deny: deny.iter().map(|net| net.parse().unwrap()).collect(),
+        }
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn test_allow_and_deny() {
+        let list = list(&["10.0.0.0/8", "2001:db8::/32"], &["10.66.0.0/16"]);
+        assert!(list.permits(ip("10.1.2.3")));
+        assert!(!list.permits(ip("10.66.1.1")));
+        assert!(!list.permits(ip("192.0.2.1")));
+        assert!(list.permits(ip("2001:db8::1")));
+        // IPv4-mapped IPv6 addresses match IPv4 ranges
+        assert!(list.permits(ip("::ffff:10.1.2.3")));
+
+        // Deny only: everything else is allowed
+        assert!(self::list(&[], &["203.0.113.0/24"]).permits(ip("198.51.100.1")));
+    }
+
+    #[test]
+    fn test_listener_lists() {
+        let lists = AccessLists {
+            global: list(&[], &["203.0.113.0/24"]),
+            query: list(&["10.0.0.0/8"], &[]),
+            data_ports: HashMap::from([(7778, list(&["192.0.2.0/24"], &[]))]),
+        };
+
+        assert_eq!(
+            lists.rejecting_list(Listener::Data(7777), ip("203.0.113.9")),
+            Some("global")
+        );
+        // Query port limited to the matchmaker subnet, data ports stay public
+        assert_eq!(
+            lists.rejecting_list(Listener::Query, ip("198.51.100.1")),
+            Some("listener")
+        );
+        assert_eq!(lists.rejecting_list(Listener::Query, ip("10.0.0.5")), None);
+        assert_eq!(
+            lists.rejecting_list(Listener::Data(7777), ip("198.51.100.1")),
+            None
+        );
+        assert_eq!(
+            lists.rejecting_list(Listener::Data(7778), ip("198.51.100.1")),
+            Some("listener")
+        );
+    }
+
+    #[test]
+    fn test_deny_takes_precedence() {
+        // A deny range wins over a narrower allow entry
+        let narrow_allow = list(
+            &["10.0.0.5/32", "2001:db8::/32"],
+            &["10.0.0.0/8", "2001:db8:bad::/48"],
+        );
+        assert!(!narrow_allow.permits(ip("10.0.0.5")));
+        assert!(!narrow_allow.permits(ip("::ffff:10.0.0.5")));
+        assert!(!narrow_allow.permits(ip("2001:db8:bad::1")));
+        assert!(narrow_allow.permits(ip("2001:db8:1::1")));
+
+        // The global list applies before, and regardless of, the listener's list
+        let lists = AccessLists {
+            global: list(&["10.0.0.0/8", "192.0.2.0/24"], &["10.66.0.0/16"]),
+            query: list(&["10.66.0.0/16", "172.16.0.0/12"], &[]),
+            data_ports: HashMap::new(),
+        };
+        assert_eq!(
+            lists.rejecting_list(Listener::Query, ip("10.66.0.1")),
+            Some("global")
+        );
+        assert_eq!(
+            lists.rejecting_list(Listener::Query, ip("172.16.0.1")),
+            Some("global")
+        );
+        assert_eq!(
+            lists.rejecting_list(Listener::Query, ip("192.0.2.1")),
+            Some("listener")
+        );
+        assert_eq!(
+            lists.rejecting_list(Listener::Data(7777), ip("192.0.2.1")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reload_keeps_lists_on_error() {
+        let config_yaml = |access_lists: &str| {
+            format!(
+                r#"
+queryPort: 9000
+dataPort: 7777
+defaultEndpoint:
+  resourceType: gameserver
+  namespace: default
+tokenTtlSeconds: 30
+sessionTimeoutSeconds: 300
+controlPacketMagicBytes: FFFFFFFF5245534554
+resourceQueryMapping:
+  gameserver:
+    group: agones.dev
+    version: v1
+    resource: gameservers
+    addressPath: status.address
+    port: 7777
+accessLists:
+{}
+"#,
+                access_lists
+            )
+        };
+        let dir = temp_dir();
+        let path = dir.path().join("config.yaml");
+        // SAFETY: no other test reads or writes CONFIG_PATH
+        unsafe { std::env::set_var("CONFIG_PATH", &path) };
+
+        let initial = config_yaml("  query: {deny: [\"203.0.113.0/24\"]}");
+        let control = AccessControl::new(&serde_yaml::from_str(&initial).unwrap());
+        let denied = || {
+            metrics::ACCESS_DENIED
+                .with_label_values(&["query", "listener"])
+                .get()
+        };
+        let before = denied();
+        assert!(!control.allows(Listener::Query, ip("203.0.113.9")));
+        assert_eq!(denied(), before + 1);
+
+        // A broken or missing config file keeps the lists in effect
+        std::fs::write(&path, "accessLists: [").unwrap();
+        assert!(control.reload().await.is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(control.reload().await.is_err());
+        assert!(!control.allows(Listener::Query, ip("203.0.113.9")));
+
+        std::fs::write(&path, config_yaml("  query: {deny: [\"198.51.100.0/24\"]}")).unwrap();
+        control.reload().await.unwrap();
+        assert!(control.allows(Listener::Query, ip("203.0.113.9")));
+        assert!(!control.allows(Listener::Query, ip("198.51.100.9")));
+    }
+}
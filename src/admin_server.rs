@@ -0,0 +1,390 @@
+use anyhow::Result;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Bytes;
+use hyper::header::AUTHORIZATION;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::net::TcpListener;
+use tracing::{error, info, warn};
+
+use crate::query_auth::{QueryAuthConfig, QueryAuthenticator};
+use crate::session::SessionManager;
+use crate::token_cache::TokenCache;
+
+/// Largest accepted admin request body
+const MAX_ADMIN_BODY_BYTES: usize = 16 * 1024;
+
+/// Authenticated admin API (token revocation, session lookup) on its own listener,
+/// separate from the metrics port
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminApiConfig {
+    /// Address to bind (default: 127.0.0.1, reached with `kubectl port-forward`)
+    #[serde(default = "default_bind_address")]
+    pub bind_address: IpAddr,
+    /// Port of the admin API (default: 9091)
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Admin keys (same format as `queryAuth`); defaults to the `queryAuth` keyring
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<QueryAuthConfig>,
+}
+
+fn default_bind_address() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
+
+fn default_port() -> u16 {
+    9091
+}
+
+/// Body of `POST /admin/tokens/revoke`: one token, or all tokens of a backend
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RevokeRequest {
+    token: Option<String>,
+    backend: Option<String>,
+}
+
+/// Serves the admin API; every request must carry an API key or HMAC signature
+#[derive(Clone)]
+pub struct AdminServer {
+    config: AdminApiConfig,
+    token_cache: TokenCache,
+    session_manager: SessionManager,
+    authenticator: QueryAuthenticator,
+}
+
+impl AdminServer {
+    pub fn new(
+        config: AdminApiConfig,
+        token_cache: TokenCache,
+        session_manager: SessionManager,
+        authenticator: QueryAuthenticator,
+    ) -> Self {
+        Self {
+            config,
+            token_cache,
+            session_manager,
+            authenticator,
+        }
+    }
+
+    /// Start the admin HTTP server
+    pub async fn run(self) -> Result<()> {
+        let addr = SocketAddr::new(self.config.bind_address, self.config.port);
+        let listener = TcpListener::bind(addr).await?;
+
+        info!("Admin API listening on http://{}", addr);
+
+        loop {
+            let (stream, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!("Failed to accept admin connection: {}", e);
+                    continue;
+                }
+            };
+
+            let server = self.clone();
+            tokio::spawn(async move {
+                let io = TokioIo::new(stream);
+                let service = service_fn(move |req| {
+                    let server = server.clone();
+                    async move { server.handle_request(req).await }
+                });
+
+                if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
+                    error!("Error serving admin connection: {:?}", err);
+                }
+            });
+        }
+    }
+
+    /// Read the body, then authenticate and route the request
+    async fn handle_request(
+        &self,
+        req: Request<hyper::body::Incoming>,
+    ) -> Result<Response<Full<Bytes>>> {
+        let (parts, body) = req.into_parts();
+        let (status, body) = match Limited::new(body, MAX_ADMIN_BODY_BYTES).collect().await {
+            Ok(body) => {
+                let authorization = parts
+                    .headers
+                    .get(AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok());
+                self.respond(&parts.method, &parts.uri, authorization, &body.to_bytes())
+                    .await
+            }
+            Err(_) => error_body(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large"),
+        };
+
+        Response::builder()
+            .status(status)
+            .header("Content-Type", "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| anyhow::anyhow!("Failed to build admin response: {}", e))
+    }
+
+    async fn respond(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        authorization: Option<&str>,
+        body: &[u8],
+    ) -> (StatusCode, String) {
+        let path = uri.path();
+        let target = uri.path_and_query().map_or(path, |target| target.as_str());
+        if let Err(e) =
+            self.authenticator
+                .authenticate_request(authorization, method.as_str(), target, body)
+        {
+            warn!("Rejected admin request to {}: {}", path, e);
+            let body = serde_json::json!({ "error": e.to_string(), "code": e.code() });
+            return (StatusCode::UNAUTHORIZED, body.to_string());
+        }
+
+        match (method, path) {
+            (&Method::POST, "/admin/tokens/revoke") => self.revoke(body).await,
+            (_, "/admin/tokens/revoke") => error_body(StatusCode::METHOD_NOT_ALLOWED, "Use POST"),
+            (&Method::GET, "/admin/sessions") => {
+                match uri.query().and_then(|query| query_param(query, "player")) {
+                    Some(player_id) => self.player_sessions(&player_id),
+                    None => error_body(StatusCode::BAD_REQUEST, "Expected ?player=<id>"),
+                }
+            }
+            (_, "/admin/sessions") => error_body(StatusCode::METHOD_NOT_ALLOWED, "Use GET"),
+            _ => error_body(StatusCode::NOT_FOUND, "Not Found"),
+        }
+    }
+
+    /// Revoke a token or every token issued for a backend IP
+    ///
+    /// Signed tokens are refused: a revocation would only reach this replica.
+    async fn revoke(&self, body: &[u8]) -> (StatusCode, String) {
+        if self.token_cache.signs_tokens() {
+            return error_body(
+                StatusCode::CONFLICT,
+                "Signed tokens cannot be revoked; remove their signing key instead",
+            );
+        }
+
+        let revoked = match serde_json::from_slice::<RevokeRequest>(body) {
+            Ok(RevokeRequest {
+                token: Some(token),
+                backend: None,
+            }) => usize::from(self.token_cache.revoke(&token).await),
+            Ok(RevokeRequest {
+                token: None,
+                backend: Some(backend),
+            }) => {
+                let revoked = self.token_cache.revoke_backend(&backend).await;
+                info!("Revoked tokens for backend {} via admin API", backend);
+                revoked
+            }
+            _ => {
+                return error_body(
+                    StatusCode::BAD_REQUEST,
+                    r#"Expected {"token": ...} or {"backend": ...}"#,
+                );
+            }
+        };
+
+        (
+            StatusCode::OK,
+            serde_json::json!({ "revoked": revoked }).to_string(),
+        )
+    }
+
+    /// Active sessions of a JWT-authenticated player on this replica
+    fn player_sessions(&self, player_id: &str) -> (StatusCode, String) {
+        let sessions: Vec<serde_json::Value> = self
+            .session_manager
+            .find_by_player(player_id)
+            .into_iter()
+            .map(|(client_ip, session)| {
+                let mut ports: Vec<_> = session.port_mappings.iter().collect();
+                ports
+                    .sort_by_key(|((proxy_port, protocol), _)| (*proxy_port, protocol.to_string()));
+                let ports: Vec<serde_json::Value> = ports
+                    .into_iter()
+                    .map(|((proxy_port, protocol), target_port)| {
+                        serde_json::json!({
+                            "proxyPort": proxy_port,
+                            "protocol": protocol,
+                            "targetPort": target_port,
+                        })
+                    })
+                    .collect();
+                serde_json::json!({
+                    "clientIp": client_ip,
+                    "targetIp": session.target_ip,
+                    "ports": ports,
+                    "metadata": session.metadata,
+                    "idleSeconds": session.last_activity.elapsed().as_secs(),
+                })
+            })
+            .collect();
+
+        (
+            StatusCode::OK,
+            serde_json::json!({ "playerId": player_id, "sessions": sessions }).to_string(),
+        )
+    }
+}
+
+/// Percent-decoded value of a query string parameter
+fn query_param(query: &str, name: &str) -> Option<String> {
+    let value = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))?;
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded)
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+fn error_body(status: StatusCode, message: &str) -> (StatusCode, String) {
+    (status, serde_json::json!({ "error": message }).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signed_token::TokenSigner;
+    use crate::test_util::temp_dir;
+    use crate::token_cache::TokenTarget;
+
+    fn server() -> AdminServer {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        std::fs::write(dir.join("ops"), "s3cret\n").unwrap();
+        let auth_config: QueryAuthConfig =
+            serde_yaml::from_str(&format!("keysDir: {}\nmethods: [apiKey]", dir.display()))
+                .unwrap();
+
+        AdminServer::new(
+            serde_yaml::from_str("{}").unwrap(),
+            TokenCache::new(60),
+            SessionManager::new(300),
+            QueryAuthenticator::new(auth_config).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_revoke_requires_admin_key() {
+        let server = server();
+        let client_ip: IpAddr = "192.0.2.10".parse().unwrap();
+        let token = server
+            .token_cache
+            .generate_token(
+                TokenTarget::single_port("10.0.0.5".to_string(), 7777),
+                client_ip,
+            )
+            .await;
+        let body = format!(r#"{{"token":"{}"}}"#, token);
+        let uri: Uri = "/admin/tokens/revoke".parse().unwrap();
+        let revoke =
+            |authorization| server.respond(&Method::POST, &uri, authorization, body.as_bytes());
+
+        let (status, _) = revoke(None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = revoke(Some("ApiKey ops:wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(server.token_cache.lookup(&token, client_ip).await.is_some());
+
+        let (status, response) = revoke(Some("ApiKey ops:s3cret")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response, r#"{"revoked":1}"#);
+        assert!(server.token_cache.lookup(&token, client_ip).await.is_none());
+
+        let (status, _) = server
+            .respond(&Method::GET, &uri, Some("ApiKey ops:s3cret"), b"")
+            .await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_signed_tokens_not_revoked() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        std::fs::write(dir.join("k1"), "0123456789abcdef0123456789abcdef").unwrap();
+        let signer = TokenSigner::new(
+            serde_yaml::from_str(&format!("signingKeyId: k1\nkeysDir: {}", dir.display())).unwrap(),
+        )
+        .unwrap();
+        let mut server = server();
+        server.token_cache = TokenCache::new(60).with_signer(signer);
+
+        let uri: Uri = "/admin/tokens/revoke".parse().unwrap();
+        let (status, response) = server
+            .respond(
+                &Method::POST,
+                &uri,
+                Some("ApiKey ops:s3cret"),
+                br#"{"backend":"10.0.0.5"}"#,
+            )
+            .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(response.contains("signing key"));
+    }
+
+    #[tokio::test]
+    async fn test_sessions_by_player() {
+        let server = server();
+        let client_addr: SocketAddr = "192.0.2.10:50000".parse().unwrap();
+        server
+            .session_manager
+            .upsert(client_addr, "10.0.0.5:7777".parse().unwrap())
+            .await;
+        server
+            .session_manager
+            .set_player_id(&client_addr, Some("player 42".to_string()));
+        let server = &server;
+        let lookup = |query: &str| {
+            let uri: Uri = format!("/admin/sessions{}", query).parse().unwrap();
+            async move {
+                server
+                    .respond(&Method::GET, &uri, Some("ApiKey ops:s3cret"), b"")
+                    .await
+            }
+        };
+
+        let (status, response) = lookup("?player=player%2042").await;
+        assert_eq!(status, StatusCode::OK);
+        let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+        let sessions = response["sessions"].as_array().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0]["clientIp"], "192.0.2.10");
+        assert_eq!(sessions[0]["targetIp"], "10.0.0.5");
+        assert_eq!(sessions[0]["ports"][0]["targetPort"], 7777);
+
+        let (_, response) = lookup("?player=player-7").await;
+        assert_eq!(response, r#"{"playerId":"player-7","sessions":[]}"#);
+        let (status, _) = lookup("").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -3,7 +3,26 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 
+use crate::access_list::{AccessListConfig, AccessListsConfig};
+use crate::admin_server::AdminApiConfig;
+use crate::events::EventsConfig;
+use crate::handshake_cookie::HandshakeCookieConfig;
+use crate::health_checker::HealthCheckConfig;
+use crate::jwt_auth::JwtAuthConfig;
+use crate::k8s_client::NamespaceScope;
 use crate::load_balancer::LoadBalancingConfig;
+use crate::metrics_server::METRICS_PORT;
+use crate::query_auth::QueryAuthConfig;
+use crate::query_policy::QueryPolicy;
+use crate::query_preset::QueryPreset;
+use crate::query_tls::QueryTlsConfig;
+use crate::rate_limit::RateLimitConfig;
+use crate::region::RegionRoutingConfig;
+use crate::routing_webhook::RoutingWebhookConfig;
+use crate::session_writeback::SessionWritebackConfig;
+use crate::signed_token::SignedTokenConfig;
+use crate::token_cache::TokenPolicyConfig;
+use crate::traffic_limit::TrafficLimitConfig;
 
 /// Protocol type for data ports
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -29,6 +48,12 @@ pub struct DataPortConfig {
     pub port: u16,
     pub protocol: Protocol,
     pub name: String,
+    /// Packet and bandwidth limits (UDP only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<TrafficLimitConfig>,
+    /// CIDR allow/deny list for this port (in addition to `accessLists.global`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<AccessListConfig>,
 }
 
 /// Main configuration structure for the UDP Director
@@ -62,8 +87,68 @@ pub struct Config {
     pub resource_query_mapping: HashMap<String, ResourceMapping>,
 
     /// Load balancing configuration
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "crate::load_balancer::deserialize_load_balancing"
+    )]
     pub load_balancing: Option<LoadBalancingConfig>,
+
+    /// Region-aware routing for queries (preferred regions, latencies, GeoIP)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region_routing: Option<RegionRoutingConfig>,
+
+    /// Write session counts back to backend resources (annotation or label)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_writeback: Option<SessionWritebackConfig>,
+
+    /// CIDR allow/deny lists for all listeners and the query port (hot-reloaded)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_lists: Option<AccessListsConfig>,
+
+    /// Challenge new UDP sources with a stateless cookie before creating sessions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handshake_cookie: Option<HandshakeCookieConfig>,
+
+    /// Token-bucket limits on queries, session resets and new data-path sessions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limits: Option<RateLimitConfig>,
+
+    /// Single-use / max-use tokens and binding to the issuing client's network
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_policy: Option<TokenPolicyConfig>,
+
+    /// Issue signed tokens that every replica can validate instead of cached ones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signed_tokens: Option<SignedTokenConfig>,
+
+    /// TLS (and optional mTLS) listener for queries, alongside or instead of `queryPort`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_tls: Option<QueryTlsConfig>,
+
+    /// Require authenticated requests on the query port (API key or HMAC)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_auth: Option<QueryAuthConfig>,
+
+    /// Verify player JWTs on the query port and map claims to routing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwt_auth: Option<JwtAuthConfig>,
+
+    /// External HTTP webhook that can deny queries, pick the backend and add session metadata
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routing_webhook: Option<RoutingWebhookConfig>,
+
+    /// Named query presets clients select instead of sending raw selectors
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub query_presets: HashMap<String, QueryPreset>,
+
+    /// Kubernetes Events for routing lifecycle transitions (ejections, default endpoint changes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub events: Option<EventsConfig>,
+
+    /// Authenticated admin API (token revocation) on its own bind address and port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_api: Option<AdminApiConfig>,
 }
 
 /// Default endpoint query configuration
@@ -74,8 +159,21 @@ pub struct DefaultEndpoint {
     pub resource_type: String,
 
     /// Namespace to search in
+    #[serde(default)]
     pub namespace: String,
 
+    /// Additional namespaces to search in (combined with `namespace`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespaces: Option<Vec<String>>,
+
+    /// Search all namespaces whose labels match (overrides `namespace` / `namespaces`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace_selector: Option<HashMap<String, String>>,
+
+    /// Search every namespace (overrides the other namespace settings)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub all_namespaces: bool,
+
     /// Label selector for filtering resources
     #[serde(skip_serializing_if = "Option::is_none")]
     pub label_selector: Option<HashMap<String, String>>,
@@ -90,6 +188,18 @@ pub struct DefaultEndpoint {
     pub status_query: Option<StatusQueryConfig>,
 }
 
+impl DefaultEndpoint {
+    /// Namespaces the default endpoint query spans
+    pub fn namespace_scope(&self) -> NamespaceScope {
+        NamespaceScope::from_fields(
+            &self.namespace,
+            self.namespaces.as_deref(),
+            self.namespace_selector.as_ref(),
+            self.all_namespaces,
+        )
+    }
+}
+
 /// Status query configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -112,6 +222,15 @@ pub struct PortMapping {
     pub port_path: Option<String>,
 }
 
+/// Whether a mapped resource lives in namespaces or in the cluster scope
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ResourceScope {
+    #[default]
+    Namespaced,
+    Cluster,
+}
+
 /// Configuration for mapping a resource type to Kubernetes resources
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -125,6 +244,10 @@ pub struct ResourceMapping {
     /// Resource type (e.g., "gameservers")
     pub resource: String,
 
+    /// Scope of the resource (default: namespaced); cluster-scoped resources ignore query namespaces
+    #[serde(default)]
+    pub scope: ResourceScope,
+
     /// SERVICE-BASED APPROACH (Legacy/Optional)
     /// The label on a SERVICE that links it to this resource
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -158,6 +281,14 @@ pub struct ResourceMapping {
     /// Multiple port mappings (new multi-port approach)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ports: Option<Vec<PortMapping>>,
+
+    /// Optional active health check for backends of this mapping (direct resource approach)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheckConfig>,
+
+    /// Optional restrictions on client queries (namespaces, selectors, status queries)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query_policy: Option<QueryPolicy>,
 }
 
 impl Config {
@@ -191,6 +322,8 @@ impl Config {
                 port,
                 protocol: Protocol::Udp,
                 name: "default".to_string(),
+                limits: None,
+                access: None,
             }]
         } else {
             // Default fallback
@@ -198,6 +331,8 @@ impl Config {
                 port: 7777,
                 protocol: Protocol::Udp,
                 name: "default".to_string(),
+                limits: None,
+                access: None,
             }]
         }
     }
@@ -221,13 +356,31 @@ impl Config {
             if port_config.name.is_empty() {
                 anyhow::bail!("data_port name must not be empty");
             }
+            if let Some(limits) = &port_config.limits {
+                if port_config.protocol != Protocol::Udp {
+                    anyhow::bail!(
+                        "data_port '{}': limits are only supported on UDP ports",
+                        port_config.name
+                    );
+                }
+                limits.validate(&port_config.name)?;
+            }
         }
 
         if self.default_endpoint.resource_type.is_empty() {
             anyhow::bail!("default_endpoint.resource_type must not be empty");
         }
-        if self.default_endpoint.namespace.is_empty() {
-            anyhow::bail!("default_endpoint.namespace must not be empty");
+        let default_scope = self
+            .resource_query_mapping
+            .get(&self.default_endpoint.resource_type)
+            .map(|mapping| mapping.scope)
+            .unwrap_or_default();
+        if default_scope == ResourceScope::Namespaced
+            && self.default_endpoint.namespace_scope().is_empty()
+        {
+            anyhow::bail!(
+                "default_endpoint needs namespace, namespaces, namespaceSelector or allNamespaces"
+            );
         }
         if self.resource_query_mapping.is_empty() {
             anyhow::bail!("resource_query_mapping must not be empty");
@@ -237,6 +390,105 @@ impl Config {
         hex::decode(&self.control_packet_magic_bytes)
             .with_context(|| "control_packet_magic_bytes must be a valid hex string")?;
 
+        if let Some(load_balancing) = &self.load_balancing {
+            load_balancing.validate()?;
+        }
+
+        if let Some(region_routing) = &self.region_routing {
+            region_routing.validate()?;
+        }
+
+        if let Some(tls) = &self.query_tls
+            && !tls.disable_plaintext
+            && tls.port == self.query_port
+        {
+            anyhow::bail!(
+                "queryTls.port must differ from queryPort unless disablePlaintext is set"
+            );
+        }
+
+        if let Some(cookie) = &self.handshake_cookie {
+            cookie.validate()?;
+        }
+
+        if let Some(rate_limits) = &self.rate_limits {
+            rate_limits.validate()?;
+        }
+
+        if let Some(signed_tokens) = &self.signed_tokens {
+            signed_tokens.validate()?;
+        }
+
+        if let Some(policy) = &self.token_policy {
+            policy.validate()?;
+            // Replicas share no redemption state, so limits would apply per replica
+            if self.signed_tokens.is_some() && (policy.single_use || policy.max_uses.is_some()) {
+                anyhow::bail!("tokenPolicy.singleUse and maxUses cannot be used with signedTokens");
+            }
+        }
+
+        if let Some(webhook) = &self.routing_webhook {
+            webhook.validate()?;
+        }
+
+        if let Some(auth) = &self.query_auth {
+            auth.validate("queryAuth")?;
+        }
+
+        if let Some(jwt) = &self.jwt_auth {
+            jwt.validate()?;
+        }
+
+        if let Some(admin) = &self.admin_api {
+            if let Some(auth) = &admin.auth {
+                auth.validate("adminApi.auth")?;
+            }
+            if admin.auth.is_none() && self.query_auth.is_none() {
+                anyhow::bail!("adminApi needs adminApi.auth or queryAuth keys");
+            }
+            if admin.port == self.query_port
+                || admin.port == METRICS_PORT
+                || self
+                    .query_tls
+                    .as_ref()
+                    .is_some_and(|tls| admin.port == tls.port)
+            {
+                anyhow::bail!(
+                    "adminApi.port must differ from queryPort, queryTls.port and the metrics port {}",
+                    METRICS_PORT
+                );
+            }
+        }
+
+        for (name, preset) in &self.query_presets {
+            let mapping = self
+                .resource_query_mapping
+                .get(&preset.resource_type)
+                .with_context(|| {
+                    format!(
+                        "Preset '{}' uses unknown resource type '{}'",
+                        name, preset.resource_type
+                    )
+                })?;
+            if mapping.scope == ResourceScope::Namespaced && preset.namespace_scope().is_empty() {
+                anyhow::bail!(
+                    "Preset '{}' needs namespace, namespaces, namespaceSelector or allNamespaces",
+                    name
+                );
+            }
+            preset
+                .validate()
+                .with_context(|| format!("Invalid preset '{}'", name))?;
+        }
+
+        for (name, mapping) in &self.resource_query_mapping {
+            if let Some(health_check) = &mapping.health_check {
+                health_check
+                    .validate()
+                    .with_context(|| format!("Invalid healthCheck for mapping '{}'", name))?;
+            }
+        }
+
         Ok(())
     }
 
@@ -261,6 +513,8 @@ impl Config {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::load_balancer::LoadBalancingStrategy;
+    use crate::test_util::temp_dir;
 
     #[test]
     fn test_default_endpoint_config() {
@@ -274,6 +528,9 @@ mod tests {
             default_endpoint: DefaultEndpoint {
                 resource_type: "gameserver".to_string(),
                 namespace: "default".to_string(),
+                namespaces: None,
+                namespace_selector: None,
+                all_namespaces: false,
                 label_selector: Some(label_selector),
                 annotation_selector: None,
                 status_query: Some(StatusQueryConfig {
@@ -286,6 +543,20 @@ mod tests {
             control_packet_magic_bytes: "FFFFFFFF5245534554".to_string(),
             resource_query_mapping: HashMap::new(),
             load_balancing: None,
+            region_routing: None,
+            session_writeback: None,
+            access_lists: None,
+            handshake_cookie: None,
+            rate_limits: None,
+            token_policy: None,
+            signed_tokens: None,
+            query_tls: None,
+            query_auth: None,
+            jwt_auth: None,
+            routing_webhook: None,
+            query_presets: HashMap::new(),
+            events: None,
+            admin_api: None,
         };
 
         let endpoint = config.get_default_endpoint();
@@ -305,6 +576,9 @@ mod tests {
             default_endpoint: DefaultEndpoint {
                 resource_type: "gameserver".to_string(),
                 namespace: "starx".to_string(),
+                namespaces: None,
+                namespace_selector: None,
+                all_namespaces: false,
                 label_selector: Some(label_selector),
                 annotation_selector: None,
                 status_query: None, // No status filtering
@@ -314,6 +588,20 @@ mod tests {
             control_packet_magic_bytes: "FFFFFFFF5245534554".to_string(),
             resource_query_mapping: HashMap::new(),
             load_balancing: None,
+            region_routing: None,
+            session_writeback: None,
+            access_lists: None,
+            handshake_cookie: None,
+            rate_limits: None,
+            token_policy: None,
+            signed_tokens: None,
+            query_tls: None,
+            query_auth: None,
+            jwt_auth: None,
+            routing_webhook: None,
+            query_presets: HashMap::new(),
+            events: None,
+            admin_api: None,
         };
 
         let endpoint = config.get_default_endpoint();
@@ -331,6 +619,9 @@ mod tests {
             default_endpoint: DefaultEndpoint {
                 resource_type: "gameserver".to_string(),
                 namespace: "default".to_string(),
+                namespaces: None,
+                namespace_selector: None,
+                all_namespaces: false,
                 label_selector: None,
                 annotation_selector: None,
                 status_query: None,
@@ -340,6 +631,20 @@ mod tests {
             control_packet_magic_bytes: "FFFFFFFF5245534554".to_string(),
             resource_query_mapping: HashMap::new(),
             load_balancing: None,
+            region_routing: None,
+            session_writeback: None,
+            access_lists: None,
+            handshake_cookie: None,
+            rate_limits: None,
+            token_policy: None,
+            signed_tokens: None,
+            query_tls: None,
+            query_auth: None,
+            jwt_auth: None,
+            routing_webhook: None,
+            query_presets: HashMap::new(),
+            events: None,
+            admin_api: None,
         };
 
         let magic_bytes = config.get_magic_bytes().unwrap();
@@ -348,4 +653,183 @@ mod tests {
             vec![0xFF, 0xFF, 0xFF, 0xFF, 0x52, 0x45, 0x53, 0x45, 0x54]
         );
     }
+
+    /// Smallest valid configuration
+    fn minimal_config() -> Config {
+        let config: Config = serde_yaml::from_str(
+            r#"
+queryPort: 9000
+dataPort: 7777
+defaultEndpoint:
+  resourceType: gameserver
+  namespace: default
+tokenTtlSeconds: 30
+sessionTimeoutSeconds: 300
+controlPacketMagicBytes: FFFFFFFF5245534554
+resourceQueryMapping:
+  gameserver:
+    group: agones.dev
+    version: v1
+    resource: gameservers
+    addressPath: status.address
+    port: 7777
+"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        config
+    }
+
+    #[test]
+    fn test_token_use_limits_rejected_with_signed_tokens() {
+        let mut config = minimal_config();
+        config.signed_tokens =
+            Some(serde_yaml::from_str("signingKeyId: k1\nkeysDir: /tmp").unwrap());
+        config.token_policy = Some(serde_yaml::from_str("bindClientIp: true").unwrap());
+        assert!(config.validate().is_ok());
+
+        config.token_policy = Some(serde_yaml::from_str("singleUse: true").unwrap());
+        assert!(config.validate().is_err());
+        config.token_policy = Some(serde_yaml::from_str("maxUses: 3").unwrap());
+        assert!(config.validate().is_err());
+
+        config.signed_tokens = None;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_admin_port_conflicts() {
+        let mut config = minimal_config();
+        config.query_auth = Some(serde_yaml::from_str("keysDir: /tmp").unwrap());
+        config.admin_api = Some(serde_yaml::from_str("port: 9443").unwrap());
+        assert!(config.validate().is_ok());
+
+        config.query_tls = Some(
+            serde_yaml::from_str("port: 9443\ncertFile: /tmp/tls.crt\nkeyFile: /tmp/tls.key")
+                .unwrap(),
+        );
+        assert!(config.validate().is_err());
+
+        config.query_tls = None;
+        for port in [9000, METRICS_PORT] {
+            config.admin_api = Some(serde_yaml::from_str(&format!("port: {}", port)).unwrap());
+            assert!(config.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_query_auth_validation() {
+        let mut config = minimal_config();
+        config.query_auth = Some(serde_yaml::from_str("keysDir: /tmp").unwrap());
+        assert!(config.validate().is_ok());
+
+        config.query_auth = Some(serde_yaml::from_str("keysDir: /tmp\nmethods: []").unwrap());
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("queryAuth.methods"), "{}", err);
+
+        config.query_auth = Some(serde_yaml::from_str("keysDir: /nonexistent/keys").unwrap());
+        assert!(config.validate().is_err());
+
+        config.query_auth = Some(serde_yaml::from_str("keysDir: /tmp").unwrap());
+        config.admin_api =
+            Some(serde_yaml::from_str("auth:\n  keysDir: /nonexistent/keys").unwrap());
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("adminApi.auth.keysDir"), "{}", err);
+    }
+
+    #[test]
+    fn test_jwt_auth_validation() {
+        let dir = temp_dir();
+        let key_file = dir.path().join("jwt-key");
+        std::fs::write(&key_file, "secret").unwrap();
+        let jwt_auth = |extra: &str| {
+            Some(
+                serde_yaml::from_str(&format!("keyFile: {}\n{}", key_file.display(), extra))
+                    .unwrap(),
+            )
+        };
+
+        let mut config = minimal_config();
+        config.jwt_auth = jwt_auth("algorithms: [HS256]");
+        assert!(config.validate().is_ok());
+
+        config.jwt_auth = jwt_auth("algorithms: []");
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("jwtAuth.algorithms"), "{}", err);
+
+        config.jwt_auth = jwt_auth("playerIdClaim: \"\"");
+        assert!(config.validate().is_err());
+
+        config.jwt_auth = Some(serde_yaml::from_str("keyFile: /nonexistent/jwks.json").unwrap());
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("jwtAuth.keyFile"), "{}", err);
+    }
+
+    #[test]
+    fn test_signed_tokens_validation() {
+        let mut config = minimal_config();
+        config.signed_tokens =
+            Some(serde_yaml::from_str("signingKeyId: k1\nkeysDir: /tmp").unwrap());
+        assert!(config.validate().is_ok());
+
+        config.signed_tokens =
+            Some(serde_yaml::from_str("signingKeyId: \"\"\nkeysDir: /tmp").unwrap());
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("signedTokens.signingKeyId"), "{}", err);
+
+        config.signed_tokens =
+            Some(serde_yaml::from_str("signingKeyId: k1\nkeysDir: /nonexistent/keys").unwrap());
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("signedTokens.keysDir"), "{}", err);
+    }
+
+    #[test]
+    fn test_region_routing_validation() {
+        let mut config = minimal_config();
+        config.region_routing = Some(serde_yaml::from_str("fallbackRegions: [eu-west]").unwrap());
+        assert!(config.validate().is_ok());
+
+        config.region_routing = Some(serde_yaml::from_str("regionLabel: \"\"").unwrap());
+        assert!(config.validate().is_err());
+
+        config.region_routing = Some(
+            serde_yaml::from_str("geoipDatabase: /nonexistent/GeoLite2-Country.mmdb").unwrap(),
+        );
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("regionRouting.geoipDatabase"), "{}", err);
+    }
+
+    #[test]
+    fn test_legacy_load_balancing_layout() {
+        let with_load_balancing = |section: &str| -> Config {
+            let mut yaml = serde_yaml::to_string(&minimal_config()).unwrap();
+            yaml.push_str(section);
+            let config: Config = serde_yaml::from_str(&yaml).unwrap();
+            config.validate().unwrap();
+            config
+        };
+        let strategy = |config: Config| config.load_balancing.unwrap().strategy;
+
+        let expected = LoadBalancingStrategy::LabelArithmetic {
+            current_label: Some("currentUsers".to_string()),
+            max_label: Some("maxUsers".to_string()),
+            current: None,
+            max: None,
+            overlap: 2,
+        };
+        // Nested under `strategy:` with snake_case fields (up to 0.2.1)
+        let legacy = with_load_balancing(
+            "loadBalancing:\n  strategy:\n    type: labelArithmetic\n    current_label: currentUsers\n    max_label: maxUsers\n    overlap: 2\n",
+        );
+        assert_eq!(strategy(legacy), expected);
+        let current = with_load_balancing(
+            "loadBalancing:\n  type: labelArithmetic\n  currentLabel: currentUsers\n  maxLabel: maxUsers\n  overlap: 2\n  reservations: true\n",
+        );
+        assert!(current.load_balancing.as_ref().unwrap().reservations);
+        assert_eq!(strategy(current), expected);
+
+        // An empty section still means leastSessions
+        let empty = with_load_balancing("loadBalancing: {}\n");
+        assert_eq!(strategy(empty), LoadBalancingStrategy::LeastSessions);
+    }
 }
@@ -0,0 +1,598 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::ObjectMeta;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::k8s_client::K8sClient;
+use crate::load_balancer::Reservations;
+
+/// Label grouping the Leases of one director deployment
+const LEASE_GROUP_LABEL: &str = "udp-director/lease-group";
+
+/// Annotation on each Lease holding the replica's per-backend session counts (JSON)
+const SESSION_COUNTS_ANNOTATION: &str = "udp-director/session-counts";
+
+/// Annotation on each Lease holding the replica's per-backend reserved slots (JSON)
+const RESERVED_SLOTS_ANNOTATION: &str = "udp-director/reserved-slots";
+
+/// Cross-replica session count sharing through Kubernetes Leases
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CoordinationConfig {
+    /// Namespace for the Leases (default: `POD_NAMESPACE`, then "default")
+    #[serde(default)]
+    pub lease_namespace: Option<String>,
+    /// Lease name prefix and group label value shared by all replicas (default: "udp-director")
+    #[serde(default = "default_lease_prefix")]
+    pub lease_prefix: String,
+    /// Identity of this replica (default: `POD_NAME`, then `HOSTNAME`)
+    #[serde(default)]
+    pub identity: Option<String>,
+    /// Minimum seconds between Lease writes and between reads of other replicas (default: 5)
+    #[serde(default = "default_publish_interval_seconds")]
+    pub publish_interval_seconds: u64,
+    /// Counts of replicas that have not renewed their Lease for this long are ignored (default: 30)
+    #[serde(default = "default_stale_seconds")]
+    pub stale_seconds: u64,
+}
+
+fn default_lease_prefix() -> String {
+    "udp-director".to_string()
+}
+
+fn default_publish_interval_seconds() -> u64 {
+    5
+}
+
+fn default_stale_seconds() -> u64 {
+    30
+}
+
+/// Session counts and reserved slots of one replica, per backend address
+#[derive(Debug, Clone, PartialEq, Default)]
+struct ReplicaSnapshot {
+    sessions: BTreeMap<String, usize>,
+    reserved: BTreeMap<String, usize>,
+}
+
+/// Publishes this replica's session counts and aggregates those of other replicas
+#[derive(Clone)]
+pub struct SessionCoordinator {
+    config: CoordinationConfig,
+    k8s_client: K8sClient,
+    namespace: String,
+    identity: String,
+    /// The director's pod, so the Lease is garbage-collected with it
+    owner: Option<OwnerReference>,
+    /// This replica's session counts (shared with the load balancer)
+    local_counts: Arc<DashMap<String, usize>>,
+    /// This replica's unclaimed reservations (shared with the load balancer)
+    local_reservations: Reservations,
+    /// Sum of other replicas' session counts per backend address
+    remote_counts: Arc<RwLock<HashMap<String, usize>>>,
+    /// Sum of other replicas' reserved slots per backend address
+    remote_reserved: Arc<RwLock<HashMap<String, usize>>>,
+}
+
+impl SessionCoordinator {
+    /// Create a coordinator publishing `local_counts`
+    pub fn new(
+        config: CoordinationConfig,
+        k8s_client: K8sClient,
+        local_counts: Arc<DashMap<String, usize>>,
+    ) -> Self {
+        let namespace = config
+            .lease_namespace
+            .clone()
+            .or_else(|| std::env::var("POD_NAMESPACE").ok())
+            .filter(|ns| !ns.is_empty())
+            .unwrap_or_else(|| "default".to_string());
+        let identity = config
+            .identity
+            .clone()
+            .or_else(|| std::env::var("POD_NAME").ok())
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let owner = pod_owner(
+            std::env::var("POD_NAME").ok(),
+            std::env::var("POD_UID").ok(),
+            std::env::var("POD_NAMESPACE").ok(),
+            &namespace,
+        );
+        if owner.is_none() {
+            warn!(
+                "POD_NAME/POD_UID not set or leaseNamespace differs from the pod's namespace; \
+                the session count Lease will not be deleted with this replica"
+            );
+        }
+
+        Self {
+            config,
+            k8s_client,
+            namespace,
+            identity,
+            owner,
+            local_counts,
+            local_reservations: Reservations::default(),
+            remote_counts: Arc::new(RwLock::new(HashMap::new())),
+            remote_reserved: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Also publish this replica's unclaimed reservations
+    pub fn with_reservations(mut self, reservations: Reservations) -> Self {
+        self.local_reservations = reservations;
+        self
+    }
+
+    /// Name of this replica's Lease
+    fn lease_name(&self) -> String {
+        format!("{}-{}", self.config.lease_prefix, self.identity)
+    }
+
+    /// Sessions other replicas hold on a backend
+    pub fn remote_sessions(&self, address: &str) -> usize {
+        self.remote_counts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(address)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Slots other replicas have reserved on a backend
+    pub fn remote_reserved(&self, address: &str) -> usize {
+        self.remote_reserved
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(address)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Publish and refresh counts at the configured rate
+    ///
+    /// The Lease is only written when the counts changed, or to renew it before
+    /// other replicas consider it stale.
+    pub async fn run(self) -> Result<()> {
+        info!(
+            "Coordinating session counts via Leases in '{}' as '{}'",
+            self.namespace, self.identity
+        );
+
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            self.config.publish_interval_seconds.max(1),
+        ));
+        let renew_after = Duration::from_secs(self.config.stale_seconds / 2);
+        let mut last_published: Option<(ReplicaSnapshot, Instant)> = None;
+
+        loop {
+            interval.tick().await;
+
+            let snapshot = self.local_snapshot();
+            let due = match &last_published {
+                Some((published, at)) => *published != snapshot || at.elapsed() >= renew_after,
+                None => true,
+            };
+            if due {
+                match self.publish(&snapshot).await {
+                    Ok(()) => last_published = Some((snapshot, Instant::now())),
+                    Err(e) => warn!("Failed to publish session counts: {}", e),
+                }
+            }
+
+            if let Err(e) = self.refresh().await {
+                warn!("Failed to read session counts of other replicas: {}", e);
+            }
+        }
+    }
+
+    /// Non-zero local session counts and reserved slots
+    fn local_snapshot(&self) -> ReplicaSnapshot {
+        ReplicaSnapshot {
+            sessions: self
+                .local_counts
+                .iter()
+                .filter(|entry| *entry.value() > 0)
+                .map(|entry| (entry.key().clone(), *entry.value()))
+                .collect(),
+            reserved: self.local_reservations.snapshot(),
+        }
+    }
+
+    /// Write this replica's counts to its Lease
+    async fn publish(&self, snapshot: &ReplicaSnapshot) -> Result<()> {
+        let lease = Lease {
+            metadata: ObjectMeta {
+                name: Some(self.lease_name()),
+                namespace: Some(self.namespace.clone()),
+                labels: Some(BTreeMap::from([(
+                    LEASE_GROUP_LABEL.to_string(),
+                    self.config.lease_prefix.clone(),
+                )])),
+                annotations: Some(BTreeMap::from([
+                    (
+                        SESSION_COUNTS_ANNOTATION.to_string(),
+                        serde_json::to_string(&snapshot.sessions)?,
+                    ),
+                    (
+                        RESERVED_SLOTS_ANNOTATION.to_string(),
+                        serde_json::to_string(&snapshot.reserved)?,
+                    ),
+                ])),
+                owner_references: self.owner.clone().map(|owner| vec![owner]),
+                ..Default::default()
+            },
+            spec: Some(LeaseSpec {
+                holder_identity: Some(self.identity.clone()),
+                lease_duration_seconds: Some(self.config.stale_seconds as i32),
+                renew_time: Some(MicroTime(Utc::now())),
+                ..Default::default()
+            }),
+        };
+
+        self.k8s_client.apply_lease(&self.namespace, &lease).await?;
+        debug!(
+            "Published session counts for {} backend(s) and reservations for {}",
+            snapshot.sessions.len(),
+            snapshot.reserved.len()
+        );
+        Ok(())
+    }
+
+    /// Reload the counts of other replicas
+    pub async fn refresh(&self) -> Result<()> {
+        let selector = format!("{}={}", LEASE_GROUP_LABEL, self.config.lease_prefix);
+        let leases = self
+            .k8s_client
+            .list_leases(&self.namespace, &selector)
+            .await?;
+
+        let now = Utc::now();
+        let own_name = self.lease_name();
+        let remote = sum_remote_counts(&leases, &own_name, SESSION_COUNTS_ANNOTATION, now);
+        let reserved = sum_remote_counts(&leases, &own_name, RESERVED_SLOTS_ANNOTATION, now);
+        debug!(
+            "Other replicas hold sessions on {} backend(s) and reservations on {}",
+            remote.len(),
+            reserved.len()
+        );
+        *self
+            .remote_counts
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = remote;
+        *self
+            .remote_reserved
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = reserved;
+        Ok(())
+    }
+}
+
+/// Owner reference to the director's pod, if its Lease lives in the pod's namespace
+///
+/// Owner references cannot cross namespaces, so none is set when they differ.
+fn pod_owner(
+    name: Option<String>,
+    uid: Option<String>,
+    pod_namespace: Option<String>,
+    lease_namespace: &str,
+) -> Option<OwnerReference> {
+    if pod_namespace.as_deref() != Some(lease_namespace) {
+        return None;
+    }
+    Some(OwnerReference {
+        api_version: "v1".to_string(),
+        kind: "Pod".to_string(),
+        name: name.filter(|n| !n.is_empty())?,
+        uid: uid.filter(|u| !u.is_empty())?,
+        ..Default::default()
+    })
+}
+
+/// Sum the per-backend counts in `annotation` of all live Leases except `own_name`
+fn sum_remote_counts(
+    leases: &[Lease],
+    own_name: &str,
+    annotation: &str,
+    now: DateTime<Utc>,
+) -> HashMap<String, usize> {
+    let mut remote: HashMap<String, usize> = HashMap::new();
+
+    for lease in leases {
+        let name = lease.metadata.name.as_deref().unwrap_or_default();
+        if name == own_name {
+            continue;
+        }
+
+        let live = lease.spec.as_ref().is_some_and(|spec| {
+            match (&spec.renew_time, spec.lease_duration_seconds) {
+                (Some(renewed), Some(duration)) => {
+                    now.signed_duration_since(renewed.0).num_seconds() < i64::from(duration)
+                }
+                _ => false,
+            }
+        });
+        if !live {
+            debug!("Ignoring stale session count lease {}", name);
+            continue;
+        }
+
+        let Some(raw) = lease
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get(annotation))
+        else {
+            continue;
+        };
+        match serde_json::from_str::<HashMap<String, usize>>(raw) {
+            Ok(counts) => {
+                for (address, count) in counts {
+                    *remote.entry(address).or_insert(0) += count;
+                }
+            }
+            Err(e) => warn!("Invalid {} on lease {}: {}", annotation, name, e),
+        }
+    }
+
+    remote
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{Method, Request, Response};
+    use hyper_util::rt::TokioIo;
+    use kube::api::DynamicObject;
+    use serde_json::{Value, json};
+
+    use crate::load_balancer::{LoadBalancer, LoadBalancingStrategy, SelectionContext};
+    use tokio::net::TcpListener;
+
+    /// Minimal API server storing Leases in memory (apply and list only)
+    async fn start_fake_api_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let leases: Arc<RwLock<BTreeMap<String, Value>>> = Arc::new(RwLock::new(BTreeMap::new()));
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let leases = leases.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                        let leases = leases.clone();
+                        async move {
+                            let method = req.method().clone();
+                            let path = req.uri().path().to_string();
+                            let body = req.into_body().collect().await?.to_bytes();
+
+                            let response = match method {
+                                Method::PATCH => {
+                                    let lease: Value = serde_json::from_slice(&body).unwrap();
+                                    let name = path.rsplit('/').next().unwrap().to_string();
+                                    leases.write().unwrap().insert(name, lease.clone());
+                                    lease
+                                }
+                                _ => json!({
+                                    "apiVersion": "coordination.k8s.io/v1",
+                                    "kind": "LeaseList",
+                                    "metadata": {},
+                                    "items": leases.read().unwrap().values().cloned().collect::<Vec<_>>()
+                                }),
+                            };
+                            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(
+                                response.to_string(),
+                            ))))
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        url
+    }
+
+    fn coordinator(url: &str, identity: &str) -> SessionCoordinator {
+        let config: CoordinationConfig =
+            serde_yaml::from_str(&format!("leaseNamespace: games\nidentity: {}", identity))
+                .unwrap();
+        let client = kube::Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap();
+        SessionCoordinator::new(
+            config,
+            K8sClient::from_client(client),
+            Arc::new(DashMap::new()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_counts_shared_through_fake_api_server() {
+        let url = start_fake_api_server().await;
+        let replica_a = coordinator(&url, "replica-a");
+        let replica_b = coordinator(&url, "replica-b");
+
+        replica_a.local_counts.insert("10.0.0.1".to_string(), 3);
+        replica_a.local_counts.insert("10.0.0.2".to_string(), 0);
+        replica_b.local_counts.insert("10.0.0.1".to_string(), 2);
+
+        replica_a
+            .publish(&replica_a.local_snapshot())
+            .await
+            .unwrap();
+        replica_b
+            .publish(&replica_b.local_snapshot())
+            .await
+            .unwrap();
+
+        // Each replica sees only the other's sessions
+        replica_a.refresh().await.unwrap();
+        replica_b.refresh().await.unwrap();
+        assert_eq!(replica_a.remote_sessions("10.0.0.1"), 2);
+        assert_eq!(replica_b.remote_sessions("10.0.0.1"), 3);
+        assert_eq!(replica_b.remote_sessions("10.0.0.2"), 0);
+
+        // Capacity strategies subtract the other replicas' sessions
+        let strategy: LoadBalancingStrategy = serde_yaml::from_str(
+            "type: labelArithmetic\ncurrentLabel: currentUsers\nmaxLabel: maxUsers",
+        )
+        .unwrap();
+        let lb = LoadBalancer::new(strategy, replica_b.k8s_client.clone())
+            .with_coordinator(replica_b.clone());
+        let backend: DynamicObject = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": "pod-1",
+                "labels": { "currentUsers": "0", "maxUsers": "5" }
+            },
+            "status": { "podIP": "10.0.0.1" }
+        }))
+        .unwrap();
+        let select = |party_size| {
+            lb.select_backend(
+                std::slice::from_ref(&backend),
+                "status.podIP",
+                None,
+                &SelectionContext::with_party_size(party_size),
+            )
+        };
+        assert!(select(2).is_ok());
+        assert!(select(3).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reservations_shared_through_fake_api_server() {
+        let url = start_fake_api_server().await;
+        let replica_b = coordinator(&url, "replica-b");
+
+        // Replica A holds a party of 3 on the backend without any session yet
+        let lb_a = LoadBalancer::new(
+            LoadBalancingStrategy::LeastSessions,
+            replica_b.k8s_client.clone(),
+        )
+        .with_reservations(Duration::from_secs(30));
+        let replica_a = coordinator(&url, "replica-a").with_reservations(lb_a.reservations());
+        assert!(lb_a.reserve("token-a", "10.0.0.1", 3));
+        replica_a
+            .publish(&replica_a.local_snapshot())
+            .await
+            .unwrap();
+
+        replica_b.refresh().await.unwrap();
+        assert_eq!(replica_b.remote_sessions("10.0.0.1"), 0);
+        assert_eq!(replica_b.remote_reserved("10.0.0.1"), 3);
+
+        // Replica B sees only 2 of the 5 slots as free
+        let strategy: LoadBalancingStrategy =
+            serde_yaml::from_str("type: packed\ncurrentLabel: currentUsers\nmaxLabel: maxUsers")
+                .unwrap();
+        let lb_b = LoadBalancer::new(strategy, replica_b.k8s_client.clone())
+            .with_coordinator(replica_b.clone());
+        assert_eq!(lb_b.reserved_slots("10.0.0.1"), 3);
+        let backend: DynamicObject = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": "pod-1",
+                "labels": { "currentUsers": "0", "maxUsers": "5" }
+            },
+            "status": { "podIP": "10.0.0.1" }
+        }))
+        .unwrap();
+        let select = |party_size| {
+            lb_b.select_backend(
+                std::slice::from_ref(&backend),
+                "status.podIP",
+                None,
+                &SelectionContext::with_party_size(party_size),
+            )
+        };
+        assert!(select(2).is_ok());
+        assert!(select(3).is_err());
+    }
+
+    #[test]
+    fn test_pod_owner_requires_same_namespace() {
+        let owner = |namespace: &str| {
+            pod_owner(
+                Some("director-0".to_string()),
+                Some("1234-abcd".to_string()),
+                Some("games".to_string()),
+                namespace,
+            )
+        };
+
+        let reference = owner("games").unwrap();
+        assert_eq!(reference.kind, "Pod");
+        assert_eq!(reference.name, "director-0");
+        assert_eq!(reference.uid, "1234-abcd");
+
+        // Cross-namespace owners are invalid, and an owner needs a UID
+        assert!(owner("other").is_none());
+        assert!(
+            pod_owner(
+                Some("director-0".to_string()),
+                None,
+                Some("games".to_string()),
+                "games"
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_stale_leases_ignored() {
+        let lease = |name: &str, renewed_seconds_ago: i64, counts: &str| -> Lease {
+            serde_json::from_value(json!({
+                "metadata": {
+                    "name": name,
+                    "annotations": { SESSION_COUNTS_ANNOTATION: counts }
+                },
+                "spec": {
+                    "leaseDurationSeconds": 30,
+                    "renewTime": (Utc::now() - k8s_openapi::chrono::TimeDelta::seconds(renewed_seconds_ago))
+                        .to_rfc3339_opts(k8s_openapi::chrono::SecondsFormat::Micros, true)
+                }
+            }))
+            .unwrap()
+        };
+
+        let leases = vec![
+            lease("udp-director-own", 0, r#"{"10.0.0.1": 7}"#),
+            lease(
+                "udp-director-live-1",
+                5,
+                r#"{"10.0.0.1": 2, "10.0.0.2": 1}"#,
+            ),
+            lease("udp-director-live-2", 10, r#"{"10.0.0.1": 4}"#),
+            lease("udp-director-stale", 60, r#"{"10.0.0.1": 100}"#),
+            lease("udp-director-invalid", 0, "not json"),
+        ];
+
+        let remote = sum_remote_counts(
+            &leases,
+            "udp-director-own",
+            SESSION_COUNTS_ANNOTATION,
+            Utc::now(),
+        );
+        assert_eq!(remote.get("10.0.0.1"), Some(&6));
+        assert_eq!(remote.get("10.0.0.2"), Some(&1));
+    }
+}
@@ -0,0 +1,468 @@
+use dashmap::DashMap;
+use k8s_openapi::api::core::v1::ObjectReference;
+use kube::api::DynamicObject;
+use kube::runtime::events::{Event, EventType, Recorder, Reporter};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::config::ResourceMapping;
+use crate::k8s_client::K8sClient;
+use crate::metrics;
+
+/// Reporting controller of all events
+const REPORTING_CONTROLLER: &str = "udp-director";
+
+/// Backends not returned by a query for this long are forgotten
+const BACKEND_IDLE_SECONDS: u64 = 600;
+
+/// Kubernetes Events for routing lifecycle transitions
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EventsConfig {
+    /// Suppress repeats of the same reason on the same object for this long (default: 300)
+    #[serde(default = "default_dedupe_seconds")]
+    pub dedupe_seconds: u64,
+    /// Upper bound on events published per minute across all objects (default: 30)
+    #[serde(default = "default_max_events_per_minute")]
+    pub max_events_per_minute: u32,
+}
+
+fn default_dedupe_seconds() -> u64 {
+    300
+}
+
+fn default_max_events_per_minute() -> u32 {
+    30
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            dedupe_seconds: default_dedupe_seconds(),
+            max_events_per_minute: default_max_events_per_minute(),
+        }
+    }
+}
+
+/// Backend resource and the address it was last seen with
+#[derive(Debug, Clone)]
+struct BackendRef {
+    reference: ObjectReference,
+    address: String,
+    last_seen: Instant,
+}
+
+/// Rate-limited, deduplicated publisher of Kubernetes Events
+#[derive(Clone)]
+pub struct EventRecorder {
+    config: EventsConfig,
+    k8s_client: K8sClient,
+    recorder: Recorder,
+    /// The director's own pod (from `POD_NAME` / `POD_NAMESPACE` / `POD_UID`)
+    own_pod: Option<ObjectReference>,
+    /// Key: resource kind/namespace/name
+    backends: Arc<DashMap<String, BackendRef>>,
+    /// Key: object + reason -> last publish time
+    last_published: Arc<DashMap<String, Instant>>,
+    /// Start of the current minute window and events published in it
+    window: Arc<Mutex<(Instant, u32)>>,
+}
+
+impl EventRecorder {
+    /// Create a recorder; the reporting instance is the pod name if known
+    pub fn new(config: EventsConfig, k8s_client: K8sClient) -> Self {
+        let own_pod = own_pod_reference();
+        let reporter = Reporter {
+            controller: REPORTING_CONTROLLER.to_string(),
+            instance: own_pod.as_ref().and_then(|pod| pod.name.clone()),
+        };
+        info!(
+            "Kubernetes events enabled (dedupe={}s, max {}/min)",
+            config.dedupe_seconds, config.max_events_per_minute
+        );
+        if own_pod.is_none() {
+            warn!("POD_NAME / POD_NAMESPACE not set, director events will not be recorded");
+        }
+
+        Self {
+            config,
+            recorder: k8s_client.event_recorder(reporter),
+            k8s_client,
+            own_pod,
+            backends: Arc::new(DashMap::new()),
+            last_published: Arc::new(DashMap::new()),
+            window: Arc::new(Mutex::new((Instant::now(), 0))),
+        }
+    }
+
+    /// Register query results so backend addresses can be mapped back to resources
+    pub fn observe(&self, resources: &[DynamicObject], mapping: &ResourceMapping) {
+        let Some(address_path) = &mapping.address_path else {
+            return;
+        };
+
+        let now = Instant::now();
+        self.backends.retain(|_, backend| {
+            now.duration_since(backend.last_seen).as_secs() < BACKEND_IDLE_SECONDS
+        });
+
+        for resource in resources {
+            let Some(reference) = object_reference(resource) else {
+                continue;
+            };
+            let Ok(address) = self.k8s_client.extract_address(
+                resource,
+                address_path,
+                mapping.address_type.as_deref(),
+            ) else {
+                continue;
+            };
+            let key = format!(
+                "{}/{}/{}",
+                reference.kind.as_deref().unwrap_or_default(),
+                reference.namespace.as_deref().unwrap_or_default(),
+                reference.name.as_deref().unwrap_or_default()
+            );
+            self.backends.insert(
+                key,
+                BackendRef {
+                    reference,
+                    address,
+                    last_seen: now,
+                },
+            );
+        }
+    }
+
+    /// Record an event on the backend resource behind an address
+    ///
+    /// The director pod is attached as the related object. No-op for unknown addresses
+    /// and for addresses shared by several resources (e.g. GameServers on one node IP).
+    pub fn backend_event(&self, address: &str, type_: EventType, reason: &str, note: String) {
+        let mut references: Vec<ObjectReference> = self
+            .backends
+            .iter()
+            .filter(|backend| backend.address == address)
+            .map(|backend| backend.reference.clone())
+            .collect();
+        if references.len() != 1 {
+            debug!(
+                "{} resource(s) known for backend {}, skipping {} event",
+                references.len(),
+                address,
+                reason
+            );
+            return;
+        }
+        let reference = references.remove(0);
+        self.publish(reference, self.own_pod.clone(), type_, reason, note);
+    }
+
+    /// Record an event on the director's own pod, optionally relating a backend resource
+    pub fn director_event(
+        &self,
+        related: Option<&DynamicObject>,
+        type_: EventType,
+        reason: &str,
+        note: String,
+    ) {
+        let Some(own_pod) = self.own_pod.clone() else {
+            return;
+        };
+        self.publish(
+            own_pod,
+            related.and_then(object_reference),
+            type_,
+            reason,
+            note,
+        );
+    }
+
+    /// Publish in the background unless deduplicated or over the rate limit
+    fn publish(
+        &self,
+        regarding: ObjectReference,
+        related: Option<ObjectReference>,
+        type_: EventType,
+        reason: &str,
+        note: String,
+    ) {
+        let key = format!(
+            "{}/{}/{}/{}",
+            regarding.kind.as_deref().unwrap_or_default(),
+            regarding.namespace.as_deref().unwrap_or_default(),
+            regarding.name.as_deref().unwrap_or_default(),
+            reason
+        );
+        if !self.allow(&key, Instant::now()) {
+            debug!("Suppressed {} event for {}", reason, key);
+            metrics::record_k8s_event("suppressed");
+            return;
+        }
+
+        let event = Event {
+            type_,
+            reason: reason.to_string(),
+            note: Some(note),
+            action: "Route".to_string(),
+            secondary: related,
+        };
+        let recorder = self.recorder.clone();
+        tokio::spawn(async move {
+            match recorder.publish(&event, &regarding).await {
+                Ok(()) => metrics::record_k8s_event("published"),
+                Err(e) => {
+                    metrics::record_k8s_event("failed");
+                    warn!("Failed to record {} event: {}", event.reason, e);
+                }
+            }
+        });
+    }
+
+    /// Apply deduplication (per object and reason) and the per-minute budget
+    fn allow(&self, key: &str, now: Instant) -> bool {
+        let dedupe = Duration::from_secs(self.config.dedupe_seconds);
+        let last = self.last_published.get(key).map(|last| *last);
+        if last.is_some_and(|last| now.duration_since(last) < dedupe) {
+            return false;
+        }
+
+        {
+            let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+            if now.duration_since(window.0) >= Duration::from_secs(60) {
+                *window = (now, 0);
+            }
+            if window.1 >= self.config.max_events_per_minute {
+                return false;
+            }
+            window.1 += 1;
+        }
+
+        self.last_published
+            .retain(|_, last| now.duration_since(*last) < dedupe);
+        self.last_published.insert(key.to_string(), now);
+        true
+    }
+}
+
+/// Reference to the director's pod from the downward API environment
+fn own_pod_reference() -> Option<ObjectReference> {
+    let name = std::env::var("POD_NAME").ok()?;
+    let namespace = std::env::var("POD_NAMESPACE").ok()?;
+    Some(ObjectReference {
+        api_version: Some("v1".to_string()),
+        kind: Some("Pod".to_string()),
+        name: Some(name),
+        namespace: Some(namespace),
+        uid: std::env::var("POD_UID").ok(),
+        ..Default::default()
+    })
+}
+
+/// Reference to a queried resource (requires its type information)
+fn object_reference(resource: &DynamicObject) -> Option<ObjectReference> {
+    let types = resource.types.as_ref()?;
+    Some(ObjectReference {
+        api_version: Some(types.api_version.clone()),
+        kind: Some(types.kind.clone()),
+        name: resource.metadata.name.clone(),
+        namespace: resource.metadata.namespace.clone(),
+        uid: resource.metadata.uid.clone(),
+        resource_version: resource.metadata.resource_version.clone(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{Request, Response};
+    use hyper_util::rt::TokioIo;
+    use serde_json::{Value, json};
+    use tokio::net::TcpListener;
+
+    /// Minimal API server recording created events
+    async fn start_fake_api_server() -> (String, Arc<Mutex<Vec<(String, Value)>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let events: Arc<Mutex<Vec<(String, Value)>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let events = events.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                        let events = events.clone();
+                        async move {
+                            let path = req.uri().path().to_string();
+                            let body = req.into_body().collect().await?.to_bytes();
+                            let event: Value = serde_json::from_slice(&body).unwrap();
+                            events.lock().unwrap().push((path, event.clone()));
+                            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(
+                                event.to_string(),
+                            ))))
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        (url, recorded)
+    }
+
+    fn recorder(url: &str, config: EventsConfig) -> EventRecorder {
+        let client = kube::Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap();
+        EventRecorder::new(config, K8sClient::from_client(client))
+    }
+
+    #[tokio::test]
+    async fn test_dedupe_and_rate_limit() {
+        let events = recorder(
+            "http://127.0.0.1:1",
+            EventsConfig {
+                dedupe_seconds: 60,
+                max_events_per_minute: 2,
+            },
+        );
+        let now = Instant::now();
+
+        assert!(events.allow("Pod/games/gs-1/BackendEjected", now));
+        // Same object and reason is deduplicated
+        assert!(!events.allow("Pod/games/gs-1/BackendEjected", now));
+        assert!(events.allow("Pod/games/gs-1/BackendRestored", now));
+        // Budget of 2 per minute exhausted
+        assert!(!events.allow("Pod/games/gs-2/BackendEjected", now));
+
+        let later = now + Duration::from_secs(61);
+        assert!(events.allow("Pod/games/gs-2/BackendEjected", later));
+        assert!(events.allow("Pod/games/gs-1/BackendEjected", later));
+    }
+
+    #[tokio::test]
+    async fn test_backend_event_recorded_on_resource() {
+        let (url, recorded) = start_fake_api_server().await;
+        let events = recorder(&url, EventsConfig::default());
+        let mapping: ResourceMapping = serde_yaml::from_str(
+            "group: agones.dev\nversion: v1\nresource: gameservers\naddressPath: status.address\nport: 7777",
+        )
+        .unwrap();
+        let gameserver: DynamicObject = serde_json::from_value(json!({
+            "apiVersion": "agones.dev/v1",
+            "kind": "GameServer",
+            "metadata": { "name": "gs-1", "namespace": "games", "uid": "1234" },
+            "status": { "address": "10.0.0.1" }
+        }))
+        .unwrap();
+        events.observe(&[gameserver], &mapping);
+
+        events.backend_event(
+            "10.0.0.1",
+            EventType::Warning,
+            "BackendEjected",
+            "Ejected".to_string(),
+        );
+        // Unknown addresses are skipped
+        events.backend_event(
+            "10.0.0.2",
+            EventType::Warning,
+            "BackendEjected",
+            "Ejected".to_string(),
+        );
+        // So are addresses shared by several resources
+        let shared = |name: &str| -> DynamicObject {
+            serde_json::from_value(json!({
+                "apiVersion": "agones.dev/v1",
+                "kind": "GameServer",
+                "metadata": { "name": name, "namespace": "games" },
+                "status": { "address": "10.0.0.3" }
+            }))
+            .unwrap()
+        };
+        events.observe(&[shared("gs-2"), shared("gs-3")], &mapping);
+        events.backend_event(
+            "10.0.0.3",
+            EventType::Warning,
+            "BackendEjected",
+            "Ejected".to_string(),
+        );
+
+        for _ in 0..50 {
+            if !recorded.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(recorded.len(), 1);
+        let (path, event) = &recorded[0];
+        assert_eq!(path, "/apis/events.k8s.io/v1/namespaces/games/events");
+        assert_eq!(event["reason"], "BackendEjected");
+        assert_eq!(event["type"], "Warning");
+        assert_eq!(event["regarding"]["kind"], "GameServer");
+        assert_eq!(event["regarding"]["uid"], "1234");
+        assert_eq!(event["reportingController"], "udp-director");
+    }
+
+    #[tokio::test]
+    async fn test_publish_failure_counted_and_not_retried() {
+        // Nothing listens on the API server address
+        let events = recorder("http://127.0.0.1:1", EventsConfig::default());
+        let mapping: ResourceMapping = serde_yaml::from_str(
+            "group: agones.dev\nversion: v1\nresource: gameservers\naddressPath: status.address\nport: 7777",
+        )
+        .unwrap();
+        let gameserver = |name: &str, status: Value| -> DynamicObject {
+            serde_json::from_value(json!({
+                "apiVersion": "agones.dev/v1",
+                "kind": "GameServer",
+                "metadata": { "name": name, "namespace": "games" },
+                "status": status
+            }))
+            .unwrap()
+        };
+        // Resources without an address are not registered
+        events.observe(
+            &[
+                gameserver("gs-1", json!({ "address": "10.0.0.1" })),
+                gameserver("gs-2", json!({})),
+            ],
+            &mapping,
+        );
+        assert_eq!(events.backends.len(), 1);
+
+        let failed = || metrics::K8S_EVENTS.with_label_values(&["failed"]).get();
+        let before = failed();
+        events.backend_event(
+            "10.0.0.1",
+            EventType::Warning,
+            "BackendEjected",
+            "Ejected".to_string(),
+        );
+        for _ in 0..100 {
+            if failed() > before {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(failed() > before);
+
+        // A failed event still counts for deduplication, so an unreachable API
+        // server is not hammered with retries
+        assert!(!events.allow("GameServer/games/gs-1/BackendEjected", Instant::now()));
+
+        // Without the downward API environment, director events are dropped
+        events.director_event(None, EventType::Normal, "Started", "Started".to_string());
+        assert_eq!(events.last_published.len(), 1);
+    }
+}
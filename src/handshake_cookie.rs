@@ -0,0 +1,361 @@
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use moka::future::Cache;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+use tracing::{debug, info};
+
+use crate::metrics;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Length of the cookie after the magic bytes
+const COOKIE_LEN: usize = 16;
+
+/// Shortest secretFile contents accepted as the HMAC key
+const MIN_SECRET_LEN: usize = 16;
+
+/// Require UDP clients without a session to echo a stateless cookie first
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HandshakeCookieConfig {
+    /// Hex prefix of challenge and echo packets
+    #[serde(default = "default_magic_bytes")]
+    pub magic_bytes: String,
+    /// How long a cookie stays valid (it is accepted for up to twice this long)
+    #[serde(default = "default_lifetime_seconds")]
+    pub lifetime_seconds: u64,
+    /// Shared secret so any replica accepts cookies issued by another (default: random per replica)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_file: Option<String>,
+    /// Skip the challenge for IPs with a successful query in this window (0 = never)
+    #[serde(default = "default_bypass_seconds")]
+    pub bypass_seconds: u64,
+}
+
+fn default_magic_bytes() -> String {
+    // \xFF\xFF\xFF\xFF "COOKIE"
+    "FFFFFFFF434F4F4B4945".to_string()
+}
+
+fn default_lifetime_seconds() -> u64 {
+    30
+}
+
+fn default_bypass_seconds() -> u64 {
+    300
+}
+
+impl HandshakeCookieConfig {
+    pub fn validate(&self) -> Result<()> {
+        let magic = hex::decode(&self.magic_bytes)
+            .context("handshakeCookie.magicBytes must be a valid hex string")?;
+        if magic.is_empty() {
+            anyhow::bail!("handshakeCookie.magicBytes must not be empty");
+        }
+        if self.lifetime_seconds == 0 {
+            anyhow::bail!("handshakeCookie.lifetimeSeconds must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of checking a packet from a source without a session
+#[derive(Debug, PartialEq, Eq)]
+pub enum CookieCheck {
+    /// Valid echo; the client payload starts at this offset
+    Valid(usize),
+    /// Send this challenge and drop the packet
+    Challenge(Vec<u8>),
+    /// Drop the packet without replying
+    Drop,
+}
+
+/// Issues and verifies stateless cookies (HMAC of source address and time window)
+#[derive(Clone)]
+pub struct HandshakeCookie {
+    config: HandshakeCookieConfig,
+    magic: Arc<Vec<u8>>,
+    secret: Arc<Vec<u8>>,
+    /// IPs that recently completed a query
+    verified: Option<Cache<IpAddr, ()>>,
+}
+
+impl HandshakeCookie {
+    pub fn new(config: HandshakeCookieConfig) -> Result<Self> {
+        let magic = hex::decode(&config.magic_bytes)
+            .context("handshakeCookie.magicBytes must be a valid hex string")?;
+        let secret = match &config.secret_file {
+            Some(path) => {
+                let secret = std::fs::read(path)
+                    .with_context(|| format!("Failed to read handshake cookie secret {}", path))?;
+                let secret = secret.trim_ascii();
+                // A short key would let anyone forge cookies for every replica
+                if secret.len() < MIN_SECRET_LEN {
+                    anyhow::bail!(
+                        "Handshake cookie secret {} must be at least {} bytes",
+                        path,
+                        MIN_SECRET_LEN
+                    );
+                }
+                secret.to_vec()
+            }
+            None => {
+                let mut secret = vec![0u8; 32];
+                SystemRandom::new()
+                    .fill(&mut secret)
+                    .map_err(|_| anyhow::anyhow!("Failed to generate handshake cookie secret"))?;
+                secret
+            }
+        };
+        let verified = (config.bypass_seconds > 0).then(|| {
+            Cache::builder()
+                .time_to_live(Duration::from_secs(config.bypass_seconds))
+                .build()
+        });
+        info!(
+            "Handshake cookies enabled for new UDP sources (lifetime {}s, {} secret)",
+            config.lifetime_seconds,
+            if config.secret_file.is_some() {
+                "shared"
+            } else {
+                "per-replica"
+            }
+        );
+        Ok(Self {
+            config,
+            magic: Arc::new(magic),
+            secret: Arc::new(secret),
+            verified,
+        })
+    }
+
+    /// Remember an IP that completed a query, so it skips the challenge
+    pub async fn mark_verified(&self, ip: IpAddr) {
+        if let Some(verified) = &self.verified {
+            verified.insert(ip, ()).await;
+        }
+    }
+
+    /// Whether the IP may create a session without a cookie
+    pub fn is_verified(&self, ip: &IpAddr) -> bool {
+        self.verified
+            .as_ref()
+            .is_some_and(|verified| verified.contains_key(ip))
+    }
+
+    /// Check the first packet of a source without a session
+    pub fn check(&self, packet: &[u8], source: SocketAddr) -> CookieCheck {
+        let result = self.check_at(packet, source, unix_now());
+        let outcome = match &result {
+            CookieCheck::Valid(_) => "accepted",
+            CookieCheck::Challenge(_) => "challenged",
+            CookieCheck::Drop if packet.starts_with(&self.magic) => "invalid",
+            CookieCheck::Drop => "too_small",
+        };
+        debug!("Handshake cookie {} for {}", outcome, source);
+        metrics::record_handshake_cookie(outcome);
+        result
+    }
+
+    fn check_at(&self, packet: &[u8], source: SocketAddr, now: u64) -> CookieCheck {
+        let challenge_len = self.magic.len() + COOKIE_LEN;
+
+        if let Some(cookie) = packet
+            .strip_prefix(self.magic.as_slice())
+            .and_then(|rest| rest.get(..COOKIE_LEN))
+        {
+            let window = now / self.config.lifetime_seconds;
+            let valid = [window, window.saturating_sub(1)]
+                .iter()
+                .any(|&window| bool::from(self.cookie(source, window).ct_eq(cookie)));
+            return if valid {
+                CookieCheck::Valid(challenge_len)
+            } else {
+                CookieCheck::Drop
+            };
+        }
+
+        // Never answer with more bytes than were received (no amplification)
+        if packet.len() < challenge_len {
+            return CookieCheck::Drop;
+        }
+        let mut challenge = self.magic.to_vec();
+        challenge.extend_from_slice(&self.cookie(source, now / self.config.lifetime_seconds));
+        CookieCheck::Challenge(challenge)
+    }
+
+    fn cookie(&self, source: SocketAddr, window: u64) -> [u8; COOKIE_LEN] {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(&window.to_be_bytes());
+        match source.ip() {
+            IpAddr::V4(ip) => mac.update(&ip.octets()),
+            IpAddr::V6(ip) => mac.update(&ip.octets()),
+        }
+        mac.update(&source.port().to_be_bytes());
+        let digest = mac.finalize().into_bytes();
+        let mut cookie = [0u8; COOKIE_LEN];
+        cookie.copy_from_slice(&digest[..COOKIE_LEN]);
+        cookie
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn cookie() -> HandshakeCookie {
+        HandshakeCookie::new(HandshakeCookieConfig {
+            magic_bytes: default_magic_bytes(),
+            lifetime_seconds: 30,
+            secret_file: None,
+            bypass_seconds: 300,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_challenge_and_echo() {
+        let cookie = cookie();
+        let client: SocketAddr = "203.0.113.7:50000".parse().unwrap();
+        let now = 1_700_000_000;
+
+        // Small packets get no reply; larger ones get a challenge
+        assert_eq!(cookie.check_at(b"hi", client, now), CookieCheck::Drop);
+        let CookieCheck::Challenge(challenge) = cookie.check_at(&[0u8; 64], client, now) else {
+            panic!("expected a challenge");
+        };
+        assert_eq!(challenge.len(), 10 + COOKIE_LEN);
+
+        // Echo with payload is accepted, also in the next window
+        let mut echo = challenge.clone();
+        echo.extend_from_slice(b"hello");
+        assert_eq!(cookie.check_at(&echo, client, now), CookieCheck::Valid(26));
+        assert_eq!(
+            cookie.check_at(&echo, client, now + 30),
+            CookieCheck::Valid(26)
+        );
+        assert_eq!(cookie.check_at(&echo, client, now + 90), CookieCheck::Drop);
+
+        // Bound to the source address and port
+        let other_port: SocketAddr = "203.0.113.7:50001".parse().unwrap();
+        assert_eq!(cookie.check_at(&echo, other_port, now), CookieCheck::Drop);
+        let mut forged = challenge;
+        forged[12] ^= 1;
+        assert_eq!(cookie.check_at(&forged, client, now), CookieCheck::Drop);
+    }
+
+    #[tokio::test]
+    async fn test_bypass_after_query() {
+        let cookie = cookie();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        assert!(!cookie.is_verified(&ip));
+        cookie.mark_verified(ip).await;
+        assert!(cookie.is_verified(&ip));
+    }
+
+    #[test]
+    fn test_cookie_window_rollover() {
+        let cookie = cookie();
+        let client: SocketAddr = "203.0.113.7:50000".parse().unwrap();
+        // Last second of a 30s window
+        let issued = 1_700_000_009;
+        assert_eq!(issued % 30, 29);
+        let CookieCheck::Challenge(echo) = cookie.check_at(&[0u8; 64], client, issued) else {
+            panic!("expected a challenge");
+        };
+
+        // Valid for the rest of its window and the whole next one, never longer
+        assert_eq!(
+            cookie.check_at(&echo, client, issued + 1),
+            CookieCheck::Valid(echo.len())
+        );
+        assert_eq!(
+            cookie.check_at(&echo, client, issued + 30),
+            CookieCheck::Valid(echo.len())
+        );
+        assert_eq!(
+            cookie.check_at(&echo, client, issued + 31),
+            CookieCheck::Drop
+        );
+
+        // A cookie from the future is not accepted
+        assert_eq!(
+            cookie.check_at(&echo, client, issued - 30),
+            CookieCheck::Drop
+        );
+    }
+
+    #[tokio::test]
+    async fn test_secrets_and_config_errors() {
+        let client: SocketAddr = "203.0.113.7:50000".parse().unwrap();
+        let now = 1_700_000_000;
+        let config = |secret_file: Option<String>| HandshakeCookieConfig {
+            magic_bytes: default_magic_bytes(),
+            lifetime_seconds: 30,
+            secret_file,
+            bypass_seconds: 0,
+        };
+
+        // Per-replica secrets reject each other's cookies; a shared secret does not
+        let CookieCheck::Challenge(echo) = cookie().check_at(&[0u8; 64], client, now) else {
+            panic!("expected a challenge");
+        };
+        assert_eq!(cookie().check_at(&echo, client, now), CookieCheck::Drop);
+
+        let dir = temp_dir();
+        let path = dir.path().join("cookie-secret");
+        std::fs::write(&path, "shared-cookie-secret\n").unwrap();
+        let shared = Some(path.to_string_lossy().to_string());
+        let replica_a = HandshakeCookie::new(config(shared.clone())).unwrap();
+        let replica_b = HandshakeCookie::new(config(shared)).unwrap();
+        let CookieCheck::Challenge(echo) = replica_a.check_at(&[0u8; 64], client, now) else {
+            panic!("expected a challenge");
+        };
+        assert_eq!(
+            replica_b.check_at(&echo, client, now),
+            CookieCheck::Valid(echo.len())
+        );
+
+        // Truncated echoes are treated as new packets and too small to challenge
+        assert_eq!(
+            replica_a.check_at(&echo[..echo.len() - 1], client, now),
+            CookieCheck::Drop
+        );
+
+        // bypassSeconds: 0 never skips the challenge
+        let ip = client.ip();
+        replica_a.mark_verified(ip).await;
+        assert!(!replica_a.is_verified(&ip));
+
+        assert!(HandshakeCookie::new(config(Some("/nonexistent/secret".to_string()))).is_err());
+        for weak in ["", " \n\t\n", "short-secret"] {
+            std::fs::write(&path, weak).unwrap();
+            let err = HandshakeCookie::new(config(Some(path.to_string_lossy().to_string())));
+            assert!(err.err().unwrap().to_string().contains("at least 16 bytes"));
+        }
+
+        let mut invalid = config(None);
+        invalid.magic_bytes = "FFZZ".to_string();
+        assert!(invalid.validate().is_err());
+        invalid.magic_bytes = String::new();
+        assert!(invalid.validate().is_err());
+        invalid.magic_bytes = default_magic_bytes();
+        invalid.lifetime_seconds = 0;
+        assert!(invalid.validate().is_err());
+    }
+}
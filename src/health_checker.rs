@@ -0,0 +1,570 @@
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use kube::api::DynamicObject;
+use kube::runtime::events::EventType;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpStream, UdpSocket};
+use tracing::{debug, info, warn};
+
+use crate::config::{Protocol, ResourceMapping};
+use crate::events::EventRecorder;
+use crate::k8s_client::K8sClient;
+use crate::load_balancer::RttTracker;
+use crate::metrics;
+
+/// Probe targets not seen in a query for this long are no longer checked
+const TARGET_IDLE_SECONDS: u64 = 600;
+
+/// Active health check for the backends of a resource mapping
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCheckConfig {
+    /// Probe protocol: "tcp" (connect) or "udp" (payload + response)
+    pub protocol: Protocol,
+    /// Fixed port to probe (default: the mapping's port)
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// Name of an entry in the mapping's `ports` to probe (multi-port mappings)
+    #[serde(default)]
+    pub port_name: Option<String>,
+    /// UDP probe payload as a hex string
+    #[serde(default)]
+    pub payload: Option<String>,
+    /// Hex bytes the UDP response must contain (default: any response)
+    #[serde(default)]
+    pub expect_response: Option<String>,
+    /// Seconds between probes (default: 10)
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    /// Probe timeout in milliseconds (default: 1000)
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Consecutive probe failures before the backend is ejected (default: 3)
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+    /// Eject backends failing the check; set false to only measure RTT (default: true)
+    #[serde(default = "default_eject_on_failure")]
+    pub eject_on_failure: bool,
+}
+
+fn default_interval_seconds() -> u64 {
+    10
+}
+
+fn default_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+fn default_eject_on_failure() -> bool {
+    true
+}
+
+impl HealthCheckConfig {
+    /// Validate the health check configuration
+    pub fn validate(&self) -> Result<()> {
+        if self.protocol == Protocol::Udp && self.payload.is_none() {
+            anyhow::bail!("healthCheck.payload is required for udp health checks");
+        }
+        for (field, value) in [
+            ("payload", &self.payload),
+            ("expectResponse", &self.expect_response),
+        ] {
+            if let Some(hex_value) = value {
+                hex::decode(hex_value)
+                    .with_context(|| format!("healthCheck.{} must be a valid hex string", field))?;
+            }
+        }
+        if self.interval_seconds == 0 || self.timeout_ms == 0 {
+            anyhow::bail!("healthCheck intervalSeconds and timeoutMs must be non-zero");
+        }
+        Ok(())
+    }
+}
+
+/// Passive outlier detection from proxy traffic
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlierDetectionConfig {
+    /// Consecutive connect failures / ICMP errors before ejection (default: 5)
+    #[serde(default = "default_consecutive_failures")]
+    pub consecutive_failures: u32,
+    /// How long an outlier stays ejected (default: 30)
+    #[serde(default = "default_ejection_seconds")]
+    pub ejection_seconds: u64,
+}
+
+fn default_consecutive_failures() -> u32 {
+    5
+}
+
+fn default_ejection_seconds() -> u64 {
+    30
+}
+
+/// Health state of a single backend address
+#[derive(Debug, Default)]
+struct BackendHealth {
+    /// Consecutive active probe failures
+    probe_failures: u32,
+    /// Failing active checks (cleared by the next successful probe)
+    unhealthy: bool,
+    /// Consecutive passive failures
+    passive_failures: u32,
+    /// Passive ejection expiry
+    ejected_until: Option<Instant>,
+}
+
+impl BackendHealth {
+    fn is_ejected(&self, now: Instant) -> bool {
+        self.unhealthy || self.ejected_until.is_some_and(|until| until > now)
+    }
+}
+
+/// Backend to probe actively
+#[derive(Debug, Clone)]
+struct ProbeTarget {
+    target: SocketAddr,
+    check: HealthCheckConfig,
+    last_seen: Instant,
+    next_probe: Instant,
+}
+
+/// Tracks backend health from active probes and passive failures
+///
+/// Successful probes also feed their round-trip time into the load balancer's
+/// `RttTracker`.
+#[derive(Clone)]
+pub struct HealthChecker {
+    k8s_client: K8sClient,
+    outlier_detection: Option<OutlierDetectionConfig>,
+    rtt: RttTracker,
+    /// Key: backend IP address
+    backends: Arc<DashMap<String, BackendHealth>>,
+    /// Key: backend IP address
+    targets: Arc<DashMap<String, ProbeTarget>>,
+    /// Optional Kubernetes Events for ejections
+    events: Option<EventRecorder>,
+}
+
+impl HealthChecker {
+    /// Create a new health checker
+    pub fn new(
+        k8s_client: K8sClient,
+        outlier_detection: Option<OutlierDetectionConfig>,
+        rtt: RttTracker,
+    ) -> Self {
+        Self {
+            k8s_client,
+            outlier_detection,
+            rtt,
+            backends: Arc::new(DashMap::new()),
+            targets: Arc::new(DashMap::new()),
+            events: None,
+        }
+    }
+
+    /// Record ejections and restorations as Kubernetes Events on the backend
+    pub fn with_event_recorder(mut self, events: EventRecorder) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Record an event on a backend resource if events are enabled
+    fn backend_event(&self, address: &str, type_: EventType, reason: &str, note: String) {
+        if let Some(events) = &self.events {
+            events.backend_event(address, type_, reason, note);
+        }
+    }
+
+    /// Whether a backend is currently ejected
+    pub fn is_ejected(&self, address: &str) -> bool {
+        self.backends
+            .get(address)
+            .is_some_and(|health| health.is_ejected(Instant::now()))
+    }
+
+    /// Register query results for active probing (mappings with a `healthCheck`)
+    pub fn observe(&self, resources: &[DynamicObject], mapping: &ResourceMapping) {
+        let (Some(check), Some(address_path)) = (&mapping.health_check, &mapping.address_path)
+        else {
+            return;
+        };
+
+        let now = Instant::now();
+        for resource in resources {
+            let Ok(address) = self.k8s_client.extract_address(
+                resource,
+                address_path,
+                mapping.address_type.as_deref(),
+            ) else {
+                continue;
+            };
+
+            if let Some(mut target) = self.targets.get_mut(&address) {
+                target.last_seen = now;
+                continue;
+            }
+
+            let port = match self.probe_port(resource, mapping, check) {
+                Ok(port) => port,
+                Err(e) => {
+                    debug!("Not health checking {}: {}", address, e);
+                    continue;
+                }
+            };
+            let Ok(ip) = address.parse() else {
+                continue;
+            };
+
+            debug!("Health checking backend {}:{}", address, port);
+            self.targets.insert(
+                address,
+                ProbeTarget {
+                    target: SocketAddr::new(ip, port),
+                    check: check.clone(),
+                    last_seen: now,
+                    next_probe: now,
+                },
+            );
+        }
+    }
+
+    /// Resolve the port to probe for a resource
+    fn probe_port(
+        &self,
+        resource: &DynamicObject,
+        mapping: &ResourceMapping,
+        check: &HealthCheckConfig,
+    ) -> Result<u16> {
+        if let Some(port) = check.port {
+            return Ok(port);
+        }
+
+        if let Some(port_mappings) = &mapping.ports {
+            let ports = self.k8s_client.extract_ports(resource, port_mappings)?;
+            let name = check
+                .port_name
+                .as_deref()
+                .or_else(|| port_mappings.first().map(|p| p.name.as_str()))
+                .context("No port mappings configured")?;
+            return ports
+                .get(name)
+                .copied()
+                .with_context(|| format!("Port '{}' not found", name));
+        }
+
+        self.k8s_client.extract_port(
+            resource,
+            mapping.port_path.as_deref(),
+            mapping.port_name.as_deref(),
+        )
+    }
+
+    /// Record a passive failure (connect failure, ICMP error) for a backend
+    pub fn record_failure(&self, address: &str) {
+        let Some(outlier) = &self.outlier_detection else {
+            return;
+        };
+
+        let mut health = self.backends.entry(address.to_string()).or_default();
+        health.passive_failures += 1;
+        if health.passive_failures >= outlier.consecutive_failures.max(1) {
+            let already_ejected = health.is_ejected(Instant::now());
+            health.ejected_until =
+                Some(Instant::now() + Duration::from_secs(outlier.ejection_seconds));
+            health.passive_failures = 0;
+            if !already_ejected {
+                warn!(
+                    "Ejecting backend {} for {}s after {} consecutive failures",
+                    address, outlier.ejection_seconds, outlier.consecutive_failures
+                );
+                metrics::record_backend_ejection("passive");
+                self.backend_event(
+                    address,
+                    EventType::Warning,
+                    "BackendEjected",
+                    format!(
+                        "Ejected for {}s after {} consecutive connection failures",
+                        outlier.ejection_seconds, outlier.consecutive_failures
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Record a passive success (e.g. successful connect) for a backend
+    pub fn record_success(&self, address: &str) {
+        if let Some(mut health) = self.backends.get_mut(address) {
+            health.passive_failures = 0;
+        }
+    }
+
+    /// Run active probes for registered targets
+    pub async fn run(self) -> Result<()> {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+
+            // Forget backends that have not been returned by a query recently
+            self.targets.retain(|address, target| {
+                let active = now.duration_since(target.last_seen).as_secs() < TARGET_IDLE_SECONDS;
+                if !active {
+                    debug!("No longer health checking backend {}", address);
+                    self.backends.remove(address);
+                    self.rtt.remove(address);
+                }
+                active
+            });
+
+            for mut entry in self.targets.iter_mut() {
+                if entry.next_probe > now {
+                    continue;
+                }
+                entry.next_probe = now + Duration::from_secs(entry.check.interval_seconds);
+
+                let address = entry.key().clone();
+                let target = entry.target;
+                let check = entry.check.clone();
+                let checker = self.clone();
+                tokio::spawn(async move {
+                    let result = probe(target, &check).await;
+                    checker.record_probe_result(&address, &check, result);
+                });
+            }
+
+            metrics::EJECTED_BACKENDS.set(
+                self.backends
+                    .iter()
+                    .filter(|entry| entry.is_ejected(now))
+                    .count() as i64,
+            );
+        }
+    }
+
+    /// Apply an active probe result
+    fn record_probe_result(
+        &self,
+        address: &str,
+        check: &HealthCheckConfig,
+        result: Result<Duration>,
+    ) {
+        let mut health = self.backends.entry(address.to_string()).or_default();
+        match result {
+            Ok(rtt) => {
+                metrics::record_health_probe("success");
+                self.rtt.record(address, rtt);
+                if health.unhealthy {
+                    info!("Backend {} passed health check, restoring", address);
+                    self.backend_event(
+                        address,
+                        EventType::Normal,
+                        "BackendRestored",
+                        "Passed health check, routing resumed".to_string(),
+                    );
+                }
+                health.probe_failures = 0;
+                health.unhealthy = false;
+            }
+            Err(e) => {
+                metrics::record_health_probe("failure");
+                health.probe_failures += 1;
+                debug!(
+                    "Health check failed for {} ({} consecutive): {}",
+                    address, health.probe_failures, e
+                );
+                if check.eject_on_failure
+                    && !health.unhealthy
+                    && health.probe_failures >= check.unhealthy_threshold.max(1)
+                {
+                    warn!(
+                        "Ejecting backend {} after {} failed health checks: {}",
+                        address, health.probe_failures, e
+                    );
+                    health.unhealthy = true;
+                    metrics::record_backend_ejection("active");
+                    self.backend_event(
+                        address,
+                        EventType::Warning,
+                        "BackendEjected",
+                        format!(
+                            "Ejected after {} failed health checks: {}",
+                            health.probe_failures, e
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Probe a backend once, returning the round-trip time
+///
+/// TCP probes time the handshake; UDP probes time the payload/response exchange.
+async fn probe(target: SocketAddr, check: &HealthCheckConfig) -> Result<Duration> {
+    let timeout = Duration::from_millis(check.timeout_ms);
+
+    match check.protocol {
+        Protocol::Tcp => {
+            let started = Instant::now();
+            tokio::time::timeout(timeout, TcpStream::connect(target))
+                .await
+                .context("TCP connect timed out")?
+                .context("TCP connect failed")?;
+            Ok(started.elapsed())
+        }
+        Protocol::Udp => {
+            let payload = hex::decode(check.payload.as_deref().unwrap_or_default())
+                .context("Invalid UDP probe payload")?;
+            let expected = check
+                .expect_response
+                .as_deref()
+                .map(hex::decode)
+                .transpose()
+                .context("Invalid expected response")?;
+
+            let bind_addr = if target.is_ipv4() {
+                "0.0.0.0:0"
+            } else {
+                "[::]:0"
+            };
+            let socket = UdpSocket::bind(bind_addr).await?;
+            // Connected so ICMP port-unreachable is reported as an error
+            socket.connect(target).await?;
+            let started = Instant::now();
+            socket.send(&payload).await?;
+
+            let mut buffer = vec![0u8; 2048];
+            let len = tokio::time::timeout(timeout, socket.recv(&mut buffer))
+                .await
+                .context("UDP probe timed out")?
+                .context("UDP probe failed")?;
+            let rtt = started.elapsed();
+
+            if let Some(expected) = expected {
+                let response = &buffer[..len];
+                if !expected.is_empty() && !response.windows(expected.len()).any(|w| w == expected)
+                {
+                    anyhow::bail!("UDP probe response did not match");
+                }
+            }
+            Ok(rtt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn udp_check(expect_response: Option<&str>) -> HealthCheckConfig {
+        HealthCheckConfig {
+            protocol: Protocol::Udp,
+            port: None,
+            port_name: None,
+            payload: Some(hex::encode(b"ping")),
+            expect_response: expect_response.map(|r| hex::encode(r.as_bytes())),
+            interval_seconds: 1,
+            timeout_ms: 500,
+            unhealthy_threshold: 2,
+            eject_on_failure: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_udp_probe() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 64];
+            loop {
+                let (len, from) = server.recv_from(&mut buffer).await.unwrap();
+                if &buffer[..len] == b"ping" {
+                    server.send_to(b"pong v1.2", from).await.unwrap();
+                }
+            }
+        });
+
+        assert!(probe(target, &udp_check(None)).await.is_ok());
+        assert!(probe(target, &udp_check(Some("pong"))).await.is_ok());
+        assert!(probe(target, &udp_check(Some("nope"))).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap();
+        let check = HealthCheckConfig {
+            protocol: Protocol::Tcp,
+            payload: None,
+            ..udp_check(None)
+        };
+
+        assert!(probe(target, &check).await.is_ok());
+        drop(listener);
+        assert!(probe(target, &check).await.is_err());
+    }
+
+    #[test]
+    fn test_health_check_validation() {
+        assert!(udp_check(Some("pong")).validate().is_ok());
+
+        let mut check = udp_check(None);
+        check.payload = None;
+        assert!(check.validate().is_err());
+
+        let mut check = udp_check(None);
+        check.expect_response = Some("not-hex".to_string());
+        assert!(check.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ejection() {
+        let k8s_client = K8sClient::from_client(
+            kube::Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap()))
+                .unwrap(),
+        );
+
+        let outlier = OutlierDetectionConfig {
+            consecutive_failures: 2,
+            ejection_seconds: 30,
+        };
+        let checker = HealthChecker::new(k8s_client, Some(outlier), RttTracker::default());
+
+        // Passive: a success resets the failure streak
+        checker.record_failure("10.0.0.1");
+        checker.record_success("10.0.0.1");
+        checker.record_failure("10.0.0.1");
+        assert!(!checker.is_ejected("10.0.0.1"));
+        checker.record_failure("10.0.0.1");
+        assert!(checker.is_ejected("10.0.0.1"));
+
+        // Active: ejected after the threshold, restored by the next success
+        let check = udp_check(None);
+        checker.record_probe_result("10.0.0.2", &check, Err(anyhow::anyhow!("timeout")));
+        assert!(!checker.is_ejected("10.0.0.2"));
+        checker.record_probe_result("10.0.0.2", &check, Err(anyhow::anyhow!("timeout")));
+        assert!(checker.is_ejected("10.0.0.2"));
+        checker.record_probe_result("10.0.0.2", &check, Ok(Duration::from_millis(20)));
+        assert!(!checker.is_ejected("10.0.0.2"));
+        assert_eq!(checker.rtt.get("10.0.0.2"), Some(20.0));
+
+        // RTT-only checks never eject
+        let check = HealthCheckConfig {
+            eject_on_failure: false,
+            ..udp_check(None)
+        };
+        for _ in 0..3 {
+            checker.record_probe_result("10.0.0.3", &check, Err(anyhow::anyhow!("timeout")));
+        }
+        assert!(!checker.is_ejected("10.0.0.3"));
+    }
+}
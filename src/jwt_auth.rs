@@ -0,0 +1,430 @@
+use anyhow::{Context, Result, bail};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, errors::ErrorKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::metrics;
+use crate::query_auth::AuthError;
+
+/// Verify player JWTs on the query port and map their claims to routing
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JwtAuthConfig {
+    /// JWKS (JSON) or PEM public key file; for HS* algorithms the shared secret
+    #[serde(default = "default_key_file")]
+    pub key_file: String,
+    /// Accepted signing algorithms (default: RS256)
+    #[serde(default = "default_algorithms")]
+    pub algorithms: Vec<Algorithm>,
+    /// Required `iss` claim
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Required `aud` claim
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audience: Option<String>,
+    /// Reject queries without a token (default: true)
+    #[serde(default = "default_required")]
+    pub required: bool,
+    /// Accept an `Authorization: Bearer` token instead of queryAuth credentials
+    /// (default: false, so both are required when queryAuth is enabled)
+    #[serde(default)]
+    pub replaces_query_auth: bool,
+    /// Claim holding the player ID attached to sessions (default: `sub`)
+    #[serde(default = "default_player_id_claim")]
+    pub player_id_claim: String,
+    /// Label key -> claim whose value becomes a mandatory label selector
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub label_claims: HashMap<String, String>,
+    /// Annotation key -> claim whose value becomes a mandatory annotation selector
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub annotation_claims: HashMap<String, String>,
+    /// Claim -> values that reject the query (e.g. `banned: [true]`)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub reject_claims: HashMap<String, Vec<serde_json::Value>>,
+    /// Allowed clock difference for `exp` / `nbf` (default: 60)
+    #[serde(default = "default_leeway_seconds")]
+    pub leeway_seconds: u64,
+    /// How often the key file is re-read (default: 30)
+    #[serde(default = "default_reload_seconds")]
+    pub reload_seconds: u64,
+}
+
+fn default_key_file() -> String {
+    "/var/run/secrets/udp-director/jwt/jwks.json".to_string()
+}
+
+fn default_algorithms() -> Vec<Algorithm> {
+    vec![Algorithm::RS256]
+}
+
+fn default_required() -> bool {
+    true
+}
+
+fn default_player_id_claim() -> String {
+    "sub".to_string()
+}
+
+fn default_leeway_seconds() -> u64 {
+    60
+}
+
+fn default_reload_seconds() -> u64 {
+    30
+}
+
+impl JwtAuthConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.algorithms.is_empty() {
+            bail!("jwtAuth.algorithms must not be empty");
+        }
+        if !std::path::Path::new(&self.key_file).is_file() {
+            bail!("jwtAuth.keyFile {} is not a file", self.key_file);
+        }
+        if self.player_id_claim.is_empty() {
+            bail!("jwtAuth.playerIdClaim must not be empty");
+        }
+        Ok(())
+    }
+}
+
+/// Player identity and routing constraints taken from a verified token
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JwtIdentity {
+    pub player_id: Option<String>,
+    /// Mandatory label selector entries from `labelClaims`
+    pub label_selector: HashMap<String, String>,
+    /// Mandatory annotation selector entries from `annotationClaims`
+    pub annotation_selector: HashMap<String, String>,
+}
+
+/// A decoding key and the `kid` it is published under
+struct VerificationKey {
+    key_id: Option<String>,
+    key: DecodingKey,
+}
+
+/// Verifies bearer tokens against a locally mounted, reloadable key file
+#[derive(Clone)]
+pub struct JwtVerifier {
+    config: JwtAuthConfig,
+    keys: Arc<RwLock<Arc<Vec<VerificationKey>>>>,
+    /// Raw key file, to skip reparsing when unchanged
+    key_file_contents: Arc<RwLock<Vec<u8>>>,
+}
+
+impl JwtVerifier {
+    /// Create a verifier and load the initial keys
+    pub fn new(config: JwtAuthConfig) -> Result<Self> {
+        if config.algorithms.is_empty() {
+            bail!("jwtAuth.algorithms must not be empty");
+        }
+        let contents = std::fs::read(&config.key_file)
+            .with_context(|| format!("Failed to read JWT key file {}", config.key_file))?;
+        let keys = parse_keys(&contents, config.algorithms[0])?;
+        info!(
+            "JWT authentication enabled with {} key(s) from {}",
+            keys.len(),
+            config.key_file
+        );
+        Ok(Self {
+            config,
+            keys: Arc::new(RwLock::new(Arc::new(keys))),
+            key_file_contents: Arc::new(RwLock::new(contents)),
+        })
+    }
+
+    /// Whether queries without a token are rejected
+    pub fn required(&self) -> bool {
+        self.config.required
+    }
+
+    /// Whether a Bearer token stands in for queryAuth credentials
+    pub fn replaces_query_auth(&self) -> bool {
+        self.config.replaces_query_auth
+    }
+
+    /// Re-read the key file periodically so key rotation needs no restart
+    pub async fn run(self) -> Result<()> {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.reload_seconds.max(1)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.reload() {
+                warn!("Failed to reload JWT keys: {:#}", e);
+            }
+        }
+    }
+
+    /// Replace the keys if the file changed, keeping the old ones on error
+    fn reload(&self) -> Result<()> {
+        let contents = std::fs::read(&self.config.key_file)
+            .with_context(|| format!("Failed to read JWT key file {}", self.config.key_file))?;
+        if *self
+            .key_file_contents
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            == contents
+        {
+            return Ok(());
+        }
+        let keys = parse_keys(&contents, self.config.algorithms[0])?;
+        info!("Reloaded {} JWT key(s)", keys.len());
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(keys);
+        *self
+            .key_file_contents
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = contents;
+        Ok(())
+    }
+
+    /// Verify a token and map its claims
+    pub fn verify(&self, token: &str) -> Result<JwtIdentity, AuthError> {
+        let result = self.decode(token).and_then(|claims| self.identity(&claims));
+        match &result {
+            Ok(identity) => {
+                debug!("JWT verified for player {:?}", identity.player_id);
+                metrics::record_query_auth("ok");
+            }
+            Err(e) => metrics::record_query_auth(e.code()),
+        }
+        result
+    }
+
+    fn decode(&self, token: &str) -> Result<serde_json::Map<String, serde_json::Value>, AuthError> {
+        let header =
+            jsonwebtoken::decode_header(token).map_err(|_| AuthError::Malformed("invalid JWT"))?;
+        if !self.config.algorithms.contains(&header.alg) {
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = self.config.leeway_seconds;
+        validation.validate_nbf = true;
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let keys = self.keys.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let candidates = keys
+            .iter()
+            .filter(|k| header.kid.is_none() || k.key_id == header.kid);
+
+        let mut error = AuthError::InvalidCredentials;
+        for candidate in candidates {
+            match jsonwebtoken::decode(token, &candidate.key, &validation) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) => {
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::ExpiredSignature | ErrorKind::ImmatureSignature
+                    ) {
+                        error = AuthError::Expired;
+                    }
+                }
+            }
+        }
+        Err(error)
+    }
+
+    /// Apply rejection rules and build the mandatory selectors
+    fn identity(
+        &self,
+        claims: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<JwtIdentity, AuthError> {
+        let mut rejected: Vec<&String> = self
+            .config
+            .reject_claims
+            .iter()
+            .filter(|(claim, values)| claims.get(*claim).is_some_and(|v| values.contains(v)))
+            .map(|(claim, _)| claim)
+            .collect();
+        rejected.sort();
+        if let Some(claim) = rejected.first() {
+            return Err(AuthError::Forbidden(format!(
+                "token claim '{}' is not allowed",
+                claim
+            )));
+        }
+
+        let selector = |mapping: &HashMap<String, String>| {
+            mapping
+                .iter()
+                .map(|(key, claim)| {
+                    claim_string(claims, claim)
+                        .map(|value| (key.clone(), value))
+                        .ok_or_else(|| {
+                            AuthError::Forbidden(format!("token lacks claim '{}'", claim))
+                        })
+                })
+                .collect::<Result<HashMap<_, _>, _>>()
+        };
+
+        Ok(JwtIdentity {
+            player_id: claim_string(claims, &self.config.player_id_claim),
+            label_selector: selector(&self.config.label_claims)?,
+            annotation_selector: selector(&self.config.annotation_claims)?,
+        })
+    }
+}
+
+/// Token of an `Authorization: Bearer <token>` header
+pub fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("Bearer")
+        .then_some(token.trim())
+}
+
+/// String form of a scalar claim
+fn claim_string(
+    claims: &serde_json::Map<String, serde_json::Value>,
+    claim: &str,
+) -> Option<String> {
+    match claims.get(claim)? {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Parse a JWKS document, or a single PEM key / HMAC secret for `algorithm`
+fn parse_keys(contents: &[u8], algorithm: Algorithm) -> Result<Vec<VerificationKey>> {
+    if contents.trim_ascii_start().starts_with(b"{") {
+        let jwks: JwkSet = serde_json::from_slice(contents).context("Invalid JWKS")?;
+        return jwks
+            .keys
+            .iter()
+            .map(|jwk| {
+                Ok(VerificationKey {
+                    key_id: jwk.common.key_id.clone(),
+                    key: DecodingKey::from_jwk(jwk).context("Unsupported JWK")?,
+                })
+            })
+            .collect();
+    }
+
+    let key = match algorithm {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            DecodingKey::from_secret(contents.trim_ascii_end())
+        }
+        Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(contents)?,
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(contents)?,
+        _ => DecodingKey::from_rsa_pem(contents)?,
+    };
+    Ok(vec![VerificationKey { key_id: None, key }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use jsonwebtoken::{EncodingKey, Header};
+    use tempfile::TempDir;
+
+    const SECRET: &[u8] = b"test-signing-secret";
+
+    fn verifier() -> (JwtVerifier, TempDir) {
+        let dir = temp_dir();
+        let path = dir.path().join("key");
+        std::fs::write(&path, SECRET).unwrap();
+        let config: JwtAuthConfig = serde_yaml::from_str(&format!(
+            r#"
+keyFile: "{}"
+algorithms: ["HS256"]
+issuer: "https://auth.example.com"
+playerIdClaim: "player_id"
+labelClaims:
+  tier: "tier"
+rejectClaims:
+  banned: [true]
+"#,
+            path.display()
+        ))
+        .unwrap();
+        (JwtVerifier::new(config).unwrap(), dir)
+    }
+
+    fn token(claims: serde_json::Value, secret: &[u8]) -> String {
+        jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(secret),
+        )
+        .unwrap()
+    }
+
+    fn claims(extra: serde_json::Value) -> serde_json::Value {
+        let mut claims = serde_json::json!({
+            "iss": "https://auth.example.com",
+            "exp": jsonwebtoken::get_current_timestamp() + 600,
+            "player_id": "player-42",
+            "tier": "gold",
+        });
+        claims
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        claims
+    }
+
+    #[test]
+    fn test_jwt_claims_mapping() {
+        let (verifier, _dir) = verifier();
+
+        let identity = verifier
+            .verify(&token(claims(serde_json::json!({})), SECRET))
+            .unwrap();
+        assert_eq!(identity.player_id.as_deref(), Some("player-42"));
+        assert_eq!(identity.label_selector["tier"], "gold");
+
+        let err = verifier
+            .verify(&token(claims(serde_json::json!({"banned": true})), SECRET))
+            .unwrap_err();
+        assert_eq!(err.code(), "auth_forbidden");
+
+        let without_tier = serde_json::json!({
+            "iss": "https://auth.example.com",
+            "exp": jsonwebtoken::get_current_timestamp() + 600,
+        });
+        assert!(matches!(
+            verifier.verify(&token(without_tier, SECRET)),
+            Err(AuthError::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn test_jwt_rejections() {
+        let (verifier, _dir) = verifier();
+
+        assert_eq!(
+            verifier.verify(&token(claims(serde_json::json!({})), b"other-secret")),
+            Err(AuthError::InvalidCredentials)
+        );
+        assert_eq!(
+            verifier.verify(&token(
+                claims(serde_json::json!({"iss": "https://evil.example.com"})),
+                SECRET
+            )),
+            Err(AuthError::InvalidCredentials)
+        );
+        assert_eq!(
+            verifier.verify(&token(claims(serde_json::json!({"exp": 1000})), SECRET)),
+            Err(AuthError::Expired)
+        );
+        assert!(verifier.verify("not-a-jwt").is_err());
+
+        assert_eq!(bearer_token("Bearer abc.def.ghi"), Some("abc.def.ghi"));
+        assert_eq!(bearer_token("ApiKey a:b"), None);
+    }
+}
@@ -1,15 +1,19 @@
 use anyhow::{Context, Result};
-use k8s_openapi::api::core::v1::Service;
+use k8s_openapi::api::coordination::v1::Lease;
+use k8s_openapi::api::core::v1::{Namespace, Node, Service};
 use kube::{
     Client,
-    api::{Api, DynamicObject, ListParams},
+    api::{Api, DynamicObject, ListParams, Patch, PatchParams},
+    core::GroupVersion,
     discovery::ApiResource,
+    runtime::events::{Recorder, Reporter},
 };
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use tracing::{debug, info};
 
-use crate::config::{PortMapping, ResourceMapping};
+use crate::config::{PortMapping, ResourceMapping, ResourceScope};
 
 /// Kubernetes client wrapper
 #[derive(Clone)]
@@ -28,10 +32,24 @@ impl K8sClient {
         Ok(Self { client })
     }
 
+    /// Wrap an existing client pointed at a test API server
+    #[cfg(test)]
+    pub fn from_client(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Event recorder publishing through this client
+    pub fn event_recorder(&self, reporter: Reporter) -> Recorder {
+        Recorder::new(self.client.clone(), reporter)
+    }
+
     /// Query for resources matching the given criteria
+    ///
+    /// Namespaced mappings are listed in every namespace of `scope`;
+    /// cluster-scoped mappings ignore it.
     pub async fn query_resources(
         &self,
-        namespace: &str,
+        scope: &NamespaceScope,
         mapping: &ResourceMapping,
         status_query: Option<&StatusQuery>,
         label_selector: Option<&HashMap<String, String>>,
@@ -50,8 +68,29 @@ impl K8sClient {
             plural: mapping.resource.clone(),
         };
 
-        let api: Api<DynamicObject> =
-            Api::namespaced_with(self.client.clone(), namespace, &api_resource);
+        let apis: Vec<Api<DynamicObject>> = match (mapping.scope, scope) {
+            (ResourceScope::Cluster, _) | (ResourceScope::Namespaced, NamespaceScope::All) => {
+                vec![Api::all_with(self.client.clone(), &api_resource)]
+            }
+            (ResourceScope::Namespaced, NamespaceScope::Namespaces(namespaces)) => {
+                if namespaces.is_empty() {
+                    anyhow::bail!(
+                        "A namespace is required for namespaced resource {}",
+                        mapping.resource
+                    );
+                }
+                namespaces
+                    .iter()
+                    .map(|ns| Api::namespaced_with(self.client.clone(), ns, &api_resource))
+                    .collect()
+            }
+            (ResourceScope::Namespaced, NamespaceScope::Selector(selector)) => self
+                .list_namespaces(selector)
+                .await?
+                .iter()
+                .map(|ns| Api::namespaced_with(self.client.clone(), ns, &api_resource))
+                .collect(),
+        };
 
         // Build label selector string
         let label_selector_str = label_selector.map(|labels| {
@@ -67,27 +106,30 @@ impl K8sClient {
             list_params = list_params.labels(&selector);
         }
 
-        // List resources
-        let resource_list = api
-            .list(&list_params)
-            .await
-            .with_context(|| format!("Failed to list resources: {}", mapping.resource))?;
+        // List resources (one request per namespace)
+        let items: Vec<DynamicObject> =
+            futures::future::try_join_all(apis.iter().map(|api| api.list(&list_params)))
+                .await
+                .with_context(|| format!("Failed to list resources: {}", mapping.resource))?
+                .into_iter()
+                .flat_map(|list| list.items)
+                .collect();
 
         debug!(
-            "Found {} resources of type {}",
-            resource_list.items.len(),
-            mapping.resource
+            "Found {} resources of type {} ({})",
+            items.len(),
+            mapping.resource,
+            scope
         );
 
         // Filter by status query if provided
         let mut filtered: Vec<DynamicObject> = if let Some(query) = status_query {
-            resource_list
-                .items
+            items
                 .into_iter()
                 .filter(|resource| self.matches_status_query(resource, query))
                 .collect()
         } else {
-            resource_list.items
+            items
         };
 
         // Filter by annotations if provided (client-side filtering)
@@ -99,6 +141,26 @@ impl K8sClient {
         Ok(filtered)
     }
 
+    /// Names of the namespaces whose labels match a selector
+    async fn list_namespaces(&self, selector: &HashMap<String, String>) -> Result<Vec<String>> {
+        let api: Api<Namespace> = Api::all(self.client.clone());
+        let label_selector = selector
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(",");
+        let namespaces = api
+            .list(&ListParams::default().labels(&label_selector))
+            .await
+            .context("Failed to list namespaces")?;
+
+        Ok(namespaces
+            .items
+            .into_iter()
+            .filter_map(|ns| ns.metadata.name)
+            .collect())
+    }
+
     /// Check if a resource matches the status query
     fn matches_status_query(&self, resource: &DynamicObject, query: &StatusQuery) -> bool {
         // Parse the JSONPath and extract the value
@@ -185,6 +247,145 @@ impl K8sClient {
         Some(current.clone())
     }
 
+    /// Extract a string from a resource using JSONPath; returns None if absent or not a string
+    pub fn extract_string(&self, resource: &DynamicObject, path: &str) -> Option<String> {
+        let resource_json = serde_json::to_value(resource).ok()?;
+        match self.extract_json_path(&resource_json, path)? {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// List the value of the given label for every node (node name -> label value)
+    /// Nodes without the label are omitted
+    pub async fn list_node_labels(&self, label: &str) -> Result<HashMap<String, String>> {
+        let api: Api<Node> = Api::all(self.client.clone());
+        let nodes = api
+            .list(&ListParams::default())
+            .await
+            .context("Failed to list nodes")?;
+
+        Ok(nodes
+            .items
+            .into_iter()
+            .filter_map(|node| {
+                let name = node.metadata.name?;
+                let value = node.metadata.labels?.get(label)?.clone();
+                Some((name, value))
+            })
+            .collect())
+    }
+
+    /// Create or update a Lease with server-side apply
+    pub async fn apply_lease(&self, namespace: &str, lease: &Lease) -> Result<()> {
+        let name = lease
+            .metadata
+            .name
+            .as_deref()
+            .context("Lease must have a name")?;
+        let api: Api<Lease> = Api::namespaced(self.client.clone(), namespace);
+        api.patch(
+            name,
+            &PatchParams::apply("udp-director").force(),
+            &Patch::Apply(lease),
+        )
+        .await
+        .with_context(|| format!("Failed to apply lease {}/{}", namespace, name))?;
+        Ok(())
+    }
+
+    /// Resolve the full API resource (including kind) of a group/version/plural via discovery
+    pub async fn resolve_api_resource(
+        &self,
+        group: &str,
+        version: &str,
+        plural: &str,
+    ) -> Result<ApiResource> {
+        let gv = GroupVersion::gv(group, version);
+        let group = kube::discovery::oneshot::pinned_group(&self.client, &gv)
+            .await
+            .with_context(|| format!("Failed to discover API group {}", gv.api_version()))?;
+
+        group
+            .versioned_resources(version)
+            .into_iter()
+            .map(|(resource, _)| resource)
+            .find(|resource| resource.plural == plural)
+            .with_context(|| format!("Resource {} not found in {}", plural, gv.api_version()))
+    }
+
+    /// Server-side apply labels and annotations on a resource, owned by `field_manager`
+    ///
+    /// `namespace` is `None` for cluster-scoped resources.
+    pub async fn apply_metadata(
+        &self,
+        api_resource: &ApiResource,
+        namespace: Option<&str>,
+        name: &str,
+        labels: BTreeMap<String, String>,
+        annotations: BTreeMap<String, String>,
+        field_manager: &str,
+    ) -> Result<()> {
+        let mut object = DynamicObject::new(name, api_resource);
+        object.metadata.namespace = namespace.map(str::to_string);
+        if !labels.is_empty() {
+            object.metadata.labels = Some(labels);
+        }
+        if !annotations.is_empty() {
+            object.metadata.annotations = Some(annotations);
+        }
+
+        let api: Api<DynamicObject> = match namespace {
+            Some(namespace) => Api::namespaced_with(self.client.clone(), namespace, api_resource),
+            None => Api::all_with(self.client.clone(), api_resource),
+        };
+        api.patch(
+            name,
+            &PatchParams::apply(field_manager).force(),
+            &Patch::Apply(&object),
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to apply metadata to {}/{}",
+                namespace.unwrap_or("-"),
+                name
+            )
+        })?;
+        Ok(())
+    }
+
+    /// List Leases matching a label selector
+    pub async fn list_leases(&self, namespace: &str, label_selector: &str) -> Result<Vec<Lease>> {
+        let api: Api<Lease> = Api::namespaced(self.client.clone(), namespace);
+        let leases = api
+            .list(&ListParams::default().labels(label_selector))
+            .await
+            .with_context(|| format!("Failed to list leases in {}", namespace))?;
+        Ok(leases.items)
+    }
+
+    /// Extract an integer from a resource using JSONPath
+    /// Accepts JSON numbers and numeric strings; returns None if the path does not exist
+    pub fn extract_integer(&self, resource: &DynamicObject, path: &str) -> Result<Option<i64>> {
+        let resource_json =
+            serde_json::to_value(resource).context("Failed to serialize resource to JSON")?;
+
+        match self.extract_json_path(&resource_json, path) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Number(n)) => n
+                .as_i64()
+                .or_else(|| n.as_f64().map(|f| f as i64))
+                .map(Some)
+                .context(format!("Value at path '{}' is not an integer: {}", path, n)),
+            Some(Value::String(s)) => s
+                .parse::<i64>()
+                .map(Some)
+                .context(format!("Value at path '{}' is not an integer: {}", path, s)),
+            Some(other) => anyhow::bail!("Value at path '{}' is not an integer: {}", path, other),
+        }
+    }
+
     /// Extract address from a resource using JSONPath
     /// If address_type is provided, will search an array of addresses for the matching type
     pub fn extract_address(
@@ -405,21 +606,85 @@ pub struct StatusQuery {
     pub expected_values: Vec<String>,
 }
 
+/// Namespaces a query spans
+#[derive(Debug, Clone, PartialEq)]
+pub enum NamespaceScope {
+    /// Explicit list of namespaces (empty only for cluster-scoped mappings)
+    Namespaces(Vec<String>),
+    /// Namespaces whose labels match
+    Selector(HashMap<String, String>),
+    /// Every namespace
+    All,
+}
+
+impl NamespaceScope {
+    /// Build the scope from the namespace fields of a query or the default endpoint
+    ///
+    /// `allNamespaces` takes precedence over `namespaceSelector`, which takes
+    /// precedence over `namespace` / `namespaces` (combined).
+    pub fn from_fields(
+        namespace: &str,
+        namespaces: Option<&[String]>,
+        namespace_selector: Option<&HashMap<String, String>>,
+        all_namespaces: bool,
+    ) -> Self {
+        if all_namespaces {
+            return Self::All;
+        }
+        if let Some(selector) = namespace_selector {
+            return Self::Selector(selector.clone());
+        }
+
+        let mut list: Vec<String> = Vec::new();
+        let requested = std::iter::once(namespace)
+            .chain(namespaces.unwrap_or_default().iter().map(String::as_str));
+        for ns in requested {
+            if !ns.is_empty() && !list.iter().any(|existing| existing == ns) {
+                list.push(ns.to_string());
+            }
+        }
+        Self::Namespaces(list)
+    }
+
+    /// Whether no namespace was given at all
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Self::Namespaces(namespaces) if namespaces.is_empty())
+    }
+}
+
+impl fmt::Display for NamespaceScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Namespaces(namespaces) if namespaces.is_empty() => write!(f, "no namespace"),
+            Self::Namespaces(namespaces) => write!(f, "namespaces: {}", namespaces.join(", ")),
+            Self::Selector(selector) => {
+                let mut labels: Vec<String> = selector
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect();
+                labels.sort();
+                write!(f, "namespace selector: {}", labels.join(","))
+            }
+            Self::All => write!(f, "all namespaces"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
 
+    /// Client for a local API server that is never contacted (extraction needs no network)
+    fn offline_client() -> K8sClient {
+        K8sClient::from_client(
+            Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap())).unwrap(),
+        )
+    }
+
     #[tokio::test]
     async fn test_extract_json_path() {
-        // Create a mock client for testing
-        let client = match K8sClient::new().await {
-            Ok(c) => c,
-            Err(_) => {
-                // Skip test if not in a k8s environment
-                return;
-            }
-        };
+        let client = offline_client();
 
         let json = json!({
             "status": {
@@ -442,14 +707,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_extract_json_path_with_arrays() {
-        // Create a mock client for testing
-        let client = match K8sClient::new().await {
-            Ok(c) => c,
-            Err(_) => {
-                // Skip test if not in a k8s environment
-                return;
-            }
-        };
+        let client = offline_client();
 
         // Test with pod-like structure
         let json = json!({
@@ -497,14 +755,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_extract_port_from_pod_spec() {
-        // Create a mock client for testing
-        let client = match K8sClient::new().await {
-            Ok(c) => c,
-            Err(_) => {
-                // Skip test if not in a k8s environment
-                return;
-            }
-        };
+        let client = offline_client();
 
         // Create a mock pod resource
         let pod_json = json!({
@@ -573,9 +824,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_annotation_selector_matching() {
-        let client = K8sClient {
-            client: Client::try_default().await.ok().unwrap(),
-        };
+        let client = offline_client();
 
         // Create a resource with annotations
         let resource_json = json!({
@@ -625,4 +874,172 @@ mod tests {
         let resource_no_annot: DynamicObject = serde_json::from_value(resource_no_annot).unwrap();
         assert!(!client.matches_annotation_selector(&resource_no_annot, &selector));
     }
+
+    #[test]
+    fn test_namespace_scope_from_fields() {
+        let selector = HashMap::from([("team".to_string(), "blue".to_string())]);
+        let extra = vec!["team-b".to_string(), "team-a".to_string()];
+
+        assert_eq!(
+            NamespaceScope::from_fields("team-a", Some(&extra), None, false),
+            NamespaceScope::Namespaces(vec!["team-a".to_string(), "team-b".to_string()])
+        );
+        assert_eq!(
+            NamespaceScope::from_fields("team-a", Some(&extra), Some(&selector), false),
+            NamespaceScope::Selector(selector.clone())
+        );
+        assert_eq!(
+            NamespaceScope::from_fields("team-a", None, Some(&selector), true),
+            NamespaceScope::All
+        );
+        assert!(NamespaceScope::from_fields("", None, None, false).is_empty());
+    }
+
+    /// API server answering namespace and gameserver lists (one gameserver per namespace)
+    async fn start_fake_api_server() -> String {
+        use http_body_util::Full;
+        use hyper::body::Bytes;
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper::{Request, Response};
+        use hyper_util::rt::TokioIo;
+        use tokio::net::TcpListener;
+
+        fn gameserver(namespace: &str) -> Value {
+            json!({
+                "apiVersion": "agones.dev/v1",
+                "kind": "GameServer",
+                "metadata": { "name": format!("gs-{}", namespace), "namespace": namespace },
+                "status": { "address": "10.0.0.1" }
+            })
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|req: Request<hyper::body::Incoming>| async move {
+                        let segments: Vec<&str> = req.uri().path().split('/').collect();
+                        let response = match segments.as_slice() {
+                            // Namespaces labelled team=blue
+                            ["", "api", "v1", "namespaces"] => json!({
+                                "apiVersion": "v1", "kind": "NamespaceList", "metadata": {},
+                                "items": [
+                                    { "metadata": { "name": "blue-1" } },
+                                    { "metadata": { "name": "blue-2" } }
+                                ]
+                            }),
+                            [
+                                "",
+                                "apis",
+                                "agones.dev",
+                                "v1",
+                                "namespaces",
+                                ns,
+                                "gameservers",
+                            ] => {
+                                json!({ "apiVersion": "agones.dev/v1", "kind": "GameServerList",
+                                        "metadata": {}, "items": [gameserver(ns)] })
+                            }
+                            _ => json!({ "apiVersion": "agones.dev/v1", "kind": "GameServerList",
+                                         "metadata": {},
+                                         "items": [gameserver("a"), gameserver("b"), gameserver("c")] }),
+                        };
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(
+                            response.to_string(),
+                        ))))
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn test_query_resources_across_namespaces() {
+        let url = start_fake_api_server().await;
+        let client = K8sClient::from_client(
+            Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap(),
+        );
+        let mut mapping: ResourceMapping = serde_yaml::from_str(
+            "group: agones.dev\nversion: v1\nresource: gameservers\naddressPath: status.address",
+        )
+        .unwrap();
+        let namespaces_of = |resources: Vec<DynamicObject>| -> Vec<String> {
+            resources
+                .into_iter()
+                .filter_map(|r| r.metadata.namespace)
+                .collect()
+        };
+
+        let scope = NamespaceScope::Namespaces(vec!["x".to_string(), "y".to_string()]);
+        let found = client
+            .query_resources(&scope, &mapping, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(namespaces_of(found), vec!["x", "y"]);
+
+        let scope =
+            NamespaceScope::Selector(HashMap::from([("team".to_string(), "blue".to_string())]));
+        let found = client
+            .query_resources(&scope, &mapping, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(namespaces_of(found), vec!["blue-1", "blue-2"]);
+
+        let found = client
+            .query_resources(&NamespaceScope::All, &mapping, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 3);
+
+        // Namespaced mappings need a namespace, cluster-scoped ones ignore it
+        let none = NamespaceScope::Namespaces(Vec::new());
+        assert!(
+            client
+                .query_resources(&none, &mapping, None, None, None)
+                .await
+                .is_err()
+        );
+        mapping.scope = ResourceScope::Cluster;
+        let found = client
+            .query_resources(&none, &mapping, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_query_resources_api_errors() {
+        // Nothing listens on the API server address
+        let client = K8sClient::from_client(
+            Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap())).unwrap(),
+        );
+        let mapping: ResourceMapping = serde_yaml::from_str(
+            "group: agones.dev\nversion: v1\nresource: gameservers\naddressPath: status.address",
+        )
+        .unwrap();
+
+        // Failures surface as errors instead of an empty backend list
+        let scope =
+            NamespaceScope::Selector(HashMap::from([("team".to_string(), "blue".to_string())]));
+        let err = client
+            .query_resources(&scope, &mapping, None, None, None)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to list namespaces"));
+
+        let scope = NamespaceScope::Namespaces(vec!["x".to_string(), "y".to_string()]);
+        let err = client
+            .query_resources(&scope, &mapping, None, None, None)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to list resources: gameservers"));
+    }
 }
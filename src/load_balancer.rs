@@ -1,15 +1,29 @@
 use anyhow::Result;
 use dashmap::DashMap;
+use k8s_openapi::chrono::{DateTime, Utc};
 use kube::api::DynamicObject;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
 use tracing::{debug, info, warn};
 
+use crate::config::ResourceMapping;
+use crate::coordination::{CoordinationConfig, SessionCoordinator};
+use crate::events::EventRecorder;
+use crate::health_checker::{HealthChecker, OutlierDetectionConfig};
 use crate::k8s_client::K8sClient;
+use crate::metrics;
+use crate::score_expr::ScoreExpression;
+use crate::topology::{TopologyConfig, TopologyResolver};
 
 /// Load balancing strategy configuration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
-#[serde(tag = "type", rename_all = "camelCase")]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
 pub enum LoadBalancingStrategy {
     /// Least sessions - route to the backend with the fewest active sessions
     #[default]
@@ -17,28 +31,511 @@ pub enum LoadBalancingStrategy {
     /// Label-based arithmetic - evaluate expressions on resource labels
     LabelArithmetic {
         /// Label containing current user count (e.g., "currentUsers")
-        current_label: String,
+        #[serde(default, alias = "current_label")]
+        current_label: Option<String>,
         /// Label containing maximum user count (e.g., "maxUsers")
-        max_label: String,
+        #[serde(default, alias = "max_label")]
+        max_label: Option<String>,
+        /// Source of the current user count (takes precedence over current_label)
+        #[serde(default)]
+        current: Option<ValueSource>,
+        /// Source of the maximum user count (takes precedence over max_label)
+        #[serde(default)]
+        max: Option<ValueSource>,
+        /// Overlap allowance for concurrent proxy instances (default: 0)
+        #[serde(default)]
+        overlap: i64,
+    },
+    /// Packed (bin-packing) - route to the fullest backend that can still fit the request
+    Packed {
+        /// Label containing current user count (e.g., "currentUsers")
+        #[serde(default)]
+        current_label: Option<String>,
+        /// Label containing maximum user count (e.g., "maxUsers")
+        #[serde(default)]
+        max_label: Option<String>,
+        /// Source of the current user count (takes precedence over current_label)
+        #[serde(default)]
+        current: Option<ValueSource>,
+        /// Source of the maximum user count (takes precedence over max_label)
+        #[serde(default)]
+        max: Option<ValueSource>,
         /// Overlap allowance for concurrent proxy instances (default: 0)
         #[serde(default)]
         overlap: i64,
+        /// Capacity that must remain free after placing the request (default: 0)
+        #[serde(default)]
+        min_headroom: i64,
+    },
+    /// Consistent hash - map each client to the same backend on every replica
+    ConsistentHash {
+        /// Client identity to hash on (default: clientIp)
+        #[serde(default)]
+        key: HashKey,
+        /// Points placed on the hash ring per backend (default: 160)
+        #[serde(default = "default_virtual_nodes")]
+        virtual_nodes: u32,
+    },
+    /// Scored - evaluate an arithmetic expression per backend and pick the highest score
+    Scored {
+        /// Expression over named inputs and builtins (e.g. "(max - current - sessions) * 2")
+        expression: ScoreExpression,
+        /// Named inputs read from each backend resource
+        #[serde(default)]
+        inputs: HashMap<String, ValueSource>,
+        /// Backends scoring below this value are skipped (default: none)
+        #[serde(default)]
+        threshold: Option<f64>,
     },
 }
 
+/// Builtin score expression inputs provided by the director
+///
+/// - `sessions`: active director sessions to the backend
+/// - `rtt_ms`: smoothed round-trip time to the backend from health check probes (0 when not measured)
+pub const SCORE_BUILTINS: &[&str] = &["sessions", "rtt_ms"];
+
+/// Client identity used by the consistent hash strategy
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum HashKey {
+    /// Hash on the client IP address
+    #[default]
+    ClientIp,
+    /// Hash on the `affinityKey` supplied in the query (e.g. a player token),
+    /// falling back to the client IP when none is supplied
+    AffinityKey,
+}
+
+fn default_virtual_nodes() -> u32 {
+    160
+}
+
+/// Upper bound for `virtualNodes`, keeping rings small enough to build per backend set
+pub const MAX_VIRTUAL_NODES: u32 = 1000;
+
+/// Hash rings kept for distinct backend sets before the cache is cleared
+const MAX_CACHED_RINGS: usize = 16;
+
+/// Cache key for a hash ring: (virtual nodes, sorted backend identities)
+type RingKey = (u32, Vec<String>);
+
+/// Consistent hash ring over a set of backend identities (`namespace/name`)
+struct HashRing {
+    /// Sorted ring points: (point, index into `members`)
+    points: Vec<(u64, usize)>,
+    /// Sorted backend identities
+    members: Vec<String>,
+}
+
+impl HashRing {
+    fn new(members: Vec<String>, virtual_nodes: u32) -> Self {
+        let mut points = Vec::with_capacity(members.len() * virtual_nodes as usize);
+        for (index, identity) in members.iter().enumerate() {
+            for vnode in 0..virtual_nodes.max(1) {
+                let point = stable_hash(format!("{}#{}", identity, vnode).as_bytes());
+                points.push((point, index));
+            }
+        }
+        points.sort_unstable();
+        Self { points, members }
+    }
+
+    /// Members in ring order starting at the owner of `key_hash`, each listed once
+    fn walk(&self, key_hash: u64) -> impl Iterator<Item = &str> {
+        let start = self.points.partition_point(|(point, _)| *point < key_hash);
+        let mut seen = vec![false; self.members.len()];
+        (0..self.points.len()).filter_map(move |offset| {
+            let (_, index) = self.points[(start + offset) % self.points.len()];
+            if seen[index] {
+                return None;
+            }
+            seen[index] = true;
+            Some(self.members[index].as_str())
+        })
+    }
+}
+
+/// Where to read an integer value from on a backend resource
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ValueSource {
+    /// Value of a metadata label
+    Label(String),
+    /// Value of a metadata annotation
+    Annotation(String),
+    /// JSONPath into the resource (e.g. "status.counters.players.count")
+    JsonPath(String),
+}
+
+impl std::fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueSource::Label(key) => write!(f, "label '{}'", key),
+            ValueSource::Annotation(key) => write!(f, "annotation '{}'", key),
+            ValueSource::JsonPath(path) => write!(f, "jsonPath '{}'", path),
+        }
+    }
+}
+
+/// Resolved current/max inputs for the capacity-based strategies
+#[derive(Debug, Clone)]
+struct CapacitySources {
+    current: ValueSource,
+    max: ValueSource,
+}
+
+impl CapacitySources {
+    /// Resolve sources, preferring `current`/`max` over the label shorthand
+    fn resolve(
+        current: &Option<ValueSource>,
+        current_label: &Option<String>,
+        max: &Option<ValueSource>,
+        max_label: &Option<String>,
+    ) -> Result<Self> {
+        let resolve_one = |source: &Option<ValueSource>, label: &Option<String>, field: &str| match (
+            source, label,
+        ) {
+            (Some(source), _) => Ok(source.clone()),
+            (None, Some(label)) => Ok(ValueSource::Label(label.clone())),
+            (None, None) => Err(anyhow::anyhow!(
+                "loadBalancing requires either '{}' or '{}Label'",
+                field,
+                field
+            )),
+        };
+
+        Ok(Self {
+            current: resolve_one(current, current_label, "current")?,
+            max: resolve_one(max, max_label, "max")?,
+        })
+    }
+}
+
+impl LoadBalancingStrategy {
+    /// Validate the strategy configuration
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            LoadBalancingStrategy::LabelArithmetic {
+                current_label,
+                max_label,
+                current,
+                max,
+                ..
+            }
+            | LoadBalancingStrategy::Packed {
+                current_label,
+                max_label,
+                current,
+                max,
+                ..
+            } => {
+                let sources = CapacitySources::resolve(current, current_label, max, max_label)?;
+                for source in [&sources.current, &sources.max] {
+                    let key = match source {
+                        ValueSource::Label(key)
+                        | ValueSource::Annotation(key)
+                        | ValueSource::JsonPath(key) => key,
+                    };
+                    if key.is_empty() {
+                        anyhow::bail!("loadBalancing {} must not be empty", source);
+                    }
+                }
+                // A negative headroom would let backends without room for the party fit
+                if let LoadBalancingStrategy::Packed {
+                    overlap,
+                    min_headroom,
+                    ..
+                } = self
+                    && (*overlap < 0 || *min_headroom < 0)
+                {
+                    anyhow::bail!("loadBalancing overlap and minHeadroom must not be negative");
+                }
+                Ok(())
+            }
+            LoadBalancingStrategy::Scored {
+                expression, inputs, ..
+            } => {
+                for name in inputs.keys() {
+                    if SCORE_BUILTINS.contains(&name.as_str()) {
+                        anyhow::bail!("loadBalancing input '{}' shadows a builtin input", name);
+                    }
+                }
+
+                let mut unknown: Vec<&str> = expression
+                    .expr()
+                    .variables()
+                    .into_iter()
+                    .filter(|name| !inputs.contains_key(*name) && !SCORE_BUILTINS.contains(name))
+                    .collect();
+                unknown.sort_unstable();
+                if !unknown.is_empty() {
+                    anyhow::bail!(
+                        "loadBalancing expression references undefined input(s): {} \
+                        (define them under 'inputs' or use one of: {})",
+                        unknown.join(", "),
+                        SCORE_BUILTINS.join(", ")
+                    );
+                }
+                Ok(())
+            }
+            LoadBalancingStrategy::ConsistentHash { virtual_nodes, .. } => {
+                if *virtual_nodes == 0 || *virtual_nodes > MAX_VIRTUAL_NODES {
+                    anyhow::bail!(
+                        "loadBalancing virtualNodes must be between 1 and {}",
+                        MAX_VIRTUAL_NODES
+                    );
+                }
+                Ok(())
+            }
+            LoadBalancingStrategy::LeastSessions => Ok(()),
+        }
+    }
+}
+
 /// Load balancing configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LoadBalancingConfig {
-    /// Load balancing strategy to use
-    #[serde(default)]
+    /// Load balancing strategy to use (configured inline via `type`)
+    #[serde(flatten)]
     pub strategy: LoadBalancingStrategy,
+    /// Optional zone preference applied on top of the strategy
+    #[serde(default)]
+    pub topology: Option<TopologyConfig>,
+    /// Optional passive ejection of backends that fail connects or return ICMP errors
+    #[serde(default)]
+    pub outlier_detection: Option<OutlierDetectionConfig>,
+    /// Count issued-but-unclaimed tokens against backend capacity until they expire
+    #[serde(default)]
+    pub reservations: bool,
+    /// Optional capacity ramp for newly ready backends
+    #[serde(default)]
+    pub slow_start: Option<SlowStartConfig>,
+    /// Optional sharing of session counts between director replicas
+    #[serde(default)]
+    pub coordination: Option<CoordinationConfig>,
+}
+
+/// Deserialize `loadBalancing`, also accepting the layout of releases up to 0.2.1
+///
+/// Those nested the strategy under `strategy:` with snake_case fields, and an empty
+/// section meant `leastSessions`.
+pub fn deserialize_load_balancing<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<LoadBalancingConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let Some(mut value) = Option::<serde_json::Value>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    if let serde_json::Value::Object(fields) = &mut value
+        && !fields.contains_key("type")
+    {
+        if let Some(serde_json::Value::Object(strategy)) = fields.remove("strategy") {
+            warn!(
+                "loadBalancing.strategy is deprecated; set its fields directly under loadBalancing"
+            );
+            fields.extend(strategy);
+        }
+        fields
+            .entry("type")
+            .or_insert_with(|| "leastSessions".into());
+    }
+    serde_json::from_value(value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+impl LoadBalancingConfig {
+    /// Validate the load balancing configuration
+    pub fn validate(&self) -> Result<()> {
+        self.strategy.validate()?;
+
+        if let Some(slow_start) = &self.slow_start {
+            if !matches!(
+                self.strategy,
+                LoadBalancingStrategy::LabelArithmetic { .. }
+                    | LoadBalancingStrategy::Packed { .. }
+            ) {
+                anyhow::bail!("slowStart requires the labelArithmetic or packed strategy");
+            }
+            if slow_start.window_seconds == 0 || slow_start.initial_percent > 100 {
+                anyhow::bail!(
+                    "slowStart.windowSeconds must be non-zero and initialPercent at most 100"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Capacity ramp for newly ready backends (capacity-based strategies)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SlowStartConfig {
+    /// Time for a backend to reach its full capacity (default: 60)
+    #[serde(default = "default_slow_start_window_seconds")]
+    pub window_seconds: u64,
+    /// Share of the max capacity available immediately, in percent (default: 10)
+    #[serde(default = "default_slow_start_initial_percent")]
+    pub initial_percent: u8,
+    /// JSONPath to an RFC 3339 timestamp of when the backend became ready
+    /// (default: metadata.creationTimestamp)
+    #[serde(default)]
+    pub ready_time_path: Option<String>,
+}
+
+fn default_slow_start_window_seconds() -> u64 {
+    60
+}
+
+fn default_slow_start_initial_percent() -> u8 {
+    10
+}
+
+/// Capacity held for an issued token until its party connects or the token expires
+#[derive(Debug, Clone)]
+struct Reservation {
+    /// Slots not yet claimed by a connecting party member
+    slots: i64,
+    expires_at: std::time::Instant,
+}
+
+/// Unclaimed reservations, indexed by backend address
+#[derive(Clone, Default)]
+pub struct Reservations {
+    /// Key: backend IP address -> (token -> reservation)
+    by_backend: Arc<DashMap<String, HashMap<String, Reservation>>>,
+    /// Key: token -> backend IP address
+    by_token: Arc<DashMap<String, String>>,
+}
+
+impl Reservations {
+    /// Hold `slots` on a backend for a token until `expires_at`
+    fn insert(&self, token: &str, address: &str, slots: i64, expires_at: std::time::Instant) {
+        self.remove(token);
+        self.by_backend
+            .entry(address.to_string())
+            .or_default()
+            .insert(token.to_string(), Reservation { slots, expires_at });
+        self.by_token.insert(token.to_string(), address.to_string());
+    }
+
+    /// Take one slot from a token's reservation, dropping it once every slot is taken
+    ///
+    /// Returns the slots still held, or None when the token holds no reservation.
+    fn take_slot(&self, token: &str) -> Option<i64> {
+        let address = self.by_token.get(token)?.clone();
+        let remaining = {
+            let mut held = self.by_backend.get_mut(&address)?;
+            let reservation = held.get_mut(token)?;
+            reservation.slots -= 1;
+            reservation.slots
+        };
+        if remaining <= 0 {
+            self.remove(token);
+        }
+        Some(remaining.max(0))
+    }
+
+    /// Drop a token's reservation
+    fn remove(&self, token: &str) {
+        let Some((_, address)) = self.by_token.remove(token) else {
+            return;
+        };
+        self.by_backend.remove_if_mut(&address, |_, held| {
+            held.remove(token);
+            held.is_empty()
+        });
+    }
+
+    /// Drop every reservation that expired before `now`
+    fn purge_expired(&self, now: std::time::Instant) {
+        self.by_backend.retain(|_, held| {
+            held.retain(|_, reservation| reservation.expires_at > now);
+            !held.is_empty()
+        });
+        self.by_token.retain(|token, address| {
+            self.by_backend
+                .get(address)
+                .is_some_and(|held| held.contains_key(token))
+        });
+    }
+
+    /// Slots held by unexpired reservations on a backend
+    pub fn slots(&self, address: &str) -> i64 {
+        let now = std::time::Instant::now();
+        self.by_backend.get(address).map_or(0, |held| {
+            held.values()
+                .filter(|reservation| reservation.expires_at > now)
+                .map(|reservation| reservation.slots)
+                .sum()
+        })
+    }
+
+    /// Non-zero held slots per backend (published to other replicas)
+    pub fn snapshot(&self) -> std::collections::BTreeMap<String, usize> {
+        self.by_backend
+            .iter()
+            .map(|entry| (entry.key().clone(), self.slots(entry.key()) as usize))
+            .filter(|(_, slots)| *slots > 0)
+            .collect()
+    }
 }
 
 impl Default for LoadBalancingConfig {
     fn default() -> Self {
         Self {
             strategy: LoadBalancingStrategy::LeastSessions,
+            topology: None,
+            outlier_detection: None,
+            reservations: false,
+            slow_start: None,
+            coordination: None,
+        }
+    }
+}
+
+/// Weight of a new sample in the smoothed RTT (exponentially weighted moving average)
+const RTT_SMOOTHING: f64 = 0.3;
+
+/// Smoothed round-trip time per backend, measured by health check probes
+#[derive(Clone, Default)]
+pub struct RttTracker {
+    /// Key: backend IP address -> smoothed RTT in milliseconds
+    rtt_ms: Arc<DashMap<String, f64>>,
+}
+
+impl RttTracker {
+    /// Add an RTT sample for a backend
+    pub fn record(&self, address: &str, sample: std::time::Duration) {
+        let sample_ms = sample.as_secs_f64() * 1000.0;
+        let mut rtt = self.rtt_ms.entry(address.to_string()).or_insert(sample_ms);
+        *rtt += RTT_SMOOTHING * (sample_ms - *rtt);
+        metrics::record_backend_rtt(address, *rtt);
+    }
+
+    /// Smoothed RTT for a backend in milliseconds, if measured
+    pub fn get(&self, address: &str) -> Option<f64> {
+        self.rtt_ms.get(address).map(|rtt| *rtt)
+    }
+
+    /// Forget a backend
+    pub fn remove(&self, address: &str) {
+        if self.rtt_ms.remove(address).is_some() {
+            metrics::remove_backend_rtt(address);
+        }
+    }
+
+    /// Order backends by RTT, lowest first; unmeasured backends sort last
+    fn compare(&self, a: &str, b: &str) -> std::cmp::Ordering {
+        match (self.get(a), self.get(b)) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
         }
     }
 }
@@ -57,6 +554,55 @@ pub struct Backend {
     pub labels: std::collections::HashMap<String, String>,
 }
 
+/// Per-request parameters for backend selection
+#[derive(Debug, Clone)]
+pub struct SelectionContext {
+    /// Number of slots the session (or party) needs on the selected backend
+    pub party_size: i64,
+    /// Address of the client the backend is selected for
+    pub client_ip: Option<IpAddr>,
+    /// Client-supplied affinity key (e.g. a player token) for consistent hashing
+    pub affinity_key: Option<String>,
+}
+
+impl Default for SelectionContext {
+    fn default() -> Self {
+        Self {
+            party_size: 1,
+            client_ip: None,
+            affinity_key: None,
+        }
+    }
+}
+
+impl SelectionContext {
+    /// Create a selection context for a party of the given size
+    pub fn with_party_size(party_size: u32) -> Self {
+        Self {
+            party_size: i64::from(party_size.max(1)),
+            ..Self::default()
+        }
+    }
+
+    /// Resolve the hash input for the given key, falling back to the client IP
+    fn hash_input(&self, key: HashKey) -> Option<String> {
+        let affinity = match key {
+            HashKey::AffinityKey => self.affinity_key.clone(),
+            HashKey::ClientIp => None,
+        };
+        affinity.or_else(|| self.client_ip.map(|ip| ip.to_string()))
+    }
+}
+
+/// Backend with capacity computed from its current/max labels
+struct CapacityCandidate {
+    resource: DynamicObject,
+    address: String,
+    /// Remaining capacity: max - current - sessions - reserved (both across replicas) - overlap
+    available: i64,
+    current: i64,
+}
+
 /// Load balancer for selecting backend resources
 pub struct LoadBalancer {
     /// Strategy to use for load balancing
@@ -66,6 +612,24 @@ pub struct LoadBalancer {
     session_counts: Arc<DashMap<String, usize>>,
     /// K8s client for extracting labels
     k8s_client: K8sClient,
+    /// Optional zone preference layered over the strategy
+    topology: Option<TopologyResolver>,
+    /// Optional health tracking used to skip ejected backends
+    health: Option<HealthChecker>,
+    /// Smoothed RTT per backend (tie-breaker and `rtt_ms` score input)
+    rtt: RttTracker,
+    /// Token TTL when reservation accounting is enabled
+    reservation_ttl: Option<std::time::Duration>,
+    /// Unclaimed reservations (each slot becomes a session once a party member claims it)
+    reservations: Reservations,
+    /// Optional capacity ramp for newly ready backends
+    slow_start: Option<SlowStartConfig>,
+    /// Optional view of other replicas' session counts
+    coordinator: Option<SessionCoordinator>,
+    /// Optional Kubernetes Events (needs backend addresses mapped to resources)
+    events: Option<EventRecorder>,
+    /// Consistent hash rings, one per distinct backend set
+    hash_rings: Arc<DashMap<RingKey, Arc<HashRing>>>,
 }
 
 impl LoadBalancer {
@@ -76,20 +640,354 @@ impl LoadBalancer {
             strategy,
             session_counts: Arc::new(DashMap::new()),
             k8s_client,
+            topology: None,
+            health: None,
+            rtt: RttTracker::default(),
+            reservation_ttl: None,
+            reservations: Reservations::default(),
+            slow_start: None,
+            coordinator: None,
+            events: None,
+            hash_rings: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Use another strategy, keeping all shared state (used by query presets)
+    pub fn with_strategy(mut self, strategy: LoadBalancingStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Prefer backends in the director's own zone
+    pub fn with_topology(mut self, topology: TopologyResolver) -> Self {
+        info!("Topology-aware routing enabled: {:?}", topology.config());
+        self.topology = Some(topology);
+        self
+    }
+
+    /// Skip backends ejected by active health checks or outlier detection
+    pub fn with_health_checker(mut self, health: HealthChecker) -> Self {
+        info!("Backend health checking enabled");
+        self.health = Some(health);
+        self
+    }
+
+    /// Count issued-but-unclaimed tokens against capacity for `ttl` (the token TTL)
+    pub fn with_reservations(mut self, ttl: std::time::Duration) -> Self {
+        info!("Reservation accounting enabled (ttl={}s)", ttl.as_secs());
+        self.reservation_ttl = Some(ttl);
+        self
+    }
+
+    /// Ramp the capacity of newly ready backends
+    pub fn with_slow_start(mut self, slow_start: SlowStartConfig) -> Self {
+        info!("Slow start enabled: {:?}", slow_start);
+        self.slow_start = Some(slow_start);
+        self
+    }
+
+    /// Subtract other replicas' sessions in capacity-based strategies
+    pub fn with_coordinator(mut self, coordinator: SessionCoordinator) -> Self {
+        info!("Cross-replica session coordination enabled");
+        self.coordinator = Some(coordinator);
+        self
+    }
+
+    /// Map backend addresses to resources for Kubernetes Events
+    pub fn with_event_recorder(mut self, events: EventRecorder) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Register query results with the health checker and event recorder
+    pub fn observe_backends(&self, resources: &[DynamicObject], mapping: &ResourceMapping) {
+        if let Some(health) = &self.health {
+            health.observe(resources, mapping);
+        }
+        if let Some(events) = &self.events {
+            events.observe(resources, mapping);
+        }
+    }
+
+    /// Session counts of this replica, shared with the coordinator
+    pub fn session_counts(&self) -> Arc<DashMap<String, usize>> {
+        self.session_counts.clone()
+    }
+
+    /// Sessions other director replicas hold on a backend
+    fn remote_sessions(&self, backend_address: &str) -> i64 {
+        self.coordinator
+            .as_ref()
+            .map_or(0, |c| c.remote_sessions(backend_address) as i64)
+    }
+
+    /// Reservations of this replica, shared with the coordinator
+    pub fn reservations(&self) -> Reservations {
+        self.reservations.clone()
+    }
+
+    /// Reserve capacity on a backend for an issued token
+    ///
+    /// Returns false when reservations are disabled; the caller then counts the
+    /// session right away with `increment_session`.
+    pub fn reserve(&self, token: &str, backend_address: &str, slots: i64) -> bool {
+        let Some(ttl) = self.reservation_ttl else {
+            return false;
+        };
+
+        let now = std::time::Instant::now();
+        self.reservations.purge_expired(now);
+        self.reservations
+            .insert(token, backend_address, slots.max(1), now + ttl);
+        debug!("Reserved {} slot(s) on {}", slots, backend_address);
+        true
+    }
+
+    /// Turn one slot of a token's reservation into a session as a party member connects
+    ///
+    /// The remaining slots stay held until they are claimed or the token expires.
+    /// The session is counted even if the reservation has already expired.
+    pub fn claim_reservation(&self, token: &str, backend_address: &str) {
+        if let Some(remaining) = self.reservations.take_slot(token) {
+            debug!(
+                "Claimed a reserved slot on {} ({} still held)",
+                backend_address, remaining
+            );
+        }
+        self.increment_session(backend_address);
+    }
+
+    /// Give back one slot of a token's reservation without counting a session
+    ///
+    /// Used when a session holding an unclaimed slot ends before its first packet.
+    pub fn release_reservation(&self, token: &str) {
+        self.reservations.take_slot(token);
+    }
+
+    /// Slots held by unexpired reservations on a backend, including other replicas'
+    pub fn reserved_slots(&self, backend_address: &str) -> i64 {
+        let remote = self
+            .coordinator
+            .as_ref()
+            .map_or(0, |c| c.remote_reserved(backend_address) as i64);
+        self.reservations.slots(backend_address) + remote
+    }
+
+    /// Max capacity after applying slow start to a backend at `now`
+    fn slow_start_max(&self, resource: &DynamicObject, max_value: i64, now: DateTime<Utc>) -> i64 {
+        let Some(slow_start) = &self.slow_start else {
+            return max_value;
+        };
+
+        let ready_time = match &slow_start.ready_time_path {
+            Some(path) => self
+                .k8s_client
+                .extract_string(resource, path)
+                .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                .map(|t| t.with_timezone(&Utc)),
+            None => resource.metadata.creation_timestamp.as_ref().map(|t| t.0),
+        };
+        let Some(ready_time) = ready_time else {
+            return max_value;
+        };
+
+        let elapsed = (now - ready_time).num_milliseconds().max(0) as f64 / 1000.0;
+        let window = slow_start.window_seconds as f64;
+        if elapsed >= window {
+            return max_value;
+        }
+
+        let initial = f64::from(slow_start.initial_percent) / 100.0;
+        let fraction = initial + (1.0 - initial) * elapsed / window;
+        (max_value as f64 * fraction).ceil() as i64
+    }
+
+    /// RTT tracker shared with the health checker
+    pub fn rtt_tracker(&self) -> RttTracker {
+        self.rtt.clone()
+    }
+
+    /// Smoothed RTT to a backend in milliseconds, if measured
+    pub fn backend_rtt_ms(&self, address: &str) -> Option<f64> {
+        self.rtt.get(address)
+    }
+
+    /// Record a passive failure (connect failure, ICMP error) for a backend
+    pub fn record_backend_failure(&self, address: &str) {
+        if let Some(health) = &self.health {
+            health.record_failure(address);
+        }
+    }
+
+    /// Record a successful connection to a backend
+    pub fn record_backend_success(&self, address: &str) {
+        if let Some(health) = &self.health {
+            health.record_success(address);
         }
     }
 
     /// Select the best backend from a list of resources
+    ///
+    /// Ejected backends are skipped unless every backend is ejected. With
+    /// topology enabled, the strategy is first applied to backends in the
+    /// local zone and spills over to all backends when the local zone is full.
+    ///
+    /// Consistent hashing ignores topology and hashes over every backend so
+    /// all replicas share one ring; it skips ejected owners on the ring itself.
     pub fn select_backend(
         &self,
         resources: &[DynamicObject],
         address_path: &str,
         address_type: Option<&str>,
+        context: &SelectionContext,
     ) -> Result<DynamicObject> {
         if resources.is_empty() {
             anyhow::bail!("No resources available for load balancing");
         }
 
+        if let LoadBalancingStrategy::ConsistentHash { key, virtual_nodes } = &self.strategy {
+            if let Some(hash_input) = context.hash_input(*key) {
+                return self.select_consistent_hash(
+                    resources,
+                    address_path,
+                    address_type,
+                    &hash_input,
+                    *virtual_nodes,
+                );
+            }
+        }
+
+        let healthy = self.healthy_candidates(resources, address_path, address_type);
+        let resources = healthy.as_deref().unwrap_or(resources);
+
+        if let Some(local) = self.local_zone_candidates(resources, address_path, address_type) {
+            match self.select_with_strategy(&local, address_path, address_type, context) {
+                Ok(selected) => {
+                    metrics::record_topology_selection("local");
+                    return Ok(selected);
+                }
+                Err(e) => {
+                    debug!("No eligible backend in local zone ({}), spilling over", e);
+                    metrics::record_topology_selection("spillover_no_capacity");
+                }
+            }
+        }
+
+        self.select_with_strategy(resources, address_path, address_type, context)
+    }
+
+    /// Backends that are not ejected, or None when no filtering applies
+    ///
+    /// Fails open: when every backend is ejected all of them are returned.
+    fn healthy_candidates(
+        &self,
+        resources: &[DynamicObject],
+        address_path: &str,
+        address_type: Option<&str>,
+    ) -> Option<Vec<DynamicObject>> {
+        let health = self.health.as_ref()?;
+
+        let healthy: Vec<DynamicObject> = resources
+            .iter()
+            .filter(|r| {
+                self.k8s_client
+                    .extract_address(r, address_path, address_type)
+                    .map(|address| !health.is_ejected(&address))
+                    .unwrap_or(true)
+            })
+            .cloned()
+            .collect();
+
+        if healthy.len() == resources.len() {
+            return None;
+        }
+        if healthy.is_empty() {
+            warn!(
+                "All {} backend(s) are ejected, ignoring health state",
+                resources.len()
+            );
+            return None;
+        }
+
+        debug!(
+            "Skipping {} ejected backend(s)",
+            resources.len() - healthy.len()
+        );
+        Some(healthy)
+    }
+
+    /// Backends in the director's zone, or None when all zones should be considered
+    ///
+    /// Returns None when topology is disabled, the local zone is unknown, every
+    /// backend is local, or a spill-over threshold is reached.
+    fn local_zone_candidates(
+        &self,
+        resources: &[DynamicObject],
+        address_path: &str,
+        address_type: Option<&str>,
+    ) -> Option<Vec<DynamicObject>> {
+        let topology = self.topology.as_ref()?;
+        let local_zone = topology.local_zone()?;
+
+        let local: Vec<DynamicObject> = resources
+            .iter()
+            .filter(|r| topology.backend_zone(r).as_deref() == Some(local_zone.as_str()))
+            .cloned()
+            .collect();
+
+        if local.len() == resources.len() {
+            return None;
+        }
+
+        let config = topology.config();
+        if local.len() < config.min_local_backends.max(1) {
+            debug!(
+                "Only {} backend(s) in zone '{}' (minLocalBackends={}), using all zones",
+                local.len(),
+                local_zone,
+                config.min_local_backends
+            );
+            metrics::record_topology_selection("spillover_min_backends");
+            return None;
+        }
+
+        if let Some(max_sessions) = config.max_local_sessions {
+            let local_sessions: usize = local
+                .iter()
+                .filter_map(|r| {
+                    self.k8s_client
+                        .extract_address(r, address_path, address_type)
+                        .ok()
+                })
+                .map(|address| self.get_session_count(&address))
+                .sum();
+            if local_sessions >= max_sessions {
+                debug!(
+                    "Zone '{}' holds {} session(s) (maxLocalSessions={}), using all zones",
+                    local_zone, local_sessions, max_sessions
+                );
+                metrics::record_topology_selection("spillover_max_sessions");
+                return None;
+            }
+        }
+
+        debug!(
+            "{} of {} backend(s) are in local zone '{}'",
+            local.len(),
+            resources.len(),
+            local_zone
+        );
+        Some(local)
+    }
+
+    /// Apply the configured strategy to the given resources
+    fn select_with_strategy(
+        &self,
+        resources: &[DynamicObject],
+        address_path: &str,
+        address_type: Option<&str>,
+        context: &SelectionContext,
+    ) -> Result<DynamicObject> {
         match &self.strategy {
             LoadBalancingStrategy::LeastSessions => {
                 self.select_least_sessions(resources, address_path, address_type)
@@ -97,14 +995,48 @@ impl LoadBalancer {
             LoadBalancingStrategy::LabelArithmetic {
                 current_label,
                 max_label,
+                current,
+                max,
                 overlap,
             } => self.select_label_arithmetic(
                 resources,
                 address_path,
                 address_type,
+                &CapacitySources::resolve(current, current_label, max, max_label)?,
+                *overlap,
+                context.party_size,
+            ),
+            LoadBalancingStrategy::Packed {
                 current_label,
                 max_label,
+                current,
+                max,
+                overlap,
+                min_headroom,
+            } => self.select_packed(
+                resources,
+                address_path,
+                address_type,
+                &CapacitySources::resolve(current, current_label, max, max_label)?,
                 *overlap,
+                *min_headroom,
+                context.party_size,
+            ),
+            LoadBalancingStrategy::ConsistentHash { .. } => {
+                debug!("No client identity available for consistent hashing, using least sessions");
+                self.select_least_sessions(resources, address_path, address_type)
+            }
+            LoadBalancingStrategy::Scored {
+                expression,
+                inputs,
+                threshold,
+            } => self.select_scored(
+                resources,
+                address_path,
+                address_type,
+                expression,
+                inputs,
+                *threshold,
             ),
         }
     }
@@ -146,37 +1078,380 @@ impl LoadBalancer {
             backends.push((resource.clone(), address, session_count));
         }
 
-        if backends.is_empty() {
-            anyhow::bail!("No valid backends found after address extraction");
+        if backends.is_empty() {
+            anyhow::bail!("No valid backends found after address extraction");
+        }
+
+        // Sort by session count (ascending), lower RTT as tiebreaker, and select the first
+        backends.sort_by(|(_, a_addr, a_count), (_, b_addr, b_count)| {
+            a_count
+                .cmp(b_count)
+                .then_with(|| self.rtt.compare(a_addr, b_addr))
+        });
+        let (selected, address, count) = &backends[0];
+
+        let name = selected.metadata.name.as_deref().unwrap_or("unknown");
+
+        debug!(
+            "Selected backend '{}' ({}) with {} sessions (least of {} backends)",
+            name,
+            address,
+            count,
+            backends.len()
+        );
+
+        Ok(selected.clone())
+    }
+
+    /// Select backend using label-based arithmetic strategy
+    fn select_label_arithmetic(
+        &self,
+        resources: &[DynamicObject],
+        address_path: &str,
+        address_type: Option<&str>,
+        sources: &CapacitySources,
+        overlap: i64,
+        party_size: i64,
+    ) -> Result<DynamicObject> {
+        let mut candidates: Vec<CapacityCandidate> = self
+            .collect_capacity_candidates(resources, address_path, address_type, sources, overlap)
+            .into_iter()
+            .filter(|candidate| {
+                let fits = candidate.available >= party_size;
+                if !fits {
+                    debug!(
+                        "Backend ({}) is at capacity (available={}, needed={})",
+                        candidate.address, candidate.available, party_size
+                    );
+                }
+                fits
+            })
+            .collect();
+
+        if candidates.is_empty() {
+            anyhow::bail!(
+                "No backends available with capacity (checked {} resources). \
+                All backends may be at max capacity or missing required {} and {}",
+                resources.len(),
+                sources.current,
+                sources.max
+            );
+        }
+
+        // Sort by available capacity (descending), then by current load (ascending)
+        candidates.sort_by(|a, b| {
+            b.available
+                .cmp(&a.available) // More available capacity first
+                .then_with(|| a.current.cmp(&b.current)) // Lower current load as tiebreaker
+                .then_with(|| self.rtt.compare(&a.address, &b.address)) // Then lower RTT
+        });
+
+        let selected = &candidates[0];
+        let name = selected
+            .resource
+            .metadata
+            .name
+            .as_deref()
+            .unwrap_or("unknown");
+
+        info!(
+            "Selected backend '{}' ({}) with {} available capacity (current={}, {} candidates)",
+            name,
+            selected.address,
+            selected.available,
+            selected.current,
+            candidates.len()
+        );
+
+        Ok(selected.resource.clone())
+    }
+
+    /// Select backend using packed (bin-packing) strategy
+    ///
+    /// Picks the backend with the *least* remaining capacity that still fits the
+    /// request plus `min_headroom`, so partially filled backends fill up first and
+    /// empty ones can be scaled down.
+    #[allow(clippy::too_many_arguments)]
+    fn select_packed(
+        &self,
+        resources: &[DynamicObject],
+        address_path: &str,
+        address_type: Option<&str>,
+        sources: &CapacitySources,
+        overlap: i64,
+        min_headroom: i64,
+        party_size: i64,
+    ) -> Result<DynamicObject> {
+        let needed = party_size + min_headroom;
+
+        let mut candidates: Vec<CapacityCandidate> = self
+            .collect_capacity_candidates(resources, address_path, address_type, sources, overlap)
+            .into_iter()
+            .filter(|candidate| {
+                let fits = candidate.available >= needed;
+                if !fits {
+                    debug!(
+                        "Backend ({}) cannot fit request (available={}, needed={})",
+                        candidate.address, candidate.available, needed
+                    );
+                }
+                fits
+            })
+            .collect();
+
+        if candidates.is_empty() {
+            anyhow::bail!(
+                "No backends available with capacity for {} slot(s) plus {} headroom \
+                (checked {} resources). All backends may be full or missing required {} and {}",
+                party_size,
+                min_headroom,
+                resources.len(),
+                sources.current,
+                sources.max
+            );
+        }
+
+        // Sort by available capacity (ascending), then by current load (descending)
+        candidates.sort_by(|a, b| {
+            a.available
+                .cmp(&b.available) // Least available capacity first
+                .then_with(|| b.current.cmp(&a.current)) // Fuller backend as tiebreaker
+                .then_with(|| self.rtt.compare(&a.address, &b.address)) // Then lower RTT
+                .then_with(|| a.address.cmp(&b.address)) // Stable order across replicas
+        });
+
+        let selected = &candidates[0];
+        let name = selected
+            .resource
+            .metadata
+            .name
+            .as_deref()
+            .unwrap_or("unknown");
+
+        info!(
+            "Packed backend '{}' ({}) with {} available capacity (current={}, {} candidates)",
+            name,
+            selected.address,
+            selected.available,
+            selected.current,
+            candidates.len()
+        );
+
+        Ok(selected.resource.clone())
+    }
+
+    /// Select backend using consistent hashing on the client identity
+    ///
+    /// The ring is built from resource identities (`namespace/name`) with a hash
+    /// that is stable across processes, so every replica maps a client to the
+    /// same backend and only ~1/N of clients move when a backend is added or removed.
+    /// When the owner is ejected, the next backend clockwise on the ring is used.
+    fn select_consistent_hash(
+        &self,
+        resources: &[DynamicObject],
+        address_path: &str,
+        address_type: Option<&str>,
+        hash_input: &str,
+        virtual_nodes: u32,
+    ) -> Result<DynamicObject> {
+        // Only backends with a routable address take part in the ring
+        let mut members: HashMap<String, (&DynamicObject, String)> = HashMap::new();
+        for resource in resources {
+            let name = resource.metadata.name.as_deref().unwrap_or("unknown");
+            match self
+                .k8s_client
+                .extract_address(resource, address_path, address_type)
+            {
+                Ok(address) => {
+                    let identity = format!(
+                        "{}/{}",
+                        resource.metadata.namespace.as_deref().unwrap_or_default(),
+                        name
+                    );
+                    members.insert(identity, (resource, address));
+                }
+                Err(e) => warn!("Failed to extract address from resource {}: {}", name, e),
+            }
+        }
+
+        if members.is_empty() {
+            anyhow::bail!("No valid backends found after address extraction");
+        }
+
+        let ring = self.hash_ring(members.keys().cloned().collect(), virtual_nodes);
+        let key_hash = stable_hash(hash_input.as_bytes());
+
+        let is_ejected = |identity: &str| {
+            self.health
+                .as_ref()
+                .is_some_and(|health| health.is_ejected(&members[identity].1))
+        };
+        let Some(owner) = ring.walk(key_hash).next() else {
+            anyhow::bail!("Consistent hash ring is empty");
+        };
+        let selected = match ring.walk(key_hash).find(|identity| !is_ejected(identity)) {
+            Some(identity) => {
+                if identity != owner {
+                    debug!("Ring owner '{}' is ejected, using '{}'", owner, identity);
+                }
+                identity
+            }
+            None => {
+                // Fail open like the other strategies when every backend is ejected
+                warn!(
+                    "All {} backend(s) are ejected, ignoring health state",
+                    ring.members.len()
+                );
+                owner
+            }
+        };
+
+        debug!(
+            "Consistent hash selected backend '{}' for key '{}' ({} backends)",
+            selected,
+            hash_input,
+            ring.members.len()
+        );
+
+        Ok(members[selected].0.clone())
+    }
+
+    /// Ring for a backend set, built once per distinct set
+    fn hash_ring(&self, mut members: Vec<String>, virtual_nodes: u32) -> Arc<HashRing> {
+        members.sort_unstable();
+        let key = (virtual_nodes, members);
+        if let Some(ring) = self.hash_rings.get(&key) {
+            return ring.clone();
+        }
+
+        if self.hash_rings.len() >= MAX_CACHED_RINGS {
+            self.hash_rings.clear();
+        }
+        let ring = Arc::new(HashRing::new(key.1.clone(), virtual_nodes));
+        self.hash_rings.insert(key, ring.clone());
+        ring
+    }
+
+    /// Select backend with the highest score from the configured expression
+    ///
+    /// Backends whose inputs cannot be read, whose score is not finite, or whose
+    /// score is below `threshold` are skipped.
+    fn select_scored(
+        &self,
+        resources: &[DynamicObject],
+        address_path: &str,
+        address_type: Option<&str>,
+        expression: &ScoreExpression,
+        inputs: &HashMap<String, ValueSource>,
+        threshold: Option<f64>,
+    ) -> Result<DynamicObject> {
+        let expr = expression.expr();
+        let mut candidates: Vec<(DynamicObject, String, f64)> = Vec::new();
+
+        for resource in resources {
+            let name = resource.metadata.name.as_deref().unwrap_or("unknown");
+
+            let address =
+                match self
+                    .k8s_client
+                    .extract_address(resource, address_path, address_type)
+                {
+                    Ok(addr) => addr,
+                    Err(e) => {
+                        warn!("Failed to extract address from resource {}: {}", name, e);
+                        continue;
+                    }
+                };
+
+            let mut values: HashMap<&str, f64> = HashMap::new();
+            let mut missing = None;
+            for (input, source) in inputs {
+                match self.read_value(resource, source) {
+                    Ok(Some(v)) => {
+                        values.insert(input.as_str(), v as f64);
+                    }
+                    Ok(None) => {
+                        missing = Some(format!("missing {}", source));
+                        break;
+                    }
+                    Err(e) => {
+                        missing = Some(e.to_string());
+                        break;
+                    }
+                }
+            }
+            if let Some(reason) = missing {
+                warn!("Backend '{}': {}, skipping", name, reason);
+                continue;
+            }
+
+            let sessions = self.session_counts.get(&address).map(|v| *v).unwrap_or(0) as f64;
+            let rtt_ms = self.rtt.get(&address).unwrap_or(0.0);
+            let score = match expr.eval(&|var| match var {
+                "sessions" => Some(sessions),
+                "rtt_ms" => Some(rtt_ms),
+                _ => values.get(var).copied(),
+            }) {
+                Ok(score) => score,
+                Err(e) => {
+                    warn!("Backend '{}': failed to evaluate score: {}", name, e);
+                    continue;
+                }
+            };
+
+            debug!("Backend '{}' ({}): score={}", name, address, score);
+
+            if let Some(min) = threshold {
+                if score < min {
+                    debug!(
+                        "Backend '{}' ({}) is below threshold (score={}, threshold={})",
+                        name, address, score, min
+                    );
+                    continue;
+                }
+            }
+
+            candidates.push((resource.clone(), address, score));
         }
 
-        // Sort by session count (ascending) and select the first
-        backends.sort_by_key(|(_, _, count)| *count);
-        let (selected, address, count) = &backends[0];
+        if candidates.is_empty() {
+            anyhow::bail!(
+                "No backends available with a valid score (checked {} resources)",
+                resources.len()
+            );
+        }
 
-        let name = selected.metadata.name.as_deref().unwrap_or("unknown");
+        // Highest score first, then lower RTT, address as a stable tiebreaker across replicas
+        candidates.sort_by(|a, b| {
+            b.2.total_cmp(&a.2)
+                .then_with(|| self.rtt.compare(&a.1, &b.1))
+                .then_with(|| a.1.cmp(&b.1))
+        });
+        let (selected, address, score) = &candidates[0];
 
-        debug!(
-            "Selected backend '{}' ({}) with {} sessions (least of {} backends)",
-            name,
+        info!(
+            "Selected backend '{}' ({}) with score {} ({} candidates)",
+            selected.metadata.name.as_deref().unwrap_or("unknown"),
             address,
-            count,
-            backends.len()
+            score,
+            candidates.len()
         );
 
         Ok(selected.clone())
     }
 
-    /// Select backend using label-based arithmetic strategy
-    fn select_label_arithmetic(
+    /// Compute remaining capacity for each resource from its current/max sources
+    ///
+    /// Resources without a valid address or max value are skipped. The returned
+    /// candidates are not filtered by capacity.
+    fn collect_capacity_candidates(
         &self,
         resources: &[DynamicObject],
         address_path: &str,
         address_type: Option<&str>,
-        current_label: &str,
-        max_label: &str,
+        sources: &CapacitySources,
         overlap: i64,
-    ) -> Result<DynamicObject> {
+    ) -> Vec<CapacityCandidate> {
         let mut candidates = Vec::new();
 
         for resource in resources {
@@ -200,107 +1475,94 @@ impl LoadBalancer {
                     }
                 };
 
-            // Get labels
-            let labels = resource
-                .metadata
-                .labels
-                .as_ref()
-                .cloned()
-                .unwrap_or_default();
-
-            // Extract current and max values from labels
-            let current_value = match labels.get(current_label) {
-                Some(val) => match val.parse::<i64>() {
-                    Ok(v) => v,
-                    Err(_) => {
-                        warn!(
-                            "Backend '{}': label '{}' is not a valid integer: {}",
-                            name, current_label, val
-                        );
-                        continue;
-                    }
-                },
-                None => {
+            // Extract current and max values
+            let current_value = match self.read_value(resource, &sources.current) {
+                Ok(Some(v)) => v,
+                Ok(None) => {
                     debug!(
-                        "Backend '{}': missing label '{}', assuming 0",
-                        name, current_label
+                        "Backend '{}': missing {}, assuming 0",
+                        name, sources.current
                     );
                     0
                 }
+                Err(e) => {
+                    warn!("Backend '{}': {}", name, e);
+                    continue;
+                }
             };
 
-            let max_value = match labels.get(max_label) {
-                Some(val) => match val.parse::<i64>() {
-                    Ok(v) => v,
-                    Err(_) => {
-                        warn!(
-                            "Backend '{}': label '{}' is not a valid integer: {}",
-                            name, max_label, val
-                        );
-                        continue;
-                    }
-                },
-                None => {
+            let max_value = match self.read_value(resource, &sources.max) {
+                Ok(Some(v)) => v,
+                Ok(None) => {
                     warn!(
-                        "Backend '{}': missing required label '{}', skipping",
-                        name, max_label
+                        "Backend '{}': missing required {}, skipping",
+                        name, sources.max
                     );
                     continue;
                 }
+                Err(e) => {
+                    warn!("Backend '{}': {}", name, e);
+                    continue;
+                }
             };
 
-            // Get session count for this backend
-            let session_count = self.session_counts.get(&address).map(|v| *v).unwrap_or(0) as i64;
+            // Get session count (all replicas) and unclaimed reservations for this backend
+            let session_count = self.session_counts.get(&address).map(|v| *v).unwrap_or(0) as i64
+                + self.remote_sessions(&address);
+            let reserved = self.reserved_slots(&address);
+            let max_value = self.slow_start_max(resource, max_value, Utc::now());
 
-            // Calculate available capacity: max - current - sessions - overlap
-            // This ensures: current + sessions + overlap <= max
-            let available = max_value - current_value - session_count - overlap;
+            // Calculate available capacity: max - current - sessions - reserved - overlap
+            // This ensures: current + sessions + reserved + overlap <= max
+            let available = max_value - current_value - session_count - reserved - overlap;
 
             debug!(
-                "Backend '{}' ({}): current={}, max={}, sessions={}, overlap={}, available={}",
-                name, address, current_value, max_value, session_count, overlap, available
+                "Backend '{}' ({}): current={}, max={}, sessions={}, reserved={}, overlap={}, available={}",
+                name,
+                address,
+                current_value,
+                max_value,
+                session_count,
+                reserved,
+                overlap,
+                available
             );
 
-            // Only consider backends with available capacity
-            if available > 0 {
-                candidates.push((resource.clone(), address, available, current_value));
-            } else {
-                debug!(
-                    "Backend '{}' ({}) is at capacity (available={})",
-                    name, address, available
-                );
-            }
-        }
-
-        if candidates.is_empty() {
-            anyhow::bail!(
-                "No backends available with capacity (checked {} resources). \
-                All backends may be at max capacity or missing required labels '{}' and '{}'",
-                resources.len(),
-                current_label,
-                max_label
-            );
+            candidates.push(CapacityCandidate {
+                resource: resource.clone(),
+                address,
+                available,
+                current: current_value,
+            });
         }
 
-        // Sort by available capacity (descending), then by current load (ascending)
-        candidates.sort_by(|a, b| {
-            b.2.cmp(&a.2) // More available capacity first
-                .then_with(|| a.3.cmp(&b.3)) // Lower current load as tiebreaker
-        });
-
-        let (selected, address, available, current) = &candidates[0];
-        let name = selected.metadata.name.as_deref().unwrap_or("unknown");
+        candidates
+    }
 
-        info!(
-            "Selected backend '{}' ({}) with {} available capacity (current={}, {} candidates)",
-            name,
-            address,
-            available,
-            current,
-            candidates.len()
-        );
+    /// Read an integer value from a resource label, annotation or JSONPath
+    fn read_value(&self, resource: &DynamicObject, source: &ValueSource) -> Result<Option<i64>> {
+        let raw = match source {
+            ValueSource::Label(key) => resource
+                .metadata
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.get(key)),
+            ValueSource::Annotation(key) => resource
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|annotations| annotations.get(key)),
+            ValueSource::JsonPath(path) => {
+                return self.k8s_client.extract_integer(resource, path);
+            }
+        };
 
-        Ok(selected.clone())
+        raw.map(|val| {
+            val.trim()
+                .parse::<i64>()
+                .map_err(|_| anyhow::anyhow!("{} is not a valid integer: {}", source, val))
+        })
+        .transpose()
     }
 
     /// Increment session count for a backend
@@ -361,12 +1623,38 @@ impl LoadBalancer {
     }
 }
 
+/// Hash bytes to a u64 that is identical on every replica and build
+///
+/// FNV-1a followed by the MurmurHash3 finalizer for better avalanche on the ring.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^= hash >> 33;
+    hash
+}
+
 impl Clone for LoadBalancer {
     fn clone(&self) -> Self {
         Self {
             strategy: self.strategy.clone(),
             session_counts: self.session_counts.clone(),
             k8s_client: self.k8s_client.clone(),
+            topology: self.topology.clone(),
+            health: self.health.clone(),
+            rtt: self.rtt.clone(),
+            reservation_ttl: self.reservation_ttl,
+            reservations: self.reservations.clone(),
+            slow_start: self.slow_start.clone(),
+            coordinator: self.coordinator.clone(),
+            events: self.events.clone(),
+            hash_rings: self.hash_rings.clone(),
         }
     }
 }
@@ -377,6 +1665,14 @@ mod tests {
     use serde_json::json;
     use std::collections::HashMap;
 
+    /// Client for a local API server that is never contacted (selection needs no network)
+    fn offline_client() -> K8sClient {
+        K8sClient::from_client(
+            kube::Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap()))
+                .unwrap(),
+        )
+    }
+
     fn create_mock_resource(
         name: &str,
         address: &str,
@@ -402,10 +1698,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_least_sessions_selection() {
-        let k8s_client = match K8sClient::new().await {
-            Ok(c) => c,
-            Err(_) => return, // Skip if not in k8s environment
-        };
+        let k8s_client = offline_client();
 
         let lb = LoadBalancer::new(LoadBalancingStrategy::LeastSessions, k8s_client);
 
@@ -422,27 +1715,40 @@ mod tests {
         lb.increment_session("10.0.0.2");
 
         // Select backend - should pick pod-3 (0 sessions)
-        let selected = lb.select_backend(&resources, "status.podIP", None).unwrap();
+        let selected = lb
+            .select_backend(
+                &resources,
+                "status.podIP",
+                None,
+                &SelectionContext::default(),
+            )
+            .unwrap();
         assert_eq!(selected.metadata.name.as_ref().unwrap(), "pod-3");
 
         // Add session to pod-3, now pod-2 has least
         lb.increment_session("10.0.0.3");
         lb.increment_session("10.0.0.3");
 
-        let selected = lb.select_backend(&resources, "status.podIP", None).unwrap();
+        let selected = lb
+            .select_backend(
+                &resources,
+                "status.podIP",
+                None,
+                &SelectionContext::default(),
+            )
+            .unwrap();
         assert_eq!(selected.metadata.name.as_ref().unwrap(), "pod-2");
     }
 
     #[tokio::test]
     async fn test_label_arithmetic_selection() {
-        let k8s_client = match K8sClient::new().await {
-            Ok(c) => c,
-            Err(_) => return, // Skip if not in k8s environment
-        };
+        let k8s_client = offline_client();
 
         let strategy = LoadBalancingStrategy::LabelArithmetic {
-            current_label: "currentUsers".to_string(),
-            max_label: "maxUsers".to_string(),
+            current_label: Some("currentUsers".to_string()),
+            max_label: Some("maxUsers".to_string()),
+            current: None,
+            max: None,
             overlap: 2,
         };
         let lb = LoadBalancer::new(strategy, k8s_client);
@@ -467,7 +1773,14 @@ mod tests {
         ];
 
         // Select backend - should pick pod-3 (most available: 10-2-0-2=6)
-        let selected = lb.select_backend(&resources, "status.podIP", None).unwrap();
+        let selected = lb
+            .select_backend(
+                &resources,
+                "status.podIP",
+                None,
+                &SelectionContext::default(),
+            )
+            .unwrap();
         assert_eq!(selected.metadata.name.as_ref().unwrap(), "pod-3");
 
         // Add sessions to pod-3
@@ -477,20 +1790,26 @@ mod tests {
         lb.increment_session("10.0.0.3");
 
         // Now pod-1 should be selected (10-5-0-2=3 vs 10-2-4-2=2)
-        let selected = lb.select_backend(&resources, "status.podIP", None).unwrap();
+        let selected = lb
+            .select_backend(
+                &resources,
+                "status.podIP",
+                None,
+                &SelectionContext::default(),
+            )
+            .unwrap();
         assert_eq!(selected.metadata.name.as_ref().unwrap(), "pod-1");
     }
 
     #[tokio::test]
     async fn test_label_arithmetic_at_capacity() {
-        let k8s_client = match K8sClient::new().await {
-            Ok(c) => c,
-            Err(_) => return,
-        };
+        let k8s_client = offline_client();
 
         let strategy = LoadBalancingStrategy::LabelArithmetic {
-            current_label: "currentUsers".to_string(),
-            max_label: "maxUsers".to_string(),
+            current_label: Some("currentUsers".to_string()),
+            max_label: Some("maxUsers".to_string()),
+            current: None,
+            max: None,
             overlap: 1,
         };
         let lb = LoadBalancer::new(strategy, k8s_client);
@@ -503,7 +1822,807 @@ mod tests {
         let resources = vec![create_mock_resource("pod-1", "10.0.0.1", labels)];
 
         // Should fail - no capacity (10-9-0-1=0)
-        let result = lb.select_backend(&resources, "status.podIP", None);
+        let result = lb.select_backend(
+            &resources,
+            "status.podIP",
+            None,
+            &SelectionContext::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    fn capacity_labels(current: &str, max: &str) -> HashMap<String, String> {
+        let mut labels = HashMap::new();
+        labels.insert("currentUsers".to_string(), current.to_string());
+        labels.insert("maxUsers".to_string(), max.to_string());
+        labels
+    }
+
+    #[tokio::test]
+    async fn test_packed_selection() {
+        let k8s_client = offline_client();
+
+        let strategy = LoadBalancingStrategy::Packed {
+            current_label: Some("currentUsers".to_string()),
+            max_label: Some("maxUsers".to_string()),
+            current: None,
+            max: None,
+            overlap: 0,
+            min_headroom: 0,
+        };
+        let lb = LoadBalancer::new(strategy, k8s_client);
+
+        let resources = vec![
+            create_mock_resource("pod-1", "10.0.0.1", capacity_labels("5", "10")),
+            create_mock_resource("pod-2", "10.0.0.2", capacity_labels("8", "10")),
+            create_mock_resource("pod-3", "10.0.0.3", capacity_labels("0", "10")),
+        ];
+
+        // Should pick pod-2 (fullest that still fits: 10-8=2)
+        let ctx = SelectionContext::default();
+        let selected = lb
+            .select_backend(&resources, "status.podIP", None, &ctx)
+            .unwrap();
+        assert_eq!(selected.metadata.name.as_ref().unwrap(), "pod-2");
+
+        // Fill pod-2, now pod-1 is the fullest with room
+        lb.increment_session("10.0.0.2");
+        lb.increment_session("10.0.0.2");
+        let selected = lb
+            .select_backend(&resources, "status.podIP", None, &ctx)
+            .unwrap();
+        assert_eq!(selected.metadata.name.as_ref().unwrap(), "pod-1");
+
+        // A party of 6 only fits on the empty pod-3
+        let party = SelectionContext::with_party_size(6);
+        let selected = lb
+            .select_backend(&resources, "status.podIP", None, &party)
+            .unwrap();
+        assert_eq!(selected.metadata.name.as_ref().unwrap(), "pod-3");
+
+        // A party of 11 fits nowhere
+        let party = SelectionContext::with_party_size(11);
+        assert!(
+            lb.select_backend(&resources, "status.podIP", None, &party)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_packed_min_headroom() {
+        let k8s_client = offline_client();
+
+        let strategy = LoadBalancingStrategy::Packed {
+            current_label: Some("currentUsers".to_string()),
+            max_label: Some("maxUsers".to_string()),
+            current: None,
+            max: None,
+            overlap: 0,
+            min_headroom: 2,
+        };
+        let lb = LoadBalancer::new(strategy, k8s_client);
+
+        let resources = vec![
+            create_mock_resource("pod-1", "10.0.0.1", capacity_labels("8", "10")),
+            create_mock_resource("pod-2", "10.0.0.2", capacity_labels("6", "10")),
+        ];
+
+        // pod-1 has 2 free but must keep 2 free after placement, so pod-2 is used
+        let selected = lb
+            .select_backend(
+                &resources,
+                "status.podIP",
+                None,
+                &SelectionContext::default(),
+            )
+            .unwrap();
+        assert_eq!(selected.metadata.name.as_ref().unwrap(), "pod-2");
+    }
+
+    #[test]
+    fn test_packed_strategy_deserialization() {
+        let yaml = r#"
+type: "packed"
+currentLabel: "currentUsers"
+maxLabel: "maxUsers"
+minHeadroom: 3
+"#;
+        let config: LoadBalancingConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.strategy,
+            LoadBalancingStrategy::Packed {
+                current_label: Some("currentUsers".to_string()),
+                max_label: Some("maxUsers".to_string()),
+                current: None,
+                max: None,
+                overlap: 0,
+                min_headroom: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn test_packed_strategy_validation() {
+        let packed = |overlap: i64, min_headroom: i64| LoadBalancingStrategy::Packed {
+            current_label: Some("currentUsers".to_string()),
+            max_label: Some("maxUsers".to_string()),
+            current: None,
+            max: None,
+            overlap,
+            min_headroom,
+        };
+        assert!(packed(0, 0).validate().is_ok());
+        assert!(packed(2, 3).validate().is_ok());
+        let err = packed(0, -2).validate().unwrap_err();
+        assert!(err.to_string().contains("minHeadroom"), "{}", err);
+        assert!(packed(-1, 0).validate().is_err());
+    }
+
+    #[test]
+    fn test_value_source_deserialization() {
+        let yaml = r#"
+type: "labelArithmetic"
+current:
+  jsonPath: "status.counters.players.count"
+max:
+  annotation: "game.example.com/max-players"
+"#;
+        let config: LoadBalancingConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.strategy,
+            LoadBalancingStrategy::LabelArithmetic {
+                current_label: None,
+                max_label: None,
+                current: Some(ValueSource::JsonPath(
+                    "status.counters.players.count".to_string()
+                )),
+                max: Some(ValueSource::Annotation(
+                    "game.example.com/max-players".to_string()
+                )),
+                overlap: 0,
+            }
+        );
+        assert!(config.strategy.validate().is_ok());
+
+        // Neither a source nor a label for max
+        let yaml = r#"
+type: "packed"
+currentLabel: "currentUsers"
+"#;
+        let config: LoadBalancingConfig = serde_yaml::from_str(yaml).unwrap();
+        let err = config.strategy.validate().unwrap_err().to_string();
+        assert!(err.contains("maxLabel"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_capacity_from_counters_and_annotations() {
+        let k8s_client = offline_client();
+
+        let strategy = LoadBalancingStrategy::LabelArithmetic {
+            current_label: None,
+            max_label: None,
+            current: Some(ValueSource::JsonPath(
+                "status.counters.players.count".to_string(),
+            )),
+            max: Some(ValueSource::Annotation("maxPlayers".to_string())),
+            overlap: 0,
+        };
+        let lb = LoadBalancer::new(strategy, k8s_client);
+
+        let gameserver = |name: &str, address: &str, count: i64, max: &str| -> DynamicObject {
+            serde_json::from_value(json!({
+                "apiVersion": "agones.dev/v1",
+                "kind": "GameServer",
+                "metadata": {
+                    "name": name,
+                    "annotations": { "maxPlayers": max }
+                },
+                "status": {
+                    "address": address,
+                    "counters": { "players": { "count": count, "capacity": 10 } }
+                }
+            }))
+            .unwrap()
+        };
+
+        let resources = vec![
+            gameserver("gs-1", "10.0.0.1", 8, "10"),
+            gameserver("gs-2", "10.0.0.2", 3, "10"),
+            gameserver("gs-3", "10.0.0.3", 0, "not-a-number"),
+        ];
+
+        // gs-2 has the most capacity; gs-3 is skipped because its max is invalid
+        let selected = lb
+            .select_backend(
+                &resources,
+                "status.address",
+                None,
+                &SelectionContext::default(),
+            )
+            .unwrap();
+        assert_eq!(selected.metadata.name.as_deref(), Some("gs-2"));
+
+        // A party of 8 fits nowhere
+        let result = lb.select_backend(
+            &resources,
+            "status.address",
+            None,
+            &SelectionContext::with_party_size(8),
+        );
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_scored_strategy_validation() {
+        let yaml = r#"
+type: "scored"
+expression: "(max - current - sessions) * 2 - rtt_ms / 10"
+inputs:
+  current:
+    jsonPath: "status.players.count"
+  max:
+    label: "maxUsers"
+threshold: 1
+"#;
+        let config: LoadBalancingConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.strategy.validate().is_ok());
+
+        let strategy = |expression: &str, input: &str| LoadBalancingStrategy::Scored {
+            expression: expression.to_string().try_into().unwrap(),
+            inputs: HashMap::from([(input.to_string(), ValueSource::Label("x".to_string()))]),
+            threshold: None,
+        };
+
+        // Undefined input
+        let err = strategy("max - current", "max").validate().unwrap_err();
+        assert!(err.to_string().contains("current"), "{}", err);
+        // Syntax errors are reported when the config is read
+        let err =
+            serde_yaml::from_str::<LoadBalancingConfig>("type: scored\nexpression: \"max -\"")
+                .unwrap_err();
+        assert!(
+            err.to_string().contains("Invalid loadBalancing expression"),
+            "{}",
+            err
+        );
+        // Input shadowing a builtin
+        assert!(strategy("sessions", "sessions").validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scored_selection() {
+        let k8s_client = offline_client();
+
+        let strategy = LoadBalancingStrategy::Scored {
+            expression: "(max - current - sessions) * weight"
+                .to_string()
+                .try_into()
+                .unwrap(),
+            inputs: HashMap::from([
+                (
+                    "current".to_string(),
+                    ValueSource::Label("currentUsers".to_string()),
+                ),
+                (
+                    "max".to_string(),
+                    ValueSource::Label("maxUsers".to_string()),
+                ),
+                (
+                    "weight".to_string(),
+                    ValueSource::Label("weight".to_string()),
+                ),
+            ]),
+            threshold: Some(5.0),
+        };
+        let lb = LoadBalancer::new(strategy, k8s_client);
+
+        let labels = |current: &str, weight: &str| {
+            let mut labels = capacity_labels(current, "10");
+            labels.insert("weight".to_string(), weight.to_string());
+            labels
+        };
+        let resources = vec![
+            // (10 - 2) * 1 = 8
+            create_mock_resource("pod-1", "10.0.0.1", labels("2", "1")),
+            // (10 - 6) * 3 = 12
+            create_mock_resource("pod-2", "10.0.0.2", labels("6", "3")),
+            // Missing weight input - skipped
+            create_mock_resource("pod-3", "10.0.0.3", capacity_labels("0", "10")),
+        ];
+
+        let select = |lb: &LoadBalancer| {
+            lb.select_backend(
+                &resources,
+                "status.podIP",
+                None,
+                &SelectionContext::default(),
+            )
+            .map(|r| r.metadata.name.unwrap())
+        };
+
+        assert_eq!(select(&lb).unwrap(), "pod-2");
+
+        // (10 - 6 - 3) * 3 = 3 is below the threshold, pod-1 wins
+        for _ in 0..3 {
+            lb.increment_session("10.0.0.2");
+        }
+        assert_eq!(select(&lb).unwrap(), "pod-1");
+
+        // (10 - 2 - 4) * 1 = 4 is below the threshold too
+        for _ in 0..4 {
+            lb.increment_session("10.0.0.1");
+        }
+        assert!(select(&lb).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_topology_prefers_local_zone() {
+        let k8s_client = offline_client();
+
+        let config: LoadBalancingConfig = serde_yaml::from_str(
+            r#"
+type: "leastSessions"
+topology:
+  zone: "zone-a"
+  maxLocalSessions: 3
+"#,
+        )
+        .unwrap();
+        let resolver = TopologyResolver::new(config.topology.unwrap(), k8s_client.clone());
+        resolver.set_node_zones(HashMap::from([
+            ("node-a".to_string(), "zone-a".to_string()),
+            ("node-b".to_string(), "zone-b".to_string()),
+        ]));
+        let lb = LoadBalancer::new(config.strategy, k8s_client).with_topology(resolver);
+
+        let pod = |name: &str, address: &str, node: &str| -> DynamicObject {
+            serde_json::from_value(json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": { "name": name },
+                "spec": { "nodeName": node },
+                "status": { "podIP": address }
+            }))
+            .unwrap()
+        };
+        let resources = vec![
+            pod("remote-1", "10.0.1.1", "node-b"),
+            pod("local-1", "10.0.0.1", "node-a"),
+            pod("local-2", "10.0.0.2", "node-a"),
+        ];
+
+        let select = || {
+            lb.select_backend(
+                &resources,
+                "status.podIP",
+                None,
+                &SelectionContext::default(),
+            )
+            .unwrap()
+            .metadata
+            .name
+            .unwrap()
+        };
+
+        // Remote backend is idle, but local backends are preferred
+        lb.increment_session("10.0.0.1");
+        lb.increment_session("10.0.0.2");
+        assert_eq!(select(), "local-1");
+
+        // Local zone reaches maxLocalSessions, spill over to the idle remote backend
+        lb.increment_session("10.0.0.1");
+        assert_eq!(select(), "remote-1");
+    }
+
+    #[tokio::test]
+    async fn test_reservations_disabled_and_expiry() {
+        let k8s_client = offline_client();
+
+        let lb = LoadBalancer::new(LoadBalancingStrategy::LeastSessions, k8s_client.clone());
+        assert!(!lb.reserve("token-a", "10.0.0.1", 4));
+        assert_eq!(lb.reserved_slots("10.0.0.1"), 0);
+
+        let lb = LoadBalancer::new(LoadBalancingStrategy::LeastSessions, k8s_client)
+            .with_reservations(std::time::Duration::from_millis(20));
+        assert!(lb.reserve("token-a", "10.0.0.1", 4));
+        assert_eq!(lb.reserved_slots("10.0.0.1"), 4);
+        tokio::time::sleep(std::time::Duration::from_millis(40)).await;
+        assert_eq!(lb.reserved_slots("10.0.0.1"), 0);
+
+        // A late claim still counts the session
+        lb.claim_reservation("token-a", "10.0.0.1");
+        assert_eq!(lb.get_session_count("10.0.0.1"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reservation_claimed_slot_by_slot() {
+        let lb = LoadBalancer::new(LoadBalancingStrategy::LeastSessions, offline_client())
+            .with_reservations(std::time::Duration::from_secs(30));
+
+        assert!(lb.reserve("party", "10.0.0.1", 3));
+        assert!(lb.reserve("solo", "10.0.0.1", 1));
+        assert!(lb.reserve("other", "10.0.0.2", 2));
+        assert_eq!(lb.reserved_slots("10.0.0.1"), 4);
+
+        // Each member that connects turns one slot into a session
+        lb.claim_reservation("party", "10.0.0.1");
+        assert_eq!(lb.reserved_slots("10.0.0.1"), 3);
+        assert_eq!(lb.get_session_count("10.0.0.1"), 1);
+
+        // A member that leaves before connecting gives back only its own slot
+        lb.release_reservation("party");
+        assert_eq!(lb.reserved_slots("10.0.0.1"), 2);
+
+        lb.claim_reservation("party", "10.0.0.1");
+        lb.claim_reservation("solo", "10.0.0.1");
+        assert_eq!(lb.reserved_slots("10.0.0.1"), 0);
+        assert_eq!(lb.get_session_count("10.0.0.1"), 3);
+        assert_eq!(lb.reserved_slots("10.0.0.2"), 2);
+
+        // Fully claimed reservations are dropped from the backend index
+        assert!(lb.reservations.by_backend.get("10.0.0.1").is_none());
+        assert!(lb.reservations.by_token.get("party").is_none());
+        assert_eq!(
+            lb.reservations().snapshot(),
+            std::collections::BTreeMap::from([("10.0.0.2".to_string(), 2)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_start_ramp() {
+        let k8s_client = offline_client();
+
+        let config: LoadBalancingConfig = serde_yaml::from_str(
+            r#"
+type: "labelArithmetic"
+currentLabel: "currentUsers"
+maxLabel: "maxUsers"
+slowStart:
+  windowSeconds: 100
+  initialPercent: 10
+  readyTimePath: "status.readySince"
+"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        let lb = LoadBalancer::new(config.strategy, k8s_client)
+            .with_slow_start(config.slow_start.unwrap());
+
+        let now = Utc::now();
+        let backend = |ready_seconds_ago: i64| -> DynamicObject {
+            let ready_since = now - k8s_openapi::chrono::TimeDelta::seconds(ready_seconds_ago);
+            serde_json::from_value(json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": { "name": "pod-1" },
+                "status": { "podIP": "10.0.0.1", "readySince": ready_since.to_rfc3339() }
+            }))
+            .unwrap()
+        };
+
+        // Just ready: 10% of 50; halfway: 55% of 50 (rounded up); after the window: full
+        assert_eq!(lb.slow_start_max(&backend(0), 50, now), 5);
+        assert_eq!(lb.slow_start_max(&backend(50), 50, now), 28);
+        assert_eq!(lb.slow_start_max(&backend(500), 50, now), 50);
+
+        // No ready time available: full capacity
+        let mut no_time = backend(0);
+        no_time.data = json!({ "status": { "podIP": "10.0.0.1" } });
+        assert_eq!(lb.slow_start_max(&no_time, 50, now), 50);
+    }
+
+    #[test]
+    fn test_slow_start_requires_capacity_strategy() {
+        let config: LoadBalancingConfig = serde_yaml::from_str(
+            r#"
+type: "leastSessions"
+slowStart: {}
+"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_rtt_tracker_smoothing() {
+        let rtt = RttTracker::default();
+        assert_eq!(rtt.get("10.0.0.1"), None);
+
+        rtt.record("10.0.0.1", std::time::Duration::from_millis(10));
+        assert_eq!(rtt.get("10.0.0.1"), Some(10.0));
+
+        // New samples move the average by RTT_SMOOTHING
+        rtt.record("10.0.0.1", std::time::Duration::from_millis(20));
+        assert!((rtt.get("10.0.0.1").unwrap() - 13.0).abs() < 1e-9);
+
+        // Measured backends sort before unmeasured ones
+        assert_eq!(
+            rtt.compare("10.0.0.1", "10.0.0.2"),
+            std::cmp::Ordering::Less
+        );
+
+        rtt.remove("10.0.0.1");
+        assert_eq!(rtt.get("10.0.0.1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_rtt_breaks_ties() {
+        let k8s_client = offline_client();
+
+        let pod = |name: &str, address: &str| -> DynamicObject {
+            serde_json::from_value(json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": { "name": name },
+                "status": { "podIP": address }
+            }))
+            .unwrap()
+        };
+        let resources = vec![
+            pod("pod-1", "10.0.0.1"),
+            pod("pod-2", "10.0.0.2"),
+            pod("pod-3", "10.0.0.3"),
+        ];
+
+        let lb = LoadBalancer::new(LoadBalancingStrategy::LeastSessions, k8s_client);
+        let rtt = lb.rtt_tracker();
+        rtt.record("10.0.0.1", std::time::Duration::from_millis(40));
+        rtt.record("10.0.0.2", std::time::Duration::from_millis(15));
+
+        let select = || {
+            lb.select_backend(
+                &resources,
+                "status.podIP",
+                None,
+                &SelectionContext::default(),
+            )
+            .unwrap()
+            .metadata
+            .name
+            .unwrap()
+        };
+
+        // Equal sessions: lowest RTT wins
+        assert_eq!(select(), "pod-2");
+
+        // Session count still takes precedence over RTT
+        lb.increment_session("10.0.0.2");
+        assert_eq!(select(), "pod-1");
+        assert_eq!(lb.backend_rtt_ms("10.0.0.2"), Some(15.0));
+    }
+
+    #[tokio::test]
+    async fn test_ejected_backends_skipped() {
+        let k8s_client = offline_client();
+
+        let config: LoadBalancingConfig = serde_yaml::from_str(
+            r#"
+type: "leastSessions"
+outlierDetection:
+  consecutiveFailures: 1
+"#,
+        )
+        .unwrap();
+        let lb = LoadBalancer::new(config.strategy, k8s_client.clone());
+        let health = HealthChecker::new(k8s_client, config.outlier_detection, lb.rtt_tracker());
+        let lb = lb.with_health_checker(health);
+
+        let pod = |name: &str, address: &str| -> DynamicObject {
+            serde_json::from_value(json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": { "name": name },
+                "status": { "podIP": address }
+            }))
+            .unwrap()
+        };
+        let resources = vec![pod("pod-1", "10.0.0.1"), pod("pod-2", "10.0.0.2")];
+
+        let select = || {
+            lb.select_backend(
+                &resources,
+                "status.podIP",
+                None,
+                &SelectionContext::default(),
+            )
+            .unwrap()
+            .metadata
+            .name
+            .unwrap()
+        };
+
+        assert_eq!(select(), "pod-1");
+
+        // Ejected backend is skipped even though it has fewer sessions
+        lb.increment_session("10.0.0.2");
+        lb.record_backend_failure("10.0.0.1");
+        assert_eq!(select(), "pod-2");
+
+        // All backends ejected: fail open and use the strategy over all of them
+        lb.record_backend_failure("10.0.0.2");
+        assert_eq!(select(), "pod-1");
+    }
+
+    #[test]
+    fn test_consistent_hash_strategy_deserialization() {
+        let config: LoadBalancingConfig =
+            serde_yaml::from_str("type: \"consistentHash\"\nkey: \"affinityKey\"\n").unwrap();
+        assert_eq!(
+            config.strategy,
+            LoadBalancingStrategy::ConsistentHash {
+                key: HashKey::AffinityKey,
+                virtual_nodes: 160,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consistent_hash_is_stable() {
+        let k8s_client = offline_client();
+
+        let strategy = LoadBalancingStrategy::ConsistentHash {
+            key: HashKey::ClientIp,
+            virtual_nodes: 160,
+        };
+        let lb_a = LoadBalancer::new(strategy.clone(), k8s_client.clone());
+        let lb_b = LoadBalancer::new(strategy, k8s_client);
+
+        let resources: Vec<DynamicObject> = (1..=5)
+            .map(|i| {
+                create_mock_resource(
+                    &format!("pod-{}", i),
+                    &format!("10.0.0.{}", i),
+                    HashMap::new(),
+                )
+            })
+            .collect();
+
+        // Session counts on one replica must not influence the mapping
+        lb_a.increment_session("10.0.0.1");
+        lb_a.increment_session("10.0.0.2");
+
+        for i in 0..50u8 {
+            let context = SelectionContext {
+                client_ip: Some(IpAddr::from([192, 168, 1, i])),
+                ..SelectionContext::default()
+            };
+            let a = lb_a
+                .select_backend(&resources, "status.podIP", None, &context)
+                .unwrap();
+            let b = lb_b
+                .select_backend(&resources, "status.podIP", None, &context)
+                .unwrap();
+            assert_eq!(a.metadata.name, b.metadata.name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consistent_hash_minimal_disruption() {
+        let k8s_client = offline_client();
+
+        let strategy = LoadBalancingStrategy::ConsistentHash {
+            key: HashKey::AffinityKey,
+            virtual_nodes: 160,
+        };
+        let lb = LoadBalancer::new(strategy, k8s_client);
+
+        let resources: Vec<DynamicObject> = (1..=5)
+            .map(|i| {
+                create_mock_resource(
+                    &format!("pod-{}", i),
+                    &format!("10.0.0.{}", i),
+                    HashMap::new(),
+                )
+            })
+            .collect();
+
+        let select = |resources: &[DynamicObject], key: &str| {
+            let context = SelectionContext {
+                affinity_key: Some(key.to_string()),
+                ..SelectionContext::default()
+            };
+            lb.select_backend(resources, "status.podIP", None, &context)
+                .unwrap()
+                .metadata
+                .name
+                .unwrap()
+        };
+
+        // Remove pod-5: only clients that were on pod-5 may move
+        let keys: Vec<String> = (0..200).map(|i| format!("player-{}", i)).collect();
+        let before: Vec<String> = keys.iter().map(|k| select(&resources, k)).collect();
+        let after: Vec<String> = keys.iter().map(|k| select(&resources[..4], k)).collect();
+
+        for (b, a) in before.iter().zip(after.iter()) {
+            if b != "pod-5" {
+                assert_eq!(a, b);
+            }
+        }
+        assert!(before.iter().any(|b| b == "pod-5"));
+    }
+
+    #[tokio::test]
+    async fn test_consistent_hash_walks_past_ejected_owner() {
+        let k8s_client = offline_client();
+
+        let config: LoadBalancingConfig = serde_yaml::from_str(
+            r#"
+type: "consistentHash"
+key: "affinityKey"
+outlierDetection:
+  consecutiveFailures: 1
+"#,
+        )
+        .unwrap();
+        let lb = LoadBalancer::new(config.strategy, k8s_client.clone());
+        let health = HealthChecker::new(k8s_client, config.outlier_detection, lb.rtt_tracker());
+        let lb = lb.with_health_checker(health);
+
+        let resources: Vec<DynamicObject> = (1..=4)
+            .map(|i| {
+                create_mock_resource(
+                    &format!("pod-{}", i),
+                    &format!("10.0.0.{}", i),
+                    HashMap::new(),
+                )
+            })
+            .collect();
+
+        let select = |resources: &[DynamicObject], key: &str| {
+            let context = SelectionContext {
+                affinity_key: Some(key.to_string()),
+                ..SelectionContext::default()
+            };
+            lb.select_backend(resources, "status.podIP", None, &context)
+                .unwrap()
+                .metadata
+                .name
+                .unwrap()
+        };
+
+        let keys: Vec<String> = (0..100).map(|i| format!("player-{}", i)).collect();
+        let healthy: Vec<String> = keys.iter().map(|k| select(&resources, k)).collect();
+        let without_pod_1: Vec<String> = keys.iter().map(|k| select(&resources[1..], k)).collect();
+
+        // An ejected owner hands its clients to the next node on the same ring,
+        // which is where they would land if the backend were gone entirely
+        lb.record_backend_failure("10.0.0.1");
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(select(&resources, key), without_pod_1[i]);
+            if healthy[i] != "pod-1" {
+                assert_eq!(select(&resources, key), healthy[i]);
+            }
+        }
+
+        // Every backend ejected: fail open to the ring owner
+        for i in 2..=4 {
+            lb.record_backend_failure(&format!("10.0.0.{}", i));
+        }
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(select(&resources, key), healthy[i]);
+        }
+
+        // One ring per distinct backend set, reused across selections
+        assert_eq!(lb.hash_rings.len(), 2);
+    }
+
+    #[test]
+    fn test_consistent_hash_virtual_nodes_bounds() {
+        let strategy = |virtual_nodes| LoadBalancingStrategy::ConsistentHash {
+            key: HashKey::ClientIp,
+            virtual_nodes,
+        };
+        assert!(strategy(1).validate().is_ok());
+        assert!(strategy(MAX_VIRTUAL_NODES).validate().is_ok());
+        assert!(strategy(0).validate().is_err());
+        assert!(strategy(MAX_VIRTUAL_NODES + 1).validate().is_err());
+    }
+
+    #[test]
+    fn test_stable_hash_is_deterministic() {
+        // Fixed value guards against accidental changes that would remap clients
+        // during a rolling upgrade of a multi-replica deployment
+        assert_eq!(stable_hash(b"10.0.0.1"), 0xc879_e56d_dc6c_5c88);
+        assert_ne!(stable_hash(b"10.0.0.1"), stable_hash(b"10.0.0.2"));
+    }
 }
@@ -1,27 +1,63 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use tokio::signal;
 use tracing::{info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod access_list;
+mod admin_server;
 mod config;
+mod coordination;
+mod events;
+mod handshake_cookie;
+mod health_checker;
+mod jwt_auth;
 mod k8s_client;
 mod load_balancer;
 mod metrics;
 mod metrics_server;
 mod proxy;
+mod query_auth;
+mod query_policy;
+mod query_preset;
 mod query_server;
+mod query_tls;
+mod rate_limit;
+mod region;
 mod resource_monitor;
+mod routing_webhook;
+mod score_expr;
 mod session;
+mod session_writeback;
+mod signed_token;
+#[cfg(test)]
+mod test_util;
 mod token_cache;
+mod topology;
+mod traffic_limit;
 
+use access_list::AccessControl;
+use admin_server::AdminServer;
 use config::Config;
+use coordination::SessionCoordinator;
+use events::EventRecorder;
+use handshake_cookie::HandshakeCookie;
+use health_checker::HealthChecker;
+use jwt_auth::JwtVerifier;
 use k8s_client::K8sClient;
 use load_balancer::LoadBalancer;
 use proxy::{DataProxy, DefaultEndpointCacheHandle};
+use query_auth::QueryAuthenticator;
 use query_server::QueryServer;
+use query_tls::QueryTls;
+use rate_limit::RateLimiter;
+use region::RegionRouter;
 use resource_monitor::ResourceMonitor;
+use routing_webhook::RoutingWebhook;
 use session::SessionManager;
+use session_writeback::SessionWriter;
+use signed_token::TokenSigner;
 use token_cache::TokenCache;
+use topology::TopologyResolver;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -58,29 +94,266 @@ async fn main() -> Result<()> {
     verify_default_endpoint(&config, &k8s_client).await;
 
     // Initialize shared state
-    let token_cache = TokenCache::new(config.token_ttl_seconds);
-    let mut session_manager = SessionManager::new(config.session_timeout_seconds);
+    let mut token_cache = TokenCache::new(config.token_ttl_seconds);
+    if let Some(signed_config) = config.signed_tokens.clone() {
+        let signer = TokenSigner::new(signed_config)?;
+        let reloader = signer.clone();
+        tokio::spawn(async move {
+            if let Err(e) = reloader.run().await {
+                warn!("Token key reload error: {}", e);
+            }
+        });
+        token_cache = token_cache.with_signer(signer);
+    }
+    if let Some(policy) = config.token_policy.clone() {
+        token_cache = token_cache.with_policy(policy);
+    }
+    let session_manager = SessionManager::new(config.session_timeout_seconds);
     let default_endpoint_cache = DefaultEndpointCacheHandle::new();
 
-    // Initialize load balancer for session tracking
+    // Optional Kubernetes Events for routing lifecycle transitions
+    let event_recorder = config
+        .events
+        .clone()
+        .map(|events_config| EventRecorder::new(events_config, k8s_client.clone()));
+
+    // Initialize load balancer for session tracking (shared by all components)
     let lb_config = config.get_load_balancing();
-    let load_balancer = LoadBalancer::new(lb_config.strategy, k8s_client.clone());
+    let mut load_balancer = LoadBalancer::new(lb_config.strategy, k8s_client.clone());
+    if let Some(events) = &event_recorder {
+        load_balancer = load_balancer.with_event_recorder(events.clone());
+    }
+
+    // Optional zone preference (node -> zone map refreshed in the background)
+    if let Some(topology_config) = lb_config.topology {
+        let resolver = TopologyResolver::new(topology_config, k8s_client.clone());
+        if let Err(e) = resolver.refresh().await {
+            warn!("Failed to load node zones: {}", e);
+        }
+        load_balancer = load_balancer.with_topology(resolver.clone());
+        tokio::spawn(async move {
+            if let Err(e) = resolver.run().await {
+                warn!("Topology refresh error: {}", e);
+            }
+        });
+    }
+
+    // Optional reservation accounting (held for the token TTL) and slow start
+    if lb_config.reservations {
+        load_balancer = load_balancer
+            .with_reservations(std::time::Duration::from_secs(config.token_ttl_seconds));
+    }
+    if let Some(slow_start) = lb_config.slow_start {
+        load_balancer = load_balancer.with_slow_start(slow_start);
+    }
+
+    // Optional session count sharing with other replicas (via Leases)
+    if let Some(coordination_config) = lb_config.coordination {
+        let coordinator = SessionCoordinator::new(
+            coordination_config,
+            k8s_client.clone(),
+            load_balancer.session_counts(),
+        )
+        .with_reservations(load_balancer.reservations());
+        load_balancer = load_balancer.with_coordinator(coordinator.clone());
+        tokio::spawn(async move {
+            if let Err(e) = coordinator.run().await {
+                warn!("Session coordination error: {}", e);
+            }
+        });
+    }
+
+    // Optional session count writeback to backend resources
+    if let Some(writeback_config) = config.session_writeback.clone() {
+        let writer = SessionWriter::new(
+            writeback_config,
+            k8s_client.clone(),
+            session_manager.clone(),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = writer.run().await {
+                warn!("Session writeback error: {}", e);
+            }
+        });
+    }
+
+    // Optional active health checks and passive outlier ejection
+    let health_checks_configured = config
+        .resource_query_mapping
+        .values()
+        .any(|mapping| mapping.health_check.is_some());
+    if health_checks_configured || lb_config.outlier_detection.is_some() {
+        let mut health_checker = HealthChecker::new(
+            k8s_client.clone(),
+            lb_config.outlier_detection,
+            load_balancer.rtt_tracker(),
+        );
+        if let Some(events) = &event_recorder {
+            health_checker = health_checker.with_event_recorder(events.clone());
+        }
+        load_balancer = load_balancer.with_health_checker(health_checker.clone());
+        tokio::spawn(async move {
+            if let Err(e) = health_checker.run().await {
+                warn!("Health checker error: {}", e);
+            }
+        });
+    }
 
     // Set up cleanup callback to decrement load balancer counts
+    // (sessions that never claimed their reservation were not counted; drop the hold instead)
     let lb_for_callback = load_balancer.clone();
-    session_manager.set_cleanup_callback(std::sync::Arc::new(move |target_ip: &str| {
-        lb_for_callback.decrement_session(target_ip);
+    session_manager.set_cleanup_callback(std::sync::Arc::new(move |session: &session::Session| {
+        match &session.reservation {
+            Some(token) => lb_for_callback.release_reservation(token),
+            None => lb_for_callback.decrement_session(&session.target_ip),
+        }
     }));
 
+    // Feed ICMP errors from session sockets into outlier detection
+    let lb_for_failures = load_balancer.clone();
+    session_manager.set_backend_failure_callback(std::sync::Arc::new(move |target_ip: &str| {
+        lb_for_failures.record_backend_failure(target_ip);
+    }));
+
+    // Optional region routing for queries (loads the GeoIP database if configured)
+    let region_router = match config.region_routing.clone() {
+        Some(region_config) => Some(RegionRouter::new(region_config)?),
+        None => None,
+    };
+
+    // Optional query port authentication (keys reloaded from a mounted Secret)
+    let query_authenticator = match config.query_auth.clone() {
+        Some(auth_config) => {
+            let authenticator = QueryAuthenticator::new(auth_config)?;
+            let reloader = authenticator.clone();
+            tokio::spawn(async move {
+                if let Err(e) = reloader.run().await {
+                    warn!("Query auth key reload error: {}", e);
+                }
+            });
+            Some(authenticator)
+        }
+        None => None,
+    };
+
+    // Optional admin API, authenticated with its own keys or the query auth keyring
+    let admin_server = match config.admin_api.clone() {
+        Some(admin_config) => {
+            let authenticator = match admin_config.auth.clone() {
+                Some(auth_config) => {
+                    let authenticator = QueryAuthenticator::new(auth_config)?;
+                    let reloader = authenticator.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = reloader.run().await {
+                            warn!("Admin auth key reload error: {}", e);
+                        }
+                    });
+                    authenticator
+                }
+                None => query_authenticator
+                    .clone()
+                    .context("adminApi needs adminApi.auth or queryAuth keys")?,
+            };
+            Some(AdminServer::new(
+                admin_config,
+                token_cache.clone(),
+                session_manager.clone(),
+                authenticator,
+            ))
+        }
+        None => None,
+    };
+
+    // Optional player JWT verification (keys reloaded from a mounted file)
+    let jwt_verifier = match config.jwt_auth.clone() {
+        Some(jwt_config) => {
+            let verifier = JwtVerifier::new(jwt_config)?;
+            let reloader = verifier.clone();
+            tokio::spawn(async move {
+                if let Err(e) = reloader.run().await {
+                    warn!("JWT key reload error: {}", e);
+                }
+            });
+            Some(verifier)
+        }
+        None => None,
+    };
+
+    // Optional TLS listener for queries (certificates reloaded from mounted files)
+    let query_tls = match config.query_tls.clone() {
+        Some(tls_config) => {
+            let tls = QueryTls::new(tls_config)?;
+            let reloader = tls.clone();
+            tokio::spawn(async move {
+                if let Err(e) = reloader.run().await {
+                    warn!("Query TLS reload error: {}", e);
+                }
+            });
+            Some(tls)
+        }
+        None => None,
+    };
+
+    // Optional rate limits shared by the query server and data proxy
+    let rate_limiter = config.rate_limits.clone().map(|limits_config| {
+        let limiter = RateLimiter::new(limits_config);
+        let pruner = limiter.clone();
+        tokio::spawn(async move {
+            if let Err(e) = pruner.run().await {
+                warn!("Rate limiter error: {}", e);
+            }
+        });
+        limiter
+    });
+
+    // CIDR access lists, re-read from the config file even when none are configured yet
+    let access_control = AccessControl::new(&config);
+    {
+        let reloader = access_control.clone();
+        tokio::spawn(async move {
+            if let Err(e) = reloader.run().await {
+                warn!("Access list reload error: {}", e);
+            }
+        });
+    }
+
+    // Optional handshake cookie: the query server marks verified clients for the data proxy
+    let handshake_cookie = config
+        .handshake_cookie
+        .clone()
+        .map(HandshakeCookie::new)
+        .transpose()?;
+
     // Start Query Server (Phase 1)
     let query_handle = {
-        let query_server = QueryServer::new(
+        let mut query_server = QueryServer::new(
             config.query_port,
             k8s_client.clone(),
             token_cache.clone(),
             session_manager.clone(),
             config.clone(),
+            load_balancer.clone(),
+            region_router,
         );
+        if let Some(authenticator) = query_authenticator {
+            query_server = query_server.with_authenticator(authenticator);
+        }
+        if let Some(verifier) = jwt_verifier {
+            query_server = query_server.with_jwt_verifier(verifier);
+        }
+        if let Some(tls) = query_tls {
+            query_server = query_server.with_tls(tls);
+        }
+        if let Some(limiter) = rate_limiter.clone() {
+            query_server = query_server.with_rate_limiter(limiter);
+        }
+        query_server = query_server.with_access_control(access_control.clone());
+        if let Some(cookie) = handshake_cookie.clone() {
+            query_server = query_server.with_handshake_cookie(cookie);
+        }
+        if let Some(webhook_config) = config.routing_webhook.clone() {
+            query_server = query_server.with_routing_webhook(RoutingWebhook::new(webhook_config));
+        }
         tokio::spawn(async move {
             if let Err(e) = query_server.run().await {
                 warn!("Query server error: {}", e);
@@ -90,13 +363,21 @@ async fn main() -> Result<()> {
 
     // Start Multi-Port Data Proxy (Phase 2 & 3)
     let proxy_handle = {
-        let data_proxy = DataProxy::new(
+        let mut data_proxy = DataProxy::new(
             token_cache.clone(),
             session_manager.clone(),
             config.clone(),
             k8s_client.clone(),
             default_endpoint_cache.clone(),
+            load_balancer.clone(),
         );
+        if let Some(limiter) = rate_limiter {
+            data_proxy = data_proxy.with_rate_limiter(limiter);
+        }
+        data_proxy = data_proxy.with_access_control(access_control);
+        if let Some(cookie) = handshake_cookie {
+            data_proxy = data_proxy.with_handshake_cookie(cookie);
+        }
         tokio::spawn(async move {
             if let Err(e) = data_proxy.run().await {
                 warn!("Data proxy error: {}", e);
@@ -106,13 +387,16 @@ async fn main() -> Result<()> {
 
     // Start Resource Monitor
     let monitor_handle = {
-        let resource_monitor = ResourceMonitor::new(
+        let mut resource_monitor = ResourceMonitor::new(
             config.clone(),
             k8s_client.clone(),
             session_manager.clone(),
             10, // Check every 10 seconds
             default_endpoint_cache.clone(),
         );
+        if let Some(events) = event_recorder {
+            resource_monitor = resource_monitor.with_event_recorder(events);
+        }
         tokio::spawn(async move {
             if let Err(e) = resource_monitor.run().await {
                 warn!("Resource monitor error: {}", e);
@@ -123,12 +407,20 @@ async fn main() -> Result<()> {
     // Start Metrics Server
     let metrics_handle = {
         tokio::spawn(async move {
-            if let Err(e) = metrics_server::run_metrics_server(9090).await {
+            if let Err(e) = metrics_server::run_metrics_server(metrics_server::METRICS_PORT).await {
                 warn!("Metrics server error: {}", e);
             }
         })
     };
 
+    if let Some(admin_server) = admin_server {
+        tokio::spawn(async move {
+            if let Err(e) = admin_server.run().await {
+                warn!("Admin API error: {}", e);
+            }
+        });
+    }
+
     info!("UDP Director is running");
     info!("Query port: {}", config.query_port);
 
@@ -142,7 +434,7 @@ async fn main() -> Result<()> {
         );
     }
 
-    info!("Metrics port: 9090");
+    info!("Metrics port: {}", metrics_server::METRICS_PORT);
 
     // Wait for shutdown signal or task termination
     tokio::select! {
@@ -205,7 +497,7 @@ async fn verify_default_endpoint(config: &Config, k8s_client: &K8sClient) {
 
     match k8s_client
         .query_resources(
-            &default_endpoint.namespace,
+            &default_endpoint.namespace_scope(),
             mapping,
             status_query.as_ref(),
             default_endpoint.label_selector.as_ref(),
@@ -224,7 +516,7 @@ async fn verify_default_endpoint(config: &Config, k8s_client: &K8sClient) {
 fn log_endpoint_config(endpoint: &config::DefaultEndpoint) {
     info!("=== Default Endpoint Configuration ===");
     info!("  Resource Type: {}", endpoint.resource_type);
-    info!("  Namespace: {}", endpoint.namespace);
+    info!("  Namespaces: {}", endpoint.namespace_scope());
 
     if let Some(labels) = &endpoint.label_selector {
         info!("  Label Selector:");
@@ -328,7 +620,7 @@ fn handle_query_error(
     use tracing::error;
 
     error!("  ✗ Failed to query default endpoint resources: {}", error);
-    error!("  Namespace: {}", endpoint.namespace);
+    error!("  Namespaces: {}", endpoint.namespace_scope());
     error!(
         "  Resource: {}/{}/{}",
         mapping.group, mapping.version, mapping.resource
@@ -95,6 +95,48 @@ lazy_static! {
     )
     .unwrap();
 
+    pub static ref TOKEN_REJECTIONS: IntCounterVec = register_int_counter_vec!(
+        "udp_director_token_rejections_total",
+        "Tokens rejected on redemption",
+        &["reason"] // "unknown", "ip_mismatch", "exhausted"
+    )
+    .unwrap();
+
+    pub static ref RATE_LIMITED: IntCounterVec = register_int_counter_vec!(
+        "udp_director_rate_limited_total",
+        "Requests rejected or dropped by a rate limit",
+        &["limit", "scope"] // limit: "connection", "auth_failure", "query", "reset", "session"; scope: "client", "global"
+    )
+    .unwrap();
+
+    pub static ref TRAFFIC_LIMITED: IntCounterVec = register_int_counter_vec!(
+        "udp_director_traffic_limited_packets_total",
+        "Data packets over a traffic limit",
+        &["port", "direction", "action"] // direction: "upstream", "downstream"; action: "dropped", "shaped"
+    )
+    .unwrap();
+
+    pub static ref TRAFFIC_DROPPED_BYTES: IntCounterVec = register_int_counter_vec!(
+        "udp_director_traffic_dropped_bytes_total",
+        "Bytes of data packets dropped by a traffic limit",
+        &["port", "direction"]
+    )
+    .unwrap();
+
+    pub static ref HANDSHAKE_COOKIES: IntCounterVec = register_int_counter_vec!(
+        "udp_director_handshake_cookies_total",
+        "First packets from UDP sources without a session, by cookie outcome",
+        &["result"] // "challenged", "accepted", "invalid", "too_small"
+    )
+    .unwrap();
+
+    pub static ref ACCESS_DENIED: IntCounterVec = register_int_counter_vec!(
+        "udp_director_access_denied_total",
+        "Connections and packets rejected by CIDR access lists",
+        &["listener", "list"] // listener: "query" or data port; list: "global", "listener"
+    )
+    .unwrap();
+
     // Kubernetes metrics
     pub static ref K8S_QUERIES: IntCounterVec = register_int_counter_vec!(
         "udp_director_k8s_queries_total",
@@ -124,6 +166,63 @@ lazy_static! {
     )
     .unwrap();
 
+    // Load balancing metrics
+    pub static ref TOPOLOGY_SELECTIONS: IntCounterVec = register_int_counter_vec!(
+        "udp_director_topology_selections_total",
+        "Topology-aware backend selections by outcome",
+        &["result"] // "local", "spillover_min_backends", "spillover_max_sessions", "spillover_no_capacity"
+    )
+    .unwrap();
+
+    // Backend health metrics
+    pub static ref BACKEND_EJECTIONS: IntCounterVec = register_int_counter_vec!(
+        "udp_director_backend_ejections_total",
+        "Backends ejected from load balancing",
+        &["reason"] // "active", "passive"
+    )
+    .unwrap();
+
+    pub static ref HEALTH_PROBES: IntCounterVec = register_int_counter_vec!(
+        "udp_director_health_probes_total",
+        "Active backend health probes by result",
+        &["result"] // "success", "failure"
+    )
+    .unwrap();
+
+    pub static ref BACKEND_RTT_MS: GaugeVec = register_gauge_vec!(
+        "udp_director_backend_rtt_ms",
+        "Smoothed round-trip time to each probed backend in milliseconds",
+        &["backend"]
+    )
+    .unwrap();
+
+    pub static ref EJECTED_BACKENDS: IntGauge = register_int_gauge!(
+        "udp_director_ejected_backends",
+        "Number of backends currently ejected"
+    )
+    .unwrap();
+
+    pub static ref K8S_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "udp_director_k8s_events_total",
+        "Kubernetes Events recorded by the director",
+        &["result"] // "published", "suppressed", "failed"
+    )
+    .unwrap();
+
+    pub static ref QUERY_AUTH: IntCounterVec = register_int_counter_vec!(
+        "udp_director_query_auth_total",
+        "Query port authentication attempts",
+        &["result"] // "ok" or the error code, e.g. "auth_invalid"
+    )
+    .unwrap();
+
+    pub static ref ROUTING_WEBHOOK: IntCounterVec = register_int_counter_vec!(
+        "udp_director_routing_webhook_total",
+        "Routing webhook reviews",
+        &["result"] // "allowed", "denied", "failed"
+    )
+    .unwrap();
+
     // Error metrics
     pub static ref ERRORS: IntCounterVec = register_int_counter_vec!(
         "udp_director_errors_total",
@@ -230,6 +329,38 @@ pub fn record_token_cache_access(hit: bool) {
     TOKEN_CACHE_HITS.with_label_values(&[result]).inc();
 }
 
+/// Record a token rejected by the token policy or revocation
+pub fn record_token_rejection(reason: &str) {
+    TOKEN_REJECTIONS.with_label_values(&[reason]).inc();
+}
+
+/// Record a request over a rate limit
+pub fn record_rate_limited(limit: &str, scope: &str) {
+    RATE_LIMITED.with_label_values(&[limit, scope]).inc();
+}
+
+/// Record a data packet that was dropped or delayed by a traffic limit
+pub fn record_traffic_limited(port: &str, direction: &str, action: &str, size: usize) {
+    TRAFFIC_LIMITED
+        .with_label_values(&[port, direction, action])
+        .inc();
+    if action == "dropped" {
+        TRAFFIC_DROPPED_BYTES
+            .with_label_values(&[port, direction])
+            .inc_by(size as u64);
+    }
+}
+
+/// Record a handshake cookie outcome
+pub fn record_handshake_cookie(result: &str) {
+    HANDSHAKE_COOKIES.with_label_values(&[result]).inc();
+}
+
+/// Record a client rejected by an access list
+pub fn record_access_denied(listener: &str, list: &str) {
+    ACCESS_DENIED.with_label_values(&[listener, list]).inc();
+}
+
 /// Record Kubernetes query
 #[allow(dead_code)]
 pub fn record_k8s_query(resource_type: &str, status: &str, duration_seconds: f64) {
@@ -247,6 +378,46 @@ pub fn record_error(error_type: &str, component: &str) {
     ERRORS.with_label_values(&[error_type, component]).inc();
 }
 
+/// Record the outcome of a topology-aware backend selection
+pub fn record_topology_selection(result: &str) {
+    TOPOLOGY_SELECTIONS.with_label_values(&[result]).inc();
+}
+
+/// Record a backend ejection
+pub fn record_backend_ejection(reason: &str) {
+    BACKEND_EJECTIONS.with_label_values(&[reason]).inc();
+}
+
+/// Record an active health probe result
+pub fn record_health_probe(result: &str) {
+    HEALTH_PROBES.with_label_values(&[result]).inc();
+}
+
+/// Record the outcome of a Kubernetes Event
+pub fn record_k8s_event(result: &str) {
+    K8S_EVENTS.with_label_values(&[result]).inc();
+}
+
+/// Record a query authentication attempt
+pub fn record_query_auth(result: &str) {
+    QUERY_AUTH.with_label_values(&[result]).inc();
+}
+
+/// Record a routing webhook review
+pub fn record_routing_webhook(result: &str) {
+    ROUTING_WEBHOOK.with_label_values(&[result]).inc();
+}
+
+/// Update the smoothed RTT of a backend
+pub fn record_backend_rtt(backend: &str, rtt_ms: f64) {
+    BACKEND_RTT_MS.with_label_values(&[backend]).set(rtt_ms);
+}
+
+/// Remove the RTT series of a backend that is no longer probed
+pub fn remove_backend_rtt(backend: &str) {
+    let _ = BACKEND_RTT_MS.remove_label_values(&[backend]);
+}
+
 /// Update default endpoint availability
 #[allow(dead_code)]
 pub fn update_default_endpoint_available(available: bool) {
@@ -284,10 +455,27 @@ mod tests {
         // Test token cache
         record_token_cache_access(true);
         record_token_cache_access(false);
+        record_token_rejection("exhausted");
+        record_rate_limited("query", "client");
+        record_traffic_limited("7777", "upstream", "dropped", 1200);
+        record_handshake_cookie("challenged");
+        record_access_denied("query", "global");
 
         // Test K8s metrics
         record_k8s_query("gameserver", "success", 0.1);
 
+        // Test topology metrics
+        record_topology_selection("local");
+
+        // Test backend health metrics
+        record_backend_ejection("passive");
+        record_health_probe("failure");
+        record_k8s_event("suppressed");
+        record_query_auth("auth_invalid");
+        record_routing_webhook("allowed");
+        record_backend_rtt("10.0.0.1", 12.5);
+        remove_backend_rtt("10.0.0.1");
+
         // Test error recording
         record_error("timeout", "proxy");
 
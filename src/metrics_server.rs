@@ -11,6 +11,9 @@ use tracing::{error, info};
 
 use crate::metrics;
 
+/// Port of the Prometheus metrics listener
+pub const METRICS_PORT: u16 = 9090;
+
 /// Start the metrics HTTP server
 pub async fn run_metrics_server(port: u16) -> Result<()> {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
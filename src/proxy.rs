@@ -6,20 +6,30 @@ use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tokio::sync::RwLock;
 use tracing::{debug, error, info};
 
+use crate::access_list::{AccessControl, Listener};
 use crate::config::{Config, DataPortConfig, Protocol};
+use crate::handshake_cookie::{CookieCheck, HandshakeCookie};
 use crate::k8s_client::K8sClient;
-use crate::load_balancer::LoadBalancer;
-use crate::session::SessionManager;
+use crate::load_balancer::{LoadBalancer, SelectionContext};
+use crate::rate_limit::{LimitKind, RateLimiter};
+use crate::session::{BackendResource, SessionManager};
 use crate::token_cache::TokenCache;
+use crate::traffic_limit::PortTrafficLimiter;
 
-/// Cached default endpoint target with multi-port support
+/// Cached default endpoint candidates
+/// The load balancer picks one of these per new session
 #[derive(Clone, Debug)]
 pub(crate) struct DefaultEndpointCache {
-    address: String,
-    /// Port mappings: (proxy_port, protocol) -> target_port
-    port_mappings: HashMap<(u16, Protocol), u16>,
+    resources: Vec<kube::api::DynamicObject>,
 }
 
+/// Default endpoint target: address, port mappings and the selected resource
+type DefaultTarget = (
+    String,
+    HashMap<(u16, Protocol), u16>,
+    Option<BackendResource>,
+);
+
 /// Data Proxy for Phase 2 & 3 (TCP/UDP with session reset) - Multi-port support
 pub struct DataProxy {
     data_ports: Vec<DataPortConfig>,
@@ -29,6 +39,11 @@ pub struct DataProxy {
     k8s_client: K8sClient,
     default_endpoint_cache: Arc<RwLock<Option<DefaultEndpointCache>>>,
     load_balancer: LoadBalancer,
+    rate_limiter: Option<RateLimiter>,
+    handshake_cookie: Option<HandshakeCookie>,
+    access_control: Option<AccessControl>,
+    /// Traffic limits of UDP ports that configure them
+    traffic_limiters: HashMap<u16, Arc<PortTrafficLimiter>>,
 }
 
 /// Shared cache for default endpoint that can be invalidated
@@ -65,10 +80,20 @@ impl DataProxy {
         config: Config,
         k8s_client: K8sClient,
         cache_handle: DefaultEndpointCacheHandle,
+        load_balancer: LoadBalancer,
     ) -> Self {
         let data_ports = config.get_data_ports();
-        let lb_config = config.get_load_balancing();
-        let load_balancer = LoadBalancer::new(lb_config.strategy, k8s_client.clone());
+        let traffic_limiters = data_ports
+            .iter()
+            .filter(|port_config| port_config.protocol == Protocol::Udp)
+            .filter_map(|port_config| {
+                let limits = port_config.limits.clone()?;
+                Some((
+                    port_config.port,
+                    Arc::new(PortTrafficLimiter::new(port_config.port, limits)),
+                ))
+            })
+            .collect();
 
         Self {
             data_ports,
@@ -78,9 +103,31 @@ impl DataProxy {
             k8s_client,
             default_endpoint_cache: cache_handle.get_cache(),
             load_balancer,
+            rate_limiter: None,
+            handshake_cookie: None,
+            access_control: None,
+            traffic_limiters,
         }
     }
 
+    /// Reject clients outside the global and per-port access lists
+    pub fn with_access_control(mut self, access_control: AccessControl) -> Self {
+        self.access_control = Some(access_control);
+        self
+    }
+
+    /// Require a cookie echo before creating UDP sessions for unknown sources
+    pub fn with_handshake_cookie(mut self, handshake_cookie: HandshakeCookie) -> Self {
+        self.handshake_cookie = Some(handshake_cookie);
+        self
+    }
+
+    /// Limit how fast new default-endpoint sessions are created
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
     /// Run the multi-port data proxy
     pub async fn run(&self) -> Result<()> {
         let mut tasks = vec![];
@@ -124,6 +171,13 @@ impl DataProxy {
         Ok(())
     }
 
+    /// Check the access lists before any session lookup or allocation
+    fn allows(&self, proxy_port: u16, client_addr: SocketAddr) -> bool {
+        self.access_control.as_ref().is_none_or(|access_control| {
+            access_control.allows(Listener::Data(proxy_port), client_addr.ip())
+        })
+    }
+
     /// Run a UDP socket listener
     async fn run_udp_socket(&self, socket: Arc<UdpSocket>, proxy_port: u16) -> Result<()> {
         let mut buffer = vec![0u8; 65535]; // Max UDP packet size
@@ -131,6 +185,9 @@ impl DataProxy {
         loop {
             match socket.recv_from(&mut buffer).await {
                 Ok((len, client_addr)) => {
+                    if !self.allows(proxy_port, client_addr) {
+                        continue;
+                    }
                     let packet_data = buffer[..len].to_vec();
                     let socket_clone = socket.clone();
                     let proxy = self.clone();
@@ -159,6 +216,9 @@ impl DataProxy {
         loop {
             match listener.accept().await {
                 Ok((stream, client_addr)) => {
+                    if !self.allows(proxy_port, client_addr) {
+                        continue;
+                    }
                     let proxy = self.clone();
                     tokio::spawn(async move {
                         if let Err(e) = proxy
@@ -208,7 +268,10 @@ impl DataProxy {
         let session = self.session_manager.get_by_addr(&client_addr);
 
         if session.is_none() {
-            // No session - establish default route
+            // No session - establish default route unless over the session rate limit
+            if !self.allow_new_session(client_addr) {
+                return Ok(());
+            }
             self.establish_default_session(client_addr, proxy_port, Protocol::Tcp)
                 .await?;
         }
@@ -220,9 +283,22 @@ impl DataProxy {
             .ok_or_else(|| anyhow::anyhow!("Failed to establish session for TCP connection"))?;
 
         let target_addr = session.get_target_addr(proxy_port, Protocol::Tcp)?;
+        if session.reservation.is_some() {
+            self.claim_reservation(client_addr);
+        }
 
-        // Connect to target
-        let mut target_stream = TcpStream::connect(target_addr).await?;
+        // Connect to target, feeding the result into outlier detection
+        let target_ip = target_addr.ip().to_string();
+        let mut target_stream = match TcpStream::connect(target_addr).await {
+            Ok(target_stream) => {
+                self.load_balancer.record_backend_success(&target_ip);
+                target_stream
+            }
+            Err(e) => {
+                self.load_balancer.record_backend_failure(&target_ip);
+                return Err(e.into());
+            }
+        };
         info!(
             "TCP connection established: {} -> {}",
             client_addr, target_addr
@@ -260,8 +336,13 @@ impl DataProxy {
         proxy_port: u16,
     ) -> Result<()> {
         // Check if session exists for this client IP
-        if self.session_manager.get_by_addr(&client_addr).is_some() {
-            // Session exists - get or create dedicated socket and forward packet
+        if let Some(session) = self.session_manager.get_by_addr(&client_addr) {
+            // Session exists - the client has connected, count it instead of its reservation
+            if session.reservation.is_some() {
+                self.claim_reservation(client_addr);
+            }
+
+            // Get or create dedicated socket and forward packet
             self.proxy_packet_bidirectional(socket, client_addr, packet_data, proxy_port)
                 .await?;
             self.session_manager.touch_by_addr(&client_addr);
@@ -281,20 +362,57 @@ impl DataProxy {
         &self,
         socket: Arc<UdpSocket>,
         client_addr: SocketAddr,
-        packet_data: Vec<u8>,
+        mut packet_data: Vec<u8>,
         proxy_port: u16,
     ) -> Result<()> {
+        // Prove the source address first, so spoofed packets create no state
+        if let Some(cookie) = &self.handshake_cookie
+            && !cookie.is_verified(&client_addr.ip())
+        {
+            match cookie.check(&packet_data, client_addr) {
+                CookieCheck::Valid(payload_offset) => {
+                    packet_data.drain(..payload_offset);
+                }
+                CookieCheck::Challenge(challenge) => {
+                    socket.send_to(&challenge, client_addr).await?;
+                    return Ok(());
+                }
+                CookieCheck::Drop => return Ok(()),
+            }
+        }
+
+        // Over the session rate limit: drop the packet
+        if !self.allow_new_session(client_addr) {
+            return Ok(());
+        }
+
         // Establish default session
         self.establish_default_session(client_addr, proxy_port, Protocol::Udp)
             .await?;
 
-        // Forward this first packet using bi-directional proxy
-        self.proxy_packet_bidirectional(socket, client_addr, packet_data, proxy_port)
-            .await?;
+        // Forward this first packet using bi-directional proxy (a bare cookie echo has no payload)
+        if !packet_data.is_empty() {
+            self.proxy_packet_bidirectional(socket, client_addr, packet_data, proxy_port)
+                .await?;
+        }
 
         Ok(())
     }
 
+    /// Convert the session's reservation (held since its query) into a counted session
+    fn claim_reservation(&self, client_addr: SocketAddr) {
+        if let Some((token, target_ip)) = self.session_manager.take_reservation(&client_addr) {
+            self.load_balancer.claim_reservation(&token, &target_ip);
+        }
+    }
+
+    /// Take a token from the new-session rate limit
+    fn allow_new_session(&self, client_addr: SocketAddr) -> bool {
+        self.rate_limiter
+            .as_ref()
+            .is_none_or(|limiter| limiter.allow(LimitKind::Session, client_addr.ip()))
+    }
+
     /// Establish a default session for a client
     async fn establish_default_session(
         &self,
@@ -309,62 +427,49 @@ impl DataProxy {
 
         // Check cache first
         let cached_endpoint = self.default_endpoint_cache.read().await;
-        let (target_ip, port_mappings) = if let Some(cache) = cached_endpoint.as_ref() {
-            // Use cached endpoint
-            if cache.port_mappings.contains_key(&(proxy_port, protocol)) {
-                debug!(
-                    "Using cached default endpoint for port {} ({})",
-                    proxy_port, protocol
-                );
-                (cache.address.clone(), cache.port_mappings.clone())
-            } else {
-                // Port not in cache, need to query
-                drop(cached_endpoint);
-                let (address, mappings) = self.query_default_endpoint().await?;
-                if !mappings.contains_key(&(proxy_port, protocol)) {
-                    anyhow::bail!(
-                        "Default endpoint does not support port {} ({})",
-                        proxy_port,
-                        protocol
-                    );
-                }
-                (address, mappings)
-            }
+        let resources = if let Some(cache) = cached_endpoint.as_ref() {
+            debug!(
+                "Using {} cached default endpoint candidate(s)",
+                cache.resources.len()
+            );
+            cache.resources.clone()
         } else {
             // Cache miss - need to query and cache
             drop(cached_endpoint); // Release read lock
 
             debug!("Cache miss, querying for default endpoint");
-            let (address, mappings) = self.query_default_endpoint().await?;
+            let resources = self.query_default_endpoint().await?;
 
             // Cache the result
             let mut cache_write = self.default_endpoint_cache.write().await;
             *cache_write = Some(DefaultEndpointCache {
-                address: address.clone(),
-                port_mappings: mappings.clone(),
+                resources: resources.clone(),
             });
             drop(cache_write);
 
             info!(
-                "Cached default endpoint: {} ({} ports)",
-                address,
-                mappings.len()
+                "Cached default endpoint: {} candidate resource(s)",
+                resources.len()
             );
-
-            if !mappings.contains_key(&(proxy_port, protocol)) {
-                anyhow::bail!(
-                    "Default endpoint does not support port {} ({})",
-                    proxy_port,
-                    protocol
-                );
-            }
-            (address, mappings)
+            resources
         };
 
+        let (target_ip, port_mappings, backend) =
+            self.select_default_target(&resources, client_addr).await?;
+
+        if !port_mappings.contains_key(&(proxy_port, protocol)) {
+            anyhow::bail!(
+                "Default endpoint does not support port {} ({})",
+                proxy_port,
+                protocol
+            );
+        }
+
         // Create multi-port session for default endpoint
         self.session_manager
             .upsert_multi_port(client_addr, target_ip.clone(), port_mappings)
             .await;
+        self.session_manager.set_backend(&client_addr, backend);
 
         // Increment load balancer session count
         self.load_balancer.increment_session(&target_ip);
@@ -377,8 +482,8 @@ impl DataProxy {
         Ok(())
     }
 
-    /// Query Kubernetes for the default endpoint with multi-port support
-    async fn query_default_endpoint(&self) -> Result<(String, HashMap<(u16, Protocol), u16>)> {
+    /// Query Kubernetes for the default endpoint candidates
+    async fn query_default_endpoint(&self) -> Result<Vec<kube::api::DynamicObject>> {
         let default_endpoint = self.config.get_default_endpoint();
 
         let mapping = self
@@ -393,9 +498,9 @@ impl DataProxy {
             })?;
 
         debug!(
-            "Querying for default endpoint: type={}, namespace={}, labels={:?}",
+            "Querying for default endpoint: type={}, {}, labels={:?}",
             default_endpoint.resource_type,
-            default_endpoint.namespace,
+            default_endpoint.namespace_scope(),
             default_endpoint.label_selector
         );
 
@@ -411,7 +516,7 @@ impl DataProxy {
         let resources = self
             .k8s_client
             .query_resources(
-                &default_endpoint.namespace,
+                &default_endpoint.namespace_scope(),
                 mapping,
                 status_query.as_ref(),
                 default_endpoint.label_selector.as_ref(),
@@ -421,27 +526,63 @@ impl DataProxy {
 
         debug!("Query returned {} resources", resources.len());
 
+        self.load_balancer.observe_backends(&resources, mapping);
+
         if resources.is_empty() {
             anyhow::bail!("No matching resources found for default endpoint");
         }
 
+        Ok(resources)
+    }
+
+    /// Select a default endpoint backend for a client with multi-port support
+    async fn select_default_target(
+        &self,
+        resources: &[kube::api::DynamicObject],
+        client_addr: SocketAddr,
+    ) -> Result<DefaultTarget> {
+        let default_endpoint = self.config.get_default_endpoint();
+
+        let mapping = self
+            .config
+            .resource_query_mapping
+            .get(&default_endpoint.resource_type)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown resource type in default_endpoint: {}",
+                    default_endpoint.resource_type
+                )
+            })?;
+
         // Use load balancer to select the best backend
         let address_path = mapping
             .address_path
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("address_path is required for default endpoint"))?;
 
+        let context = SelectionContext {
+            client_ip: Some(client_addr.ip()),
+            ..SelectionContext::default()
+        };
         let selected_resource = self.load_balancer.select_backend(
-            &resources,
+            resources,
             address_path,
             mapping.address_type.as_deref(),
+            &context,
         )?;
-        self.extract_endpoint_target_multi_port(
-            &selected_resource,
-            mapping,
-            &default_endpoint.namespace,
-        )
-        .await
+        let (target_ip, port_mappings) = self
+            .extract_endpoint_target_multi_port(
+                &selected_resource,
+                mapping,
+                selected_resource
+                    .metadata
+                    .namespace
+                    .as_deref()
+                    .unwrap_or_default(),
+            )
+            .await?;
+        let backend = BackendResource::new(&selected_resource, mapping);
+        Ok((target_ip, port_mappings, backend))
     }
 
     /// Extract target address and ports from a resource (multi-port)
@@ -579,24 +720,31 @@ impl DataProxy {
         packet_data: Vec<u8>,
         proxy_port: u16,
     ) -> Result<()> {
-        // Get mutable session to create/get dedicated socket (by IP only)
-        let mut session_ref = self
-            .session_manager
-            .get_mut_by_addr(&client_addr)
-            .ok_or_else(|| anyhow::anyhow!("Session not found for client {}", client_addr.ip()))?;
-
-        // Get target address
-        let target_addr = session_ref.get_target_addr(proxy_port, Protocol::Udp)?;
-
-        // Get or create dedicated socket for this session/port
-        let (session_socket, _client_port) = session_ref
-            .get_or_create_udp_socket(
-                proxy_port,
-                client_addr,
-                proxy_socket.clone(),
-                Arc::new(self.session_manager.clone()),
-            )
-            .await?;
+        let (target_addr, session_socket) = {
+            // Get mutable session to create/get dedicated socket (by IP only)
+            let mut session_ref = self
+                .session_manager
+                .get_mut_by_addr(&client_addr)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Session not found for client {}", client_addr.ip())
+                })?;
+
+            // Get target address
+            let target_addr = session_ref.get_target_addr(proxy_port, Protocol::Udp)?;
+
+            // Get or create dedicated socket for this session/port
+            let (session_socket, _client_port) = session_ref
+                .get_or_create_udp_socket(
+                    proxy_port,
+                    target_addr,
+                    client_addr,
+                    proxy_socket.clone(),
+                    Arc::new(self.session_manager.clone()),
+                    self.traffic_limiters.get(&proxy_port),
+                )
+                .await?;
+            (target_addr, session_socket)
+        };
 
         debug!(
             "Proxying packet via dedicated socket: {} -> {} ({} bytes)",
@@ -605,12 +753,17 @@ impl DataProxy {
             packet_data.len()
         );
 
-        // Send packet to target using dedicated socket
+        // Send packet to target using dedicated socket (connected to the target);
+        // limited sessions queue it, keeping packet order while shaped
         // The receive task is already running to handle responses
-        session_socket
-            .socket()
-            .send_to(&packet_data, target_addr)
-            .await?;
+        if let Err(e) = session_socket.send_upstream(packet_data).await {
+            if e.kind() == std::io::ErrorKind::ConnectionRefused {
+                // Pending ICMP port unreachable from an earlier packet
+                self.load_balancer
+                    .record_backend_failure(&target_addr.ip().to_string());
+            }
+            return Err(e.into());
+        }
 
         Ok(())
     }
@@ -627,6 +780,10 @@ impl Clone for DataProxy {
             k8s_client: self.k8s_client.clone(),
             default_endpoint_cache: self.default_endpoint_cache.clone(),
             load_balancer: self.load_balancer.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            handshake_cookie: self.handshake_cookie.clone(),
+            access_control: self.access_control.clone(),
+            traffic_limiters: self.traffic_limiters.clone(),
         }
     }
 }
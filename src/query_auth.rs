@@ -0,0 +1,563 @@
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::metrics;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Authentication scheme of the `Authorization` header
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AuthMethod {
+    /// `Authorization: ApiKey <keyId>:<secret>`
+    ApiKey,
+    /// `Authorization: HMAC-SHA256 keyId=..,timestamp=..,nonce=..,signature=..`
+    Hmac,
+}
+
+/// Optional authentication of query port requests
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryAuthConfig {
+    /// Directory of key files, one per key ID (a mounted Secret)
+    #[serde(default = "default_keys_dir")]
+    pub keys_dir: String,
+    /// Accepted schemes (default: both)
+    #[serde(default = "default_methods")]
+    pub methods: Vec<AuthMethod>,
+    /// Maximum difference between an HMAC timestamp and the director's clock (default: 300)
+    ///
+    /// Used nonces are remembered per replica, so behind a Service a signed request can
+    /// be replayed once against each other replica within this window.
+    #[serde(default = "default_max_clock_skew_seconds")]
+    pub max_clock_skew_seconds: u64,
+    /// How often keys are re-read from `keysDir` (default: 30)
+    #[serde(default = "default_reload_seconds")]
+    pub reload_seconds: u64,
+}
+
+fn default_keys_dir() -> String {
+    "/var/run/secrets/udp-director/query-keys".to_string()
+}
+
+fn default_methods() -> Vec<AuthMethod> {
+    vec![AuthMethod::ApiKey, AuthMethod::Hmac]
+}
+
+fn default_max_clock_skew_seconds() -> u64 {
+    300
+}
+
+fn default_reload_seconds() -> u64 {
+    30
+}
+
+impl QueryAuthConfig {
+    /// Validate the config; `field` names it in errors (`queryAuth` or `adminApi.auth`)
+    pub fn validate(&self, field: &str) -> Result<()> {
+        if self.methods.is_empty() {
+            anyhow::bail!("{}.methods must not be empty", field);
+        }
+        if !Path::new(&self.keys_dir).is_dir() {
+            anyhow::bail!("{}.keysDir {} is not a directory", field, self.keys_dir);
+        }
+        if self.methods.contains(&AuthMethod::Hmac) && self.max_clock_skew_seconds == 0 {
+            anyhow::bail!("{}.maxClockSkewSeconds must be greater than 0", field);
+        }
+        Ok(())
+    }
+}
+
+/// Why a request was not authenticated
+///
+/// Messages are returned to the client and must not reveal which keys exist.
+#[derive(Debug, Error, PartialEq)]
+pub enum AuthError {
+    #[error("Authentication required")]
+    Missing,
+    #[error("Malformed authentication: {0}")]
+    Malformed(&'static str),
+    #[error("Authentication scheme not accepted")]
+    MethodNotAllowed,
+    #[error("Invalid credentials")]
+    InvalidCredentials,
+    #[error("Request timestamp outside the allowed clock skew")]
+    Expired,
+    #[error("Nonce already used")]
+    Replayed,
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+}
+
+impl AuthError {
+    /// Stable code clients can branch on
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::Missing => "auth_required",
+            AuthError::Malformed(_) | AuthError::MethodNotAllowed => "auth_malformed",
+            AuthError::InvalidCredentials => "auth_invalid",
+            AuthError::Expired => "auth_expired",
+            AuthError::Replayed => "auth_replayed",
+            AuthError::Forbidden(_) => "auth_forbidden",
+        }
+    }
+}
+
+/// Verifies the `Authorization` header of query requests against a rotatable keyring
+#[derive(Clone)]
+pub struct QueryAuthenticator {
+    config: QueryAuthConfig,
+    /// Key ID -> shared secret
+    keys: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+    /// Key ID + nonce -> request timestamp (kept for the clock skew window)
+    nonces: Arc<DashMap<String, u64>>,
+}
+
+impl QueryAuthenticator {
+    /// Create an authenticator and load the initial keys
+    pub fn new(config: QueryAuthConfig) -> Result<Self> {
+        let keys = load_keys(Path::new(&config.keys_dir))?;
+        if keys.is_empty() {
+            warn!(
+                "No query auth keys in {}; all queries will be rejected",
+                config.keys_dir
+            );
+        }
+        info!(
+            "Query authentication enabled with {} key(s) from {}",
+            keys.len(),
+            config.keys_dir
+        );
+        Ok(Self {
+            config,
+            keys: Arc::new(RwLock::new(keys)),
+            nonces: Arc::new(DashMap::new()),
+        })
+    }
+
+    /// Re-read keys periodically so Secret rotation needs no restart
+    pub async fn run(self) -> Result<()> {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.reload_seconds.max(1)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            self.reload();
+            self.prune_nonces(unix_now());
+        }
+    }
+
+    /// Replace the keyring, keeping the old one if the directory cannot be read
+    fn reload(&self) {
+        match load_keys(Path::new(&self.config.keys_dir)) {
+            Ok(keys) => {
+                let mut current = self.keys.write().unwrap_or_else(|e| e.into_inner());
+                if *current != keys {
+                    info!("Reloaded {} query auth key(s)", keys.len());
+                    *current = keys;
+                }
+            }
+            Err(e) => warn!("Failed to reload query auth keys: {:#}", e),
+        }
+    }
+
+    /// Forget nonces whose timestamps can no longer pass the skew check
+    fn prune_nonces(&self, now: u64) {
+        let skew = self.config.max_clock_skew_seconds;
+        self.nonces
+            .retain(|_, timestamp| timestamp.abs_diff(now) <= skew);
+    }
+
+    /// Check a query port request; returns the key ID it was authenticated with
+    pub fn authenticate(
+        &self,
+        authorization: Option<&str>,
+        body: &[u8],
+    ) -> Result<String, AuthError> {
+        Self::record(self.verify(authorization, None, body, unix_now()))
+    }
+
+    /// Check an HTTP request; HMAC signatures also cover `<METHOD>\n<path>`
+    ///
+    /// `path` includes the query string, so a signature is bound to one endpoint and
+    /// its parameters and cannot be replayed against another.
+    pub fn authenticate_request(
+        &self,
+        authorization: Option<&str>,
+        method: &str,
+        path: &str,
+        body: &[u8],
+    ) -> Result<String, AuthError> {
+        let target = format!("{}\n{}", method, path);
+        Self::record(self.verify(authorization, Some(&target), body, unix_now()))
+    }
+
+    fn record(result: Result<String, AuthError>) -> Result<String, AuthError> {
+        match &result {
+            Ok(key_id) => {
+                debug!("Query authenticated with key {}", key_id);
+                metrics::record_query_auth("ok");
+            }
+            Err(e) => metrics::record_query_auth(e.code()),
+        }
+        result
+    }
+
+    fn verify(
+        &self,
+        authorization: Option<&str>,
+        target: Option<&str>,
+        body: &[u8],
+        now: u64,
+    ) -> Result<String, AuthError> {
+        let header = authorization.ok_or(AuthError::Missing)?;
+        let (scheme, credentials) = header
+            .trim()
+            .split_once(' ')
+            .ok_or(AuthError::Malformed("expected '<scheme> <credentials>'"))?;
+
+        let method = if scheme.eq_ignore_ascii_case("ApiKey") {
+            AuthMethod::ApiKey
+        } else if scheme.eq_ignore_ascii_case("HMAC-SHA256") {
+            AuthMethod::Hmac
+        } else {
+            return Err(AuthError::Malformed("unknown scheme"));
+        };
+        if !self.config.methods.contains(&method) {
+            return Err(AuthError::MethodNotAllowed);
+        }
+
+        match method {
+            AuthMethod::ApiKey => self.verify_api_key(credentials.trim()),
+            AuthMethod::Hmac => self.verify_hmac(credentials, target, body, now),
+        }
+    }
+
+    fn verify_api_key(&self, credentials: &str) -> Result<String, AuthError> {
+        let (key_id, secret) = credentials
+            .split_once(':')
+            .ok_or(AuthError::Malformed("expected '<keyId>:<secret>'"))?;
+
+        let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        let expected = keys.get(key_id).ok_or(AuthError::InvalidCredentials)?;
+        if bool::from(expected.as_slice().ct_eq(secret.as_bytes())) {
+            Ok(key_id.to_string())
+        } else {
+            Err(AuthError::InvalidCredentials)
+        }
+    }
+
+    /// Signature is hex HMAC-SHA256 of `<timestamp>\n<nonce>\n<body>`, with
+    /// `<target>\n` (method and path) before the body for HTTP requests
+    fn verify_hmac(
+        &self,
+        credentials: &str,
+        target: Option<&str>,
+        body: &[u8],
+        now: u64,
+    ) -> Result<String, AuthError> {
+        let params: HashMap<&str, &str> = credentials
+            .split(',')
+            .filter_map(|part| part.trim().split_once('='))
+            .collect();
+        let field = |name: &'static str| {
+            params.get(name).copied().ok_or(AuthError::Malformed(
+                "expected keyId, timestamp, nonce and signature",
+            ))
+        };
+        let key_id = field("keyId")?;
+        let nonce = field("nonce")?;
+        let timestamp: u64 = field("timestamp")?
+            .parse()
+            .map_err(|_| AuthError::Malformed("timestamp must be Unix seconds"))?;
+        let signature = hex::decode(field("signature")?)
+            .map_err(|_| AuthError::Malformed("signature must be hex"))?;
+        if nonce.is_empty() {
+            return Err(AuthError::Malformed("empty nonce"));
+        }
+
+        if timestamp.abs_diff(now) > self.config.max_clock_skew_seconds {
+            return Err(AuthError::Expired);
+        }
+
+        {
+            let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+            let secret = keys.get(key_id).ok_or(AuthError::InvalidCredentials)?;
+            let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+            mac.update(timestamp.to_string().as_bytes());
+            mac.update(b"\n");
+            mac.update(nonce.as_bytes());
+            mac.update(b"\n");
+            if let Some(target) = target {
+                mac.update(target.as_bytes());
+                mac.update(b"\n");
+            }
+            mac.update(body);
+            mac.verify_slice(&signature)
+                .map_err(|_| AuthError::InvalidCredentials)?;
+        }
+
+        // Only a correctly signed request may claim its nonce
+        let nonce_key = format!("{}:{}", key_id, nonce);
+        if self.nonces.insert(nonce_key, timestamp).is_some() {
+            return Err(AuthError::Replayed);
+        }
+        Ok(key_id.to_string())
+    }
+}
+
+/// Authentication headers of a query request
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RequestHeaders<'a> {
+    /// `Authorization` header (API key, HMAC or Bearer JWT)
+    pub authorization: Option<&'a str>,
+    /// `X-Player-Token` header, a player JWT sent next to queryAuth credentials
+    pub player_token: Option<&'a str>,
+}
+
+/// Split optional header lines from the JSON body
+///
+/// A request is either a bare JSON body or `Name: value` lines, a blank line and
+/// the body (like HTTP). Returns the authentication headers if present.
+pub fn split_headers(data: &[u8]) -> Result<(RequestHeaders<'_>, &[u8]), AuthError> {
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    if data.get(start) == Some(&b'{') {
+        return Ok((RequestHeaders::default(), data));
+    }
+
+    let (head_len, sep_len) = find_subslice(data, b"\r\n\r\n")
+        .map(|pos| (pos, 4))
+        .or_else(|| find_subslice(data, b"\n\n").map(|pos| (pos, 2)))
+        .ok_or(AuthError::Malformed("headers must end with a blank line"))?;
+    let head = std::str::from_utf8(&data[..head_len])
+        .map_err(|_| AuthError::Malformed("headers must be UTF-8"))?;
+
+    let mut headers = RequestHeaders::default();
+    for line in head.lines() {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(AuthError::Malformed("expected 'Name: value' header lines"))?;
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("authorization") {
+            headers.authorization = Some(value.trim());
+        } else if name.eq_ignore_ascii_case("x-player-token") {
+            headers.player_token = Some(value.trim());
+        }
+    }
+    Ok((headers, &data[head_len + sep_len..]))
+}
+
+fn find_subslice(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Read one key per file; the file name is the key ID
+///
+/// Hidden entries (the `..data` links of Secret volumes) and directories are skipped.
+fn load_keys(dir: &Path) -> Result<HashMap<String, Vec<u8>>> {
+    let mut keys = HashMap::new();
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read query auth keys from {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || !entry.path().is_file() {
+            continue;
+        }
+        let secret = std::fs::read(entry.path())
+            .with_context(|| format!("Failed to read query auth key {}", name))?;
+        let secret = secret.trim_ascii_end().to_vec();
+        if secret.is_empty() {
+            warn!("Skipping empty query auth key {}", name);
+            continue;
+        }
+        keys.insert(name, secret);
+    }
+    Ok(keys)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn authenticator(dir: &Path) -> QueryAuthenticator {
+        std::fs::write(dir.join("client-a"), "s3cret\n").unwrap();
+        QueryAuthenticator::new(QueryAuthConfig {
+            keys_dir: dir.to_string_lossy().to_string(),
+            methods: default_methods(),
+            max_clock_skew_seconds: 300,
+            reload_seconds: 30,
+        })
+        .unwrap()
+    }
+
+    fn sign(secret: &[u8], timestamp: u64, nonce: &str, body: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+        mac.update(format!("{}\n{}\n", timestamp, nonce).as_bytes());
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn test_split_headers() {
+        let body = br#"{"type":"query"}"#;
+        assert_eq!(
+            split_headers(body).unwrap(),
+            (RequestHeaders::default(), &body[..])
+        );
+
+        let request = b"Authorization: ApiKey client-a:s3cret\r\nX-Player-Token: a.b.c\r\nX-Other: 1\r\n\r\n{\"type\":\"query\"}";
+        let (headers, rest) = split_headers(request).unwrap();
+        assert_eq!(headers.authorization, Some("ApiKey client-a:s3cret"));
+        assert_eq!(headers.player_token, Some("a.b.c"));
+        assert_eq!(rest, body);
+
+        assert!(split_headers(b"Authorization: ApiKey x:y\n{}").is_err());
+    }
+
+    #[test]
+    fn test_api_key_and_rotation() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let auth = authenticator(dir);
+
+        assert_eq!(
+            auth.verify(Some("ApiKey client-a:s3cret"), None, b"{}", 0),
+            Ok("client-a".to_string())
+        );
+        assert_eq!(
+            auth.verify(Some("ApiKey client-a:wrong"), None, b"{}", 0),
+            Err(AuthError::InvalidCredentials)
+        );
+        assert_eq!(
+            auth.verify(None, None, b"{}", 0).unwrap_err().code(),
+            "auth_required"
+        );
+
+        // Rotate: add a new key and remove the old one
+        std::fs::write(dir.join("client-b"), "n3w").unwrap();
+        std::fs::remove_file(dir.join("client-a")).unwrap();
+        auth.reload();
+        assert!(
+            auth.verify(Some("ApiKey client-b:n3w"), None, b"{}", 0)
+                .is_ok()
+        );
+        assert!(
+            auth.verify(Some("ApiKey client-a:s3cret"), None, b"{}", 0)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_hmac_replay_and_skew() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let auth = authenticator(dir);
+        let body = br#"{"type":"query","resourceType":"gameserver"}"#;
+        let now = 1_700_000_000;
+
+        let header = |timestamp: u64, nonce: &str, signed_body: &[u8]| {
+            format!(
+                "HMAC-SHA256 keyId=client-a,timestamp={},nonce={},signature={}",
+                timestamp,
+                nonce,
+                sign(b"s3cret", timestamp, nonce, signed_body)
+            )
+        };
+
+        let valid = header(now, "n1", body);
+        assert_eq!(
+            auth.verify(Some(&valid), None, body, now + 10),
+            Ok("client-a".to_string())
+        );
+        assert_eq!(
+            auth.verify(Some(&valid), None, body, now + 10),
+            Err(AuthError::Replayed)
+        );
+
+        // Tampered body
+        let signed_other = header(now, "n2", b"{}");
+        assert_eq!(
+            auth.verify(Some(&signed_other), None, body, now),
+            Err(AuthError::InvalidCredentials)
+        );
+
+        let stale = header(now - 301, "n3", body);
+        assert_eq!(
+            auth.verify(Some(&stale), None, body, now),
+            Err(AuthError::Expired)
+        );
+
+        // A bad signature does not burn the nonce
+        assert!(
+            auth.verify(Some(&header(now, "n2", body)), None, body, now)
+                .is_ok()
+        );
+
+        auth.prune_nonces(now + 1000);
+        assert!(auth.nonces.is_empty());
+    }
+
+    #[test]
+    fn test_hmac_bound_to_method_and_path() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let auth = authenticator(dir);
+        let body = br#"{"backend":"10.0.0.5"}"#;
+        let now = 1_700_000_000;
+
+        let header = |nonce: &str, target: &str| {
+            let mut signed = format!("{}\n", target).into_bytes();
+            signed.extend_from_slice(body);
+            format!(
+                "HMAC-SHA256 keyId=client-a,timestamp={},nonce={},signature={}",
+                now,
+                nonce,
+                sign(b"s3cret", now, nonce, &signed)
+            )
+        };
+        let revoke = "POST\n/admin/tokens/revoke";
+
+        assert!(
+            auth.verify(Some(&header("n1", revoke)), Some(revoke), body, now)
+                .is_ok()
+        );
+        // Signed for another endpoint, or without the target (a query signature)
+        let other = header("n2", "GET\n/admin/sessions?player=p1");
+        assert_eq!(
+            auth.verify(Some(&other), Some(revoke), body, now),
+            Err(AuthError::InvalidCredentials)
+        );
+        let query_signature = format!(
+            "HMAC-SHA256 keyId=client-a,timestamp={},nonce=n3,signature={}",
+            now,
+            sign(b"s3cret", now, "n3", body)
+        );
+        assert_eq!(
+            auth.verify(Some(&query_signature), Some(revoke), body, now),
+            Err(AuthError::InvalidCredentials)
+        );
+        assert!(auth.verify(Some(&query_signature), None, body, now).is_ok());
+    }
+}
@@ -0,0 +1,331 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::k8s_client::NamespaceScope;
+
+/// Server-enforced restrictions on client queries for one resource type
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryPolicy {
+    /// Namespaces clients may query (unrestricted if unset)
+    ///
+    /// Queries without a namespace and `allNamespaces` queries span all of them;
+    /// `namespaceSelector` queries are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_namespaces: Option<Vec<String>>,
+
+    /// Labels always added to the label selector
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub mandatory_label_selector: HashMap<String, String>,
+
+    /// Annotations always added to the annotation selector
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub mandatory_annotation_selector: HashMap<String, String>,
+
+    /// Label keys clients may select on (any if unset, none if empty)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_label_keys: Option<Vec<String>>,
+
+    /// Annotation keys clients may select on (any if unset, none if empty)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_annotation_keys: Option<Vec<String>>,
+
+    /// Whether clients may send a `statusQuery` (default: true)
+    #[serde(default = "default_allow_status_query")]
+    pub allow_status_query: bool,
+}
+
+fn default_allow_status_query() -> bool {
+    true
+}
+
+/// Namespaces and selectors of a query after the policy is applied
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyScope {
+    pub namespace_scope: NamespaceScope,
+    pub label_selector: Option<HashMap<String, String>>,
+    pub annotation_selector: Option<HashMap<String, String>>,
+}
+
+impl QueryPolicy {
+    /// Check a client query and merge in the mandatory selectors
+    ///
+    /// Errors describe the violation and are returned to the client.
+    pub fn apply(
+        &self,
+        namespace_scope: NamespaceScope,
+        label_selector: Option<HashMap<String, String>>,
+        annotation_selector: Option<HashMap<String, String>>,
+        has_status_query: bool,
+    ) -> Result<PolicyScope> {
+        if has_status_query && !self.allow_status_query {
+            bail!("statusQuery is not allowed for this resource type");
+        }
+
+        Ok(PolicyScope {
+            namespace_scope: self.restrict_namespaces(namespace_scope)?,
+            label_selector: merge_selector(
+                SelectorKind::Label,
+                label_selector,
+                &self.mandatory_label_selector,
+                self.allowed_label_keys.as_deref(),
+            )?,
+            annotation_selector: merge_selector(
+                SelectorKind::Annotation,
+                annotation_selector,
+                &self.mandatory_annotation_selector,
+                self.allowed_annotation_keys.as_deref(),
+            )?,
+        })
+    }
+
+    /// Limit the namespaces to `allowedNamespaces`
+    fn restrict_namespaces(&self, scope: NamespaceScope) -> Result<NamespaceScope> {
+        let Some(allowed) = &self.allowed_namespaces else {
+            return Ok(scope);
+        };
+
+        match scope {
+            NamespaceScope::All => Ok(NamespaceScope::Namespaces(allowed.clone())),
+            NamespaceScope::Selector(_) => {
+                bail!("namespaceSelector is not allowed for this resource type")
+            }
+            NamespaceScope::Namespaces(namespaces) if namespaces.is_empty() => {
+                Ok(NamespaceScope::Namespaces(allowed.clone()))
+            }
+            NamespaceScope::Namespaces(namespaces) => {
+                if let Some(denied) = namespaces.iter().find(|ns| !allowed.contains(ns)) {
+                    bail!(
+                        "Namespace '{}' is not allowed (allowed: {})",
+                        denied,
+                        allowed.join(", ")
+                    );
+                }
+                Ok(NamespaceScope::Namespaces(namespaces))
+            }
+        }
+    }
+}
+
+/// Longest label value Kubernetes accepts
+pub const MAX_LABEL_VALUE_LENGTH: usize = 63;
+
+/// Whether `value` is a valid label value, so it cannot add selector terms
+pub fn is_valid_label_value(value: &str) -> bool {
+    value.len() <= MAX_LABEL_VALUE_LENGTH
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Whether `key` is made of label key characters (optional `prefix/`)
+fn is_valid_label_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+}
+
+/// Which selector is being merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectorKind {
+    /// Sent to the API server as a label selector
+    Label,
+    /// Matched by the director against resource annotations
+    Annotation,
+}
+
+impl std::fmt::Display for SelectorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SelectorKind::Label => "label",
+            SelectorKind::Annotation => "annotation",
+        })
+    }
+}
+
+/// Check client selector entries and add the mandatory entries
+///
+/// A client may repeat a mandatory entry with the same value, but not change it.
+/// Client keys must be valid key syntax. Label values must be valid label values: the
+/// label selector is sent to the API server as `k=v,...`, so a `,` or `=` would select
+/// on keys that are not allowed. Annotation values are compared locally and may hold
+/// anything annotations can.
+pub fn merge_selector(
+    kind: SelectorKind,
+    client: Option<HashMap<String, String>>,
+    mandatory: &HashMap<String, String>,
+    allowed_keys: Option<&[String]>,
+) -> Result<Option<HashMap<String, String>>> {
+    let mut merged = client.unwrap_or_default();
+
+    let mut keys: Vec<&String> = merged.keys().collect();
+    keys.sort();
+    for key in keys {
+        match mandatory.get(key) {
+            Some(required) if merged[key] != *required => {
+                bail!("{} '{}' is fixed by server policy", kind, key);
+            }
+            Some(_) => {}
+            None => {
+                if !is_valid_label_key(key) {
+                    bail!("{} selector key '{}' is not a valid key", kind, key);
+                }
+                if allowed_keys.is_some_and(|allowed| !allowed.contains(key)) {
+                    bail!("{} selector key '{}' is not allowed", kind, key);
+                }
+                if kind == SelectorKind::Label && !is_valid_label_value(&merged[key]) {
+                    bail!(
+                        "{} '{}' must be at most {} characters of [A-Za-z0-9._-]",
+                        kind,
+                        key,
+                        MAX_LABEL_VALUE_LENGTH
+                    );
+                }
+            }
+        }
+    }
+
+    merged.extend(mandatory.iter().map(|(k, v)| (k.clone(), v.clone())));
+    Ok((!merged.is_empty()).then_some(merged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> QueryPolicy {
+        serde_yaml::from_str(
+            r#"
+allowedNamespaces: ["team-a", "team-b"]
+mandatoryLabelSelector:
+  agones.dev/fleet: public
+allowedLabelKeys: ["map"]
+allowedAnnotationKeys: []
+allowStatusQuery: false
+"#,
+        )
+        .unwrap()
+    }
+
+    fn labels(entries: &[(&str, &str)]) -> Option<HashMap<String, String>> {
+        Some(
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_policy_merges_mandatory_selectors() {
+        let scope = policy()
+            .apply(
+                NamespaceScope::Namespaces(vec!["team-a".to_string()]),
+                labels(&[("map", "de_dust2"), ("agones.dev/fleet", "public")]),
+                None,
+                false,
+            )
+            .unwrap();
+        assert_eq!(
+            scope.label_selector,
+            labels(&[("map", "de_dust2"), ("agones.dev/fleet", "public")])
+        );
+        assert_eq!(scope.annotation_selector, None);
+
+        // No namespace or all namespaces span the allowed ones
+        let allowed = NamespaceScope::Namespaces(vec!["team-a".to_string(), "team-b".to_string()]);
+        let scope = policy()
+            .apply(NamespaceScope::All, None, None, false)
+            .unwrap();
+        assert_eq!(scope.namespace_scope, allowed);
+        assert_eq!(
+            scope.label_selector,
+            labels(&[("agones.dev/fleet", "public")])
+        );
+    }
+
+    #[test]
+    fn test_policy_violations() {
+        let policy = policy();
+        let team_a = || NamespaceScope::Namespaces(vec!["team-a".to_string()]);
+
+        let err = policy
+            .apply(
+                NamespaceScope::Namespaces(vec!["kube-system".to_string()]),
+                None,
+                None,
+                false,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("'kube-system' is not allowed"));
+
+        let selector = NamespaceScope::Selector(HashMap::new());
+        assert!(policy.apply(selector, None, None, false).is_err());
+
+        let err = policy
+            .apply(
+                team_a(),
+                labels(&[("agones.dev/fleet", "internal")]),
+                None,
+                false,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("fixed by server policy"));
+
+        let err = policy
+            .apply(team_a(), labels(&[("tier", "premium")]), None, false)
+            .unwrap_err();
+        assert!(err.to_string().contains("'tier' is not allowed"));
+
+        assert!(
+            policy
+                .apply(team_a(), None, labels(&[("currentPlayers", "0")]), false)
+                .is_err()
+        );
+
+        // Values cannot smuggle in selector terms on keys that are not allowed
+        let err = policy
+            .apply(
+                team_a(),
+                labels(&[("map", "de_dust2,tier=premium")]),
+                None,
+                false,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("label 'map' must be"));
+        // Annotation values are matched locally, so any value is fine
+        assert_eq!(
+            merge_selector(
+                SelectorKind::Annotation,
+                labels(&[("notes", "a=b, see https://example.com")]),
+                &HashMap::new(),
+                None
+            )
+            .unwrap(),
+            labels(&[("notes", "a=b, see https://example.com")])
+        );
+        assert!(
+            merge_selector(
+                SelectorKind::Annotation,
+                labels(&[("notes,tier", "x")]),
+                &HashMap::new(),
+                None
+            )
+            .is_err()
+        );
+        assert!(
+            merge_selector(
+                SelectorKind::Label,
+                labels(&[("map,tier", "premium")]),
+                &HashMap::new(),
+                None
+            )
+            .is_err()
+        );
+
+        let err = policy.apply(team_a(), None, None, true).unwrap_err();
+        assert!(err.to_string().contains("statusQuery"));
+    }
+}
@@ -0,0 +1,325 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::config::StatusQueryConfig;
+use crate::k8s_client::NamespaceScope;
+use crate::load_balancer::LoadBalancingStrategy;
+use crate::query_policy::{MAX_LABEL_VALUE_LENGTH, is_valid_label_value};
+
+/// Named query a client selects by intent (e.g. `ranked-eu`) instead of raw selectors
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryPreset {
+    /// Resource type from `resourceQueryMapping`
+    pub resource_type: String,
+
+    /// Namespace to search in
+    #[serde(default)]
+    pub namespace: String,
+
+    /// Additional namespaces to search in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespaces: Option<Vec<String>>,
+
+    /// Search all namespaces whose labels match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace_selector: Option<HashMap<String, String>>,
+
+    /// Search every namespace
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub all_namespaces: bool,
+
+    /// Label selector; values may contain `{param}` placeholders
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label_selector: Option<HashMap<String, String>>,
+
+    /// Annotation selector; values may contain `{param}` placeholders
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotation_selector: Option<HashMap<String, String>>,
+
+    /// Status query for filtering resources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_query: Option<StatusQueryConfig>,
+
+    /// Load balancing strategy for this preset (default: the global strategy)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_balancing: Option<LoadBalancingStrategy>,
+
+    /// Parameters clients may send; anything else is rejected
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub params: HashMap<String, PresetParam>,
+}
+
+/// A whitelisted preset parameter
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresetParam {
+    /// Value used when the client omits the parameter (required if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+
+    /// Values clients may send (any valid label value if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_values: Option<Vec<String>>,
+}
+
+/// A preset with its parameters substituted
+#[derive(Debug, Clone)]
+pub struct ExpandedPreset {
+    pub resource_type: String,
+    pub namespace_scope: NamespaceScope,
+    pub label_selector: Option<HashMap<String, String>>,
+    pub annotation_selector: Option<HashMap<String, String>>,
+    pub status_query: Option<StatusQueryConfig>,
+    pub load_balancing: Option<LoadBalancingStrategy>,
+}
+
+impl QueryPreset {
+    /// Namespaces the preset spans
+    pub fn namespace_scope(&self) -> NamespaceScope {
+        NamespaceScope::from_fields(
+            &self.namespace,
+            self.namespaces.as_deref(),
+            self.namespace_selector.as_ref(),
+            self.all_namespaces,
+        )
+    }
+
+    /// Check that every placeholder names a declared parameter and the strategy is valid
+    pub fn validate(&self) -> Result<()> {
+        for selector in [&self.label_selector, &self.annotation_selector]
+            .into_iter()
+            .flatten()
+        {
+            for template in selector.values() {
+                for name in placeholders(template)? {
+                    if !self.params.contains_key(name) {
+                        bail!("placeholder '{{{}}}' is not a declared param", name);
+                    }
+                }
+            }
+        }
+
+        for (name, param) in &self.params {
+            if let Some(default) = &param.default {
+                param
+                    .check(default)
+                    .with_context(|| format!("Invalid default for param '{}'", name))?;
+            }
+        }
+
+        if let Some(strategy) = &self.load_balancing {
+            strategy.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Substitute client parameters into the selector templates
+    ///
+    /// Errors describe the offending parameter and are returned to the client.
+    pub fn expand(&self, params: &HashMap<String, String>) -> Result<ExpandedPreset> {
+        let mut names: Vec<&String> = params.keys().collect();
+        names.sort();
+        if let Some(unknown) = names.into_iter().find(|n| !self.params.contains_key(*n)) {
+            bail!("Unknown param '{}'", unknown);
+        }
+
+        let mut values: HashMap<&str, &str> = HashMap::new();
+        for (name, param) in &self.params {
+            let value = match (params.get(name), &param.default) {
+                (Some(value), _) => {
+                    param
+                        .check(value)
+                        .with_context(|| format!("Invalid value for param '{}'", name))?;
+                    value
+                }
+                (None, Some(default)) => default,
+                (None, None) => bail!("Missing param '{}'", name),
+            };
+            values.insert(name, value);
+        }
+
+        Ok(ExpandedPreset {
+            resource_type: self.resource_type.clone(),
+            namespace_scope: self.namespace_scope(),
+            label_selector: substitute(&self.label_selector, &values)?,
+            annotation_selector: substitute(&self.annotation_selector, &values)?,
+            status_query: self.status_query.clone(),
+            load_balancing: self.load_balancing.clone(),
+        })
+    }
+}
+
+impl PresetParam {
+    /// Values must be allowed and safe to place in a label selector
+    fn check(&self, value: &str) -> Result<()> {
+        if let Some(allowed) = &self.allowed_values
+            && !allowed.iter().any(|a| a == value)
+        {
+            bail!("'{}' is not one of {}", value, allowed.join(", "));
+        }
+        if !is_valid_label_value(value) {
+            bail!(
+                "'{}' must be at most {} characters of [A-Za-z0-9._-]",
+                value,
+                MAX_LABEL_VALUE_LENGTH
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Names of the `{param}` placeholders in a template
+fn placeholders(template: &str) -> Result<Vec<&str>> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            bail!("Unclosed placeholder in '{}'", template);
+        };
+        names.push(&rest[start + 1..start + len]);
+        rest = &rest[start + len + 1..];
+    }
+    Ok(names)
+}
+
+/// Replace every `{param}` in the selector values
+fn substitute(
+    selector: &Option<HashMap<String, String>>,
+    values: &HashMap<&str, &str>,
+) -> Result<Option<HashMap<String, String>>> {
+    let Some(selector) = selector else {
+        return Ok(None);
+    };
+
+    let mut expanded = HashMap::new();
+    for (key, template) in selector {
+        let mut value = template.clone();
+        for name in placeholders(template)? {
+            let replacement = values
+                .get(name)
+                .with_context(|| format!("Undeclared param '{}'", name))?;
+            value = value.replace(&format!("{{{}}}", name), replacement);
+        }
+        expanded.insert(key.clone(), value);
+    }
+    Ok(Some(expanded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset() -> QueryPreset {
+        serde_yaml::from_str(
+            r#"
+resourceType: gameserver
+namespace: ranked
+labelSelector:
+  agones.dev/fleet: "ranked-{region}"
+  map: "{map}"
+statusQuery:
+  jsonPath: status.state
+  expectedValues: ["Ready"]
+loadBalancing:
+  type: packed
+  currentLabel: players
+  maxLabel: maxPlayers
+params:
+  map: {}
+  region:
+    default: eu
+    allowedValues: [eu, us]
+"#,
+        )
+        .unwrap()
+    }
+
+    fn params(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_preset_expansion() {
+        let preset = preset();
+        assert!(preset.validate().is_ok());
+
+        let expanded = preset.expand(&params(&[("map", "de_dust2")])).unwrap();
+        assert_eq!(expanded.resource_type, "gameserver");
+        assert_eq!(
+            expanded.namespace_scope,
+            NamespaceScope::Namespaces(vec!["ranked".to_string()])
+        );
+        let labels = expanded.label_selector.unwrap();
+        assert_eq!(labels["agones.dev/fleet"], "ranked-eu");
+        assert_eq!(labels["map"], "de_dust2");
+        assert!(expanded.status_query.is_some());
+        assert!(matches!(
+            expanded.load_balancing,
+            Some(LoadBalancingStrategy::Packed { .. })
+        ));
+
+        let expanded = preset
+            .expand(&params(&[("map", "inferno"), ("region", "us")]))
+            .unwrap();
+        assert_eq!(
+            expanded.label_selector.unwrap()["agones.dev/fleet"],
+            "ranked-us"
+        );
+    }
+
+    #[test]
+    fn test_preset_param_errors() {
+        let preset = preset();
+
+        let err = preset.expand(&params(&[])).unwrap_err();
+        assert!(err.to_string().contains("Missing param 'map'"));
+
+        let err = preset
+            .expand(&params(&[("map", "x"), ("tier", "gold")]))
+            .unwrap_err();
+        assert!(err.to_string().contains("Unknown param 'tier'"));
+
+        let err = preset
+            .expand(&params(&[("map", "x"), ("region", "ap")]))
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("not one of eu, us"));
+
+        // Selector injection is rejected
+        assert!(preset.expand(&params(&[("map", "x,tier=gold")])).is_err());
+
+        let mut undeclared = preset.clone();
+        undeclared
+            .label_selector
+            .as_mut()
+            .unwrap()
+            .insert("mode".to_string(), "{mode}".to_string());
+        assert!(undeclared.validate().is_err());
+    }
+
+    #[test]
+    fn test_preset_validation_errors() {
+        let mut unclosed = preset();
+        unclosed
+            .label_selector
+            .as_mut()
+            .unwrap()
+            .insert("mode".to_string(), "{map".to_string());
+        let err = unclosed.validate().unwrap_err();
+        assert!(err.to_string().contains("Unclosed placeholder"));
+
+        // Defaults must pass the same checks as client values
+        let mut bad_default = preset();
+        bad_default.params.get_mut("region").unwrap().default = Some("ap".to_string());
+        let err = bad_default.validate().unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid default for param 'region'"));
+
+        let too_long = "x".repeat(MAX_LABEL_VALUE_LENGTH + 1);
+        let err = preset().expand(&params(&[("map", &too_long)])).unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid value for param 'map'"));
+    }
+}
@@ -1,26 +1,67 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
 use tracing::{debug, error, info};
 
-use crate::config::Config;
-use crate::k8s_client::{K8sClient, StatusQuery};
-use crate::session::SessionManager;
+use crate::access_list::{AccessControl, Listener};
+use crate::config::{Config, ResourceScope};
+use crate::handshake_cookie::HandshakeCookie;
+use crate::jwt_auth::{self, JwtIdentity, JwtVerifier};
+use crate::k8s_client::{K8sClient, NamespaceScope, StatusQuery};
+use crate::load_balancer::{LoadBalancer, LoadBalancingStrategy, SelectionContext};
+use crate::metrics;
+use crate::query_auth::{self, QueryAuthenticator};
+use crate::query_policy::{self, SelectorKind};
+use crate::query_tls::QueryTls;
+use crate::rate_limit::{LimitKind, RateLimiter};
+use crate::region::{RegionPreference, RegionRouter};
+use crate::routing_webhook::{RoutingWebhook, WebhookCandidate, WebhookDecision, WebhookRequest};
+use crate::session::{BackendResource, SessionManager};
 use crate::token_cache::{TokenCache, TokenTarget};
 
 /// Query request from client
 #[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase", tag = "type")]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "type"
+)]
 pub enum QueryRequest {
     /// Query for a resource and establish a session
     Query {
+        /// Resource type from `resourceQueryMapping` (not used with `preset`)
+        #[serde(default, alias = "resource_type")]
         resource_type: String,
+        /// Namespace to search (optional for cluster-scoped resources)
+        #[serde(default)]
         namespace: String,
+        /// Additional namespaces to search
+        namespaces: Option<Vec<String>>,
+        /// Search all namespaces whose labels match
+        namespace_selector: Option<HashMap<String, String>>,
+        /// Search every namespace
+        all_namespaces: Option<bool>,
+        // snake_case aliases keep clients of releases up to 0.2.1 working
+        #[serde(alias = "status_query")]
         status_query: Option<StatusQueryDto>,
+        #[serde(alias = "label_selector")]
         label_selector: Option<HashMap<String, String>>,
+        #[serde(alias = "annotation_selector")]
         annotation_selector: Option<HashMap<String, String>>,
+        /// Number of players that must fit on the selected backend (default: 1)
+        party_size: Option<u32>,
+        /// Stable client identity (e.g. player ID) for consistent hashing
+        affinity_key: Option<String>,
+        /// Regions to try in order of preference
+        preferred_regions: Option<Vec<String>>,
+        /// Measured client latency (ms) per region; lowest is tried first
+        region_latencies: Option<HashMap<String, f64>>,
+        /// Named query preset from config (replaces resource type, namespaces and selectors)
+        preset: Option<String>,
+        /// Parameters substituted into the preset's selectors
+        params: Option<HashMap<String, String>>,
     },
     /// Reset an existing session with a new token
     SessionReset { token: String },
@@ -40,15 +81,55 @@ pub struct StatusQueryDto {
 pub enum QueryResponse {
     Success {
         token: String,
+        /// Namespace of the selected backend (absent for cluster-scoped resources)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        namespace: Option<String>,
+        /// Region of the selected backend (when region routing is enabled)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        region: Option<String>,
     },
     SuccessMultiPort {
         token: String,
         address: String,
         ports: HashMap<String, u16>,
+        /// Namespace of the selected backend (absent for cluster-scoped resources)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        namespace: Option<String>,
+        /// Region of the selected backend (when region routing is enabled)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        region: Option<String>,
+        /// Smoothed RTT from the director to the backend (when health checks measure it)
+        #[serde(rename = "rttMs", skip_serializing_if = "Option::is_none")]
+        rtt_ms: Option<f64>,
     },
     Error {
         error: String,
     },
+    /// Refused before processing; `code` identifies the reason (e.g. `auth_required`)
+    Rejected {
+        error: String,
+        code: &'static str,
+    },
+}
+
+/// Connections that do not finish the TLS handshake in time are dropped
+const TLS_HANDSHAKE_TIMEOUT_SECONDS: u64 = 10;
+
+/// Largest accepted query request (optional headers and JSON body)
+const MAX_QUERY_REQUEST_BYTES: usize = 64 * 1024;
+
+/// How long a client has to send a complete request; what arrived by then is processed
+const QUERY_READ_TIMEOUT_SECONDS: u64 = 10;
+
+/// A query resolved from client fields or a preset
+struct ResourceQuery {
+    resource_type: String,
+    namespace_scope: NamespaceScope,
+    status_query: Option<StatusQuery>,
+    label_selector: Option<HashMap<String, String>>,
+    annotation_selector: Option<HashMap<String, String>>,
+    /// Strategy overriding the global one (presets)
+    strategy: Option<LoadBalancingStrategy>,
 }
 
 /// TCP Query Server (Phase 1)
@@ -59,6 +140,15 @@ pub struct QueryServer {
     token_cache: TokenCache,
     session_manager: SessionManager,
     config: Config,
+    load_balancer: LoadBalancer,
+    region_router: Option<RegionRouter>,
+    authenticator: Option<QueryAuthenticator>,
+    jwt_verifier: Option<JwtVerifier>,
+    routing_webhook: Option<RoutingWebhook>,
+    tls: Option<QueryTls>,
+    rate_limiter: Option<RateLimiter>,
+    handshake_cookie: Option<HandshakeCookie>,
+    access_control: Option<AccessControl>,
 }
 
 impl QueryServer {
@@ -69,6 +159,8 @@ impl QueryServer {
         token_cache: TokenCache,
         session_manager: SessionManager,
         config: Config,
+        load_balancer: LoadBalancer,
+        region_router: Option<RegionRouter>,
     ) -> Self {
         Self {
             port,
@@ -76,11 +168,71 @@ impl QueryServer {
             token_cache,
             session_manager,
             config,
+            load_balancer,
+            region_router,
+            authenticator: None,
+            jwt_verifier: None,
+            routing_webhook: None,
+            tls: None,
+            rate_limiter: None,
+            handshake_cookie: None,
+            access_control: None,
         }
     }
 
-    /// Run the query server
+    /// Require authenticated requests
+    pub fn with_authenticator(mut self, authenticator: QueryAuthenticator) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// Accept player JWTs (`Authorization: Bearer` or `X-Player-Token`) and apply their claims
+    pub fn with_jwt_verifier(mut self, verifier: JwtVerifier) -> Self {
+        self.jwt_verifier = Some(verifier);
+        self
+    }
+
+    /// Consult an external webhook before selecting a backend
+    pub fn with_routing_webhook(mut self, webhook: RoutingWebhook) -> Self {
+        self.routing_webhook = Some(webhook);
+        self
+    }
+
+    /// Also serve queries over TLS (optionally instead of plaintext)
+    pub fn with_tls(mut self, tls: QueryTls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Limit queries and session resets per client and globally
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Let clients with a successful query skip the data-port handshake cookie
+    pub fn with_handshake_cookie(mut self, handshake_cookie: HandshakeCookie) -> Self {
+        self.handshake_cookie = Some(handshake_cookie);
+        self
+    }
+
+    /// Reject clients outside the query and global access lists
+    pub fn with_access_control(mut self, access_control: AccessControl) -> Self {
+        self.access_control = Some(access_control);
+        self
+    }
+
+    /// Run the query server (plaintext, TLS or both)
     pub async fn run(&self) -> Result<()> {
+        match &self.tls {
+            Some(tls) if tls.disable_plaintext() => self.run_tls(tls).await,
+            Some(tls) => tokio::try_join!(self.run_plaintext(), self.run_tls(tls)).map(|_| ()),
+            None => self.run_plaintext().await,
+        }
+    }
+
+    /// Accept plaintext connections on `queryPort`
+    async fn run_plaintext(&self) -> Result<()> {
         let listener = TcpListener::bind(format!("0.0.0.0:{}", self.port))
             .await
             .with_context(|| format!("Failed to bind query server to port {}", self.port))?;
@@ -90,10 +242,13 @@ impl QueryServer {
         loop {
             match listener.accept().await {
                 Ok((stream, addr)) => {
+                    if !self.allows(addr) {
+                        continue;
+                    }
                     debug!("New query connection from {}", addr);
                     let server = self.clone();
                     tokio::spawn(async move {
-                        if let Err(e) = server.handle_connection(stream).await {
+                        if let Err(e) = server.handle_connection(stream, addr).await {
                             error!("Error handling query connection: {}", e);
                         }
                     });
@@ -105,71 +260,341 @@ impl QueryServer {
         }
     }
 
-    /// Handle a single query connection
-    async fn handle_connection(&self, mut stream: TcpStream) -> Result<()> {
-        // Get client address for session establishment
-        let client_addr = stream.peer_addr()?;
-
-        // Read the JSON payload
-        let mut buffer = vec![0u8; 4096];
-        let n = stream
-            .read(&mut buffer)
+    /// Accept TLS connections on the TLS port
+    async fn run_tls(&self, tls: &QueryTls) -> Result<()> {
+        let listener = TcpListener::bind(format!("0.0.0.0:{}", tls.port()))
             .await
-            .context("Failed to read from stream")?;
+            .with_context(|| format!("Failed to bind query TLS listener to port {}", tls.port()))?;
 
-        if n == 0 {
+        info!("Query server listening on port {} (TLS)", tls.port());
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    if !self.allows(addr) {
+                        continue;
+                    }
+                    debug!("New TLS query connection from {}", addr);
+                    let server = self.clone();
+                    let acceptor = tls.acceptor();
+                    tokio::spawn(async move {
+                        let handshake = tokio::time::timeout(
+                            std::time::Duration::from_secs(TLS_HANDSHAKE_TIMEOUT_SECONDS),
+                            acceptor.accept(stream),
+                        )
+                        .await;
+                        let stream = match handshake {
+                            Ok(Ok(stream)) => stream,
+                            Ok(Err(e)) => {
+                                debug!("TLS handshake with {} failed: {}", addr, e);
+                                metrics::record_error("tls_handshake", "query_server");
+                                return;
+                            }
+                            Err(_) => {
+                                debug!("TLS handshake with {} timed out", addr);
+                                metrics::record_error("tls_handshake", "query_server");
+                                return;
+                            }
+                        };
+                        if let Err(e) = server.handle_connection(stream, addr).await {
+                            error!("Error handling TLS query connection: {}", e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    error!("Failed to accept TLS connection: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Check the access lists before reading anything from the client
+    fn allows(&self, client_addr: std::net::SocketAddr) -> bool {
+        self.access_control
+            .as_ref()
+            .is_none_or(|access_control| access_control.allows(Listener::Query, client_addr.ip()))
+    }
+
+    /// Handle a single query connection
+    async fn handle_connection<S>(
+        &self,
+        mut stream: S,
+        client_addr: std::net::SocketAddr,
+    ) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        // Taken before reading so unauthenticated floods cost no auth work
+        let admitted = self.rate_limit_allows(LimitKind::Connection, client_addr);
+
+        // Read the request until EOF, a complete JSON body, the size cap or the timeout
+        let mut data = Vec::new();
+        match tokio::time::timeout(
+            std::time::Duration::from_secs(QUERY_READ_TIMEOUT_SECONDS),
+            read_request(&mut stream, &mut data),
+        )
+        .await
+        {
+            Ok(result) => result.context("Failed to read from stream")?,
+            Err(_) => debug!("Timed out reading query from {}", client_addr),
+        }
+
+        if data.is_empty() {
             return Ok(());
         }
 
-        let request_data = &buffer[..n];
-        let request: QueryRequest = match serde_json::from_slice(request_data) {
-            Ok(req) => req,
-            Err(e) => {
-                let response = QueryResponse::Error {
-                    error: format!("Invalid JSON: {}", e),
-                };
-                let response_json = serde_json::to_string(&response)?;
-                stream.write_all(response_json.as_bytes()).await?;
-                return Ok(());
+        let response = if !admitted || self.auth_failures_exhausted(client_addr) {
+            rate_limited()
+        } else if data.len() > MAX_QUERY_REQUEST_BYTES {
+            QueryResponse::Error {
+                error: format!("Request exceeds {} bytes", MAX_QUERY_REQUEST_BYTES),
+            }
+        } else {
+            match self.authenticate(&data, client_addr) {
+                Ok((request_data, identity)) => match serde_json::from_slice(request_data) {
+                    Ok(request) => {
+                        debug!("Received query: {:?}", request);
+                        if self.allow_request(&request, client_addr) {
+                            // Process the query and establish session
+                            self.process_query(request, client_addr, identity).await
+                        } else {
+                            rate_limited()
+                        }
+                    }
+                    Err(e) => QueryResponse::Error {
+                        error: format!("Invalid JSON: {}", e),
+                    },
+                },
+                Err(response) => {
+                    // Each failure spends a token of the auth failure limit
+                    self.rate_limit_allows(LimitKind::AuthFailure, client_addr);
+                    response
+                }
             }
         };
-
-        debug!("Received query: {:?}", request);
-
-        // Process the query and establish session
-        let response = self.process_query(request, client_addr).await;
+        if let Some(cookie) = &self.handshake_cookie
+            && matches!(
+                response,
+                QueryResponse::Success { .. } | QueryResponse::SuccessMultiPort { .. }
+            )
+        {
+            cookie.mark_verified(client_addr.ip()).await;
+        }
         let response_json = serde_json::to_string(&response)?;
 
-        // Send response
+        // Send response; shutdown also sends the TLS close_notify
         stream.write_all(response_json.as_bytes()).await?;
         stream.flush().await?;
+        let _ = stream.shutdown().await;
 
         Ok(())
     }
 
+    /// Take a token from the rate limit matching the request type
+    fn allow_request(&self, request: &QueryRequest, client_addr: std::net::SocketAddr) -> bool {
+        let kind = match request {
+            QueryRequest::Query { .. } => LimitKind::Query,
+            QueryRequest::SessionReset { .. } => LimitKind::Reset,
+        };
+        self.rate_limit_allows(kind, client_addr)
+    }
+
+    /// Take a token from a rate limit; true without rate limits
+    fn rate_limit_allows(&self, kind: LimitKind, client_addr: std::net::SocketAddr) -> bool {
+        self.rate_limiter
+            .as_ref()
+            .is_none_or(|limiter| limiter.allow(kind, client_addr.ip()))
+    }
+
+    /// Whether the client used up its failed authentications
+    fn auth_failures_exhausted(&self, client_addr: std::net::SocketAddr) -> bool {
+        self.rate_limiter
+            .as_ref()
+            .is_some_and(|limiter| limiter.exhausted(LimitKind::AuthFailure, client_addr.ip()))
+    }
+
+    /// Strip optional headers and check the credentials if auth is enabled
+    ///
+    /// With both queryAuth and jwtAuth, the `Authorization` header carries the
+    /// API key / HMAC signature and the player JWT goes in `X-Player-Token`,
+    /// unless `jwtAuth.replacesQueryAuth` lets a Bearer token stand in for both.
+    #[allow(clippy::result_large_err)]
+    fn authenticate<'a>(
+        &self,
+        data: &'a [u8],
+        client_addr: std::net::SocketAddr,
+    ) -> Result<(&'a [u8], Option<JwtIdentity>), QueryResponse> {
+        let rejected = |e: query_auth::AuthError| {
+            info!("Rejected unauthenticated query from {}: {}", client_addr, e);
+            QueryResponse::Rejected {
+                error: e.to_string(),
+                code: e.code(),
+            }
+        };
+
+        if self.authenticator.is_none() && self.jwt_verifier.is_none() {
+            // Unparseable input is reported as invalid JSON when auth is off
+            let body = query_auth::split_headers(data).map_or(data, |(_, body)| body);
+            return Ok((body, None));
+        }
+        let (headers, body) = query_auth::split_headers(data).map_err(rejected)?;
+        let bearer = headers.authorization.and_then(jwt_auth::bearer_token);
+
+        if let Some(authenticator) = &self.authenticator {
+            let replaced = bearer.is_some()
+                && self
+                    .jwt_verifier
+                    .as_ref()
+                    .is_some_and(JwtVerifier::replaces_query_auth);
+            if !replaced {
+                authenticator
+                    .authenticate(headers.authorization, body)
+                    .map_err(rejected)?;
+            }
+        }
+
+        let Some(verifier) = &self.jwt_verifier else {
+            return Ok((body, None));
+        };
+        match headers.player_token.or(bearer) {
+            Some(token) => {
+                let identity = verifier.verify(token).map_err(rejected)?;
+                Ok((body, Some(identity)))
+            }
+            None if verifier.required() => Err(rejected(query_auth::AuthError::Missing)),
+            None => Ok((body, None)),
+        }
+    }
+
+    /// Merge the selectors a player's token mandates into the query
+    #[allow(clippy::result_large_err)]
+    fn apply_identity(
+        &self,
+        mut query: ResourceQuery,
+        identity: &JwtIdentity,
+        client_addr: std::net::SocketAddr,
+    ) -> Result<ResourceQuery, QueryResponse> {
+        let rejected = |e: anyhow::Error| {
+            info!(
+                "Rejected {} query from {}: {}",
+                query.resource_type, client_addr, e
+            );
+            metrics::record_error("policy_violation", "query_server");
+            QueryResponse::Error {
+                error: format!("Query not allowed: {}", e),
+            }
+        };
+
+        let label_selector = query_policy::merge_selector(
+            SelectorKind::Label,
+            query.label_selector.clone(),
+            &identity.label_selector,
+            None,
+        )
+        .map_err(rejected)?;
+        let annotation_selector = query_policy::merge_selector(
+            SelectorKind::Annotation,
+            query.annotation_selector.clone(),
+            &identity.annotation_selector,
+            None,
+        )
+        .map_err(rejected)?;
+
+        query.label_selector = label_selector;
+        query.annotation_selector = annotation_selector;
+        Ok(query)
+    }
+
     /// Process a query request and establish session for client
     async fn process_query(
         &self,
         request: QueryRequest,
         client_addr: std::net::SocketAddr,
+        identity: Option<JwtIdentity>,
     ) -> QueryResponse {
         match request {
             QueryRequest::Query {
                 resource_type,
                 namespace,
+                namespaces,
+                namespace_selector,
+                all_namespaces,
                 status_query,
                 label_selector,
                 annotation_selector,
+                party_size,
+                affinity_key,
+                preferred_regions,
+                region_latencies,
+                preset,
+                params,
             } => {
-                self.process_resource_query(
-                    resource_type,
-                    namespace,
-                    status_query,
-                    label_selector,
-                    annotation_selector,
-                    client_addr,
-                )
-                .await
+                let query = match preset {
+                    Some(preset) => {
+                        let raw_fields_set = !resource_type.is_empty()
+                            || !namespace.is_empty()
+                            || namespaces.is_some()
+                            || namespace_selector.is_some()
+                            || all_namespaces.is_some()
+                            || status_query.is_some()
+                            || label_selector.is_some()
+                            || annotation_selector.is_some();
+                        if raw_fields_set {
+                            return QueryResponse::Error {
+                                error: "preset queries cannot set resourceType, namespaces, selectors or statusQuery".to_string(),
+                            };
+                        }
+                        self.expand_preset(&preset, &params.unwrap_or_default(), client_addr)
+                    }
+                    None if params.is_some() => Err(QueryResponse::Error {
+                        error: "params requires a preset".to_string(),
+                    }),
+                    None => self.resolve_client_query(
+                        resource_type,
+                        NamespaceScope::from_fields(
+                            &namespace,
+                            namespaces.as_deref(),
+                            namespace_selector.as_ref(),
+                            all_namespaces.unwrap_or(false),
+                        ),
+                        status_query,
+                        label_selector,
+                        annotation_selector,
+                        client_addr,
+                    ),
+                };
+                let query = match (query, &identity) {
+                    (Ok(query), Some(identity)) => {
+                        match self.apply_identity(query, identity, client_addr) {
+                            Ok(query) => query,
+                            Err(e) => return e,
+                        }
+                    }
+                    (Ok(query), None) => query,
+                    (Err(e), _) => return e,
+                };
+
+                let context = SelectionContext {
+                    client_ip: Some(client_addr.ip()),
+                    affinity_key,
+                    ..party_size
+                        .map(SelectionContext::with_party_size)
+                        .unwrap_or_default()
+                };
+                let region_order = self
+                    .region_router
+                    .as_ref()
+                    .map(|router| {
+                        let preference = RegionPreference {
+                            preferred_regions,
+                            region_latencies,
+                        };
+                        router.region_order(&preference, client_addr.ip())
+                    })
+                    .unwrap_or_default();
+                let player_id = identity.and_then(|identity| identity.player_id);
+                self.process_resource_query(query, &context, &region_order, client_addr, player_id)
+                    .await
             }
             QueryRequest::SessionReset { token } => {
                 self.process_session_reset(token, client_addr).await
@@ -184,8 +609,18 @@ impl QueryServer {
         client_addr: std::net::SocketAddr,
     ) -> QueryResponse {
         // Look up the token
-        match self.token_cache.lookup(&token).await {
+        match self.token_cache.lookup(&token, client_addr.ip()).await {
             Some(target) => {
+                // Settle the client's own unclaimed slot before its session is replaced,
+                // counting it so the replaced session's cleanup balances out
+                let mut own_slot = false;
+                if let Some((held, held_target)) =
+                    self.session_manager.take_reservation(&client_addr)
+                {
+                    self.load_balancer.claim_reservation(&held, &held_target);
+                    own_slot = held == token;
+                }
+
                 // Valid token - update session
                 self.session_manager
                     .upsert_multi_port(
@@ -194,13 +629,31 @@ impl QueryServer {
                         target.port_mappings.clone(),
                     )
                     .await;
+                self.session_manager
+                    .set_player_id(&client_addr, target.player_id.clone());
+                self.session_manager
+                    .set_metadata(&client_addr, target.metadata.clone());
+                self.session_manager
+                    .set_backend(&client_addr, target.backend.clone());
+                // The reset session is counted now, using one of the token's reserved
+                // slots (a party member joining) unless the client already used its own
+                if own_slot {
+                    self.load_balancer.increment_session(&target.cluster_ip);
+                } else {
+                    self.load_balancer
+                        .claim_reservation(&token, &target.cluster_ip);
+                }
                 info!(
                     "Session reset via query port: {} -> {} ({} ports)",
                     client_addr,
                     target.cluster_ip,
                     target.port_mappings.len()
                 );
-                QueryResponse::Success { token }
+                QueryResponse::Success {
+                    token,
+                    namespace: None,
+                    region: None,
+                }
             }
             None => QueryResponse::Error {
                 error: "Invalid or expired token".to_string(),
@@ -208,16 +661,125 @@ impl QueryServer {
         }
     }
 
-    /// Process a resource query request
-    async fn process_resource_query(
+    /// Apply the mapping's query policy to the raw fields of a client query
+    #[allow(clippy::result_large_err)]
+    fn resolve_client_query(
         &self,
         resource_type: String,
-        namespace: String,
+        namespace_scope: NamespaceScope,
         status_query: Option<StatusQueryDto>,
         label_selector: Option<HashMap<String, String>>,
         annotation_selector: Option<HashMap<String, String>>,
         client_addr: std::net::SocketAddr,
+    ) -> Result<ResourceQuery, QueryResponse> {
+        let policy = self
+            .config
+            .resource_query_mapping
+            .get(&resource_type)
+            .and_then(|mapping| mapping.query_policy.as_ref());
+
+        // Enforce the mapping's query policy before touching the Kubernetes API
+        let (namespace_scope, label_selector, annotation_selector) = match policy {
+            Some(policy) => {
+                let scope = policy
+                    .apply(
+                        namespace_scope,
+                        label_selector,
+                        annotation_selector,
+                        status_query.is_some(),
+                    )
+                    .map_err(|e| {
+                        info!(
+                            "Rejected {} query from {}: {}",
+                            resource_type, client_addr, e
+                        );
+                        metrics::record_error("policy_violation", "query_server");
+                        QueryResponse::Error {
+                            error: format!("Query not allowed: {}", e),
+                        }
+                    })?;
+                (
+                    scope.namespace_scope,
+                    scope.label_selector,
+                    scope.annotation_selector,
+                )
+            }
+            None => (namespace_scope, label_selector, annotation_selector),
+        };
+
+        Ok(ResourceQuery {
+            resource_type,
+            namespace_scope,
+            status_query: status_query.map(|sq| StatusQuery {
+                json_path: sq.json_path,
+                expected_values: sq.expected_values,
+            }),
+            label_selector,
+            annotation_selector,
+            strategy: None,
+        })
+    }
+
+    /// Expand a named preset with the client's parameters
+    ///
+    /// Presets are operator-defined, so the mapping's query policy does not apply.
+    #[allow(clippy::result_large_err)]
+    fn expand_preset(
+        &self,
+        name: &str,
+        params: &HashMap<String, String>,
+        client_addr: std::net::SocketAddr,
+    ) -> Result<ResourceQuery, QueryResponse> {
+        let preset = self
+            .config
+            .query_presets
+            .get(name)
+            .ok_or_else(|| QueryResponse::Error {
+                error: format!("Unknown preset: {}", name),
+            })?;
+
+        let expanded = preset.expand(params).map_err(|e| {
+            info!(
+                "Rejected preset {} query from {}: {:#}",
+                name, client_addr, e
+            );
+            QueryResponse::Error {
+                error: format!("Invalid params for preset {}: {:#}", name, e),
+            }
+        })?;
+        debug!("Expanded preset {}: {:?}", name, expanded);
+
+        Ok(ResourceQuery {
+            resource_type: expanded.resource_type,
+            namespace_scope: expanded.namespace_scope,
+            status_query: expanded.status_query.map(|sq| StatusQuery {
+                json_path: sq.json_path,
+                expected_values: sq.expected_values,
+            }),
+            label_selector: expanded.label_selector,
+            annotation_selector: expanded.annotation_selector,
+            strategy: expanded.load_balancing,
+        })
+    }
+
+    /// Process a resource query request
+    async fn process_resource_query(
+        &self,
+        query: ResourceQuery,
+        context: &SelectionContext,
+        region_order: &[String],
+        client_addr: std::net::SocketAddr,
+        player_id: Option<String>,
     ) -> QueryResponse {
+        let ResourceQuery {
+            resource_type,
+            namespace_scope,
+            status_query,
+            label_selector,
+            annotation_selector,
+            strategy,
+        } = query;
+
         let mapping = match self.config.resource_query_mapping.get(&resource_type) {
             Some(m) => m,
             None => {
@@ -227,19 +789,21 @@ impl QueryServer {
             }
         };
 
-        let status_query_obj = status_query.as_ref().map(|sq| StatusQuery {
-            json_path: sq.json_path.clone(),
-            expected_values: sq.expected_values.clone(),
-        });
+        if mapping.scope == ResourceScope::Namespaced && namespace_scope.is_empty() {
+            return QueryResponse::Error {
+                error: "namespace, namespaces, namespaceSelector or allNamespaces is required"
+                    .to_string(),
+            };
+        }
 
         let resources = match self
             .query_k8s_resources(
                 &resource_type,
-                &namespace,
+                &namespace_scope,
                 &label_selector,
                 &annotation_selector,
                 mapping,
-                status_query_obj.as_ref(),
+                status_query.as_ref(),
             )
             .await
         {
@@ -247,23 +811,85 @@ impl QueryServer {
             Err(e) => return e,
         };
 
-        let selected_resource = &resources[0];
+        // Presets may select with their own strategy (sharing session state)
+        let preset_balancer;
+        let load_balancer = match strategy {
+            Some(strategy) => {
+                preset_balancer = self.load_balancer.clone().with_strategy(strategy);
+                &preset_balancer
+            }
+            None => &self.load_balancer,
+        };
+
+        // The routing webhook may deny the query or pick the backend itself
+        let (chosen, metadata) = match &self.routing_webhook {
+            Some(webhook) if webhook.applies_to(&resource_type) => {
+                let request = WebhookRequest {
+                    client_address: client_addr.to_string(),
+                    player_id: player_id.clone(),
+                    resource_type: resource_type.clone(),
+                    label_selector: label_selector.clone(),
+                    annotation_selector: annotation_selector.clone(),
+                    party_size: context.party_size,
+                    affinity_key: context.affinity_key.clone(),
+                    candidates: resources
+                        .iter()
+                        .map(WebhookCandidate::from_resource)
+                        .collect(),
+                };
+                match self.review_with_webhook(webhook, &request).await {
+                    Ok(decision) => decision,
+                    Err(e) => return e,
+                }
+            }
+            _ => (None, HashMap::new()),
+        };
+
+        let (selected_resource, region) = match chosen {
+            Some(index) => {
+                let resource = resources[index].clone();
+                let region = self
+                    .region_router
+                    .as_ref()
+                    .and_then(|router| router.backend_region(&resource));
+                (resource, region)
+            }
+            None => {
+                match self.select_resource(
+                    load_balancer,
+                    &resources,
+                    mapping,
+                    context,
+                    region_order,
+                ) {
+                    Ok(res) => res,
+                    Err(e) => return e,
+                }
+            }
+        };
         let resource_name = selected_resource
             .metadata
             .name
             .clone()
             .unwrap_or_else(|| "unknown".to_string());
 
-        debug!("Selected resource: {}", resource_name);
+        let namespace = selected_resource.metadata.namespace.clone();
+        let backend = BackendResource::new(&selected_resource, mapping);
+
+        debug!(
+            "Selected resource: {} (namespace: {})",
+            resource_name,
+            namespace.as_deref().unwrap_or("-")
+        );
 
         // Check if multi-port configuration is available
         if mapping.ports.is_some() {
             // Multi-port approach
             let (cluster_ip, ports_map) = match self
                 .extract_multi_port_target_info(
-                    selected_resource,
+                    &selected_resource,
                     mapping,
-                    &namespace,
+                    namespace.as_deref().unwrap_or_default(),
                     &resource_name,
                 )
                 .await
@@ -285,38 +911,66 @@ impl QueryServer {
                 }
             }
 
-            let target = TokenTarget::multi_port(cluster_ip.clone(), token_port_mappings.clone());
-            let token = self.token_cache.generate_token(target).await;
+            let target = TokenTarget::multi_port(cluster_ip.clone(), token_port_mappings.clone())
+                .with_player_id(player_id.clone())
+                .with_metadata(metadata.clone())
+                .with_backend(backend.clone());
+            let token = self
+                .token_cache
+                .generate_token(target, client_addr.ip())
+                .await;
 
             // Establish session immediately for this client
             self.session_manager
                 .upsert_multi_port(client_addr, cluster_ip.clone(), token_port_mappings)
                 .await;
+            self.session_manager
+                .set_player_id(&client_addr, player_id.clone());
+            self.session_manager.set_metadata(&client_addr, metadata);
+            self.session_manager.set_backend(&client_addr, backend);
+            self.count_session(client_addr, &token, &cluster_ip, context.party_size);
 
             info!(
-                "Generated multi-port token and established session for {} -> {} ({} ports)",
+                "Generated multi-port token and established session for {} -> {} ({} ports{})",
                 client_addr,
                 resource_name,
-                ports_map.len()
+                ports_map.len(),
+                player_suffix(&player_id)
             );
 
+            let rtt_ms = self.load_balancer.backend_rtt_ms(&cluster_ip);
+
             QueryResponse::SuccessMultiPort {
                 token,
                 address: cluster_ip,
                 ports: ports_map,
+                namespace,
+                region,
+                rtt_ms,
             }
         } else {
             // Single port approach (backwards compatibility)
             let (cluster_ip, port) = match self
-                .extract_target_info(selected_resource, mapping, &namespace, &resource_name)
+                .extract_target_info(
+                    &selected_resource,
+                    mapping,
+                    namespace.as_deref().unwrap_or_default(),
+                    &resource_name,
+                )
                 .await
             {
                 Ok(info) => info,
                 Err(e) => return e,
             };
 
-            let target = TokenTarget::single_port(cluster_ip.clone(), port);
-            let token = self.token_cache.generate_token(target).await;
+            let target = TokenTarget::single_port(cluster_ip.clone(), port)
+                .with_player_id(player_id.clone())
+                .with_metadata(metadata.clone())
+                .with_backend(backend.clone());
+            let token = self
+                .token_cache
+                .generate_token(target, client_addr.ip())
+                .await;
 
             // Establish session immediately for this client
             let target_addr =
@@ -328,13 +982,45 @@ impl QueryServer {
 
             if let Ok(addr) = target_addr {
                 self.session_manager.upsert(client_addr, addr).await;
+                self.session_manager
+                    .set_player_id(&client_addr, player_id.clone());
+                self.session_manager.set_metadata(&client_addr, metadata);
+                self.session_manager.set_backend(&client_addr, backend);
+                self.count_session(client_addr, &token, &cluster_ip, context.party_size);
                 info!(
-                    "Generated token and established session for {} -> {}",
-                    client_addr, resource_name
+                    "Generated token and established session for {} -> {}{}",
+                    client_addr,
+                    resource_name,
+                    player_suffix(&player_id)
                 );
             }
 
-            QueryResponse::Success { token }
+            QueryResponse::Success {
+                token,
+                namespace,
+                region,
+            }
+        }
+    }
+
+    /// Account a new session on its backend
+    ///
+    /// With reservations the party's slots are held under the token: the data proxy
+    /// counts the querying client when its first packet claims a slot, and each party
+    /// member resetting onto the token claims another; otherwise the
+    /// session is counted right away.
+    fn count_session(
+        &self,
+        client_addr: std::net::SocketAddr,
+        token: &str,
+        cluster_ip: &str,
+        party_size: i64,
+    ) {
+        if self.load_balancer.reserve(token, cluster_ip, party_size) {
+            self.session_manager
+                .set_reservation(&client_addr, token.to_string());
+        } else {
+            self.load_balancer.increment_session(cluster_ip);
         }
     }
 
@@ -342,7 +1028,7 @@ impl QueryServer {
     async fn query_k8s_resources(
         &self,
         _resource_type: &str,
-        namespace: &str,
+        namespace_scope: &NamespaceScope,
         label_selector: &Option<HashMap<String, String>>,
         annotation_selector: &Option<HashMap<String, String>>,
         mapping: &crate::config::ResourceMapping,
@@ -351,7 +1037,7 @@ impl QueryServer {
         let resources = self
             .k8s_client
             .query_resources(
-                namespace,
+                namespace_scope,
                 mapping,
                 status_query,
                 label_selector.as_ref(),
@@ -368,9 +1054,120 @@ impl QueryServer {
             });
         }
 
+        self.load_balancer.observe_backends(&resources, mapping);
+
         Ok(resources)
     }
 
+    /// Pick a resource from the candidates using the load balancer
+    ///
+    /// The load balancer needs an address to track sessions, so service-based
+    /// mappings (no `addressPath`) fall back to the first matching resource.
+    /// With region routing, regions are tried in `region_order` before falling
+    /// back to all candidates; the region of the selected resource is returned.
+    #[allow(clippy::result_large_err)]
+    fn select_resource(
+        &self,
+        load_balancer: &LoadBalancer,
+        resources: &[kube::api::DynamicObject],
+        mapping: &crate::config::ResourceMapping,
+        context: &SelectionContext,
+        region_order: &[String],
+    ) -> Result<(kube::api::DynamicObject, Option<String>), QueryResponse> {
+        let Some(router) = &self.region_router else {
+            return self
+                .select_from(load_balancer, resources, mapping, context)
+                .map(|resource| (resource, None));
+        };
+
+        for region in region_order {
+            let in_region: Vec<kube::api::DynamicObject> = resources
+                .iter()
+                .filter(|r| router.backend_region(r).as_deref() == Some(region.as_str()))
+                .cloned()
+                .collect();
+            if in_region.is_empty() {
+                debug!("No candidates in region '{}'", region);
+                continue;
+            }
+
+            match self.select_from(load_balancer, &in_region, mapping, context) {
+                Ok(resource) => return Ok((resource, Some(region.clone()))),
+                Err(_) => debug!("No capacity in region '{}', trying next", region),
+            }
+        }
+
+        let resource = self.select_from(load_balancer, resources, mapping, context)?;
+        let region = router.backend_region(&resource);
+        if !region_order.is_empty() {
+            info!(
+                "No preferred region had capacity ({}), selected region {:?}",
+                region_order.join(", "),
+                region
+            );
+        }
+        Ok((resource, region))
+    }
+
+    /// Ask the routing webhook; returns the chosen candidate (if any) and session metadata
+    async fn review_with_webhook(
+        &self,
+        webhook: &RoutingWebhook,
+        request: &WebhookRequest,
+    ) -> Result<(Option<usize>, HashMap<String, String>), QueryResponse> {
+        match webhook.review(request).await {
+            Ok(WebhookDecision::Allow { backend, metadata }) => Ok((backend, metadata)),
+            Ok(WebhookDecision::Skipped) => Ok((None, HashMap::new())),
+            Ok(WebhookDecision::Deny { reason }) => {
+                info!(
+                    "Routing webhook denied {} query from {}: {}",
+                    request.resource_type,
+                    request.client_address,
+                    reason.as_deref().unwrap_or("no reason given")
+                );
+                Err(QueryResponse::Rejected {
+                    error: match reason {
+                        Some(reason) => format!("Denied: {}", reason),
+                        None => "Denied by routing webhook".to_string(),
+                    },
+                    code: "webhook_denied",
+                })
+            }
+            Err(e) => {
+                error!("Routing webhook failed: {:#}", e);
+                metrics::record_error("webhook_failed", "query_server");
+                Err(QueryResponse::Rejected {
+                    error: "Routing webhook unavailable".to_string(),
+                    code: "webhook_unavailable",
+                })
+            }
+        }
+    }
+
+    /// Pick a resource from the candidates, ignoring regions
+    #[allow(clippy::result_large_err)]
+    fn select_from(
+        &self,
+        load_balancer: &LoadBalancer,
+        resources: &[kube::api::DynamicObject],
+        mapping: &crate::config::ResourceMapping,
+        context: &SelectionContext,
+    ) -> Result<kube::api::DynamicObject, QueryResponse> {
+        match &mapping.address_path {
+            Some(address_path) => load_balancer
+                .select_backend(
+                    resources,
+                    address_path,
+                    mapping.address_type.as_deref(),
+                    context,
+                )
+                .map_err(|e| QueryResponse::Error {
+                    error: format!("No backend available: {}", e),
+                }),
+            None => Ok(resources[0].clone()),
+        }
+    }
+
     /// Extract target IP and port from resource
     async fn extract_target_info(
         &self,
@@ -388,6 +1185,7 @@ impl QueryServer {
     }
 
     /// Extract target using direct resource approach
+    #[allow(clippy::result_large_err)]
     fn extract_direct_target(
         &self,
         resource: &kube::api::DynamicObject,
@@ -499,6 +1297,14 @@ impl QueryServer {
     }
 }
 
+/// `, player <id>` for session log lines of authenticated players
+fn player_suffix(player_id: &Option<String>) -> String {
+    player_id
+        .as_ref()
+        .map(|id| format!(", player {}", id))
+        .unwrap_or_default()
+}
+
 // Manual Clone implementation since TcpListener is not Clone
 impl Clone for QueryServer {
     fn clone(&self) -> Self {
@@ -508,13 +1314,66 @@ impl Clone for QueryServer {
             token_cache: self.token_cache.clone(),
             session_manager: self.session_manager.clone(),
             config: self.config.clone(),
+            load_balancer: self.load_balancer.clone(),
+            region_router: self.region_router.clone(),
+            authenticator: self.authenticator.clone(),
+            jwt_verifier: self.jwt_verifier.clone(),
+            routing_webhook: self.routing_webhook.clone(),
+            tls: self.tls.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            handshake_cookie: self.handshake_cookie.clone(),
+            access_control: self.access_control.clone(),
+        }
+    }
+}
+
+/// Response to a request over a rate limit
+fn rate_limited() -> QueryResponse {
+    QueryResponse::Rejected {
+        error: "Rate limit exceeded".to_string(),
+        code: "rate_limited",
+    }
+}
+
+/// Read a query request into `data`
+///
+/// Clients need not half-close: reading stops at EOF, once the optional headers and
+/// a complete JSON body have arrived, or past `MAX_QUERY_REQUEST_BYTES`.
+async fn read_request<S>(stream: &mut S, data: &mut Vec<u8>) -> std::io::Result<()>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buffer = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(());
         }
+        data.extend_from_slice(&buffer[..n]);
+        if data.len() > MAX_QUERY_REQUEST_BYTES || request_complete(data) {
+            return Ok(());
+        }
+    }
+}
+
+/// Whether `data` holds the headers (if any) and a whole JSON value
+///
+/// Invalid JSON counts as complete so the client gets the parse error right away.
+fn request_complete(data: &[u8]) -> bool {
+    let Ok((_, body)) = query_auth::split_headers(data) else {
+        return false;
+    };
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    match serde::de::IgnoredAny::deserialize(&mut deserializer) {
+        Ok(_) => true,
+        Err(e) => !e.is_eof(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::temp_dir;
 
     #[test]
     fn test_query_request_serialization() {
@@ -525,12 +1384,21 @@ mod tests {
         let request = QueryRequest::Query {
             resource_type: "gameserver".to_string(),
             namespace: "game-servers".to_string(),
+            namespaces: None,
+            namespace_selector: None,
+            all_namespaces: None,
             status_query: Some(StatusQueryDto {
                 json_path: "status.state".to_string(),
                 expected_values: vec!["Allocated".to_string(), "Ready".to_string()],
             }),
             label_selector: Some(label_selector),
             annotation_selector: None,
+            party_size: None,
+            affinity_key: None,
+            preferred_regions: None,
+            region_latencies: None,
+            preset: None,
+            params: None,
         };
 
         let json = serde_json::to_string(&request).unwrap();
@@ -542,9 +1410,18 @@ mod tests {
             QueryRequest::Query {
                 resource_type,
                 namespace,
+                namespaces: _,
+                namespace_selector: _,
+                all_namespaces: _,
                 status_query,
                 label_selector,
                 annotation_selector: _,
+                party_size: _,
+                affinity_key: _,
+                preferred_regions: _,
+                region_latencies: _,
+                preset: _,
+                params: _,
             } => {
                 assert_eq!(resource_type, "gameserver");
                 assert_eq!(namespace, "game-servers");
@@ -560,6 +1437,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_query_request_snake_case_fields() {
+        // Field names accepted by releases up to 0.2.1
+        let request: QueryRequest = serde_json::from_str(
+            r#"{"type": "query", "resource_type": "gameserver", "namespace": "games",
+                "status_query": {"jsonPath": "status.state", "expectedValues": ["Ready"]},
+                "label_selector": {"map": "de_dust2"}, "annotation_selector": {"mode": "ranked"}}"#,
+        )
+        .unwrap();
+        let QueryRequest::Query {
+            resource_type,
+            status_query,
+            label_selector,
+            annotation_selector,
+            ..
+        } = request
+        else {
+            panic!("Expected Query variant");
+        };
+        assert_eq!(resource_type, "gameserver");
+        assert!(status_query.is_some());
+        assert_eq!(label_selector.unwrap()["map"], "de_dust2");
+        assert_eq!(annotation_selector.unwrap()["mode"], "ranked");
+    }
+
+    #[test]
+    fn test_query_request_preset() {
+        let json = r#"{
+            "type": "query",
+            "preset": "ranked-eu",
+            "params": {"map": "de_dust2"}
+        }"#;
+
+        let request: QueryRequest = serde_json::from_str(json).unwrap();
+        match request {
+            QueryRequest::Query {
+                resource_type,
+                preset,
+                params,
+                ..
+            } => {
+                assert!(resource_type.is_empty());
+                assert_eq!(preset.as_deref(), Some("ranked-eu"));
+                assert_eq!(params.unwrap()["map"], "de_dust2");
+            }
+            _ => panic!("Expected Query variant"),
+        }
+    }
+
     #[test]
     fn test_session_reset_request_deserialization() {
         let json = r#"{
@@ -576,18 +1502,622 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_query_request_party_size() {
+        let json = r#"{
+            "type": "query",
+            "resourceType": "gameserver",
+            "namespace": "game-servers",
+            "partySize": 4,
+            "affinityKey": "player-42"
+        }"#;
+
+        let request: QueryRequest = serde_json::from_str(json).unwrap();
+        match request {
+            QueryRequest::Query {
+                party_size,
+                affinity_key,
+                ..
+            } => {
+                assert_eq!(party_size, Some(4));
+                assert_eq!(affinity_key.as_deref(), Some("player-42"));
+            }
+            _ => panic!("Expected Query variant"),
+        }
+    }
+
+    #[test]
+    fn test_query_request_regions() {
+        let json = r#"{
+            "type": "query",
+            "resourceType": "gameserver",
+            "namespace": "game-servers",
+            "preferredRegions": ["eu-west", "us-east"],
+            "regionLatencies": {"eu-west": 35.5, "us-east": 110}
+        }"#;
+
+        let request: QueryRequest = serde_json::from_str(json).unwrap();
+        match request {
+            QueryRequest::Query {
+                preferred_regions,
+                region_latencies,
+                ..
+            } => {
+                assert_eq!(
+                    preferred_regions,
+                    Some(vec!["eu-west".to_string(), "us-east".to_string()])
+                );
+                assert_eq!(region_latencies.unwrap().get("us-east"), Some(&110.0));
+            }
+            _ => panic!("Expected Query variant"),
+        }
+    }
+
     #[test]
     fn test_query_response_serialization() {
         let response = QueryResponse::Success {
             token: "test-token-123".to_string(),
+            namespace: None,
+            region: None,
         };
         let json = serde_json::to_string(&response).unwrap();
         assert!(json.contains("test-token-123"));
+        assert!(!json.contains("region"));
+
+        let response = QueryResponse::Success {
+            token: "test-token-123".to_string(),
+            namespace: None,
+            region: Some("eu-west".to_string()),
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        assert!(json.contains(r#""region":"eu-west""#));
 
         let response = QueryResponse::Error {
             error: "Test error".to_string(),
         };
         let json = serde_json::to_string(&response).unwrap();
         assert!(json.contains("Test error"));
+
+        let response = QueryResponse::Rejected {
+            error: "Authentication required".to_string(),
+            code: "auth_required",
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(
+            json,
+            r#"{"error":"Authentication required","code":"auth_required"}"#
+        );
+    }
+
+    #[test]
+    fn test_query_request_namespaces() {
+        let json = r#"{
+            "type": "query",
+            "resourceType": "gameserver",
+            "namespaces": ["team-a", "team-b"],
+            "namespaceSelector": {"fleet": "eu"},
+            "allNamespaces": true
+        }"#;
+
+        let request: QueryRequest = serde_json::from_str(json).unwrap();
+        match request {
+            QueryRequest::Query {
+                namespace,
+                namespaces,
+                namespace_selector,
+                all_namespaces,
+                ..
+            } => {
+                assert!(namespace.is_empty());
+                assert_eq!(namespaces.unwrap().len(), 2);
+                assert_eq!(namespace_selector.unwrap().get("fleet").unwrap(), "eu");
+                assert_eq!(all_namespaces, Some(true));
+            }
+            _ => panic!("Expected Query variant"),
+        }
+
+        let response = QueryResponse::Success {
+            token: "test-token-123".to_string(),
+            namespace: Some("team-b".to_string()),
+            region: None,
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        assert!(json.contains(r#""namespace":"team-b""#));
+    }
+
+    /// Fake API server listing one pod with 2 of 10 users, for any path
+    async fn start_fake_api_server() -> String {
+        use http_body_util::Full;
+        use hyper::body::Bytes;
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper::{Request, Response};
+        use hyper_util::rt::TokioIo;
+        use serde_json::json;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|_req: Request<hyper::body::Incoming>| async move {
+                        let pods = json!({
+                            "apiVersion": "v1", "kind": "PodList", "metadata": {},
+                            "items": [{
+                                "metadata": {
+                                    "name": "pod-1",
+                                    "namespace": "default",
+                                    "labels": { "currentUsers": "2", "maxUsers": "10" }
+                                },
+                                "spec": { "containers": [{
+                                    "name": "game",
+                                    "ports": [{ "name": "game-udp", "containerPort": 7001 }]
+                                }] },
+                                "status": { "podIP": "10.0.0.1" }
+                            }]
+                        });
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(
+                            pods.to_string(),
+                        ))))
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_query_reserves_until_first_packet() {
+        let url = start_fake_api_server().await;
+        let k8s_client = K8sClient::from_client(
+            kube::Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap(),
+        );
+        let config: Config = serde_yaml::from_str(
+            r#"
+queryPort: 9000
+dataPort: 7777
+tokenTtlSeconds: 30
+sessionTimeoutSeconds: 300
+controlPacketMagicBytes: "FFFFFFFF5245534554"
+defaultEndpoint:
+  resourceType: "game"
+  namespace: "default"
+resourceQueryMapping:
+  game:
+    group: ""
+    version: "v1"
+    resource: "pods"
+    addressPath: "status.podIP"
+    portName: "game-udp"
+"#,
+        )
+        .unwrap();
+        let strategy = LoadBalancingStrategy::LabelArithmetic {
+            current_label: Some("currentUsers".to_string()),
+            max_label: Some("maxUsers".to_string()),
+            current: None,
+            max: None,
+            overlap: 0,
+        };
+        let load_balancer = LoadBalancer::new(strategy, k8s_client.clone())
+            .with_reservations(std::time::Duration::from_secs(30));
+        let session_manager = SessionManager::new(300);
+        // Same accounting as main: replaced sessions give back their slot or session
+        let lb_for_callback = load_balancer.clone();
+        session_manager.set_cleanup_callback(std::sync::Arc::new(
+            move |session: &crate::session::Session| match &session.reservation {
+                Some(token) => lb_for_callback.release_reservation(token),
+                None => lb_for_callback.decrement_session(&session.target_ip),
+            },
+        ));
+        let server = QueryServer::new(
+            9000,
+            k8s_client,
+            TokenCache::new(30),
+            session_manager.clone(),
+            config,
+            load_balancer.clone(),
+            None,
+        );
+        let query = |party_size: u32| -> QueryRequest {
+            serde_json::from_value(serde_json::json!({
+                "type": "query",
+                "resourceType": "game",
+                "namespace": "default",
+                "partySize": party_size
+            }))
+            .unwrap()
+        };
+        let succeeded =
+            |response: &QueryResponse| matches!(response, QueryResponse::Success { .. });
+        let client_a: std::net::SocketAddr = "203.0.113.1:40000".parse().unwrap();
+        let client_b: std::net::SocketAddr = "203.0.113.2:40000".parse().unwrap();
+        let client_c: std::net::SocketAddr = "203.0.113.3:40000".parse().unwrap();
+
+        // Queries hold the party's slots without also counting a session:
+        // 10 - 2 current leaves room for a party of 4 and a party of 2
+        assert!(succeeded(
+            &server.process_query(query(4), client_a, None).await
+        ));
+        assert_eq!(load_balancer.get_session_count("10.0.0.1"), 0);
+        assert_eq!(load_balancer.reserved_slots("10.0.0.1"), 4);
+        assert!(succeeded(
+            &server.process_query(query(2), client_b, None).await
+        ));
+        assert!(!succeeded(
+            &server.process_query(query(4), client_c, None).await
+        ));
+
+        // A's first data packet (another source port) claims one of its 4 slots;
+        // the other 3 stay held for the rest of the party
+        let data_addr: std::net::SocketAddr = "203.0.113.1:50000".parse().unwrap();
+        let (token, target_ip) = session_manager.take_reservation(&data_addr).unwrap();
+        load_balancer.claim_reservation(&token, &target_ip);
+        assert!(session_manager.take_reservation(&data_addr).is_none());
+        assert_eq!(load_balancer.get_session_count("10.0.0.1"), 1);
+        assert_eq!(load_balancer.reserved_slots("10.0.0.1"), 5);
+        assert!(!succeeded(
+            &server.process_query(query(3), client_c, None).await
+        ));
+
+        // A party member joining with A's token claims the next slot
+        let member: std::net::SocketAddr = "203.0.113.4:40000".parse().unwrap();
+        let reset = QueryRequest::SessionReset {
+            token: token.clone(),
+        };
+        assert!(succeeded(&server.process_query(reset, member, None).await));
+        assert_eq!(load_balancer.get_session_count("10.0.0.1"), 2);
+        assert_eq!(load_balancer.reserved_slots("10.0.0.1"), 4);
+
+        // B resetting onto its own token uses its own slot instead of a second one
+        let b_token = session_manager
+            .get_by_addr(&client_b)
+            .and_then(|session| session.reservation)
+            .unwrap();
+        let reset = QueryRequest::SessionReset { token: b_token };
+        assert!(succeeded(
+            &server.process_query(reset, client_b, None).await
+        ));
+        assert_eq!(load_balancer.get_session_count("10.0.0.1"), 3);
+        assert_eq!(load_balancer.reserved_slots("10.0.0.1"), 3);
+
+        // 10 - 2 current - 3 sessions - 3 reserved leaves room for a party of 2 only
+        assert!(!succeeded(
+            &server.process_query(query(3), client_c, None).await
+        ));
+        assert!(succeeded(
+            &server.process_query(query(2), client_c, None).await
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_request_without_half_close() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+
+        // Headers and body split across writes, and the client keeps its side open
+        client
+            .write_all(b"Authorization: ApiKey a:b\r\n\r\n{\"type\": \"query\", ")
+            .await
+            .unwrap();
+        let reader = tokio::spawn(async move {
+            let mut data = Vec::new();
+            read_request(&mut server, &mut data).await.unwrap();
+            data
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        client
+            .write_all(b"\"resourceType\": \"game\"}")
+            .await
+            .unwrap();
+        let data = reader.await.unwrap();
+        assert!(data.ends_with(b"\"game\"}"));
+
+        assert!(!request_complete(b"{\"type\": \"query\""));
+        assert!(!request_complete(b"Authorization: ApiKey a:b\r\n"));
+        assert!(!request_complete(b"  "));
+        // Malformed JSON is answered without waiting for more
+        assert!(request_complete(b"{\"type\" query}"));
+    }
+
+    #[tokio::test]
+    async fn test_read_request_size_cap() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let chunk = [b' '; 1024];
+            while client.write_all(&chunk).await.is_ok() {}
+        });
+        let mut data = Vec::new();
+        read_request(&mut server, &mut data).await.unwrap();
+        assert!(data.len() > MAX_QUERY_REQUEST_BYTES);
+        assert!(data.len() <= MAX_QUERY_REQUEST_BYTES + 4096);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_apply_before_authentication() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        std::fs::write(dir.join("mm"), "s3cret\n").unwrap();
+        let config: Config = serde_yaml::from_str(
+            r#"
+queryPort: 9000
+dataPort: 7777
+tokenTtlSeconds: 30
+sessionTimeoutSeconds: 300
+controlPacketMagicBytes: "FFFFFFFF5245534554"
+defaultEndpoint:
+  resourceType: "game"
+  namespace: "default"
+resourceQueryMapping:
+  game:
+    group: ""
+    version: "v1"
+    resource: "pods"
+    addressPath: "status.podIP"
+    portName: "game-udp"
+"#,
+        )
+        .unwrap();
+        let k8s_client = K8sClient::from_client(
+            kube::Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap()))
+                .unwrap(),
+        );
+        let auth_config: crate::query_auth::QueryAuthConfig =
+            serde_yaml::from_str(&format!("keysDir: {}\nmethods: [apiKey]", dir.display()))
+                .unwrap();
+        let limits = serde_yaml::from_str(
+            r#"
+connections:
+  perClient: {ratePerSecond: 0.001, burst: 3}
+authFailures:
+  perClient: {ratePerSecond: 0.001, burst: 1}
+"#,
+        )
+        .unwrap();
+        let server = QueryServer::new(
+            9000,
+            k8s_client.clone(),
+            TokenCache::new(30),
+            SessionManager::new(300),
+            config,
+            LoadBalancer::new(LoadBalancingStrategy::LeastSessions, k8s_client),
+            None,
+        )
+        .with_authenticator(QueryAuthenticator::new(auth_config).unwrap())
+        .with_rate_limiter(RateLimiter::new(limits));
+
+        let reset = |key: &str| {
+            format!(
+                "Authorization: ApiKey mm:{}\r\n\r\n{{\"type\":\"sessionReset\",\"token\":\"t\"}}",
+                key
+            )
+        };
+        let exchange = |client: &str, request: String| {
+            let server = server.clone();
+            let client_addr: std::net::SocketAddr = client.parse().unwrap();
+            async move {
+                let (mut client_io, server_io) = tokio::io::duplex(4096);
+                client_io.write_all(request.as_bytes()).await.unwrap();
+                client_io.shutdown().await.unwrap();
+                server
+                    .handle_connection(server_io, client_addr)
+                    .await
+                    .unwrap();
+                let mut response = Vec::new();
+                client_io.read_to_end(&mut response).await.unwrap();
+                serde_json::from_slice::<serde_json::Value>(&response).unwrap()
+            }
+        };
+
+        // A failed authentication uses up the client's allowance, so even a valid
+        // key is refused without being checked
+        let response = exchange("203.0.113.1:40000", reset("wrong")).await;
+        assert_eq!(response["code"], "auth_invalid");
+        let response = exchange("203.0.113.1:40000", reset("s3cret")).await;
+        assert_eq!(response["code"], "rate_limited");
+
+        // Connections are limited per client before authentication; requests that
+        // parse still reach their own limits (none configured for resets here)
+        for _ in 0..3 {
+            let response = exchange("203.0.113.2:40000", reset("s3cret")).await;
+            assert!(response.get("code").is_none());
+        }
+        let response = exchange("203.0.113.2:40000", reset("s3cret")).await;
+        assert_eq!(response["code"], "rate_limited");
+    }
+
+    #[tokio::test]
+    async fn test_jwt_combined_with_query_auth() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        std::fs::create_dir_all(dir.join("keys")).unwrap();
+        std::fs::write(dir.join("keys").join("mm"), "s3cret\n").unwrap();
+        std::fs::write(dir.join("jwt"), "jwt-secret").unwrap();
+        let config: Config = serde_yaml::from_str(
+            r#"
+queryPort: 9000
+dataPort: 7777
+tokenTtlSeconds: 30
+sessionTimeoutSeconds: 300
+controlPacketMagicBytes: "FFFFFFFF5245534554"
+defaultEndpoint:
+  resourceType: "game"
+  namespace: "default"
+resourceQueryMapping:
+  game:
+    group: ""
+    version: "v1"
+    resource: "pods"
+    addressPath: "status.podIP"
+    portName: "game-udp"
+"#,
+        )
+        .unwrap();
+        let k8s_client = K8sClient::from_client(
+            kube::Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap()))
+                .unwrap(),
+        );
+        let auth_config: crate::query_auth::QueryAuthConfig = serde_yaml::from_str(&format!(
+            "keysDir: {}\nmethods: [apiKey]",
+            dir.join("keys").display()
+        ))
+        .unwrap();
+        let server = |replaces_query_auth: bool| {
+            let jwt_config = serde_yaml::from_str(&format!(
+                "keyFile: {}\nalgorithms: [HS256]\nreplacesQueryAuth: {}",
+                dir.join("jwt").display(),
+                replaces_query_auth
+            ))
+            .unwrap();
+            QueryServer::new(
+                9000,
+                k8s_client.clone(),
+                TokenCache::new(30),
+                SessionManager::new(300),
+                config.clone(),
+                LoadBalancer::new(LoadBalancingStrategy::LeastSessions, k8s_client.clone()),
+                None,
+            )
+            .with_authenticator(QueryAuthenticator::new(auth_config.clone()).unwrap())
+            .with_jwt_verifier(JwtVerifier::new(jwt_config).unwrap())
+        };
+        let jwt = jsonwebtoken::encode(
+            &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256),
+            &serde_json::json!({
+                "sub": "player-42",
+                "exp": jsonwebtoken::get_current_timestamp() + 600,
+            }),
+            &jsonwebtoken::EncodingKey::from_secret(b"jwt-secret"),
+        )
+        .unwrap();
+        let client_addr: std::net::SocketAddr = "203.0.113.1:40000".parse().unwrap();
+        let code = |server: &QueryServer, headers: String| {
+            let request = format!("{}\r\n\r\n{{}}", headers);
+            match server.authenticate(request.as_bytes(), client_addr) {
+                Ok((_, identity)) => Ok(identity.and_then(|identity| identity.player_id)),
+                Err(QueryResponse::Rejected { code, .. }) => Err(code),
+                Err(other) => panic!("unexpected response {:?}", other),
+            }
+        };
+
+        // Both are required by default: the API key and the player token
+        let both = server(false);
+        assert_eq!(
+            code(
+                &both,
+                format!("Authorization: ApiKey mm:s3cret\r\nX-Player-Token: {}", jwt)
+            ),
+            Ok(Some("player-42".to_string()))
+        );
+        assert_eq!(
+            code(&both, "Authorization: ApiKey mm:s3cret".to_string()),
+            Err("auth_required")
+        );
+        assert_eq!(
+            code(&both, format!("Authorization: Bearer {}", jwt)),
+            Err("auth_malformed")
+        );
+
+        // With replacesQueryAuth a Bearer token alone is enough
+        let replaced = server(true);
+        assert_eq!(
+            code(&replaced, format!("Authorization: Bearer {}", jwt)),
+            Ok(Some("player-42".to_string()))
+        );
+        assert_eq!(
+            code(&replaced, "Authorization: ApiKey mm:wrong".to_string()),
+            Err("auth_invalid")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preset_query_errors() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+queryPort: 9000
+dataPort: 7777
+tokenTtlSeconds: 30
+sessionTimeoutSeconds: 300
+controlPacketMagicBytes: "FFFFFFFF5245534554"
+defaultEndpoint:
+  resourceType: "game"
+  namespace: "default"
+resourceQueryMapping:
+  game:
+    group: ""
+    version: "v1"
+    resource: "pods"
+    addressPath: "status.podIP"
+    portName: "game-udp"
+queryPresets:
+  ranked:
+    resourceType: game
+    namespace: ranked
+    labelSelector:
+      map: "{map}"
+    params:
+      map: {}
+"#,
+        )
+        .unwrap();
+        let k8s_client = K8sClient::from_client(
+            kube::Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap()))
+                .unwrap(),
+        );
+        let server = QueryServer::new(
+            9000,
+            k8s_client.clone(),
+            TokenCache::new(30),
+            SessionManager::new(300),
+            config,
+            LoadBalancer::new(LoadBalancingStrategy::LeastSessions, k8s_client),
+            None,
+        );
+        let client_addr: std::net::SocketAddr = "203.0.113.1:40000".parse().unwrap();
+        let error = |request: serde_json::Value| {
+            let server = server.clone();
+            async move {
+                let request = serde_json::from_value(request).unwrap();
+                match server.process_query(request, client_addr, None).await {
+                    QueryResponse::Error { error } => error,
+                    other => panic!("unexpected response {:?}", other),
+                }
+            }
+        };
+
+        let err = error(serde_json::json!({"type": "query", "preset": "casual"})).await;
+        assert_eq!(err, "Unknown preset: casual");
+
+        let err = error(serde_json::json!({
+            "type": "query", "preset": "ranked", "params": {"map": "x", "mode": "y"}
+        }))
+        .await;
+        assert!(err.contains("Unknown param 'mode'"), "{}", err);
+
+        let err = error(serde_json::json!({
+            "type": "query", "preset": "ranked", "params": {"map": "a,b=c"}
+        }))
+        .await;
+        assert!(
+            err.starts_with("Invalid params for preset ranked"),
+            "{}",
+            err
+        );
+
+        // Presets cannot be widened with raw query fields
+        let err = error(serde_json::json!({
+            "type": "query", "preset": "ranked", "params": {"map": "x"}, "allNamespaces": true
+        }))
+        .await;
+        assert!(err.starts_with("preset queries cannot set"), "{}", err);
+
+        let err = error(serde_json::json!({
+            "type": "query", "resourceType": "game", "namespace": "default", "params": {"map": "x"}
+        }))
+        .await;
+        assert_eq!(err, "params requires a preset");
     }
 }
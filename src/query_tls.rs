@@ -0,0 +1,408 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::crypto::{CryptoProvider, ring};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::server::danger::ClientCertVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tracing::{info, warn};
+
+/// TLS listener for the query port, optionally requiring client certificates
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryTlsConfig {
+    /// Port of the TLS listener (the plaintext `queryPort` keeps running unless disabled)
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// PEM certificate chain (e.g. `tls.crt` of a mounted Secret)
+    pub cert_file: String,
+    /// PEM private key (e.g. `tls.key`)
+    pub key_file: String,
+    /// PEM CA bundle to verify client certificates against (mTLS)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ca_file: Option<String>,
+    /// Reject clients without a certificate when `clientCaFile` is set (default: true)
+    #[serde(default = "default_require_client_cert")]
+    pub require_client_cert: bool,
+    /// Stop serving plaintext on `queryPort` once all clients use TLS (default: false)
+    #[serde(default)]
+    pub disable_plaintext: bool,
+    /// How often the files are checked for rotation (default: 30)
+    #[serde(default = "default_reload_seconds")]
+    pub reload_seconds: u64,
+}
+
+fn default_port() -> u16 {
+    9443
+}
+
+fn default_require_client_cert() -> bool {
+    true
+}
+
+fn default_reload_seconds() -> u64 {
+    30
+}
+
+/// Contents of the certificate, key and client CA files
+#[derive(PartialEq)]
+struct TlsFiles {
+    cert: Vec<u8>,
+    key: Vec<u8>,
+    client_ca: Option<Vec<u8>>,
+}
+
+/// Builds TLS acceptors from files that are reloaded when they change
+#[derive(Clone)]
+pub struct QueryTls {
+    config: QueryTlsConfig,
+    server_config: Arc<RwLock<Arc<ServerConfig>>>,
+    files: Arc<RwLock<TlsFiles>>,
+}
+
+impl QueryTls {
+    /// Load the certificate and key; fails if they are missing or invalid
+    pub fn new(config: QueryTlsConfig) -> Result<Self> {
+        let files = read_files(&config)?;
+        let server_config = build_server_config(&files, config.require_client_cert)?;
+        info!(
+            "Query TLS enabled on port {} (client certificates: {})",
+            config.port,
+            match (&config.client_ca_file, config.require_client_cert) {
+                (None, _) => "not verified",
+                (Some(_), true) => "required",
+                (Some(_), false) => "optional",
+            }
+        );
+        Ok(Self {
+            config,
+            server_config: Arc::new(RwLock::new(server_config)),
+            files: Arc::new(RwLock::new(files)),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.config.port
+    }
+
+    /// Whether the plaintext listener should stay off
+    pub fn disable_plaintext(&self) -> bool {
+        self.config.disable_plaintext
+    }
+
+    /// Acceptor using the current certificate (new connections pick up rotations)
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(
+            self.server_config
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        )
+    }
+
+    /// Re-read the files periodically so certificate rotation needs no restart
+    pub async fn run(self) -> Result<()> {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.reload_seconds.max(1)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.reload() {
+                warn!("Failed to reload query TLS certificate: {:#}", e);
+            }
+        }
+    }
+
+    /// Rebuild the server config if any file changed; keeps the old one on error
+    fn reload(&self) -> Result<bool> {
+        let files = read_files(&self.config)?;
+        if *self.files.read().unwrap_or_else(|e| e.into_inner()) == files {
+            return Ok(false);
+        }
+        let server_config = build_server_config(&files, self.config.require_client_cert)?;
+        *self
+            .server_config
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = server_config;
+        *self.files.write().unwrap_or_else(|e| e.into_inner()) = files;
+        info!("Reloaded query TLS certificate");
+        Ok(true)
+    }
+}
+
+fn read_files(config: &QueryTlsConfig) -> Result<TlsFiles> {
+    let read = |path: &str| {
+        std::fs::read(path).with_context(|| format!("Failed to read TLS file {}", path))
+    };
+    Ok(TlsFiles {
+        cert: read(&config.cert_file)?,
+        key: read(&config.key_file)?,
+        client_ca: config.client_ca_file.as_deref().map(read).transpose()?,
+    })
+}
+
+fn build_server_config(files: &TlsFiles, require_client_cert: bool) -> Result<Arc<ServerConfig>> {
+    let certs = CertificateDer::pem_slice_iter(&files.cert)
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid TLS certificate")?;
+    if certs.is_empty() {
+        bail!("TLS certificate file contains no certificates");
+    }
+    let key = PrivateKeyDer::from_pem_slice(&files.key).context("Invalid TLS private key")?;
+
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match &files.client_ca {
+        Some(client_ca) => builder.with_client_cert_verifier(client_verifier(
+            client_ca,
+            require_client_cert,
+            provider,
+        )?),
+        None => builder.with_no_client_auth(),
+    };
+
+    let config = builder
+        .with_single_cert(certs, key)
+        .context("TLS certificate does not match the private key")?;
+    Ok(Arc::new(config))
+}
+
+fn client_verifier(
+    client_ca: &[u8],
+    require_client_cert: bool,
+    provider: Arc<CryptoProvider>,
+) -> Result<Arc<dyn ClientCertVerifier>> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(client_ca) {
+        roots
+            .add(cert.context("Invalid client CA certificate")?)
+            .context("Invalid client CA certificate")?;
+    }
+    if roots.is_empty() {
+        bail!("Client CA file contains no certificates");
+    }
+
+    let builder = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+    let builder = if require_client_cert {
+        builder
+    } else {
+        builder.allow_unauthenticated()
+    };
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::TlsConnector;
+    use tokio_rustls::rustls::ClientConfig;
+    use tokio_rustls::rustls::pki_types::ServerName;
+
+    struct TestPki {
+        ca_pem: String,
+        ca: rcgen::Certificate,
+        ca_key: KeyPair,
+    }
+
+    impl TestPki {
+        fn new() -> Self {
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca_key = KeyPair::generate().unwrap();
+            let ca = params.self_signed(&ca_key).unwrap();
+            Self {
+                ca_pem: ca.pem(),
+                ca,
+                ca_key,
+            }
+        }
+
+        /// Certificate and key PEM signed by the CA
+        fn issue(&self, name: &str, usage: ExtendedKeyUsagePurpose) -> (String, String) {
+            let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+            params.extended_key_usages = vec![usage];
+            let key = KeyPair::generate().unwrap();
+            let cert = params.signed_by(&key, &self.ca, &self.ca_key).unwrap();
+            (cert.pem(), key.serialize_pem())
+        }
+    }
+
+    fn write_files(dir: &std::path::Path, pki: &TestPki) -> QueryTlsConfig {
+        let (cert, key) = pki.issue("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+        std::fs::write(dir.join("tls.crt"), cert).unwrap();
+        std::fs::write(dir.join("tls.key"), key).unwrap();
+        std::fs::write(dir.join("ca.crt"), &pki.ca_pem).unwrap();
+        QueryTlsConfig {
+            port: 0,
+            cert_file: dir.join("tls.crt").to_string_lossy().to_string(),
+            key_file: dir.join("tls.key").to_string_lossy().to_string(),
+            client_ca_file: Some(dir.join("ca.crt").to_string_lossy().to_string()),
+            require_client_cert: true,
+            disable_plaintext: false,
+            reload_seconds: 30,
+        }
+    }
+
+    fn client_config(pki: &TestPki, client_cert: Option<(String, String)>) -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_slice(pki.ca_pem.as_bytes()).unwrap())
+            .unwrap();
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = match client_cert {
+            Some((cert, key)) => builder
+                .with_client_auth_cert(
+                    vec![CertificateDer::from_pem_slice(cert.as_bytes()).unwrap()],
+                    PrivateKeyDer::from_pem_slice(key.as_bytes()).unwrap(),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+        Arc::new(config)
+    }
+
+    /// Echo one message over TLS; returns what the client read back
+    async fn round_trip(tls: &QueryTls, client: Arc<ClientConfig>) -> std::io::Result<Vec<u8>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = tls.acceptor();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            if let Ok(mut stream) = acceptor.accept(stream).await {
+                let mut buf = [0u8; 64];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                let _ = stream.write_all(&buf[..n]).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = TlsConnector::from(client)
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await?;
+        stream.write_all(b"{}").await?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        Ok(reply)
+    }
+
+    #[tokio::test]
+    async fn test_mtls_requires_client_certificate() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let pki = TestPki::new();
+        let tls = QueryTls::new(write_files(dir, &pki)).unwrap();
+
+        let client_cert = pki.issue("matchmaker", ExtendedKeyUsagePurpose::ClientAuth);
+        let reply = round_trip(&tls, client_config(&pki, Some(client_cert)))
+            .await
+            .unwrap();
+        assert_eq!(reply, b"{}");
+
+        // Without a client certificate the handshake (or first read) fails
+        assert!(round_trip(&tls, client_config(&pki, None)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_certificate_reload() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let pki = TestPki::new();
+        let mut config = write_files(dir, &pki);
+        config.client_ca_file = None;
+        let tls = QueryTls::new(config).unwrap();
+        assert!(!tls.reload().unwrap());
+
+        // Rotate to a certificate from a new CA
+        let rotated = TestPki::new();
+        let (cert, key) = rotated.issue("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+        std::fs::write(dir.join("tls.crt"), cert).unwrap();
+        std::fs::write(dir.join("tls.key"), key).unwrap();
+        assert!(tls.reload().unwrap());
+        assert!(
+            round_trip(&tls, client_config(&rotated, None))
+                .await
+                .is_ok()
+        );
+        assert!(round_trip(&tls, client_config(&pki, None)).await.is_err());
+
+        // A broken key keeps the previous certificate
+        std::fs::write(dir.join("tls.key"), "not a key").unwrap();
+        assert!(tls.reload().is_err());
+        assert!(
+            round_trip(&tls, client_config(&rotated, None))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_files_rejected() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let pki = TestPki::new();
+        let config = write_files(dir, &pki);
+        assert!(QueryTls::new(config.clone()).is_ok());
+
+        let error = |config: QueryTlsConfig| format!("{:#}", QueryTls::new(config).err().unwrap());
+
+        let mut missing = config.clone();
+        missing.cert_file = dir.join("missing.crt").to_string_lossy().to_string();
+        assert!(error(missing).contains("Failed to read TLS file"));
+
+        // Key of another certificate
+        let (_, other_key) = pki.issue("other", ExtendedKeyUsagePurpose::ServerAuth);
+        std::fs::write(dir.join("other.key"), other_key).unwrap();
+        let mut mismatched = config.clone();
+        mismatched.key_file = dir.join("other.key").to_string_lossy().to_string();
+        assert!(error(mismatched).contains("does not match the private key"));
+
+        std::fs::write(dir.join("empty.crt"), "").unwrap();
+        let mut no_certs = config.clone();
+        no_certs.cert_file = dir.join("empty.crt").to_string_lossy().to_string();
+        assert!(error(no_certs).contains("contains no certificates"));
+        let mut no_ca = config;
+        no_ca.client_ca_file = Some(dir.join("empty.crt").to_string_lossy().to_string());
+        assert!(error(no_ca).contains("Client CA file contains no certificates"));
+    }
+
+    #[tokio::test]
+    async fn test_client_certificate_from_other_ca_rejected() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let pki = TestPki::new();
+        let mut config = write_files(dir, &pki);
+        let tls = QueryTls::new(config.clone()).unwrap();
+
+        let untrusted = TestPki::new();
+        let client_cert = untrusted.issue("matchmaker", ExtendedKeyUsagePurpose::ClientAuth);
+        assert!(
+            round_trip(&tls, client_config(&pki, Some(client_cert.clone())))
+                .await
+                .is_err()
+        );
+
+        // Optional client certificates still have to verify when presented
+        config.require_client_cert = false;
+        let tls = QueryTls::new(config).unwrap();
+        assert!(round_trip(&tls, client_config(&pki, None)).await.is_ok());
+        assert!(
+            round_trip(&tls, client_config(&pki, Some(client_cert)))
+                .await
+                .is_err()
+        );
+    }
+}
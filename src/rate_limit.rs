@@ -0,0 +1,455 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::metrics;
+
+/// Token bucket: `burst` requests at once, refilled at `ratePerSecond`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketConfig {
+    pub rate_per_second: f64,
+    pub burst: u32,
+}
+
+impl BucketConfig {
+    pub fn is_valid(&self) -> bool {
+        self.rate_per_second > 0.0 && self.burst > 0
+    }
+}
+
+/// Limits for one kind of request; either scope may be omitted
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LimitConfig {
+    /// Per client IP (or prefix, see `ipv4PrefixLen` / `ipv6PrefixLen`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_client: Option<BucketConfig>,
+    /// Shared by all clients of this replica
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub global: Option<BucketConfig>,
+}
+
+/// Rate limits for queries, session resets and new data-path sessions
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitConfig {
+    /// Connections to the query port, taken before the request is authenticated
+    #[serde(default)]
+    pub connections: LimitConfig,
+    /// Failed authentications on the query port; once exhausted, requests are
+    /// rejected without being authenticated
+    #[serde(default)]
+    pub auth_failures: LimitConfig,
+    /// Resource queries on the query port
+    #[serde(default)]
+    pub queries: LimitConfig,
+    /// Session resets on the query port
+    #[serde(default)]
+    pub resets: LimitConfig,
+    /// Default-endpoint sessions created by the data proxy
+    #[serde(default)]
+    pub sessions: LimitConfig,
+    /// Clients in the same IPv4 prefix share a bucket (default: 32, per address)
+    #[serde(default = "default_ipv4_prefix_len")]
+    pub ipv4_prefix_len: u8,
+    /// Clients in the same IPv6 prefix share a bucket (default: 64)
+    #[serde(default = "default_ipv6_prefix_len")]
+    pub ipv6_prefix_len: u8,
+}
+
+fn default_ipv4_prefix_len() -> u8 {
+    32
+}
+
+fn default_ipv6_prefix_len() -> u8 {
+    64
+}
+
+impl RateLimitConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (name, limit) in [
+            ("connections", &self.connections),
+            ("authFailures", &self.auth_failures),
+            ("queries", &self.queries),
+            ("resets", &self.resets),
+            ("sessions", &self.sessions),
+        ] {
+            for bucket in [limit.per_client, limit.global].into_iter().flatten() {
+                if !bucket.is_valid() {
+                    anyhow::bail!(
+                        "rateLimits.{}: ratePerSecond and burst must be greater than 0",
+                        name
+                    );
+                }
+            }
+        }
+        if self.ipv4_prefix_len > 32 || self.ipv6_prefix_len > 128 {
+            anyhow::bail!("rateLimits prefix lengths must be at most 32 (IPv4) and 128 (IPv6)");
+        }
+        Ok(())
+    }
+}
+
+/// What is being limited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Connection,
+    AuthFailure,
+    Query,
+    Reset,
+    Session,
+}
+
+impl LimitKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitKind::Connection => "connection",
+            LimitKind::AuthFailure => "auth_failure",
+            LimitKind::Query => "query",
+            LimitKind::Reset => "reset",
+            LimitKind::Session => "session",
+        }
+    }
+}
+
+/// Token bucket state; tokens go negative while shaped traffic waits
+#[derive(Debug)]
+pub struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    pub fn full(config: &BucketConfig, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(config.burst),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, config: &BucketConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.rate_per_second).min(f64::from(config.burst));
+        self.updated = now;
+    }
+
+    fn try_take(&mut self, config: &BucketConfig, now: Instant) -> bool {
+        if self.has_token(config, now) {
+            self.take(1.0);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn has_token(&mut self, config: &BucketConfig, now: Instant) -> bool {
+        self.delay_for(config, 1.0, now, Duration::ZERO).is_some()
+    }
+
+    /// How long until `cost` tokens are available; `None` if longer than `max_delay`
+    pub fn delay_for(
+        &mut self,
+        config: &BucketConfig,
+        cost: f64,
+        now: Instant,
+        max_delay: Duration,
+    ) -> Option<Duration> {
+        self.refill(config, now);
+        let deficit = cost - self.tokens;
+        if deficit <= 0.0 {
+            return Some(Duration::ZERO);
+        }
+        let delay = deficit / config.rate_per_second;
+        (delay <= max_delay.as_secs_f64()).then(|| Duration::from_secs_f64(delay))
+    }
+
+    /// Spend tokens after `delay_for` allowed it
+    pub fn take(&mut self, cost: f64) {
+        self.tokens -= cost;
+    }
+
+    /// Whether the bucket would be full again by `now`
+    fn is_idle(&self, config: &BucketConfig, now: Instant) -> bool {
+        let missing = f64::from(config.burst) - self.tokens;
+        now.saturating_duration_since(self.updated).as_secs_f64() * config.rate_per_second
+            >= missing
+    }
+}
+
+/// Buckets of one limit kind
+struct Limiter {
+    config: LimitConfig,
+    clients: DashMap<IpAddr, Bucket>,
+    global: Option<Mutex<Bucket>>,
+}
+
+impl Limiter {
+    fn new(config: LimitConfig, now: Instant) -> Self {
+        Self {
+            config,
+            clients: DashMap::new(),
+            global: config
+                .global
+                .map(|bucket| Mutex::new(Bucket::full(&bucket, now))),
+        }
+    }
+
+    /// Returns the exceeded scope, if any
+    fn check(&self, client: IpAddr, now: Instant) -> Option<&'static str> {
+        // The client bucket goes first so one noisy client cannot drain the global one
+        if let Some(bucket_config) = &self.config.per_client {
+            let mut bucket = self
+                .clients
+                .entry(client)
+                .or_insert_with(|| Bucket::full(bucket_config, now));
+            if !bucket.try_take(bucket_config, now) {
+                return Some("client");
+            }
+        }
+        if let (Some(bucket_config), Some(global)) = (&self.config.global, &self.global)
+            && !global
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .try_take(bucket_config, now)
+        {
+            return Some("global");
+        }
+        None
+    }
+
+    /// Like `check`, but without taking a token
+    fn peek(&self, client: IpAddr, now: Instant) -> Option<&'static str> {
+        if let (Some(bucket_config), Some(mut bucket)) =
+            (&self.config.per_client, self.clients.get_mut(&client))
+            && !bucket.has_token(bucket_config, now)
+        {
+            return Some("client");
+        }
+        if let (Some(bucket_config), Some(global)) = (&self.config.global, &self.global)
+            && !global
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .has_token(bucket_config, now)
+        {
+            return Some("global");
+        }
+        None
+    }
+
+    fn prune(&self, now: Instant) {
+        if let Some(bucket_config) = &self.config.per_client {
+            self.clients
+                .retain(|_, bucket| !bucket.is_idle(bucket_config, now));
+        }
+    }
+}
+
+/// Token-bucket rate limiting per client prefix and per replica
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    connections: Arc<Limiter>,
+    auth_failures: Arc<Limiter>,
+    queries: Arc<Limiter>,
+    resets: Arc<Limiter>,
+    sessions: Arc<Limiter>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            connections: Arc::new(Limiter::new(config.connections, now)),
+            auth_failures: Arc::new(Limiter::new(config.auth_failures, now)),
+            queries: Arc::new(Limiter::new(config.queries, now)),
+            resets: Arc::new(Limiter::new(config.resets, now)),
+            sessions: Arc::new(Limiter::new(config.sessions, now)),
+            config,
+        }
+    }
+
+    /// Forget client buckets that have refilled, so memory follows active clients
+    pub async fn run(self) -> Result<()> {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            for limiter in [
+                &self.connections,
+                &self.auth_failures,
+                &self.queries,
+                &self.resets,
+                &self.sessions,
+            ] {
+                limiter.prune(now);
+            }
+        }
+    }
+
+    /// Take a token for `client_ip`; false if it is over a limit
+    pub fn allow(&self, kind: LimitKind, client_ip: IpAddr) -> bool {
+        self.check(kind, client_ip, Instant::now())
+    }
+
+    /// Whether `client_ip` has no tokens left, without taking one
+    ///
+    /// Used for the auth failure limit, where only failures take a token.
+    pub fn exhausted(&self, kind: LimitKind, client_ip: IpAddr) -> bool {
+        let exceeded = self
+            .limiter(kind)
+            .peek(self.client_key(client_ip), Instant::now());
+        !self.report(kind, client_ip, exceeded)
+    }
+
+    fn check(&self, kind: LimitKind, client_ip: IpAddr, now: Instant) -> bool {
+        let exceeded = self.limiter(kind).check(self.client_key(client_ip), now);
+        self.report(kind, client_ip, exceeded)
+    }
+
+    fn limiter(&self, kind: LimitKind) -> &Limiter {
+        match kind {
+            LimitKind::Connection => &self.connections,
+            LimitKind::AuthFailure => &self.auth_failures,
+            LimitKind::Query => &self.queries,
+            LimitKind::Reset => &self.resets,
+            LimitKind::Session => &self.sessions,
+        }
+    }
+
+    /// Log and count an exceeded limit; true if none was exceeded
+    fn report(&self, kind: LimitKind, client_ip: IpAddr, exceeded: Option<&'static str>) -> bool {
+        match exceeded {
+            None => true,
+            Some(scope) => {
+                debug!(
+                    "Rate limited {} from {} ({} limit)",
+                    kind.as_str(),
+                    client_ip,
+                    scope
+                );
+                metrics::record_rate_limited(kind.as_str(), scope);
+                false
+            }
+        }
+    }
+
+    /// The client's prefix, which identifies its bucket
+    fn client_key(&self, ip: IpAddr) -> IpAddr {
+        match ip.to_canonical() {
+            IpAddr::V4(v4) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.config.ipv4_prefix_len.min(32)))
+                    .unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+            }
+            IpAddr::V6(v6) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.config.ipv6_prefix_len.min(128)))
+                    .unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(queries: LimitConfig) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            connections: LimitConfig::default(),
+            auth_failures: LimitConfig::default(),
+            queries,
+            resets: LimitConfig::default(),
+            sessions: LimitConfig::default(),
+            ipv4_prefix_len: 24,
+            ipv6_prefix_len: 64,
+        })
+    }
+
+    #[test]
+    fn test_per_client_bucket_refills() {
+        let limiter = limiter(LimitConfig {
+            per_client: Some(BucketConfig {
+                rate_per_second: 2.0,
+                burst: 3,
+            }),
+            global: None,
+        });
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let neighbour: IpAddr = "203.0.113.8".parse().unwrap();
+        let other: IpAddr = "198.51.100.1".parse().unwrap();
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check(LimitKind::Query, client, start));
+        }
+        assert!(!limiter.check(LimitKind::Query, client, start));
+        // Same /24 shares the bucket, other networks do not
+        assert!(!limiter.check(LimitKind::Query, neighbour, start));
+        assert!(limiter.check(LimitKind::Query, other, start));
+        // Other kinds are unlimited here
+        assert!(limiter.check(LimitKind::Reset, client, start));
+
+        // 2 tokens per second
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check(LimitKind::Query, client, later));
+        assert!(!limiter.check(LimitKind::Query, client, later));
+
+        // Idle buckets are pruned once refilled
+        limiter.queries.prune(start + Duration::from_secs(10));
+        assert!(limiter.queries.clients.is_empty());
+    }
+
+    #[test]
+    fn test_global_bucket() {
+        let limiter = limiter(LimitConfig {
+            per_client: Some(BucketConfig {
+                rate_per_second: 1.0,
+                burst: 1,
+            }),
+            global: Some(BucketConfig {
+                rate_per_second: 1.0,
+                burst: 2,
+            }),
+        });
+        let now = Instant::now();
+        let ips: Vec<IpAddr> = ["198.51.100.1", "198.51.101.1", "198.51.102.1"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+
+        assert!(limiter.check(LimitKind::Query, ips[0], now));
+        // Rejected by its own bucket without draining the global one
+        assert!(!limiter.check(LimitKind::Query, ips[0], now));
+        assert!(limiter.check(LimitKind::Query, ips[1], now));
+        assert!(!limiter.check(LimitKind::Query, ips[2], now));
+    }
+
+    #[test]
+    fn test_auth_failures_only_spent_on_failure() {
+        let limiter = RateLimiter::new(
+            serde_yaml::from_str("authFailures:\n  perClient: {ratePerSecond: 1, burst: 2}")
+                .unwrap(),
+        );
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let start = Instant::now();
+
+        // Checking does not spend; each failure does
+        assert!(!limiter.exhausted(LimitKind::AuthFailure, client));
+        assert!(!limiter.exhausted(LimitKind::AuthFailure, client));
+        assert!(limiter.check(LimitKind::AuthFailure, client, start));
+        assert!(!limiter.exhausted(LimitKind::AuthFailure, client));
+        assert!(limiter.check(LimitKind::AuthFailure, client, start));
+        assert!(limiter.exhausted(LimitKind::AuthFailure, client));
+        // Unrelated kinds stay unlimited
+        assert!(!limiter.exhausted(LimitKind::Connection, client));
+        assert!(limiter.check(LimitKind::Query, client, start));
+    }
+}
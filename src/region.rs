@@ -0,0 +1,245 @@
+use anyhow::{Context, Result};
+use kube::api::DynamicObject;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+/// Region-aware routing across fleets labelled by region
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionRoutingConfig {
+    /// Label on backend resources holding their region (default: "topology.kubernetes.io/region")
+    #[serde(default = "default_region_label")]
+    pub region_label: String,
+    /// Path to a MaxMind-format (GeoIP2/GeoLite2 Country or City) database file
+    #[serde(default)]
+    pub geoip_database: Option<String>,
+    /// ISO country code -> regions in preference order (e.g. "DE": ["eu-west", "eu-north"])
+    #[serde(default)]
+    pub country_regions: HashMap<String, Vec<String>>,
+    /// Continent code -> regions in preference order, used when the country is not mapped
+    #[serde(default)]
+    pub continent_regions: HashMap<String, Vec<String>>,
+    /// Regions tried after the client's preferred regions
+    #[serde(default)]
+    pub fallback_regions: Vec<String>,
+}
+
+fn default_region_label() -> String {
+    "topology.kubernetes.io/region".to_string()
+}
+
+impl RegionRoutingConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.region_label.is_empty() {
+            anyhow::bail!("regionRouting.regionLabel must not be empty");
+        }
+        if let Some(path) = &self.geoip_database
+            && !std::path::Path::new(path).is_file()
+        {
+            anyhow::bail!("regionRouting.geoipDatabase {} is not a file", path);
+        }
+        Ok(())
+    }
+}
+
+/// Region preferences supplied by a client query
+#[derive(Debug, Clone, Default)]
+pub struct RegionPreference {
+    /// Regions in the client's order of preference
+    pub preferred_regions: Option<Vec<String>>,
+    /// Measured latency (ms) from the client to each region
+    pub region_latencies: Option<HashMap<String, f64>>,
+}
+
+/// Orders regions for a client and resolves the region of backends
+#[derive(Clone)]
+pub struct RegionRouter {
+    config: RegionRoutingConfig,
+    geoip: Option<Arc<maxminddb::Reader<Vec<u8>>>>,
+}
+
+impl RegionRouter {
+    /// Create a region router, loading the GeoIP database if configured
+    pub fn new(config: RegionRoutingConfig) -> Result<Self> {
+        let geoip = match &config.geoip_database {
+            Some(path) => {
+                let reader = maxminddb::Reader::open_readfile(path)
+                    .with_context(|| format!("Failed to open GeoIP database: {}", path))?;
+                info!(
+                    "Loaded GeoIP database {} ({})",
+                    path,
+                    reader.metadata().database_type
+                );
+                Some(Arc::new(reader))
+            }
+            None => None,
+        };
+
+        Ok(Self { config, geoip })
+    }
+
+    /// Regions to try for a client, in order
+    ///
+    /// Explicit `preferredRegions` win, then `regionLatencies` (lowest first),
+    /// then the GeoIP lookup of the client IP. Configured fallback regions are
+    /// appended; duplicates are removed.
+    pub fn region_order(&self, preference: &RegionPreference, client_ip: IpAddr) -> Vec<String> {
+        let mut order: Vec<String> = if let Some(preferred) = preference
+            .preferred_regions
+            .as_ref()
+            .filter(|regions| !regions.is_empty())
+        {
+            preferred.clone()
+        } else if let Some(latencies) = preference
+            .region_latencies
+            .as_ref()
+            .filter(|latencies| !latencies.is_empty())
+        {
+            let mut by_latency: Vec<(&String, f64)> =
+                latencies.iter().map(|(r, ms)| (r, *ms)).collect();
+            by_latency.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+            by_latency.into_iter().map(|(r, _)| r.clone()).collect()
+        } else {
+            self.geoip_regions(client_ip)
+        };
+
+        order.extend(self.config.fallback_regions.iter().cloned());
+
+        let mut seen = std::collections::HashSet::new();
+        order.retain(|region| seen.insert(region.clone()));
+        order
+    }
+
+    /// Regions mapped from the client's GeoIP country or continent
+    fn geoip_regions(&self, client_ip: IpAddr) -> Vec<String> {
+        let Some(reader) = &self.geoip else {
+            return Vec::new();
+        };
+
+        let record = match reader
+            .lookup(client_ip)
+            .and_then(|result| result.decode::<maxminddb::geoip2::Country>())
+        {
+            Ok(Some(record)) => record,
+            Ok(None) => {
+                debug!("No GeoIP record for {}", client_ip);
+                return Vec::new();
+            }
+            Err(e) => {
+                warn!("GeoIP lookup failed for {}: {}", client_ip, e);
+                return Vec::new();
+            }
+        };
+
+        let country = record.country.iso_code;
+        let continent = record.continent.code;
+        debug!(
+            "GeoIP for {}: country={:?}, continent={:?}",
+            client_ip, country, continent
+        );
+
+        self.regions_for_location(country, continent)
+    }
+
+    /// Regions mapped from a country code, falling back to the continent code
+    fn regions_for_location(&self, country: Option<&str>, continent: Option<&str>) -> Vec<String> {
+        country
+            .and_then(|code| self.config.country_regions.get(code))
+            .or_else(|| continent.and_then(|code| self.config.continent_regions.get(code)))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Region of a backend resource from its region label
+    pub fn backend_region(&self, resource: &DynamicObject) -> Option<String> {
+        resource
+            .metadata
+            .labels
+            .as_ref()?
+            .get(&self.config.region_label)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> RegionRouter {
+        let config: RegionRoutingConfig = serde_yaml::from_str(
+            r#"
+countryRegions:
+  DE: ["eu-central", "eu-west"]
+continentRegions:
+  NA: ["us-east"]
+fallbackRegions: ["us-east", "eu-west"]
+"#,
+        )
+        .unwrap();
+        RegionRouter::new(config).unwrap()
+    }
+
+    #[test]
+    fn test_region_order_preference() {
+        let router = router();
+        let client_ip: IpAddr = "203.0.113.10".parse().unwrap();
+
+        // Explicit preference wins, fallbacks appended without duplicates
+        let preference = RegionPreference {
+            preferred_regions: Some(vec!["ap-south".to_string(), "eu-west".to_string()]),
+            region_latencies: Some(HashMap::from([("us-east".to_string(), 5.0)])),
+        };
+        assert_eq!(
+            router.region_order(&preference, client_ip),
+            vec!["ap-south", "eu-west", "us-east"]
+        );
+
+        // Latencies sorted ascending
+        let preference = RegionPreference {
+            preferred_regions: None,
+            region_latencies: Some(HashMap::from([
+                ("eu-west".to_string(), 80.0),
+                ("ap-south".to_string(), 20.0),
+            ])),
+        };
+        assert_eq!(
+            router.region_order(&preference, client_ip),
+            vec!["ap-south", "eu-west", "us-east"]
+        );
+
+        // No preference and no GeoIP database: fallbacks only
+        assert_eq!(
+            router.region_order(&RegionPreference::default(), client_ip),
+            vec!["us-east", "eu-west"]
+        );
+    }
+
+    #[test]
+    fn test_regions_for_location() {
+        let router = router();
+        assert_eq!(
+            router.regions_for_location(Some("DE"), Some("EU")),
+            vec!["eu-central", "eu-west"]
+        );
+        assert_eq!(
+            router.regions_for_location(Some("US"), Some("NA")),
+            vec!["us-east"]
+        );
+        assert!(router.regions_for_location(None, None).is_empty());
+    }
+
+    #[test]
+    fn test_missing_geoip_database() {
+        let config = RegionRoutingConfig {
+            region_label: default_region_label(),
+            geoip_database: Some("/nonexistent/GeoLite2-Country.mmdb".to_string()),
+            country_regions: HashMap::new(),
+            continent_regions: HashMap::new(),
+            fallback_regions: Vec::new(),
+        };
+        assert!(RegionRouter::new(config).is_err());
+    }
+}
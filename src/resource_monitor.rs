@@ -1,10 +1,13 @@
 use anyhow::Result;
+use kube::api::DynamicObject;
+use kube::runtime::events::EventType;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::interval;
 use tracing::{debug, error, info, warn};
 
 use crate::config::Config;
+use crate::events::EventRecorder;
 use crate::k8s_client::{K8sClient, StatusQuery};
 use crate::proxy::DefaultEndpointCacheHandle;
 use crate::session::SessionManager;
@@ -16,7 +19,9 @@ pub struct ResourceMonitor {
     session_manager: SessionManager,
     check_interval_seconds: u64,
     last_default_endpoint: Arc<tokio::sync::RwLock<Option<String>>>,
+    last_candidates: Arc<tokio::sync::RwLock<Vec<String>>>,
     cache_handle: DefaultEndpointCacheHandle,
+    events: Option<EventRecorder>,
 }
 
 impl ResourceMonitor {
@@ -34,7 +39,28 @@ impl ResourceMonitor {
             session_manager,
             check_interval_seconds,
             last_default_endpoint: Arc::new(tokio::sync::RwLock::new(None)),
+            last_candidates: Arc::new(tokio::sync::RwLock::new(Vec::new())),
             cache_handle,
+            events: None,
+        }
+    }
+
+    /// Record default endpoint transitions as Kubernetes Events on the director pod
+    pub fn with_event_recorder(mut self, events: EventRecorder) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Record an event on the director pod if events are enabled
+    fn director_event(
+        &self,
+        related: Option<&DynamicObject>,
+        type_: EventType,
+        reason: &str,
+        note: String,
+    ) {
+        if let Some(events) = &self.events {
+            events.director_event(related, type_, reason, note);
         }
     }
 
@@ -96,7 +122,7 @@ impl ResourceMonitor {
         let resources = self
             .k8s_client
             .query_resources(
-                &default_endpoint.namespace,
+                &default_endpoint.namespace_scope(),
                 mapping,
                 status_query.as_ref(),
                 default_endpoint.label_selector.as_ref(),
@@ -136,6 +162,34 @@ impl ResourceMonitor {
             }
         };
 
+        // The cache holds every candidate (per-session selection, e.g. consistent
+        // hashing), so any change to the candidate set must refresh it
+        let mut candidates: Vec<String> = resources
+            .iter()
+            .map(|r| {
+                format!(
+                    "{}/{}",
+                    r.metadata.namespace.as_deref().unwrap_or_default(),
+                    r.metadata.name.as_deref().unwrap_or("unknown")
+                )
+            })
+            .collect();
+        candidates.sort();
+        {
+            let mut last_candidates = self.last_candidates.write().await;
+            if *last_candidates != candidates {
+                if !last_candidates.is_empty() {
+                    info!(
+                        "Default endpoint candidates changed ({} → {})",
+                        last_candidates.len(),
+                        candidates.len()
+                    );
+                    self.cache_handle.invalidate().await;
+                }
+                *last_candidates = candidates;
+            }
+        }
+
         // Compare with last known state
         let mut last_endpoint = self.last_default_endpoint.write().await;
 
@@ -148,11 +202,23 @@ impl ResourceMonitor {
                 // Resources disappeared - invalidate cache
                 warn!("⚠️  Default endpoint lost! Previous: {}", last);
                 warn!(
-                    "No matching resources found for default endpoint (type: {}, namespace: {})",
-                    default_endpoint.resource_type, default_endpoint.namespace
+                    "No matching resources found for default endpoint (type: {}, {})",
+                    default_endpoint.resource_type,
+                    default_endpoint.namespace_scope()
                 );
                 self.cache_handle.invalidate().await;
                 info!("Invalidated default endpoint cache");
+                self.director_event(
+                    None,
+                    EventType::Warning,
+                    "DefaultEndpointLost",
+                    format!(
+                        "No {} matches the default endpoint ({}, previous: {})",
+                        default_endpoint.resource_type,
+                        default_endpoint.namespace_scope(),
+                        last
+                    ),
+                );
                 *last_endpoint = None;
             }
             (None, Some(current)) => {
@@ -164,6 +230,12 @@ impl ResourceMonitor {
                 );
                 self.cache_handle.invalidate().await;
                 info!("Invalidated default endpoint cache to force refresh");
+                self.director_event(
+                    resources.first(),
+                    EventType::Normal,
+                    "DefaultEndpointFound",
+                    format!("Default endpoint available: {}", current),
+                );
                 *last_endpoint = current_target;
             }
             (Some(last), Some(current)) => {
@@ -172,6 +244,12 @@ impl ResourceMonitor {
                     info!("🔄 Default endpoint changed: {} → {}", last, current);
                     self.cache_handle.invalidate().await;
                     info!("Invalidated default endpoint cache to force refresh");
+                    self.director_event(
+                        resources.first(),
+                        EventType::Normal,
+                        "DefaultEndpointChanged",
+                        format!("Default endpoint changed: {} → {}", last, current),
+                    );
                     *last_endpoint = current_target;
                 } else {
                     // No change
@@ -225,6 +303,9 @@ mod tests {
             default_endpoint: crate::config::DefaultEndpoint {
                 resource_type: "gameserver".to_string(),
                 namespace: "default".to_string(),
+                namespaces: None,
+                namespace_selector: None,
+                all_namespaces: false,
                 label_selector: None,
                 annotation_selector: None,
                 status_query: None,
@@ -234,6 +315,20 @@ mod tests {
             control_packet_magic_bytes: "FFFFFFFF5245534554".to_string(),
             resource_query_mapping: HashMap::new(),
             load_balancing: None,
+            region_routing: None,
+            session_writeback: None,
+            access_lists: None,
+            handshake_cookie: None,
+            rate_limits: None,
+            token_policy: None,
+            signed_tokens: None,
+            query_tls: None,
+            query_auth: None,
+            jwt_auth: None,
+            routing_webhook: None,
+            query_presets: HashMap::new(),
+            events: None,
+            admin_api: None,
         };
 
         let k8s_client = K8sClient::new().await.unwrap();
@@ -0,0 +1,456 @@
+use anyhow::{Context, Result, bail};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Request, StatusCode};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use kube::api::DynamicObject;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::metrics;
+
+/// What happens when the webhook cannot be reached or answers invalidly
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FailurePolicy {
+    /// Reject the query (fail closed)
+    #[default]
+    Fail,
+    /// Route as if no webhook were configured (fail open)
+    Ignore,
+}
+
+/// External HTTP service consulted before a backend is selected
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutingWebhookConfig {
+    /// URL receiving a POST per query (plain HTTP, e.g. an in-cluster Service)
+    pub url: String,
+    /// Request timeout (default: 500)
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Behavior on timeouts, errors and invalid responses (default: fail)
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    /// Only consult the webhook for these resource types (default: all)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_types: Option<Vec<String>>,
+}
+
+fn default_timeout_ms() -> u64 {
+    500
+}
+
+impl RoutingWebhookConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !self.url.starts_with("http://") {
+            bail!("routingWebhook.url must be an http:// URL");
+        }
+        if self.timeout_ms == 0 {
+            bail!("routingWebhook.timeoutMs must be non-zero");
+        }
+        Ok(())
+    }
+}
+
+/// Body posted to the webhook
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookRequest {
+    pub client_address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub player_id: Option<String>,
+    pub resource_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_selector: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotation_selector: Option<HashMap<String, String>>,
+    pub party_size: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub affinity_key: Option<String>,
+    pub candidates: Vec<WebhookCandidate>,
+}
+
+/// A backend the query matched
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookCandidate {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl WebhookCandidate {
+    pub fn from_resource(resource: &DynamicObject) -> Self {
+        Self {
+            name: resource.metadata.name.clone().unwrap_or_default(),
+            namespace: resource.metadata.namespace.clone(),
+            labels: resource.metadata.labels.clone().unwrap_or_default(),
+            annotations: resource.metadata.annotations.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Body the webhook answers with
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookResponse {
+    allow: bool,
+    #[serde(default)]
+    reason: Option<String>,
+    /// Backend to use instead of the load balancer's choice
+    #[serde(default)]
+    backend: Option<BackendRef>,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+}
+
+/// Name (and namespace) of a candidate chosen by the webhook
+#[derive(Debug, Clone, Deserialize)]
+struct BackendRef {
+    name: String,
+    #[serde(default)]
+    namespace: Option<String>,
+}
+
+/// Outcome of a webhook review
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebhookDecision {
+    /// Select a backend, optionally the candidate at `backend`, and attach `metadata`
+    Allow {
+        backend: Option<usize>,
+        metadata: HashMap<String, String>,
+    },
+    Deny {
+        reason: Option<String>,
+    },
+    /// The webhook failed and `failurePolicy: ignore` applies
+    Skipped,
+}
+
+/// Client for the routing webhook
+#[derive(Clone)]
+pub struct RoutingWebhook {
+    config: RoutingWebhookConfig,
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl RoutingWebhook {
+    pub fn new(config: RoutingWebhookConfig) -> Self {
+        let client = Client::builder(TokioExecutor::new()).build_http();
+        Self { config, client }
+    }
+
+    /// Whether queries for `resource_type` are reviewed
+    pub fn applies_to(&self, resource_type: &str) -> bool {
+        self.config
+            .resource_types
+            .as_ref()
+            .is_none_or(|types| types.iter().any(|t| t == resource_type))
+    }
+
+    /// Ask the webhook; errors only when it failed and `failurePolicy` is `fail`
+    pub async fn review(&self, request: &WebhookRequest) -> Result<WebhookDecision> {
+        let result = match tokio::time::timeout(
+            Duration::from_millis(self.config.timeout_ms),
+            self.call(request),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!(
+                "timed out after {}ms",
+                self.config.timeout_ms
+            )),
+        };
+
+        match result {
+            Ok(decision) => {
+                let result = match &decision {
+                    WebhookDecision::Deny { .. } => "denied",
+                    _ => "allowed",
+                };
+                metrics::record_routing_webhook(result);
+                Ok(decision)
+            }
+            Err(e) => {
+                metrics::record_routing_webhook("failed");
+                match self.config.failure_policy {
+                    FailurePolicy::Fail => Err(e),
+                    FailurePolicy::Ignore => {
+                        warn!("Routing webhook failed, ignoring: {:#}", e);
+                        Ok(WebhookDecision::Skipped)
+                    }
+                }
+            }
+        }
+    }
+
+    async fn call(&self, request: &WebhookRequest) -> Result<WebhookDecision> {
+        let body = serde_json::to_vec(request)?;
+        let http_request = Request::post(&self.config.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .context("Invalid routing webhook URL")?;
+
+        let response = self
+            .client
+            .request(http_request)
+            .await
+            .context("Routing webhook request failed")?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        if status != StatusCode::OK {
+            bail!("Routing webhook returned {}", status);
+        }
+
+        let response: WebhookResponse =
+            serde_json::from_slice(&body).context("Invalid routing webhook response")?;
+        debug!("Routing webhook response: {:?}", response);
+        decide(response, &request.candidates)
+    }
+}
+
+/// Turn a webhook response into a decision, resolving the chosen backend
+fn decide(response: WebhookResponse, candidates: &[WebhookCandidate]) -> Result<WebhookDecision> {
+    if !response.allow {
+        return Ok(WebhookDecision::Deny {
+            reason: response.reason,
+        });
+    }
+
+    let backend = match &response.backend {
+        Some(chosen) => Some(
+            candidates
+                .iter()
+                .position(|c| {
+                    c.name == chosen.name
+                        && (chosen.namespace.is_none() || c.namespace == chosen.namespace)
+                })
+                .with_context(|| {
+                    format!(
+                        "Routing webhook chose '{}', which is not a candidate",
+                        chosen.name
+                    )
+                })?,
+        ),
+        None => None,
+    };
+
+    Ok(WebhookDecision::Allow {
+        backend,
+        metadata: response.metadata,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Response;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper_util::rt::TokioIo;
+    use serde_json::{Value, json};
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    /// Stub webhook answering every request with `reply` after `delay`
+    async fn start_stub_webhook(reply: Value, delay: Duration) -> (String, Arc<Mutex<Vec<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/route", listener.local_addr().unwrap());
+        let requests: Arc<Mutex<Vec<Value>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let requests = requests.clone();
+                let reply = reply.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                        let requests = requests.clone();
+                        let reply = reply.clone();
+                        async move {
+                            let body = req.into_body().collect().await?.to_bytes();
+                            requests
+                                .lock()
+                                .unwrap()
+                                .push(serde_json::from_slice(&body).unwrap());
+                            tokio::time::sleep(delay).await;
+                            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(
+                                reply.to_string(),
+                            ))))
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        (url, recorded)
+    }
+
+    fn webhook(url: String, failure_policy: FailurePolicy) -> RoutingWebhook {
+        RoutingWebhook::new(RoutingWebhookConfig {
+            url,
+            timeout_ms: 200,
+            failure_policy,
+            resource_types: None,
+        })
+    }
+
+    fn request() -> WebhookRequest {
+        let candidate = |name: &str| WebhookCandidate {
+            name: name.to_string(),
+            namespace: Some("games".to_string()),
+            labels: BTreeMap::new(),
+            annotations: BTreeMap::new(),
+        };
+        WebhookRequest {
+            client_address: "203.0.113.7:5000".to_string(),
+            player_id: Some("player-42".to_string()),
+            resource_type: "gameserver".to_string(),
+            label_selector: None,
+            annotation_selector: None,
+            party_size: 1,
+            affinity_key: None,
+            candidates: vec![candidate("gs-1"), candidate("gs-2")],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_allow_with_backend_and_metadata() {
+        let (url, requests) = start_stub_webhook(
+            json!({
+                "allow": true,
+                "backend": {"name": "gs-2", "namespace": "games"},
+                "metadata": {"match": "m-1"}
+            }),
+            Duration::ZERO,
+        )
+        .await;
+
+        let decision = webhook(url, FailurePolicy::Fail)
+            .review(&request())
+            .await
+            .unwrap();
+        assert_eq!(
+            decision,
+            WebhookDecision::Allow {
+                backend: Some(1),
+                metadata: HashMap::from([("match".to_string(), "m-1".to_string())]),
+            }
+        );
+
+        let sent = requests.lock().unwrap()[0].clone();
+        assert_eq!(sent["playerId"], "player-42");
+        assert_eq!(sent["candidates"][1]["name"], "gs-2");
+    }
+
+    #[tokio::test]
+    async fn test_webhook_deny_and_failure_policy() {
+        let (url, _) =
+            start_stub_webhook(json!({"allow": false, "reason": "banned"}), Duration::ZERO).await;
+        assert_eq!(
+            webhook(url, FailurePolicy::Fail)
+                .review(&request())
+                .await
+                .unwrap(),
+            WebhookDecision::Deny {
+                reason: Some("banned".to_string())
+            }
+        );
+
+        // Timeouts fail closed or open
+        let (url, _) = start_stub_webhook(json!({"allow": true}), Duration::from_secs(2)).await;
+        assert!(
+            webhook(url.clone(), FailurePolicy::Fail)
+                .review(&request())
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            webhook(url, FailurePolicy::Ignore)
+                .review(&request())
+                .await
+                .unwrap(),
+            WebhookDecision::Skipped
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_errors_follow_failure_policy() {
+        // Unreachable webhook, a body that is not a decision and an unknown backend
+        let (invalid, _) = start_stub_webhook(json!("ok"), Duration::ZERO).await;
+        let (unknown, _) = start_stub_webhook(
+            json!({"allow": true, "backend": {"name": "gs-9"}}),
+            Duration::ZERO,
+        )
+        .await;
+        for url in ["http://127.0.0.1:1/route".to_string(), invalid, unknown] {
+            assert!(
+                webhook(url.clone(), FailurePolicy::Fail)
+                    .review(&request())
+                    .await
+                    .is_err()
+            );
+            assert_eq!(
+                webhook(url, FailurePolicy::Ignore)
+                    .review(&request())
+                    .await
+                    .unwrap(),
+                WebhookDecision::Skipped
+            );
+        }
+
+        // A deny is a decision, not a failure, so it is kept with failurePolicy: ignore
+        let (url, _) = start_stub_webhook(json!({"allow": false}), Duration::ZERO).await;
+        assert_eq!(
+            webhook(url, FailurePolicy::Ignore)
+                .review(&request())
+                .await
+                .unwrap(),
+            WebhookDecision::Deny { reason: None }
+        );
+    }
+
+    #[test]
+    fn test_webhook_config_validation() {
+        let config = |url: &str, timeout_ms: u64| RoutingWebhookConfig {
+            url: url.to_string(),
+            timeout_ms,
+            failure_policy: FailurePolicy::Fail,
+            resource_types: Some(vec!["gameserver".to_string()]),
+        };
+        assert!(
+            config("http://router.games:8080/route", 500)
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            config("https://router.games/route", 500)
+                .validate()
+                .is_err()
+        );
+        assert!(
+            config("http://router.games:8080/route", 0)
+                .validate()
+                .is_err()
+        );
+
+        let webhook = RoutingWebhook::new(config("http://router.games:8080/route", 500));
+        assert!(webhook.applies_to("gameserver"));
+        assert!(!webhook.applies_to("lobby"));
+    }
+}
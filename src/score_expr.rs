@@ -0,0 +1,337 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Parsed arithmetic expression used by the `scored` load balancing strategy
+///
+/// Grammar (standard precedence, left-associative):
+/// ```text
+/// expr   := term (('+' | '-') term)*
+/// term   := unary (('*' | '/') unary)*
+/// unary  := '-' unary | atom
+/// atom   := number | identifier | '(' expr ')'
+/// ```
+/// Identifiers may contain letters, digits and underscores and must not start with a digit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScoreExpr {
+    Number(f64),
+    Variable(String),
+    Negate(Box<ScoreExpr>),
+    Binary(Box<ScoreExpr>, BinaryOp, Box<ScoreExpr>),
+}
+
+/// Binary operators supported in score expressions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+}
+
+impl ScoreExpr {
+    /// Parse an expression string
+    pub fn parse(input: &str) -> Result<Self> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            anyhow::bail!("Score expression must not be empty");
+        }
+
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_expr()?;
+        if let Some((token, position)) = parser.tokens.get(parser.pos) {
+            anyhow::bail!(
+                "Unexpected {:?} at position {} in score expression",
+                token,
+                position
+            );
+        }
+        Ok(expr)
+    }
+
+    /// Names of all variables referenced by the expression
+    pub fn variables(&self) -> HashSet<&str> {
+        let mut vars = HashSet::new();
+        self.collect_variables(&mut vars);
+        vars
+    }
+
+    fn collect_variables<'a>(&'a self, vars: &mut HashSet<&'a str>) {
+        match self {
+            ScoreExpr::Number(_) => {}
+            ScoreExpr::Variable(name) => {
+                vars.insert(name.as_str());
+            }
+            ScoreExpr::Negate(inner) => inner.collect_variables(vars),
+            ScoreExpr::Binary(left, _, right) => {
+                left.collect_variables(vars);
+                right.collect_variables(vars);
+            }
+        }
+    }
+
+    /// Evaluate the expression, resolving variables through `lookup`
+    ///
+    /// Returns an error if a variable cannot be resolved or the result is not finite
+    /// (e.g. division by zero).
+    pub fn eval<F>(&self, lookup: &F) -> Result<f64>
+    where
+        F: Fn(&str) -> Option<f64>,
+    {
+        let value = self.eval_inner(lookup)?;
+        if !value.is_finite() {
+            anyhow::bail!("Score expression evaluated to a non-finite value");
+        }
+        Ok(value)
+    }
+
+    fn eval_inner<F>(&self, lookup: &F) -> Result<f64>
+    where
+        F: Fn(&str) -> Option<f64>,
+    {
+        Ok(match self {
+            ScoreExpr::Number(n) => *n,
+            ScoreExpr::Variable(name) => {
+                lookup(name).ok_or_else(|| anyhow::anyhow!("No value for input '{}'", name))?
+            }
+            ScoreExpr::Negate(inner) => -inner.eval_inner(lookup)?,
+            ScoreExpr::Binary(left, op, right) => {
+                let l = left.eval_inner(lookup)?;
+                let r = right.eval_inner(lookup)?;
+                match op {
+                    BinaryOp::Add => l + r,
+                    BinaryOp::Subtract => l - r,
+                    BinaryOp::Multiply => l * r,
+                    BinaryOp::Divide => l / r,
+                }
+            }
+        })
+    }
+}
+
+/// Split the input into tokens, keeping the character offset of each for error messages
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '+' | '-' | '*' | '/' => {
+                tokens.push((Token::Op(c), i));
+                i += 1;
+            }
+            '(' => {
+                tokens.push((Token::LParen, i));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((Token::RParen, i));
+                i += 1;
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = text.parse::<f64>().map_err(|_| {
+                    anyhow::anyhow!("Invalid number '{}' at position {}", text, start)
+                })?;
+                tokens.push((Token::Number(number), start));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push((Token::Ident(chars[start..i].iter().collect()), start));
+            }
+            _ => anyhow::bail!(
+                "Unexpected character '{}' at position {} in score expression",
+                c,
+                i
+            ),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive-descent parser over the token stream
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(token, _)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn parse_expr(&mut self) -> Result<ScoreExpr> {
+        let mut left = self.parse_term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek() {
+            let op = if *op == '+' {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Subtract
+            };
+            self.pos += 1;
+            let right = self.parse_term()?;
+            left = ScoreExpr::Binary(Box::new(left), op, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_term(&mut self) -> Result<ScoreExpr> {
+        let mut left = self.parse_unary()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek() {
+            let op = if *op == '*' {
+                BinaryOp::Multiply
+            } else {
+                BinaryOp::Divide
+            };
+            self.pos += 1;
+            let right = self.parse_unary()?;
+            left = ScoreExpr::Binary(Box::new(left), op, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<ScoreExpr> {
+        if let Some(Token::Op('-')) = self.peek() {
+            self.pos += 1;
+            return Ok(ScoreExpr::Negate(Box::new(self.parse_unary()?)));
+        }
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<ScoreExpr> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(ScoreExpr::Number(n)),
+            Some(Token::Ident(name)) => Ok(ScoreExpr::Variable(name)),
+            Some(Token::LParen) => {
+                let expr = self.parse_expr()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => anyhow::bail!("Missing closing parenthesis in score expression"),
+                }
+            }
+            Some(token) => anyhow::bail!(
+                "Unexpected {:?} at position {} in score expression",
+                token,
+                self.tokens[self.pos - 1].1
+            ),
+            None => anyhow::bail!("Unexpected end of score expression"),
+        }
+    }
+}
+
+/// A score expression as written in config, parsed once when the config is read
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ScoreExpression {
+    source: String,
+    expr: ScoreExpr,
+}
+
+impl ScoreExpression {
+    pub fn expr(&self) -> &ScoreExpr {
+        &self.expr
+    }
+}
+
+impl TryFrom<String> for ScoreExpression {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        let expr = ScoreExpr::parse(&source)
+            .map_err(|e| anyhow::anyhow!("Invalid loadBalancing expression: {}", e))?;
+        Ok(Self { source, expr })
+    }
+}
+
+impl From<ScoreExpression> for String {
+    fn from(expression: ScoreExpression) -> Self {
+        expression.source
+    }
+}
+
+impl std::fmt::Display for ScoreExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn eval(expr: &str, vars: &[(&str, f64)]) -> Result<f64> {
+        let vars: HashMap<&str, f64> = vars.iter().copied().collect();
+        ScoreExpr::parse(expr)?.eval(&|name| vars.get(name).copied())
+    }
+
+    #[test]
+    fn test_precedence_and_parentheses() {
+        assert_eq!(eval("1 + 2 * 3", &[]).unwrap(), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", &[]).unwrap(), 9.0);
+        assert_eq!(eval("10 - 4 - 3", &[]).unwrap(), 3.0);
+        assert_eq!(eval("-2 * -3", &[]).unwrap(), 6.0);
+        assert_eq!(eval("8 / 4 / 2", &[]).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_variables() {
+        let vars = [
+            ("max", 50.0),
+            ("current", 30.0),
+            ("sessions", 5.0),
+            ("weight", 2.0),
+            ("rtt_ms", 40.0),
+        ];
+        let value = eval("(max - current - sessions) * weight - rtt_ms/10", &vars).unwrap();
+        assert_eq!(value, 26.0);
+
+        let expr = ScoreExpr::parse("(max - current) * weight").unwrap();
+        let mut names: Vec<&str> = expr.variables().into_iter().collect();
+        names.sort();
+        assert_eq!(names, vec!["current", "max", "weight"]);
+
+        assert!(eval("missing + 1", &[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(ScoreExpr::parse("").is_err());
+        assert!(ScoreExpr::parse("1 +").is_err());
+        assert!(ScoreExpr::parse("(1 + 2").is_err());
+        assert!(ScoreExpr::parse("1 2").is_err());
+        assert!(ScoreExpr::parse("a % b").is_err());
+        assert!(ScoreExpr::parse("1..2").is_err());
+    }
+
+    #[test]
+    fn test_division_by_zero_is_error() {
+        assert!(eval("1 / (a - a)", &[("a", 3.0)]).is_err());
+    }
+}
@@ -1,4 +1,7 @@
 use dashmap::DashMap;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::DynamicObject;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
@@ -8,7 +11,8 @@ use tokio::sync::RwLock;
 use tokio::time::interval;
 use tracing::{debug, error, info};
 
-use crate::config::Protocol;
+use crate::config::{Protocol, ResourceMapping};
+use crate::traffic_limit::{Direction, PortTrafficLimiter, SessionTrafficLimiter, ShapingQueue};
 
 /// Dedicated socket for a session to enable bi-directional UDP communication
 #[derive(Clone)]
@@ -17,21 +21,62 @@ pub struct SessionSocket {
     socket: Arc<UdpSocket>,
     /// Shutdown signal to stop the receive task
     shutdown: Arc<RwLock<bool>>,
+    /// Packet and bandwidth limits of this session on its port
+    traffic_limiter: Option<Arc<SessionTrafficLimiter>>,
+    /// Queue of packets to the target (limited sessions only)
+    upstream: Option<Arc<ShapingQueue>>,
 }
 
 impl SessionSocket {
     /// Create a new session socket bound to an ephemeral port
-    pub async fn new() -> Result<Self, std::io::Error> {
+    pub async fn new(
+        traffic_limiter: Option<SessionTrafficLimiter>,
+    ) -> Result<Self, std::io::Error> {
         let socket = UdpSocket::bind("0.0.0.0:0").await?;
         Ok(Self {
             socket: Arc::new(socket),
             shutdown: Arc::new(RwLock::new(false)),
+            traffic_limiter: traffic_limiter.map(Arc::new),
+            upstream: None,
         })
     }
 
-    /// Get the socket for sending packets
-    pub fn socket(&self) -> Arc<UdpSocket> {
-        self.socket.clone()
+    /// Send limited sessions' packets to the (connected) target through a queue
+    fn start_upstream_queue(&mut self, session_manager: Arc<SessionManager>) {
+        let Some(limiter) = &self.traffic_limiter else {
+            return;
+        };
+        let socket = self.socket.clone();
+        let queue = ShapingQueue::spawn(limiter.clone(), Direction::Upstream, move |packet| {
+            let socket = socket.clone();
+            let session_manager = session_manager.clone();
+            async move {
+                match socket.send(&packet).await {
+                    Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+                        // Pending ICMP port unreachable from an earlier packet
+                        if let Ok(target_addr) = socket.peer_addr() {
+                            session_manager.notify_backend_failure(&target_addr.ip().to_string());
+                        }
+                    }
+                    Err(e) => debug!("Failed to forward shaped packet to target: {}", e),
+                    Ok(_) => {}
+                }
+            }
+        });
+        self.upstream = Some(Arc::new(queue));
+    }
+
+    /// Send a packet to the target; packets of limited sessions are queued
+    ///
+    /// Packets dropped by the traffic limit count as sent.
+    pub async fn send_upstream(&self, packet: Vec<u8>) -> Result<(), std::io::Error> {
+        match &self.upstream {
+            Some(queue) => {
+                queue.push(packet);
+                Ok(())
+            }
+            None => self.socket.send(&packet).await.map(|_| ()),
+        }
     }
 
     /// Get the local address of the socket
@@ -57,6 +102,26 @@ impl SessionSocket {
         let socket = self.socket.clone();
         let shutdown = self.shutdown.clone();
 
+        // Limited sessions forward through a queue, so shaping never stalls receiving
+        let downstream = self.traffic_limiter.as_ref().map(|limiter| {
+            let proxy_socket = proxy_socket.clone();
+            let session_manager = session_manager.clone();
+            ShapingQueue::spawn(limiter.clone(), Direction::Downstream, move |packet| {
+                let proxy_socket = proxy_socket.clone();
+                let session_manager = session_manager.clone();
+                async move {
+                    forward_to_client(
+                        &packet,
+                        client_ip,
+                        proxy_port,
+                        &proxy_socket,
+                        &session_manager,
+                    )
+                    .await;
+                }
+            })
+        });
+
         tokio::spawn(async move {
             let mut buffer = vec![0u8; 65535];
             loop {
@@ -74,29 +139,37 @@ impl SessionSocket {
                     .await
                 {
                     Ok(Ok((len, target_addr))) => {
+                        debug!(
+                            "Received {} bytes from target {} for client {}",
+                            len, target_addr, client_ip
+                        );
                         // Received packet from target, forward to client
-                        // Get active client ports for this session
-                        if let Some(session) = session_manager.get(&client_ip) {
-                            if let Some(client_ports) = session.client_ports.get(&proxy_port) {
-                                for client_port in client_ports {
-                                    let client_addr = SocketAddr::new(client_ip, *client_port);
-                                    debug!(
-                                        "Received {} bytes from target {} for client {} (port {})",
-                                        len, target_addr, client_ip, client_port
-                                    );
-
-                                    if let Err(e) =
-                                        proxy_socket.send_to(&buffer[..len], client_addr).await
-                                    {
-                                        error!(
-                                            "Failed to forward packet to client {}: {}",
-                                            client_addr, e
-                                        );
-                                    }
-                                }
+                        match &downstream {
+                            Some(queue) => {
+                                queue.push(buffer[..len].to_vec());
+                            }
+                            None => {
+                                forward_to_client(
+                                    &buffer[..len],
+                                    client_ip,
+                                    proxy_port,
+                                    &proxy_socket,
+                                    &session_manager,
+                                )
+                                .await;
                             }
                         }
                     }
+                    Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+                        // ICMP port unreachable from the (connected) target
+                        if let Ok(target_addr) = socket.peer_addr() {
+                            debug!(
+                                "Target {} unreachable for client {}: {}",
+                                target_addr, client_ip, e
+                            );
+                            session_manager.notify_backend_failure(&target_addr.ip().to_string());
+                        }
+                    }
                     Ok(Err(e)) => {
                         error!(
                             "Error receiving from target for client {}: {}",
@@ -118,6 +191,72 @@ impl SessionSocket {
     }
 }
 
+/// Send a packet from the target to every active client port of the session
+async fn forward_to_client(
+    packet: &[u8],
+    client_ip: IpAddr,
+    proxy_port: u16,
+    proxy_socket: &UdpSocket,
+    session_manager: &SessionManager,
+) {
+    // Get active client ports for this session
+    let client_ports: Vec<u16> = match session_manager.get(&client_ip) {
+        Some(session) => match session.client_ports.get(&proxy_port) {
+            Some(ports) => ports.iter().copied().collect(),
+            None => return,
+        },
+        None => return,
+    };
+    for client_port in client_ports {
+        let client_addr = SocketAddr::new(client_ip, client_port);
+        if let Err(e) = proxy_socket.send_to(packet, client_addr).await {
+            error!("Failed to forward packet to client {}: {}", client_addr, e);
+        }
+    }
+}
+
+/// Kubernetes resource a session is routed to (for session count writeback)
+///
+/// Sessions are attributed to resources rather than addresses, since several
+/// resources can share one address (e.g. Agones GameServers on one node IP).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendResource {
+    pub group: String,
+    pub version: String,
+    /// Plural resource name (e.g. "gameservers")
+    pub resource: String,
+    /// `None` for cluster-scoped resources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    pub name: String,
+}
+
+impl BackendResource {
+    /// Reference a resource selected through `mapping`
+    pub fn new(resource: &DynamicObject, mapping: &ResourceMapping) -> Option<Self> {
+        Some(Self {
+            group: mapping.group.clone(),
+            version: mapping.version.clone(),
+            resource: mapping.resource.clone(),
+            namespace: resource.metadata.namespace.clone(),
+            name: resource.metadata.name.clone()?,
+        })
+    }
+}
+
+impl std::fmt::Display for BackendResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}",
+            self.resource,
+            self.namespace.as_deref().unwrap_or("-"),
+            self.name
+        )
+    }
+}
+
 /// Session information for a client connection with multi-port support
 #[derive(Clone)]
 pub struct Session {
@@ -131,6 +270,17 @@ pub struct Session {
     /// Track client source ports for response routing
     /// Key: proxy_port -> Set of client source ports seen
     pub client_ports: HashMap<u16, HashSet<u16>>,
+    /// Player ID from the client's JWT (for logging and admin lookup)
+    pub player_id: Option<String>,
+    /// Extra metadata from the routing webhook
+    pub metadata: HashMap<String, String>,
+    /// Token whose capacity reservation stands in for this session until its first data packet
+    /// (the session is counted on its backend once this is claimed)
+    pub reservation: Option<String>,
+    /// Resource the session is routed to, if known
+    pub backend: Option<BackendResource>,
+    /// When the session was created
+    pub started_at: DateTime<Utc>,
 }
 
 impl Session {
@@ -144,6 +294,11 @@ impl Session {
             last_activity: Instant::now(),
             udp_sockets: HashMap::new(),
             client_ports: HashMap::new(),
+            player_id: None,
+            metadata: HashMap::new(),
+            reservation: None,
+            backend: None,
+            started_at: Utc::now(),
         }
     }
 
@@ -155,16 +310,26 @@ impl Session {
             last_activity: Instant::now(),
             udp_sockets: HashMap::new(),
             client_ports: HashMap::new(),
+            player_id: None,
+            metadata: HashMap::new(),
+            reservation: None,
+            backend: None,
+            started_at: Utc::now(),
         }
     }
 
     /// Get or create a dedicated UDP socket for a specific proxy port
+    ///
+    /// The socket is connected to `target_addr` so ICMP errors from the target
+    /// are reported to the receive task.
     pub async fn get_or_create_udp_socket(
         &mut self,
         proxy_port: u16,
+        target_addr: SocketAddr,
         client_addr: SocketAddr,
         proxy_socket: Arc<UdpSocket>,
         session_manager: Arc<SessionManager>,
+        traffic_limiter: Option<&Arc<PortTrafficLimiter>>,
     ) -> Result<(SessionSocket, u16), std::io::Error> {
         // Track this client port
         self.client_ports
@@ -179,7 +344,10 @@ impl Session {
         }
 
         // Create new socket
-        let session_socket = SessionSocket::new().await?;
+        let mut session_socket =
+            SessionSocket::new(traffic_limiter.map(|port| port.session())).await?;
+        session_socket.socket.connect(target_addr).await?;
+        session_socket.start_upstream_queue(session_manager.clone());
         let local_addr = session_socket.local_addr()?;
         debug!(
             "Created dedicated socket {} for client {} on proxy port {}",
@@ -251,8 +419,11 @@ impl Session {
     }
 }
 
-/// Callback type for session cleanup notifications
-pub type SessionCleanupCallback = Arc<dyn Fn(&str) + Send + Sync>;
+/// Callback type for session cleanup notifications (receives the ended session)
+pub type SessionCleanupCallback = Arc<dyn Fn(&Session) + Send + Sync>;
+
+/// Callback type for backend failure notifications (e.g. ICMP port unreachable)
+pub type BackendFailureCallback = Arc<dyn Fn(&str) + Send + Sync>;
 
 /// Session manager for tracking active client sessions with multi-port support
 /// Sessions are now tracked by client address only, established via query port
@@ -264,7 +435,10 @@ pub struct SessionManager {
     sessions: Arc<DashMap<IpAddr, Session>>,
     timeout_seconds: u64,
     /// Optional callback to notify when sessions are cleaned up
-    cleanup_callback: Option<SessionCleanupCallback>,
+    /// Shared so the background cleanup task sees callbacks registered after creation
+    cleanup_callback: Arc<std::sync::RwLock<Option<SessionCleanupCallback>>>,
+    /// Optional callback to notify when a session's target reports an error
+    failure_callback: Arc<std::sync::RwLock<Option<BackendFailureCallback>>>,
 }
 
 impl SessionManager {
@@ -273,7 +447,8 @@ impl SessionManager {
         let manager = Self {
             sessions: Arc::new(DashMap::new()),
             timeout_seconds,
-            cleanup_callback: None,
+            cleanup_callback: Arc::new(std::sync::RwLock::new(None)),
+            failure_callback: Arc::new(std::sync::RwLock::new(None)),
         };
 
         // Start cleanup task
@@ -285,9 +460,40 @@ impl SessionManager {
         manager
     }
 
-    /// Set a cleanup callback to be notified when sessions are removed
-    pub fn set_cleanup_callback(&mut self, callback: SessionCleanupCallback) {
-        self.cleanup_callback = Some(callback);
+    /// Set a cleanup callback to be notified when sessions are removed or replaced
+    pub fn set_cleanup_callback(&self, callback: SessionCleanupCallback) {
+        if let Ok(mut slot) = self.cleanup_callback.write() {
+            *slot = Some(callback);
+        }
+    }
+
+    /// Notify the cleanup callback (if set) that a session ended
+    fn notify_cleanup(&self, session: &Session) {
+        let callback = match self.cleanup_callback.read() {
+            Ok(slot) => slot.clone(),
+            Err(_) => None,
+        };
+        if let Some(callback) = callback {
+            callback(session);
+        }
+    }
+
+    /// Set a callback to be notified when a session's target reports an error
+    pub fn set_backend_failure_callback(&self, callback: BackendFailureCallback) {
+        if let Ok(mut slot) = self.failure_callback.write() {
+            *slot = Some(callback);
+        }
+    }
+
+    /// Notify the failure callback (if set) that `target_ip` reported an error
+    pub fn notify_backend_failure(&self, target_ip: &str) {
+        let callback = match self.failure_callback.read() {
+            Ok(slot) => slot.clone(),
+            Err(_) => None,
+        };
+        if let Some(callback) = callback {
+            callback(target_ip);
+        }
     }
 
     /// Get an existing session for a client IP address
@@ -320,9 +526,10 @@ impl SessionManager {
     pub async fn upsert(&self, client_addr: SocketAddr, target_addr: SocketAddr) {
         let client_ip = client_addr.ip();
 
-        // If session exists, shut down old sockets
+        // If session exists, shut down old sockets and release its backend
         if let Some(mut old_session) = self.sessions.get_mut(&client_ip) {
             old_session.shutdown_sockets().await;
+            self.notify_cleanup(&old_session);
         }
 
         let session = Session::new(target_addr);
@@ -339,9 +546,10 @@ impl SessionManager {
     ) {
         let client_ip = client_addr.ip();
 
-        // If session exists, shut down old sockets
+        // If session exists, shut down old sockets and release its backend
         if let Some(mut old_session) = self.sessions.get_mut(&client_ip) {
             old_session.shutdown_sockets().await;
+            self.notify_cleanup(&old_session);
         }
 
         let session = Session::new_multi_port(target_ip.clone(), port_mappings.clone());
@@ -355,6 +563,41 @@ impl SessionManager {
         );
     }
 
+    /// Attach the authenticated player to a client's session
+    pub fn set_player_id(&self, client_addr: &SocketAddr, player_id: Option<String>) {
+        if let Some(mut entry) = self.sessions.get_mut(&client_addr.ip()) {
+            entry.player_id = player_id;
+        }
+    }
+
+    /// Attach routing webhook metadata to a client's session
+    pub fn set_metadata(&self, client_addr: &SocketAddr, metadata: HashMap<String, String>) {
+        if let Some(mut entry) = self.sessions.get_mut(&client_addr.ip()) {
+            entry.metadata = metadata;
+        }
+    }
+
+    /// Attach the backend resource a client's session is routed to
+    pub fn set_backend(&self, client_addr: &SocketAddr, backend: Option<BackendResource>) {
+        if let Some(mut entry) = self.sessions.get_mut(&client_addr.ip()) {
+            entry.backend = backend;
+        }
+    }
+
+    /// Hold a token's reservation on the session until its first data packet
+    pub fn set_reservation(&self, client_addr: &SocketAddr, token: String) {
+        if let Some(mut entry) = self.sessions.get_mut(&client_addr.ip()) {
+            entry.reservation = Some(token);
+        }
+    }
+
+    /// Take the session's unclaimed reservation: (token, target IP)
+    pub fn take_reservation(&self, client_addr: &SocketAddr) -> Option<(String, String)> {
+        let mut entry = self.sessions.get_mut(&client_addr.ip())?;
+        let token = entry.reservation.take()?;
+        Some((token, entry.target_ip.clone()))
+    }
+
     /// Touch a session to update its last activity
     pub fn touch(&self, client_ip: &IpAddr) {
         if let Some(mut entry) = self.sessions.get_mut(client_ip) {
@@ -367,6 +610,36 @@ impl SessionManager {
         self.touch(&client_addr.ip());
     }
 
+    /// Sessions of an authenticated player, keyed by client IP (for admin lookup)
+    pub fn find_by_player(&self, player_id: &str) -> Vec<(IpAddr, Session)> {
+        self.sessions
+            .iter()
+            .filter(|entry| entry.player_id.as_deref() == Some(player_id))
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect()
+    }
+
+    /// Counted sessions per backend resource, with the start of the newest one
+    ///
+    /// Sessions still holding an unclaimed reservation are not counted yet.
+    pub fn backend_sessions(&self) -> HashMap<BackendResource, (usize, DateTime<Utc>)> {
+        let mut counts: HashMap<BackendResource, (usize, DateTime<Utc>)> = HashMap::new();
+        for entry in self.sessions.iter() {
+            let Some(backend) = &entry.backend else {
+                continue;
+            };
+            if entry.reservation.is_some() {
+                continue;
+            }
+            let (count, newest) = counts
+                .entry(backend.clone())
+                .or_insert((0, entry.started_at));
+            *count += 1;
+            *newest = (*newest).max(entry.started_at);
+        }
+        counts
+    }
+
     /// Get the number of active sessions
     pub fn count(&self) -> usize {
         self.sessions.len()
@@ -407,12 +680,19 @@ impl SessionManager {
             // Remove timed out sessions and shutdown their sockets
             for key in to_remove {
                 if let Some((_, mut session)) = self.sessions.remove(&key) {
-                    debug!("Session timed out: {:?}", key);
+                    match &session.player_id {
+                        Some(player_id) => debug!(
+                            "Session timed out: {:?} (player {}, metadata {:?})",
+                            key, player_id, session.metadata
+                        ),
+                        None => debug!(
+                            "Session timed out: {:?} (metadata {:?})",
+                            key, session.metadata
+                        ),
+                    }
 
                     // Notify callback if set
-                    if let Some(callback) = &self.cleanup_callback {
-                        callback(&session.target_ip);
-                    }
+                    self.notify_cleanup(&session);
 
                     session.shutdown_sockets().await;
                     removed_count += 1;
@@ -545,4 +825,72 @@ mod tests {
         assert_eq!(session1.target_ip, session2.target_ip);
         assert_eq!(manager.count(), 1); // Still only one session
     }
+
+    #[tokio::test]
+    async fn test_cleanup_callback_on_replace() {
+        use std::sync::Mutex;
+
+        let manager = SessionManager::new(300);
+        let released = Arc::new(Mutex::new(Vec::new()));
+        let released_clone = released.clone();
+        manager.set_cleanup_callback(Arc::new(move |session: &Session| {
+            released_clone
+                .lock()
+                .unwrap()
+                .push(session.target_ip.clone());
+        }));
+
+        let client_addr: SocketAddr = "127.0.0.1:12345".parse().unwrap();
+        manager
+            .upsert(client_addr, "10.0.0.1:7777".parse().unwrap())
+            .await;
+        assert!(released.lock().unwrap().is_empty());
+
+        // Replacing the session releases the previous backend
+        manager
+            .upsert(client_addr, "10.0.0.2:7777".parse().unwrap())
+            .await;
+        assert_eq!(*released.lock().unwrap(), vec!["10.0.0.1".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_session_player_and_metadata() {
+        let manager = SessionManager::new(300);
+        let client_addr: SocketAddr = "127.0.0.1:12345".parse().unwrap();
+        manager
+            .upsert(client_addr, "10.0.0.1:7777".parse().unwrap())
+            .await;
+        assert_eq!(manager.get_by_addr(&client_addr).unwrap().player_id, None);
+
+        manager.set_player_id(&client_addr, Some("player-42".to_string()));
+        assert_eq!(
+            manager
+                .get_by_addr(&client_addr)
+                .unwrap()
+                .player_id
+                .as_deref(),
+            Some("player-42")
+        );
+        let found = manager.find_by_player("player-42");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, client_addr.ip());
+        assert!(manager.find_by_player("player-7").is_empty());
+
+        manager.set_metadata(
+            &client_addr,
+            HashMap::from([("match".to_string(), "m-1".to_string())]),
+        );
+        assert_eq!(
+            manager.get_by_addr(&client_addr).unwrap().metadata["match"],
+            "m-1"
+        );
+
+        // A new session starts without a player or metadata
+        manager
+            .upsert(client_addr, "10.0.0.2:7777".parse().unwrap())
+            .await;
+        let session = manager.get_by_addr(&client_addr).unwrap();
+        assert_eq!(session.player_id, None);
+        assert!(session.metadata.is_empty());
+    }
 }
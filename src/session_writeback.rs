@@ -0,0 +1,445 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use k8s_openapi::chrono::{DateTime, SecondsFormat, Utc};
+use kube::discovery::ApiResource;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::k8s_client::K8sClient;
+use crate::session::{BackendResource, SessionManager};
+
+/// Field manager owning the written keys (server-side apply)
+const FIELD_MANAGER: &str = "udp-director-sessions";
+
+/// Where session counts are written on backend resources
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum WritebackTarget {
+    #[default]
+    Annotation,
+    Label,
+}
+
+/// Write director session counts back to backend resources
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionWritebackConfig {
+    /// Write to an annotation or a label (default: annotation)
+    #[serde(default)]
+    pub target: WritebackTarget,
+    /// Key holding the session count (default: "udp-director/sessions")
+    #[serde(default = "default_count_key")]
+    pub count_key: String,
+    /// Key holding the time of the last routed session (default: "udp-director/last-session")
+    /// RFC 3339 for annotations, Unix seconds for labels
+    #[serde(default = "default_last_session_key")]
+    pub last_session_key: Option<String>,
+    /// Minimum seconds between writes to a backend (default: 5)
+    #[serde(default = "default_debounce_seconds")]
+    pub debounce_seconds: u64,
+}
+
+fn default_count_key() -> String {
+    "udp-director/sessions".to_string()
+}
+
+fn default_last_session_key() -> Option<String> {
+    Some("udp-director/last-session".to_string())
+}
+
+fn default_debounce_seconds() -> u64 {
+    5
+}
+
+/// Last written (count, last session) of a resource
+type Written = (usize, Option<DateTime<Utc>>);
+
+/// Debounced writer of per-resource session counts
+#[derive(Clone)]
+pub struct SessionWriter {
+    config: SessionWritebackConfig,
+    k8s_client: K8sClient,
+    /// Sessions carry the resource they are routed to
+    session_manager: SessionManager,
+    /// Resources written with a non-zero count (written again with 0 once idle)
+    written: Arc<DashMap<BackendResource, Written>>,
+    /// Discovered API resources keyed by group/version/plural
+    api_resources: Arc<DashMap<String, ApiResource>>,
+}
+
+impl SessionWriter {
+    /// Create a writer counting the sessions of `session_manager`
+    pub fn new(
+        config: SessionWritebackConfig,
+        k8s_client: K8sClient,
+        session_manager: SessionManager,
+    ) -> Self {
+        Self {
+            config,
+            k8s_client,
+            session_manager,
+            written: Arc::new(DashMap::new()),
+            api_resources: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Write changed counts every `debounceSeconds`
+    pub async fn run(self) -> Result<()> {
+        info!(
+            "Writing session counts to backend {:?} '{}'",
+            self.config.target, self.config.count_key
+        );
+
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.debounce_seconds.max(1)));
+
+        loop {
+            interval.tick().await;
+            self.flush().await;
+        }
+    }
+
+    /// Write every resource whose count or last session changed since the last write
+    ///
+    /// Sessions are counted per resource (not per address), so resources sharing an
+    /// address, like Agones GameServers reporting their node IP, get their own counts.
+    /// Resources whose last session ended are written once more with 0 and forgotten.
+    async fn flush(&self) {
+        let live = self.session_manager.backend_sessions();
+
+        let mut pending: Vec<(BackendResource, Written)> = live
+            .iter()
+            .filter_map(|(backend, (count, newest))| {
+                let previous = self.written.get(backend).map(|w| *w);
+                let last_session = previous
+                    .and_then(|(_, last)| last)
+                    .map_or(*newest, |last| last.max(*newest));
+                let update = (*count, Some(last_session));
+                (previous != Some(update)).then(|| (backend.clone(), update))
+            })
+            .collect();
+        pending.extend(
+            self.written
+                .iter()
+                .filter(|entry| !live.contains_key(entry.key()))
+                .map(|entry| (entry.key().clone(), (0, entry.1))),
+        );
+
+        for (backend, (count, last_session)) in pending {
+            match self.write(&backend, count, last_session).await {
+                Ok(()) => {
+                    debug!("Wrote session count {} to {}", count, backend);
+                    if count == 0 {
+                        self.written.remove(&backend);
+                    } else {
+                        self.written.insert(backend, (count, last_session));
+                    }
+                }
+                Err(e) => warn!("Failed to write session count to {}: {}", backend, e),
+            }
+        }
+    }
+
+    /// Apply the count (and last session time) to a backend resource
+    async fn write(
+        &self,
+        backend: &BackendResource,
+        count: usize,
+        last_session: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let api_resource = self.api_resource(backend).await?;
+
+        let mut values = BTreeMap::from([(self.config.count_key.clone(), count.to_string())]);
+        if let (Some(key), Some(time)) = (&self.config.last_session_key, last_session) {
+            values.insert(key.clone(), self.format_time(time));
+        }
+
+        let (labels, annotations) = match self.config.target {
+            WritebackTarget::Annotation => (BTreeMap::new(), values),
+            WritebackTarget::Label => (values, BTreeMap::new()),
+        };
+
+        self.k8s_client
+            .apply_metadata(
+                &api_resource,
+                backend.namespace.as_deref(),
+                &backend.name,
+                labels,
+                annotations,
+                FIELD_MANAGER,
+            )
+            .await
+    }
+
+    /// Label values may not contain ':', so labels use Unix seconds
+    fn format_time(&self, time: DateTime<Utc>) -> String {
+        match self.config.target {
+            WritebackTarget::Annotation => time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            WritebackTarget::Label => time.timestamp().to_string(),
+        }
+    }
+
+    /// API resource of a backend's type, discovered once
+    async fn api_resource(&self, backend: &BackendResource) -> Result<ApiResource> {
+        let key = format!("{}/{}/{}", backend.group, backend.version, backend.resource);
+        if let Some(api_resource) = self.api_resources.get(&key) {
+            return Ok(api_resource.clone());
+        }
+
+        let api_resource = self
+            .k8s_client
+            .resolve_api_resource(&backend.group, &backend.version, &backend.resource)
+            .await?;
+        self.api_resources.insert(key, api_resource.clone());
+        Ok(api_resource)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{Method, Request, Response};
+    use hyper_util::rt::TokioIo;
+    use serde_json::{Value, json};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+
+    /// Minimal API server answering discovery and recording apply patches
+    async fn start_fake_api_server() -> (String, Arc<Mutex<Vec<(String, Value)>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let patches: Arc<Mutex<Vec<(String, Value)>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = patches.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let patches = patches.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                        let patches = patches.clone();
+                        async move {
+                            let method = req.method().clone();
+                            let path = req.uri().path().to_string();
+                            let body = req.into_body().collect().await?.to_bytes();
+
+                            let response = match method {
+                                Method::PATCH => {
+                                    let object: Value = serde_json::from_slice(&body).unwrap();
+                                    patches.lock().unwrap().push((path, object.clone()));
+                                    object
+                                }
+                                _ => json!({
+                                    "kind": "APIResourceList",
+                                    "apiVersion": "v1",
+                                    "groupVersion": "agones.dev/v1",
+                                    "resources": [{
+                                        "name": "gameservers",
+                                        "singularName": "gameserver",
+                                        "namespaced": true,
+                                        "kind": "GameServer",
+                                        "verbs": ["get", "list", "patch"]
+                                    }]
+                                }),
+                            };
+                            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(
+                                response.to_string(),
+                            ))))
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        (url, recorded)
+    }
+
+    fn gameserver(name: &str) -> BackendResource {
+        BackendResource {
+            group: "agones.dev".to_string(),
+            version: "v1".to_string(),
+            resource: "gameservers".to_string(),
+            namespace: Some("games".to_string()),
+            name: name.to_string(),
+        }
+    }
+
+    /// Route a client to a backend resource (as the query server or default endpoint does)
+    async fn route(sessions: &SessionManager, client: &str, address: &str, backend: &str) {
+        let client = client.parse().unwrap();
+        sessions
+            .upsert_multi_port(client, address.to_string(), HashMap::new())
+            .await;
+        sessions.set_backend(&client, Some(gameserver(backend)));
+    }
+
+    fn writer(url: &str, config: &str) -> SessionWriter {
+        let config: SessionWritebackConfig = serde_yaml::from_str(config).unwrap();
+        let client = kube::Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap();
+        SessionWriter::new(
+            config,
+            K8sClient::from_client(client),
+            SessionManager::new(300),
+        )
+    }
+
+    /// Written count per patched path, in write order
+    fn counts(patches: &[(String, Value)], key: &str) -> Vec<(String, String)> {
+        patches
+            .iter()
+            .map(|(path, object)| {
+                let name = path.rsplit('/').next().unwrap().to_string();
+                let count = object["metadata"]["annotations"][key]
+                    .as_str()
+                    .unwrap()
+                    .to_string();
+                (name, count)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_counts_written_as_annotations() {
+        let (url, patches) = start_fake_api_server().await;
+        let writer = writer(&url, "{}");
+        let sessions = writer.session_manager.clone();
+        route(&sessions, "192.0.2.1:5000", "10.0.0.1", "gs-1").await;
+        route(&sessions, "192.0.2.2:5000", "10.0.0.1", "gs-1").await;
+        writer.flush().await;
+
+        {
+            let patches = patches.lock().unwrap();
+            assert_eq!(patches.len(), 1);
+            let (path, object) = &patches[0];
+            assert_eq!(
+                path,
+                "/apis/agones.dev/v1/namespaces/games/gameservers/gs-1"
+            );
+            assert_eq!(object["kind"], "GameServer");
+            let annotations = &object["metadata"]["annotations"];
+            assert_eq!(annotations["udp-director/sessions"], "2");
+            assert!(
+                annotations["udp-director/last-session"]
+                    .as_str()
+                    .unwrap()
+                    .ends_with('Z')
+            );
+            assert!(object["metadata"].get("labels").is_none());
+        }
+
+        // Unchanged counts are not rewritten
+        writer.flush().await;
+        assert_eq!(patches.lock().unwrap().len(), 1);
+
+        // Sessions that end are written as 0 once, then the resource is forgotten
+        sessions.clear_all().await;
+        writer.flush().await;
+        writer.flush().await;
+        let patches = patches.lock().unwrap();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(
+            patches[1].1["metadata"]["annotations"]["udp-director/sessions"],
+            "0"
+        );
+        assert!(writer.written.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_counts_written_as_labels() {
+        let (url, patches) = start_fake_api_server().await;
+        let writer = writer(&url, "target: label\ncountKey: players");
+        route(
+            &writer.session_manager,
+            "192.0.2.1:5000",
+            "10.0.0.1",
+            "gs-1",
+        )
+        .await;
+        writer.flush().await;
+
+        let patches = patches.lock().unwrap();
+        let labels = &patches[0].1["metadata"]["labels"];
+        assert_eq!(labels["players"], "1");
+        assert!(
+            labels["udp-director/last-session"]
+                .as_str()
+                .unwrap()
+                .parse::<i64>()
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shared_address_counted_per_resource() {
+        let (url, patches) = start_fake_api_server().await;
+        let writer = writer(&url, "{}");
+        let sessions = writer.session_manager.clone();
+        // Two GameServers on the same node IP
+        route(&sessions, "192.0.2.1:5000", "10.0.0.1", "gs-1").await;
+        route(&sessions, "192.0.2.2:5000", "10.0.0.1", "gs-1").await;
+        route(&sessions, "192.0.2.3:5000", "10.0.0.1", "gs-2").await;
+        writer.flush().await;
+
+        let mut written = counts(&patches.lock().unwrap(), "udp-director/sessions");
+        written.sort();
+        assert_eq!(
+            written,
+            vec![
+                ("gs-1".to_string(), "2".to_string()),
+                ("gs-2".to_string(), "1".to_string())
+            ]
+        );
+
+        // A client moving to the other GameServer updates both counts
+        route(&sessions, "192.0.2.2:5000", "10.0.0.1", "gs-2").await;
+        writer.flush().await;
+        let mut written = counts(&patches.lock().unwrap()[2..], "udp-director/sessions");
+        written.sort();
+        assert_eq!(
+            written,
+            vec![
+                ("gs-1".to_string(), "1".to_string()),
+                ("gs-2".to_string(), "2".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unattributed_and_reserved_sessions_not_counted() {
+        let (url, patches) = start_fake_api_server().await;
+        let writer = writer(&url, "{}");
+        let sessions = writer.session_manager.clone();
+        // No resource known (e.g. routed before writeback knew it)
+        sessions
+            .upsert_multi_port(
+                "192.0.2.1:5000".parse().unwrap(),
+                "10.0.0.1".to_string(),
+                HashMap::new(),
+            )
+            .await;
+        // Still holding an unclaimed reservation
+        route(&sessions, "192.0.2.2:5000", "10.0.0.1", "gs-1").await;
+        sessions.set_reservation(&"192.0.2.2:5000".parse().unwrap(), "token".to_string());
+        writer.flush().await;
+        assert!(patches.lock().unwrap().is_empty());
+
+        // The first packet claims the reservation
+        sessions.take_reservation(&"192.0.2.2:5000".parse().unwrap());
+        writer.flush().await;
+        assert_eq!(
+            counts(&patches.lock().unwrap(), "udp-director/sessions"),
+            vec![("gs-1".to_string(), "1".to_string())]
+        );
+    }
+}
@@ -0,0 +1,656 @@
+use anyhow::{Context, Result, bail};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_rustls::rustls::pki_types::PrivatePkcs8KeyDer;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::config::Protocol;
+use crate::session::BackendResource;
+use crate::token_cache::TokenTarget;
+
+/// Signature scheme of signed tokens
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SignedTokenAlgorithm {
+    /// HMAC-SHA256 with a shared secret (HS256)
+    Hmac,
+    /// Ed25519 signatures (EdDSA); retired keys can be kept as public keys
+    Ed25519,
+}
+
+impl SignedTokenAlgorithm {
+    fn jwt_algorithm(self) -> Algorithm {
+        match self {
+            SignedTokenAlgorithm::Hmac => Algorithm::HS256,
+            SignedTokenAlgorithm::Ed25519 => Algorithm::EdDSA,
+        }
+    }
+}
+
+/// Stateless session tokens that any replica sharing the keys can validate
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedTokenConfig {
+    #[serde(default = "default_algorithm")]
+    pub algorithm: SignedTokenAlgorithm,
+    /// Directory of key files, one per key ID (a mounted Secret)
+    #[serde(default = "default_keys_dir")]
+    pub keys_dir: String,
+    /// Key ID used to sign new tokens when `keysDir` has no `current` file; other
+    /// keys in `keysDir` are still accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_key_id: Option<String>,
+    /// How often keys are re-read from `keysDir` (default: 30)
+    #[serde(default = "default_reload_seconds")]
+    pub reload_seconds: u64,
+    /// Also carry the player ID, webhook session metadata and backend resource
+    /// (default: false)
+    ///
+    /// Tokens are signed, not encrypted: clients can read these claims. Without them
+    /// a session reset on another replica has no player ID, metadata or backend for
+    /// session count writeback.
+    #[serde(default)]
+    pub include_session_data: bool,
+}
+
+/// File in `keysDir` naming the signing key, re-read with the keys
+const CURRENT_KEY_FILE: &str = "current";
+
+fn default_algorithm() -> SignedTokenAlgorithm {
+    SignedTokenAlgorithm::Hmac
+}
+
+fn default_keys_dir() -> String {
+    "/var/run/secrets/udp-director/token-keys".to_string()
+}
+
+fn default_reload_seconds() -> u64 {
+    30
+}
+
+impl SignedTokenConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self
+            .signing_key_id
+            .as_ref()
+            .is_some_and(|id| id.trim().is_empty())
+        {
+            bail!("signedTokens.signingKeyId must not be empty");
+        }
+        if !Path::new(&self.keys_dir).is_dir() {
+            bail!("signedTokens.keysDir {} is not a directory", self.keys_dir);
+        }
+        Ok(())
+    }
+}
+
+/// A single port mapping carried in the token
+#[derive(Debug, Serialize, Deserialize)]
+struct PortClaim {
+    port: u16,
+    protocol: Protocol,
+    target: u16,
+}
+
+/// Token payload
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenClaims {
+    /// Target IP
+    ip: String,
+    ports: Vec<PortClaim>,
+    exp: u64,
+    iat: u64,
+    /// Client IP the token was issued to
+    cip: IpAddr,
+    /// Random nonce so tokens for the same target differ; also the token ID
+    jti: String,
+    /// Player ID (only with `includeSessionData`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pid: Option<String>,
+    /// Webhook session metadata (only with `includeSessionData`)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    meta: HashMap<String, String>,
+    /// Backend resource for session count writeback (only with `includeSessionData`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    res: Option<BackendResource>,
+}
+
+/// A token that passed signature and expiry checks
+pub struct VerifiedToken {
+    /// Unique token ID (for use counting and revocation)
+    pub id: String,
+    pub target: TokenTarget,
+    pub client_ip: IpAddr,
+}
+
+/// Keys loaded from one file
+struct TokenKey {
+    /// Absent for verify-only keys (Ed25519 public keys)
+    encoding: Option<EncodingKey>,
+    decoding: DecodingKey,
+}
+
+/// Signs and verifies session tokens with a rotatable keyring
+#[derive(Clone)]
+pub struct TokenSigner {
+    config: SignedTokenConfig,
+    /// Key ID -> keys
+    keys: Arc<RwLock<HashMap<String, Arc<TokenKey>>>>,
+    /// Key ID used to sign new tokens
+    signing_key_id: Arc<RwLock<String>>,
+    /// Raw file contents, to detect changes on reload
+    files: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+}
+
+impl TokenSigner {
+    /// Load the keys; fails if the signing key is missing or cannot sign
+    pub fn new(config: SignedTokenConfig) -> Result<Self> {
+        let files = read_key_files(Path::new(&config.keys_dir))?;
+        let keys = parse_keys(&files, config.algorithm)?;
+        let signing_key_id = signing_key_id(&config, &files)?;
+        check_signing_key(&keys, &signing_key_id)?;
+        info!(
+            "Signed session tokens enabled ({:?}, signing key {}, {} key(s) from {})",
+            config.algorithm,
+            signing_key_id,
+            keys.len(),
+            config.keys_dir
+        );
+        Ok(Self {
+            config,
+            keys: Arc::new(RwLock::new(keys)),
+            signing_key_id: Arc::new(RwLock::new(signing_key_id)),
+            files: Arc::new(RwLock::new(files)),
+        })
+    }
+
+    /// Re-read keys periodically so rotation needs no restart
+    pub async fn run(self) -> Result<()> {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.reload_seconds.max(1)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.reload() {
+                warn!("Failed to reload token signing keys: {:#}", e);
+            }
+        }
+    }
+
+    /// Replace the keyring and signing key if any file changed; keeps the old ones on error
+    fn reload(&self) -> Result<bool> {
+        let files = read_key_files(Path::new(&self.config.keys_dir))?;
+        if *self.files.read().unwrap_or_else(|e| e.into_inner()) == files {
+            return Ok(false);
+        }
+        let keys = parse_keys(&files, self.config.algorithm)?;
+        let signing_key_id = signing_key_id(&self.config, &files)?;
+        check_signing_key(&keys, &signing_key_id)?;
+        info!(
+            "Reloaded {} token signing key(s), signing with {}",
+            keys.len(),
+            signing_key_id
+        );
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = keys;
+        *self
+            .signing_key_id
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = signing_key_id;
+        *self.files.write().unwrap_or_else(|e| e.into_inner()) = files;
+        Ok(true)
+    }
+
+    /// Encode the target into a signed token valid for `ttl_seconds`
+    pub fn sign(
+        &self,
+        target: &TokenTarget,
+        client_ip: IpAddr,
+        ttl_seconds: u64,
+    ) -> Result<String> {
+        let signing_key_id = self
+            .signing_key_id
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let key = self
+            .keys
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&signing_key_id)
+            .cloned()
+            .context("Signing key not loaded")?;
+        let encoding = key.encoding.as_ref().context("Signing key cannot sign")?;
+
+        let mut ports: Vec<PortClaim> = target
+            .port_mappings
+            .iter()
+            .map(|(&(port, protocol), &target)| PortClaim {
+                port,
+                protocol,
+                target,
+            })
+            .collect();
+        ports.sort_by_key(|p| (p.port, p.protocol.to_string()));
+
+        let session_data = self.config.include_session_data;
+        let now = jsonwebtoken::get_current_timestamp();
+        let claims = TokenClaims {
+            ip: target.cluster_ip.clone(),
+            ports,
+            exp: now + ttl_seconds,
+            iat: now,
+            cip: client_ip,
+            jti: Uuid::new_v4().simple().to_string(),
+            pid: target.player_id.clone().filter(|_| session_data),
+            meta: if session_data {
+                target.metadata.clone()
+            } else {
+                HashMap::new()
+            },
+            res: target.backend.clone().filter(|_| session_data),
+        };
+
+        let mut header = Header::new(self.config.algorithm.jwt_algorithm());
+        header.kid = Some(signing_key_id);
+        Ok(jsonwebtoken::encode(&header, &claims, encoding)?)
+    }
+
+    /// Check signature and expiry; `None` if the token is invalid
+    pub fn verify(&self, token: &str) -> Option<VerifiedToken> {
+        let header = jsonwebtoken::decode_header(token).ok()?;
+        if header.alg != self.config.algorithm.jwt_algorithm() {
+            debug!("Rejected signed token with algorithm {:?}", header.alg);
+            return None;
+        }
+        let key = self
+            .keys
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(header.kid.as_deref()?)
+            .cloned();
+        let Some(key) = key else {
+            debug!("Rejected signed token with unknown key {:?}", header.kid);
+            return None;
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = 0;
+        validation.set_required_spec_claims(&["exp"]);
+        let claims = match jsonwebtoken::decode::<TokenClaims>(token, &key.decoding, &validation) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                debug!("Rejected signed token: {}", e);
+                return None;
+            }
+        };
+
+        let port_mappings = claims
+            .ports
+            .into_iter()
+            .map(|p| ((p.port, p.protocol), p.target))
+            .collect();
+        Some(VerifiedToken {
+            id: claims.jti,
+            target: TokenTarget::multi_port(claims.ip, port_mappings)
+                .with_player_id(claims.pid)
+                .with_metadata(claims.meta)
+                .with_backend(claims.res),
+            client_ip: claims.cip,
+        })
+    }
+}
+
+/// The `current` file if present, otherwise `signingKeyId`
+fn signing_key_id(config: &SignedTokenConfig, files: &HashMap<String, Vec<u8>>) -> Result<String> {
+    if let Some(current) = files.get(CURRENT_KEY_FILE) {
+        let key_id = std::str::from_utf8(current)
+            .with_context(|| format!("{} in {} is not UTF-8", CURRENT_KEY_FILE, config.keys_dir))?
+            .trim();
+        if key_id.is_empty() {
+            bail!("{} in {} is empty", CURRENT_KEY_FILE, config.keys_dir);
+        }
+        return Ok(key_id.to_string());
+    }
+    config.signing_key_id.clone().with_context(|| {
+        format!(
+            "signedTokens needs signingKeyId or a {} file in {}",
+            CURRENT_KEY_FILE, config.keys_dir
+        )
+    })
+}
+
+fn check_signing_key(keys: &HashMap<String, Arc<TokenKey>>, signing_key_id: &str) -> Result<()> {
+    match keys.get(signing_key_id) {
+        Some(key) if key.encoding.is_some() => Ok(()),
+        Some(_) => bail!("Signing key {} is a public key", signing_key_id),
+        None => bail!("Signing key {} not found", signing_key_id),
+    }
+}
+
+/// Read one key per file; the file name is the key ID (or `current`)
+///
+/// Hidden entries (the `..data` links of Secret volumes) and directories are skipped.
+fn read_key_files(dir: &Path) -> Result<HashMap<String, Vec<u8>>> {
+    let mut files = HashMap::new();
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read token keys from {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || !entry.path().is_file() {
+            continue;
+        }
+        let contents = std::fs::read(entry.path())
+            .with_context(|| format!("Failed to read token key {}", name))?;
+        files.insert(name, contents);
+    }
+    Ok(files)
+}
+
+fn parse_keys(
+    files: &HashMap<String, Vec<u8>>,
+    algorithm: SignedTokenAlgorithm,
+) -> Result<HashMap<String, Arc<TokenKey>>> {
+    let mut keys = HashMap::new();
+    for (key_id, contents) in files {
+        if key_id == CURRENT_KEY_FILE {
+            continue;
+        }
+        let key = match algorithm {
+            SignedTokenAlgorithm::Hmac => {
+                let secret = contents.trim_ascii_end();
+                if secret.is_empty() {
+                    warn!("Skipping empty token key {}", key_id);
+                    continue;
+                }
+                TokenKey {
+                    encoding: Some(EncodingKey::from_secret(secret)),
+                    decoding: DecodingKey::from_secret(secret),
+                }
+            }
+            SignedTokenAlgorithm::Ed25519 => parse_ed25519_key(contents)
+                .with_context(|| format!("Invalid Ed25519 token key {}", key_id))?,
+        };
+        keys.insert(key_id.clone(), Arc::new(key));
+    }
+    Ok(keys)
+}
+
+/// A PKCS#8 private key (signs and verifies) or a public key (verifies only)
+fn parse_ed25519_key(contents: &[u8]) -> Result<TokenKey> {
+    if let Ok(private_key) = PrivatePkcs8KeyDer::from_pem_slice(contents) {
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(private_key.secret_pkcs8_der())
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        return Ok(TokenKey {
+            encoding: Some(EncodingKey::from_ed_der(private_key.secret_pkcs8_der())),
+            decoding: DecodingKey::from_ed_der(key_pair.public_key().as_ref()),
+        });
+    }
+    Ok(TokenKey {
+        encoding: None,
+        decoding: DecodingKey::from_ed_pem(contents)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn target() -> TokenTarget {
+        let mut port_mappings = HashMap::new();
+        port_mappings.insert((7777, Protocol::Udp), 7000);
+        port_mappings.insert((7777, Protocol::Tcp), 7001);
+        TokenTarget::multi_port("10.0.0.5".to_string(), port_mappings)
+            .with_player_id(Some("player-42".to_string()))
+    }
+
+    fn signer_for(dir: &Path, algorithm: SignedTokenAlgorithm, key_id: &str) -> TokenSigner {
+        TokenSigner::new(SignedTokenConfig {
+            algorithm,
+            keys_dir: dir.to_string_lossy().to_string(),
+            signing_key_id: Some(key_id.to_string()),
+            reload_seconds: 30,
+            include_session_data: false,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hmac_token_verifies_on_other_replica_and_rotates() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        std::fs::write(dir.join("k1"), "first-secret\n").unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+
+        let replica_a = signer_for(dir, SignedTokenAlgorithm::Hmac, "k1");
+        let replica_b = signer_for(dir, SignedTokenAlgorithm::Hmac, "k1");
+        let token = replica_a.sign(&target(), client, 60).unwrap();
+
+        let verified = replica_b.verify(&token).unwrap();
+        assert_eq!(verified.target.cluster_ip, "10.0.0.5");
+        assert_eq!(verified.target.port_mappings, target().port_mappings);
+        assert_eq!(verified.client_ip, client);
+        assert_eq!(verified.id.len(), 32);
+
+        // Tampering breaks the signature
+        let mut tampered = token.clone();
+        tampered.replace_range(tampered.len() - 4.., "AAAA");
+        assert!(replica_b.verify(&tampered).is_none());
+
+        // Rotation without restart: `current` switches signing to k2, k1 tokens stay
+        // valid until k1 is removed
+        std::fs::write(dir.join("k2"), "second-secret").unwrap();
+        std::fs::write(dir.join("current"), "k2\n").unwrap();
+        assert!(replica_a.reload().unwrap());
+        let new_token = replica_a.sign(&target(), client, 60).unwrap();
+        let kid = |token: &str| jsonwebtoken::decode_header(token).unwrap().kid;
+        assert_eq!(kid(&token).as_deref(), Some("k1"));
+        assert_eq!(kid(&new_token).as_deref(), Some("k2"));
+        assert!(replica_a.verify(&new_token).is_some());
+        assert!(replica_a.verify(&token).is_some());
+        assert!(replica_b.reload().unwrap());
+        assert!(replica_b.verify(&new_token).is_some());
+
+        std::fs::remove_file(dir.join("k1")).unwrap();
+        assert!(replica_a.reload().unwrap());
+        assert!(replica_a.verify(&token).is_none());
+        assert!(replica_a.verify(&new_token).is_some());
+    }
+
+    #[test]
+    fn test_ed25519_token_and_expiry() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+        std::fs::write(dir.join("ed1"), key_pair.serialize_pem()).unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+
+        let signer = signer_for(dir, SignedTokenAlgorithm::Ed25519, "ed1");
+        let token = signer.sign(&target(), client, 60).unwrap();
+        assert_eq!(signer.verify(&token).unwrap().target.port_mappings.len(), 2);
+
+        // Tokens of a retired key verify with just its public key
+        let old_tmp = temp_dir();
+        let old_dir = old_tmp.path();
+        let old_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+        std::fs::write(old_dir.join("ed0"), old_pair.serialize_pem()).unwrap();
+        let old_token = signer_for(old_dir, SignedTokenAlgorithm::Ed25519, "ed0")
+            .sign(&target(), client, 60)
+            .unwrap();
+        assert!(signer.verify(&old_token).is_none());
+        std::fs::write(dir.join("ed0"), old_pair.public_key_pem()).unwrap();
+        assert!(signer.reload().unwrap());
+        assert!(signer.verify(&old_token).is_some());
+
+        // Expired tokens are rejected
+        let expired = signer.sign(&target(), client, 0).unwrap();
+        std::thread::sleep(Duration::from_millis(1100));
+        assert!(signer.verify(&expired).is_none());
+    }
+
+    #[test]
+    fn test_wrong_key_and_algorithm_rejected() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        std::fs::write(dir.join("k1"), "first-secret").unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let signer = signer_for(dir, SignedTokenAlgorithm::Hmac, "k1");
+
+        // Same key ID, different secret (another deployment)
+        let other_tmp = temp_dir();
+        let other_dir = other_tmp.path();
+        std::fs::write(other_dir.join("k1"), "other-secret").unwrap();
+        let foreign = signer_for(other_dir, SignedTokenAlgorithm::Hmac, "k1")
+            .sign(&target(), client, 60)
+            .unwrap();
+        assert!(signer.verify(&foreign).is_none());
+
+        // Unknown or missing `kid`
+        let claims = serde_json::json!({
+            "ip": "10.0.0.5",
+            "ports": [],
+            "exp": jsonwebtoken::get_current_timestamp() + 60,
+            "iat": jsonwebtoken::get_current_timestamp(),
+            "cip": client,
+            "jti": "x",
+        });
+        let encode = |kid: Option<&str>, algorithm: Algorithm| {
+            let mut header = Header::new(algorithm);
+            header.kid = kid.map(str::to_string);
+            jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(b"first-secret"))
+                .unwrap()
+        };
+        assert!(
+            signer
+                .verify(&encode(Some("k1"), Algorithm::HS256))
+                .is_some()
+        );
+        assert!(
+            signer
+                .verify(&encode(Some("k9"), Algorithm::HS256))
+                .is_none()
+        );
+        assert!(signer.verify(&encode(None, Algorithm::HS256)).is_none());
+        // Only the configured algorithm is accepted
+        assert!(
+            signer
+                .verify(&encode(Some("k1"), Algorithm::HS512))
+                .is_none()
+        );
+        assert!(signer.verify("not-a-token").is_none());
+    }
+
+    #[test]
+    fn test_signing_key_errors() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        std::fs::write(dir.join("k1"), "first-secret").unwrap();
+        let config = |key_id: &str, algorithm| SignedTokenConfig {
+            algorithm,
+            keys_dir: dir.to_string_lossy().to_string(),
+            signing_key_id: Some(key_id.to_string()),
+            reload_seconds: 30,
+            include_session_data: false,
+        };
+
+        let err = TokenSigner::new(config("k2", SignedTokenAlgorithm::Hmac)).err();
+        assert!(
+            err.unwrap()
+                .to_string()
+                .contains("Signing key k2 not found")
+        );
+
+        // A public key can verify but not sign
+        let ed_tmp = temp_dir();
+        let ed_dir = ed_tmp.path();
+        let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap();
+        std::fs::write(ed_dir.join("ed1"), key_pair.public_key_pem()).unwrap();
+        let err = TokenSigner::new(SignedTokenConfig {
+            keys_dir: ed_dir.to_string_lossy().to_string(),
+            ..config("ed1", SignedTokenAlgorithm::Ed25519)
+        })
+        .err();
+        assert!(err.unwrap().to_string().contains("is a public key"));
+
+        // Removing the signing key fails the reload and keeps the loaded keys
+        let signer = TokenSigner::new(config("k1", SignedTokenAlgorithm::Hmac)).unwrap();
+        std::fs::remove_file(dir.join("k1")).unwrap();
+        std::fs::write(dir.join("k2"), "second-secret").unwrap();
+        assert!(signer.reload().is_err());
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let token = signer.sign(&target(), client, 60).unwrap();
+        assert!(signer.verify(&token).is_some());
+
+        // `current` must name a loaded key; without signingKeyId it is required
+        std::fs::write(dir.join("current"), "k9").unwrap();
+        assert!(signer.reload().is_err());
+        std::fs::write(dir.join("current"), "k2").unwrap();
+        assert!(signer.reload().unwrap());
+        let token = signer.sign(&target(), client, 60).unwrap();
+        assert!(signer.verify(&token).is_some());
+        let from_current = TokenSigner::new(SignedTokenConfig {
+            signing_key_id: None,
+            ..config("k1", SignedTokenAlgorithm::Hmac)
+        })
+        .unwrap();
+        assert!(from_current.verify(&token).is_some());
+        std::fs::remove_file(dir.join("current")).unwrap();
+        let err = TokenSigner::new(SignedTokenConfig {
+            signing_key_id: None,
+            ..config("k1", SignedTokenAlgorithm::Hmac)
+        })
+        .err();
+        assert!(err.unwrap().to_string().contains("needs signingKeyId"));
+    }
+
+    #[test]
+    fn test_session_data_only_when_enabled() {
+        let tmp = temp_dir();
+        let dir = tmp.path();
+        std::fs::write(dir.join("k1"), "first-secret").unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let target =
+            target().with_metadata(HashMap::from([("match".to_string(), "m-1".to_string())]));
+        let payload = |token: &str| -> serde_json::Value {
+            jsonwebtoken::decode(
+                token,
+                &DecodingKey::from_secret(b"first-secret"),
+                &Validation::new(Algorithm::HS256),
+            )
+            .unwrap()
+            .claims
+        };
+
+        // The payload is readable by clients, so only routing claims by default
+        let signer = signer_for(dir, SignedTokenAlgorithm::Hmac, "k1");
+        let token = signer.sign(&target, client, 60).unwrap();
+        let claims = payload(&token);
+        assert!(claims.get("pid").is_none());
+        assert!(claims.get("meta").is_none());
+        let verified = signer.verify(&token).unwrap();
+        assert_eq!(verified.target.player_id, None);
+        assert!(verified.target.metadata.is_empty());
+
+        let signer = TokenSigner {
+            config: SignedTokenConfig {
+                include_session_data: true,
+                ..signer.config.clone()
+            },
+            ..signer
+        };
+        let token = signer.sign(&target, client, 60).unwrap();
+        assert_eq!(payload(&token)["pid"], "player-42");
+        let verified = signer.verify(&token).unwrap();
+        assert_eq!(verified.target.player_id.as_deref(), Some("player-42"));
+        assert_eq!(verified.target.metadata["match"], "m-1");
+    }
+}
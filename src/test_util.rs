@@ -0,0 +1,9 @@
+use tempfile::TempDir;
+
+/// Create a scratch directory that is removed when the returned handle drops
+pub fn temp_dir() -> TempDir {
+    tempfile::Builder::new()
+        .prefix("udp-director-")
+        .tempdir()
+        .unwrap()
+}
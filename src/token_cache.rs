@@ -1,11 +1,18 @@
+use anyhow::Result;
 use moka::future::Cache;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::Duration;
+use tracing::{debug, error};
 use uuid::Uuid;
 
 use crate::config::Protocol;
+use crate::metrics;
+use crate::session::BackendResource;
+use crate::signed_token::TokenSigner;
 
 /// Target information for a token with multi-port support
 #[derive(Debug, Clone)]
@@ -13,6 +20,12 @@ pub struct TokenTarget {
     pub cluster_ip: String,
     /// Port mappings: (proxy_port, protocol) -> target_port
     pub port_mappings: HashMap<(u16, Protocol), u16>,
+    /// Player the token was issued to (JWT-authenticated queries)
+    pub player_id: Option<String>,
+    /// Session metadata from the routing webhook
+    pub metadata: HashMap<String, String>,
+    /// Resource the token routes to (for session count writeback)
+    pub backend: Option<BackendResource>,
 }
 
 impl TokenTarget {
@@ -23,6 +36,9 @@ impl TokenTarget {
         Self {
             cluster_ip,
             port_mappings,
+            player_id: None,
+            metadata: HashMap::new(),
+            backend: None,
         }
     }
 
@@ -31,9 +47,30 @@ impl TokenTarget {
         Self {
             cluster_ip,
             port_mappings,
+            player_id: None,
+            metadata: HashMap::new(),
+            backend: None,
         }
     }
 
+    /// Record the player the token is issued to
+    pub fn with_player_id(mut self, player_id: Option<String>) -> Self {
+        self.player_id = player_id;
+        self
+    }
+
+    /// Record the routing webhook's session metadata
+    pub fn with_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Record the resource the token routes to
+    pub fn with_backend(mut self, backend: Option<BackendResource>) -> Self {
+        self.backend = backend;
+        self
+    }
+
     /// Convert to a SocketAddr for a specific proxy port and protocol
     #[allow(dead_code)]
     pub fn to_socket_addr_for_port(
@@ -85,49 +122,243 @@ impl TokenTarget {
     }
 }
 
+/// Restrictions on how a token may be redeemed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenPolicyConfig {
+    /// Consume the token on its first successful use (default: false)
+    #[serde(default)]
+    pub single_use: bool,
+    /// Number of successful uses before the token is consumed (ignored with `singleUse`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_uses: Option<u32>,
+    /// Only accept the token from the network of the client it was issued to (default: false)
+    #[serde(default)]
+    pub bind_client_ip: bool,
+    /// IPv4 prefix length compared when binding (default: 32, the exact address)
+    #[serde(default = "default_ipv4_prefix_len")]
+    pub ipv4_prefix_len: u8,
+    /// IPv6 prefix length compared when binding (default: 128, the exact address)
+    #[serde(default = "default_ipv6_prefix_len")]
+    pub ipv6_prefix_len: u8,
+}
+
+fn default_ipv4_prefix_len() -> u8 {
+    32
+}
+
+fn default_ipv6_prefix_len() -> u8 {
+    128
+}
+
+impl Default for TokenPolicyConfig {
+    fn default() -> Self {
+        Self {
+            single_use: false,
+            max_uses: None,
+            bind_client_ip: false,
+            ipv4_prefix_len: default_ipv4_prefix_len(),
+            ipv6_prefix_len: default_ipv6_prefix_len(),
+        }
+    }
+}
+
+impl TokenPolicyConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_uses == Some(0) {
+            anyhow::bail!("tokenPolicy.maxUses must be at least 1");
+        }
+        if self.ipv4_prefix_len > 32 || self.ipv6_prefix_len > 128 {
+            anyhow::bail!("tokenPolicy prefix lengths must be at most 32 (IPv4) and 128 (IPv6)");
+        }
+        Ok(())
+    }
+
+    fn use_limit(&self) -> Option<u32> {
+        if self.single_use {
+            Some(1)
+        } else {
+            self.max_uses
+        }
+    }
+
+    /// Whether both addresses fall into the same bound prefix
+    fn same_network(&self, issued_to: IpAddr, client_ip: IpAddr) -> bool {
+        match (issued_to.to_canonical(), client_ip.to_canonical()) {
+            (IpAddr::V4(a), IpAddr::V4(b)) => prefix_matches(
+                u32::from(a).into(),
+                u32::from(b).into(),
+                32,
+                self.ipv4_prefix_len,
+            ),
+            (IpAddr::V6(a), IpAddr::V6(b)) => {
+                prefix_matches(a.into(), b.into(), 128, self.ipv6_prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(a: u128, b: u128, bits: u32, prefix_len: u8) -> bool {
+    let shift = bits - u32::from(prefix_len).min(bits);
+    a.checked_shr(shift).unwrap_or(0) == b.checked_shr(shift).unwrap_or(0)
+}
+
+/// A cached token and the client it was issued to
+struct IssuedToken {
+    target: TokenTarget,
+    client_ip: IpAddr,
+}
+
 /// Token cache with TTL support
+///
+/// With a signer, tokens are self-contained and verified instead of cached, so
+/// any replica sharing the keys accepts them. Replicas share no redemption state,
+/// so signed tokens have no use limits (rejected by config validation) and cannot
+/// be revoked; removing their signing key invalidates them everywhere.
 #[derive(Clone)]
 pub struct TokenCache {
-    cache: Arc<Cache<String, TokenTarget>>,
+    cache: Arc<Cache<String, Arc<IssuedToken>>>,
+    /// Token ID -> successful uses (only with a use limit)
+    uses: Arc<Cache<String, Arc<AtomicU32>>>,
+    ttl_seconds: u64,
+    policy: TokenPolicyConfig,
+    signer: Option<TokenSigner>,
 }
 
 impl TokenCache {
     /// Create a new token cache with the specified TTL in seconds
     pub fn new(ttl_seconds: u64) -> Self {
-        let cache = Cache::builder()
-            .time_to_live(Duration::from_secs(ttl_seconds))
-            .build();
-
         Self {
-            cache: Arc::new(cache),
+            cache: Arc::new(ttl_cache(ttl_seconds)),
+            uses: Arc::new(ttl_cache(ttl_seconds)),
+            ttl_seconds,
+            policy: TokenPolicyConfig::default(),
+            signer: None,
         }
     }
 
-    /// Generate a new token and store the target
-    pub async fn generate_token(&self, target: TokenTarget) -> String {
+    /// Issue signed tokens instead of caching targets
+    pub fn with_signer(mut self, signer: TokenSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Whether tokens are signed (and so cannot be revoked)
+    pub fn signs_tokens(&self) -> bool {
+        self.signer.is_some()
+    }
+
+    /// Restrict how often and from where tokens may be redeemed
+    pub fn with_policy(mut self, policy: TokenPolicyConfig) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Generate a new token for `client_ip` and store the target
+    ///
+    /// Falls back to a cached token if signing fails.
+    pub async fn generate_token(&self, target: TokenTarget, client_ip: IpAddr) -> String {
+        if let Some(signer) = &self.signer {
+            match signer.sign(&target, client_ip, self.ttl_seconds) {
+                Ok(token) => return token,
+                Err(e) => error!("Failed to sign token, issuing a local one: {:#}", e),
+            }
+        }
         let token = Uuid::new_v4().to_string();
-        self.cache.insert(token.clone(), target).await;
+        self.cache
+            .insert(token.clone(), Arc::new(IssuedToken { target, client_ip }))
+            .await;
         token
     }
-    /// Look up a token and return the target if valid
-    pub async fn lookup(&self, token: &str) -> Option<TokenTarget> {
-        self.cache.get(token).await
+
+    /// Redeem a token presented by `client_ip` and return the target if valid
+    ///
+    /// Each successful lookup counts as a use under the token policy.
+    pub async fn lookup(&self, token: &str, client_ip: IpAddr) -> Option<TokenTarget> {
+        match self.redeem(token, client_ip).await {
+            Ok(target) => Some(target),
+            Err(reason) => {
+                debug!("Rejected token from {}: {}", client_ip, reason);
+                metrics::record_token_rejection(reason);
+                None
+            }
+        }
     }
+
+    async fn redeem(&self, token: &str, client_ip: IpAddr) -> Result<TokenTarget, &'static str> {
+        let (id, target, issued_to) = match self.signer.as_ref().and_then(|s| s.verify(token)) {
+            Some(verified) => (verified.id, verified.target, verified.client_ip),
+            None => {
+                let issued = self.cache.get(token).await.ok_or("unknown")?;
+                (token.to_string(), issued.target.clone(), issued.client_ip)
+            }
+        };
+
+        // Checked before counting so a foreign client cannot burn the token
+        if self.policy.bind_client_ip && !self.policy.same_network(issued_to, client_ip) {
+            return Err("ip_mismatch");
+        }
+
+        if let Some(max_uses) = self.policy.use_limit() {
+            let uses = self
+                .uses
+                .get_with(id.clone(), async { Arc::new(AtomicU32::new(0)) })
+                .await;
+            let count = uses.fetch_add(1, Ordering::SeqCst) + 1;
+            if count >= max_uses {
+                self.cache.invalidate(&id).await;
+            }
+            if count > max_uses {
+                return Err("exhausted");
+            }
+        }
+        Ok(target)
+    }
+
+    /// Revoke a single cached token; returns whether it was valid
+    pub async fn revoke(&self, token: &str) -> bool {
+        self.cache.remove(token).await.is_some()
+    }
+
+    /// Revoke every cached token issued for a backend; returns the number removed
+    pub async fn revoke_backend(&self, cluster_ip: &str) -> usize {
+        let tokens: Vec<Arc<String>> = self
+            .cache
+            .iter()
+            .filter(|(_, issued)| issued.target.cluster_ip == cluster_ip)
+            .map(|(token, _)| token)
+            .collect();
+        for token in &tokens {
+            self.cache.invalidate(token.as_str()).await;
+        }
+        tokens.len()
+    }
+}
+
+fn ttl_cache<V: Clone + Send + Sync + 'static>(ttl_seconds: u64) -> Cache<String, V> {
+    Cache::builder()
+        .time_to_live(Duration::from_secs(ttl_seconds))
+        .build()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn client_ip() -> IpAddr {
+        "203.0.113.7".parse().unwrap()
+    }
+
     #[tokio::test]
     async fn test_token_generation_and_lookup() {
         let cache = TokenCache::new(60);
         let target = TokenTarget::single_port("10.0.0.1".to_string(), 7777);
 
-        let token = cache.generate_token(target.clone()).await;
+        let token = cache.generate_token(target.clone(), client_ip()).await;
         assert!(!token.is_empty());
 
-        let retrieved = cache.lookup(&token).await;
+        let retrieved = cache.lookup(&token, client_ip()).await;
         assert!(retrieved.is_some());
 
         let retrieved_target = retrieved.unwrap();
@@ -140,11 +371,96 @@ mod tests {
         let cache = TokenCache::new(1); // 1 second TTL
         let target = TokenTarget::single_port("10.0.0.1".to_string(), 7777);
 
-        let token = cache.generate_token(target).await;
-        assert!(cache.lookup(&token).await.is_some());
+        let token = cache.generate_token(target, client_ip()).await;
+        assert!(cache.lookup(&token, client_ip()).await.is_some());
 
         // Wait for TTL to expire
         tokio::time::sleep(Duration::from_secs(2)).await;
-        assert!(cache.lookup(&token).await.is_none());
+        assert!(cache.lookup(&token, client_ip()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_single_use_and_max_uses() {
+        let target = TokenTarget::single_port("10.0.0.1".to_string(), 7777);
+
+        let cache = TokenCache::new(60).with_policy(TokenPolicyConfig {
+            single_use: true,
+            ..Default::default()
+        });
+        let token = cache.generate_token(target.clone(), client_ip()).await;
+        let (first, second) = tokio::join!(
+            cache.lookup(&token, client_ip()),
+            cache.lookup(&token, client_ip())
+        );
+        assert!(first.is_some() != second.is_some(), "exactly one use wins");
+        assert!(cache.lookup(&token, client_ip()).await.is_none());
+
+        let cache = TokenCache::new(60).with_policy(TokenPolicyConfig {
+            max_uses: Some(2),
+            ..Default::default()
+        });
+        let token = cache.generate_token(target, client_ip()).await;
+        assert!(cache.lookup(&token, client_ip()).await.is_some());
+        assert!(cache.lookup(&token, client_ip()).await.is_some());
+        assert!(cache.lookup(&token, client_ip()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_binding() {
+        let target = TokenTarget::single_port("10.0.0.1".to_string(), 7777);
+        let cache = TokenCache::new(60).with_policy(TokenPolicyConfig {
+            single_use: true,
+            bind_client_ip: true,
+            ipv4_prefix_len: 24,
+            ..Default::default()
+        });
+        let token = cache.generate_token(target, client_ip()).await;
+
+        // A foreign client neither gets the target nor consumes the token
+        let foreign: IpAddr = "198.51.100.7".parse().unwrap();
+        assert!(cache.lookup(&token, foreign).await.is_none());
+        let same_subnet: IpAddr = "203.0.113.200".parse().unwrap();
+        assert!(cache.lookup(&token, same_subnet).await.is_some());
+
+        let policy = TokenPolicyConfig {
+            ipv6_prefix_len: 64,
+            ..Default::default()
+        };
+        let v6 = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(policy.same_network(v6("2001:db8::1"), v6("2001:db8::ffff")));
+        assert!(!policy.same_network(v6("2001:db8::1"), v6("2001:db8:1::1")));
+        assert!(policy.same_network(v6("::ffff:203.0.113.7"), client_ip()));
+        assert!(!TokenPolicyConfig::default().same_network(client_ip(), v6("203.0.113.8")));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_token_and_backend() {
+        let cache = TokenCache::new(60);
+        let token_a = cache
+            .generate_token(
+                TokenTarget::single_port("10.0.0.1".to_string(), 7777),
+                client_ip(),
+            )
+            .await;
+        let token_b = cache
+            .generate_token(
+                TokenTarget::single_port("10.0.0.1".to_string(), 7778),
+                client_ip(),
+            )
+            .await;
+        let token_c = cache
+            .generate_token(
+                TokenTarget::single_port("10.0.0.2".to_string(), 7777),
+                client_ip(),
+            )
+            .await;
+
+        assert!(cache.revoke(&token_c).await);
+        assert!(!cache.revoke(&token_c).await);
+        assert!(cache.lookup(&token_c, client_ip()).await.is_none());
+
+        assert_eq!(cache.revoke_backend("10.0.0.1").await, 2);
+        assert!(cache.lookup(&token_a, client_ip()).await.is_none());
+        assert!(cache.lookup(&token_b, client_ip()).await.is_none());
     }
 }
@@ -0,0 +1,211 @@
+use anyhow::Result;
+use kube::api::DynamicObject;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::k8s_client::K8sClient;
+
+/// Node paths tried (in order) when `nodeNamePath` is not configured
+/// Pods expose `spec.nodeName`, Agones GameServers expose `status.nodeName`
+const DEFAULT_NODE_NAME_PATHS: &[&str] = &["spec.nodeName", "status.nodeName"];
+
+/// Zone-aware backend preference layered over the load balancing strategy
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologyConfig {
+    /// Node label holding the zone (default: "topology.kubernetes.io/zone")
+    #[serde(default = "default_zone_label")]
+    pub zone_label: String,
+    /// Zone of this director replica; learned from its own node (`NODE_NAME`) when unset
+    #[serde(default)]
+    pub zone: Option<String>,
+    /// JSONPath to the backend's node name (default: spec.nodeName, then status.nodeName)
+    #[serde(default)]
+    pub node_name_path: Option<String>,
+    /// Spill over to all zones when fewer local backends are available (default: 1)
+    #[serde(default = "default_min_local_backends")]
+    pub min_local_backends: usize,
+    /// Spill over to all zones once local backends hold this many director sessions
+    #[serde(default)]
+    pub max_local_sessions: Option<usize>,
+    /// How often to refresh the node -> zone map (default: 60)
+    #[serde(default = "default_refresh_interval_seconds")]
+    pub refresh_interval_seconds: u64,
+}
+
+fn default_zone_label() -> String {
+    "topology.kubernetes.io/zone".to_string()
+}
+
+fn default_min_local_backends() -> usize {
+    1
+}
+
+fn default_refresh_interval_seconds() -> u64 {
+    60
+}
+
+/// Resolves the zone of this replica and of backend resources
+#[derive(Clone)]
+pub struct TopologyResolver {
+    config: TopologyConfig,
+    k8s_client: K8sClient,
+    /// Node this replica runs on (from the `NODE_NAME` environment variable)
+    node_name: Option<String>,
+    /// Node name -> zone
+    node_zones: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl TopologyResolver {
+    /// Create a new resolver; the node map is empty until `refresh` runs
+    pub fn new(config: TopologyConfig, k8s_client: K8sClient) -> Self {
+        let node_name = std::env::var("NODE_NAME").ok().filter(|n| !n.is_empty());
+        if config.zone.is_none() && node_name.is_none() {
+            warn!(
+                "Topology-aware routing enabled but neither topology.zone nor NODE_NAME is set; \
+                backends will be selected from all zones"
+            );
+        }
+
+        Self {
+            config,
+            k8s_client,
+            node_name,
+            node_zones: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Topology configuration
+    pub fn config(&self) -> &TopologyConfig {
+        &self.config
+    }
+
+    /// Refresh the node -> zone map periodically
+    pub async fn run(self) -> Result<()> {
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            self.config.refresh_interval_seconds.max(1),
+        ));
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.refresh().await {
+                warn!("Failed to refresh node zones: {}", e);
+            }
+        }
+    }
+
+    /// Reload the node -> zone map from the cluster
+    pub async fn refresh(&self) -> Result<()> {
+        let zones = self
+            .k8s_client
+            .list_node_labels(&self.config.zone_label)
+            .await?;
+        debug!("Loaded zones for {} node(s)", zones.len());
+        self.set_node_zones(zones);
+
+        if let Some(zone) = self.local_zone() {
+            debug!("Director zone: {}", zone);
+        }
+        Ok(())
+    }
+
+    /// Replace the node -> zone map
+    pub fn set_node_zones(&self, zones: HashMap<String, String>) {
+        let mut node_zones = self.node_zones.write().unwrap_or_else(|e| e.into_inner());
+        let first_load = node_zones.is_empty();
+        *node_zones = zones;
+        drop(node_zones);
+
+        if first_load {
+            if let Some(zone) = self.local_zone() {
+                info!("Topology-aware routing active, preferring zone '{}'", zone);
+            }
+        }
+    }
+
+    /// Zone of this director replica, if known
+    pub fn local_zone(&self) -> Option<String> {
+        if let Some(zone) = &self.config.zone {
+            return Some(zone.clone());
+        }
+
+        let node_name = self.node_name.as_ref()?;
+        self.node_zones
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(node_name)
+            .cloned()
+    }
+
+    /// Zone of the node a backend resource runs on, if known
+    pub fn backend_zone(&self, resource: &DynamicObject) -> Option<String> {
+        let node_name = match &self.config.node_name_path {
+            Some(path) => self.k8s_client.extract_string(resource, path),
+            None => DEFAULT_NODE_NAME_PATHS
+                .iter()
+                .find_map(|path| self.k8s_client.extract_string(resource, path)),
+        }?;
+
+        self.node_zones
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&node_name)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_topology_config_defaults() {
+        let config: TopologyConfig = serde_yaml::from_str("maxLocalSessions: 200").unwrap();
+        assert_eq!(config.zone_label, "topology.kubernetes.io/zone");
+        assert_eq!(config.zone, None);
+        assert_eq!(config.min_local_backends, 1);
+        assert_eq!(config.max_local_sessions, Some(200));
+        assert_eq!(config.refresh_interval_seconds, 60);
+    }
+
+    #[tokio::test]
+    async fn test_backend_zone_resolution() {
+        let k8s_client = K8sClient::from_client(
+            kube::Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap()))
+                .unwrap(),
+        );
+
+        let config: TopologyConfig = serde_yaml::from_str("zone: us-east-1a").unwrap();
+        let resolver = TopologyResolver::new(config, k8s_client);
+        resolver.set_node_zones(HashMap::from([
+            ("node-a".to_string(), "us-east-1a".to_string()),
+            ("node-b".to_string(), "us-east-1b".to_string()),
+        ]));
+
+        let pod: DynamicObject = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "name": "pod-1" },
+            "spec": { "nodeName": "node-b" }
+        }))
+        .unwrap();
+        let gameserver: DynamicObject = serde_json::from_value(json!({
+            "apiVersion": "agones.dev/v1",
+            "kind": "GameServer",
+            "metadata": { "name": "gs-1" },
+            "status": { "nodeName": "node-a" }
+        }))
+        .unwrap();
+
+        assert_eq!(resolver.local_zone().as_deref(), Some("us-east-1a"));
+        assert_eq!(resolver.backend_zone(&pod).as_deref(), Some("us-east-1b"));
+        assert_eq!(
+            resolver.backend_zone(&gameserver).as_deref(),
+            Some("us-east-1a")
+        );
+    }
+}
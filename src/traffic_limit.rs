@@ -0,0 +1,388 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::metrics;
+use crate::rate_limit::{Bucket, BucketConfig};
+
+/// What happens to packets over the limit
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LimitAction {
+    /// Drop the packet
+    #[default]
+    Drop,
+    /// Delay the packet up to `maxShapeDelayMs`, then drop
+    Shape,
+}
+
+/// Packet and byte buckets; either may be omitted
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrafficBuckets {
+    /// Packets per second (`burst` in packets)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packets: Option<BucketConfig>,
+    /// Bytes per second (`burst` in bytes; must fit the largest packet)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<BucketConfig>,
+}
+
+/// Traffic limits of a UDP data port, applied separately to each direction
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrafficLimitConfig {
+    /// Limits for each session on this port
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_session: Option<TrafficBuckets>,
+    /// Limits for all sessions on this port together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_port: Option<TrafficBuckets>,
+    #[serde(default)]
+    pub action: LimitAction,
+    /// Longest a shaped packet may wait (default: 50)
+    #[serde(default = "default_max_shape_delay_ms")]
+    pub max_shape_delay_ms: u64,
+}
+
+fn default_max_shape_delay_ms() -> u64 {
+    50
+}
+
+/// Packets a session may have waiting per direction; more are dropped
+const SHAPING_QUEUE_PACKETS: usize = 256;
+
+impl TrafficLimitConfig {
+    pub fn validate(&self, port_name: &str) -> Result<()> {
+        for buckets in [self.per_session, self.per_port].into_iter().flatten() {
+            for bucket in [buckets.packets, buckets.bytes].into_iter().flatten() {
+                if !bucket.is_valid() {
+                    anyhow::bail!(
+                        "data_port '{}' limits: ratePerSecond and burst must be greater than 0",
+                        port_name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn max_delay(&self) -> Duration {
+        match self.action {
+            LimitAction::Drop => Duration::ZERO,
+            LimitAction::Shape => Duration::from_millis(self.max_shape_delay_ms),
+        }
+    }
+}
+
+/// Packet direction through the proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Client to backend
+    Upstream,
+    /// Backend to client
+    Downstream,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Upstream => "upstream",
+            Direction::Downstream => "downstream",
+        }
+    }
+}
+
+/// Bucket state for one direction
+struct DirectionState {
+    packets: Option<Bucket>,
+    bytes: Option<Bucket>,
+}
+
+impl DirectionState {
+    fn new(config: Option<TrafficBuckets>, now: Instant) -> Self {
+        let config = config.unwrap_or_default();
+        Self {
+            packets: config.packets.map(|bucket| Bucket::full(&bucket, now)),
+            bytes: config.bytes.map(|bucket| Bucket::full(&bucket, now)),
+        }
+    }
+
+    /// Delay until both buckets allow the packet; `None` if over `max_delay`
+    fn delay_for(
+        &mut self,
+        config: Option<TrafficBuckets>,
+        len: usize,
+        now: Instant,
+        max_delay: Duration,
+    ) -> Option<Duration> {
+        let config = config.unwrap_or_default();
+        let mut delay = Duration::ZERO;
+        if let (Some(bucket_config), Some(bucket)) = (&config.packets, &mut self.packets) {
+            delay = delay.max(bucket.delay_for(bucket_config, 1.0, now, max_delay)?);
+        }
+        if let (Some(bucket_config), Some(bucket)) = (&config.bytes, &mut self.bytes) {
+            delay = delay.max(bucket.delay_for(bucket_config, len as f64, now, max_delay)?);
+        }
+        Some(delay)
+    }
+
+    fn take(&mut self, len: usize) {
+        if let Some(bucket) = &mut self.packets {
+            bucket.take(1.0);
+        }
+        if let Some(bucket) = &mut self.bytes {
+            bucket.take(len as f64);
+        }
+    }
+}
+
+/// Limits shared by all sessions of one data port
+pub struct PortTrafficLimiter {
+    proxy_port: u16,
+    config: TrafficLimitConfig,
+    /// Aggregate buckets: [upstream, downstream]
+    port: [Mutex<DirectionState>; 2],
+}
+
+impl PortTrafficLimiter {
+    pub fn new(proxy_port: u16, config: TrafficLimitConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            proxy_port,
+            port: [
+                Mutex::new(DirectionState::new(config.per_port, now)),
+                Mutex::new(DirectionState::new(config.per_port, now)),
+            ],
+            config,
+        }
+    }
+
+    /// Buckets for a new session on this port
+    pub fn session(self: &Arc<Self>) -> SessionTrafficLimiter {
+        let now = Instant::now();
+        SessionTrafficLimiter {
+            session: [
+                Mutex::new(DirectionState::new(self.config.per_session, now)),
+                Mutex::new(DirectionState::new(self.config.per_session, now)),
+            ],
+            port: self.clone(),
+        }
+    }
+}
+
+/// Limits of one session on one data port
+pub struct SessionTrafficLimiter {
+    port: Arc<PortTrafficLimiter>,
+    /// Session buckets: [upstream, downstream]
+    session: [Mutex<DirectionState>; 2],
+}
+
+impl SessionTrafficLimiter {
+    /// When a packet of `len` bytes may be forwarded; `None` if it must be dropped
+    fn schedule(&self, direction: Direction, len: usize) -> Option<Instant> {
+        let now = Instant::now();
+        match self.reserve(direction, len, now) {
+            Some(delay) if delay.is_zero() => Some(now),
+            Some(delay) => {
+                self.record(direction, "shaped", len);
+                Some(now + delay)
+            }
+            None => {
+                self.record_dropped(direction, len, "traffic limit");
+                None
+            }
+        }
+    }
+
+    fn record_dropped(&self, direction: Direction, len: usize, reason: &str) {
+        debug!(
+            "Dropped {} byte {} packet on port {} ({})",
+            len,
+            direction.as_str(),
+            self.port.proxy_port,
+            reason
+        );
+        self.record(direction, "dropped", len);
+    }
+
+    fn record(&self, direction: Direction, action: &str, len: usize) {
+        let port = self.port.proxy_port.to_string();
+        metrics::record_traffic_limited(&port, direction.as_str(), action, len);
+    }
+
+    /// Reserve tokens in the session and port buckets, or neither
+    fn reserve(&self, direction: Direction, len: usize, now: Instant) -> Option<Duration> {
+        let index = match direction {
+            Direction::Upstream => 0,
+            Direction::Downstream => 1,
+        };
+        let config = &self.port.config;
+        let max_delay = config.max_delay();
+
+        // Always session before port, so concurrent packets lock in the same order
+        let mut session = self.session[index]
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let mut port = self.port.port[index]
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let delay = session
+            .delay_for(config.per_session, len, now, max_delay)?
+            .max(port.delay_for(config.per_port, len, now, max_delay)?);
+        session.take(len);
+        port.take(len);
+        Some(delay)
+    }
+}
+
+/// Bounded queue forwarding one direction of a limited session
+///
+/// Every packet of the direction passes through the queue, so shaped packets keep
+/// their order and the loop receiving them never waits: a task sends each packet at
+/// its scheduled time.
+pub struct ShapingQueue {
+    limiter: Arc<SessionTrafficLimiter>,
+    direction: Direction,
+    sender: mpsc::Sender<(Instant, Vec<u8>)>,
+}
+
+impl ShapingQueue {
+    /// Start the sending task; it ends once the queue is dropped
+    pub fn spawn<F, Fut>(limiter: Arc<SessionTrafficLimiter>, direction: Direction, send: F) -> Self
+    where
+        F: Fn(Vec<u8>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let (sender, mut receiver) = mpsc::channel::<(Instant, Vec<u8>)>(SHAPING_QUEUE_PACKETS);
+        tokio::spawn(async move {
+            while let Some((send_at, packet)) = receiver.recv().await {
+                tokio::time::sleep_until(send_at.into()).await;
+                send(packet).await;
+            }
+        });
+        Self {
+            limiter,
+            direction,
+            sender,
+        }
+    }
+
+    /// Queue a packet; false if it was dropped by the limit or a full queue
+    pub fn push(&self, packet: Vec<u8>) -> bool {
+        let len = packet.len();
+        let Some(send_at) = self.limiter.schedule(self.direction, len) else {
+            return false;
+        };
+        if self.sender.try_send((send_at, packet)).is_err() {
+            self.limiter
+                .record_dropped(self.direction, len, "shaping queue full");
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(action: LimitAction) -> Arc<PortTrafficLimiter> {
+        Arc::new(PortTrafficLimiter::new(
+            7777,
+            TrafficLimitConfig {
+                per_session: Some(TrafficBuckets {
+                    packets: Some(BucketConfig {
+                        rate_per_second: 10.0,
+                        burst: 2,
+                    }),
+                    bytes: Some(BucketConfig {
+                        rate_per_second: 1000.0,
+                        burst: 1500,
+                    }),
+                }),
+                per_port: Some(TrafficBuckets {
+                    packets: Some(BucketConfig {
+                        rate_per_second: 10.0,
+                        burst: 3,
+                    }),
+                    bytes: None,
+                }),
+                action,
+                max_shape_delay_ms: 150,
+            },
+        ))
+    }
+
+    #[test]
+    fn test_drop_per_session_and_per_port() {
+        let port = limiter(LimitAction::Drop);
+        let first = port.session();
+        let second = port.session();
+        let now = Instant::now();
+
+        // Packet burst of 2 per session, directions are independent
+        assert_eq!(
+            first.reserve(Direction::Upstream, 100, now),
+            Some(Duration::ZERO)
+        );
+        assert!(first.reserve(Direction::Upstream, 100, now).is_some());
+        assert!(first.reserve(Direction::Upstream, 100, now).is_none());
+        assert!(first.reserve(Direction::Downstream, 100, now).is_some());
+
+        // Port burst of 3 is shared by both sessions
+        assert!(second.reserve(Direction::Upstream, 100, now).is_some());
+        assert!(second.reserve(Direction::Upstream, 100, now).is_none());
+
+        // Byte budget: 1500 burst, a rejected packet spends nothing
+        let later = now + Duration::from_secs(1);
+        assert!(first.reserve(Direction::Downstream, 1400, later).is_some());
+        assert!(first.reserve(Direction::Downstream, 1400, later).is_none());
+        assert!(first.reserve(Direction::Downstream, 50, later).is_some());
+    }
+
+    #[test]
+    fn test_shape_delays_then_drops() {
+        let port = limiter(LimitAction::Shape);
+        let session = port.session();
+        let now = Instant::now();
+
+        assert!(session.reserve(Direction::Upstream, 10, now).is_some());
+        assert!(session.reserve(Direction::Upstream, 10, now).is_some());
+        // 10 packets/s: the next one waits 100ms, the one after would wait 200ms
+        let delay = session.reserve(Direction::Upstream, 10, now).unwrap();
+        assert!((delay.as_secs_f64() - 0.1).abs() < 1e-6);
+        assert!(session.reserve(Direction::Upstream, 10, now).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shaping_queue_keeps_order_without_blocking() {
+        let port = limiter(LimitAction::Shape);
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let recorder = sent.clone();
+        let queue = ShapingQueue::spawn(
+            Arc::new(port.session()),
+            Direction::Downstream,
+            move |packet: Vec<u8>| {
+                let recorder = recorder.clone();
+                async move { recorder.lock().unwrap().push(packet[0]) }
+            },
+        );
+
+        // Two packets fit the burst, the third waits 100ms and the fourth is dropped
+        let start = Instant::now();
+        for packet in 1..=4u8 {
+            assert_eq!(queue.push(vec![packet]), packet < 4);
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(*sent.lock().unwrap(), vec![1, 2]);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(*sent.lock().unwrap(), vec![1, 2, 3]);
+    }
+}